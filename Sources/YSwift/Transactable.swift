@@ -54,4 +54,15 @@ extension Transactable {
             return document.transactSync(origin: .none, changes)
         }
     }
+
+    /// Legacy sync transaction helper for changes that can throw, rethrowing their error.
+    func withThrowingTransaction<T>(_ transaction: YrsTransaction? = nil, changes: @escaping (YrsTransaction) throws -> T) throws -> T {
+        if let transaction = transaction {
+            return try changes(transaction)
+        } else {
+            return try document.transactSync(origin: .none) { txn in
+                Result { try changes(txn) }
+            }.get()
+        }
+    }
 }
@@ -59,6 +59,15 @@ public final class YArray<T: Codable>: Transactable, YCollection, @unchecked Sen
         }
     }
 
+    /// Appends an array of objects to the end of the list asynchronously, in a single call.
+    /// - Parameter values: The objects to insert.
+    /// - Throws: `CodingError` when a value can't be decoded, in which case nothing is appended.
+    public func append(contentsOf values: [T]) async throws where T: Sendable {
+        try await document.transact { txn in
+            try self._array.pushBackRange(tx: txn, values: Coder.encodedArray(values))
+        }
+    }
+
     /// Prepends an object at the beginning of the list asynchronously.
     /// - Parameter value: The object to insert.
     public func prepend(_ value: T) async where T: Sendable {
@@ -85,6 +94,26 @@ public final class YArray<T: Codable>: Transactable, YCollection, @unchecked Sen
         }
     }
 
+    /// Removes all objects from the list asynchronously.
+    public func removeAll() async where T: Sendable {
+        await document.transact { txn in
+            self._array.clear(tx: txn)
+        }
+    }
+
+    /// Moves an object to a new location asynchronously.
+    ///
+    /// Unlike removing and re-inserting the object, a move merges with concurrent moves
+    /// of the same object without duplicating it.
+    /// - Parameters:
+    ///   - source: The index location of the object to move.
+    ///   - target: The index location, in the list before the move, to move the object in front of.
+    public func move(from source: Int, to target: Int) async where T: Sendable {
+        await document.transact { txn in
+            self._array.moveTo(tx: txn, source: UInt32(source), target: UInt32(target))
+        }
+    }
+
     /// Returns the length of the list asynchronously.
     public func lengthAsync() async -> UInt32 where T: Sendable {
         await document.transact { txn in
@@ -366,10 +395,10 @@ class YArrayObservationDelegate<T: Codable>: YrsArrayObservationDelegate {
         self.decoded = decoded
     }
 
-    func call(value: [YrsChange]) {
+    func call(value: [YrsChange], origin _: YrsOrigin?, path _: [YrsPathSegment]) {
         let result: [YArrayChange<T>] = value.map { rsChange -> YArrayChange<T> in
             switch rsChange {
-            case let .added(elements):
+            case let .added(elements, _):
                 return YArrayChange.added(elements: decoded(elements))
             case let .removed(range):
                 return YArrayChange.removed(range: range)
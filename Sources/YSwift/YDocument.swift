@@ -278,11 +278,39 @@ public final class YDocument {
     }
 
     /// Creates an Undo Manager for a document with the collections that is tracks.
-    /// - Parameter trackedRefs: The collections to track to undo and redo changes.
+    /// - Parameters:
+    ///   - trackedRefs: The collections to track to undo and redo changes.
+    ///   - captureTimeout: The period, in milliseconds, within which consecutive changes are merged into a single undo step.
+    ///   - trackedOrigins: The origins of the transactions to track. If empty, only changes without an origin are tracked.
+    ///   - captureTransactions: Whether the undo manager starts capturing changes right away.
+    /// - Returns: A reference to the undo manager to control those actions.
+    public func undoManager<T: AnyObject>(
+        trackedRefs: [YCollection],
+        captureTimeout: UInt64 = 500,
+        trackedOrigins: [Origin] = [],
+        captureTransactions: Bool = true
+    ) -> YUndoManager<T> {
+        let mapped = trackedRefs.map { $0.pointer() }
+        let options = YrsUndoManagerOptions(
+            captureTimeoutMillis: captureTimeout,
+            trackedOrigins: trackedOrigins.map { $0.origin },
+            captureTransactions: captureTransactions
+        )
+        return YUndoManager(manager: document.undoManager(trackedRefs: mapped, options: options))
+    }
+
+    /// Creates an Undo Manager that only tracks the changes of the local user.
+    ///
+    /// Make local changes in transactions started with the same origin, for example
+    /// `transactSync(origin: origin) { ... }`. Changes from other origins, such as updates
+    /// applied from remote peers, are left out of the undo history.
+    /// - Parameters:
+    ///   - trackedRefs: The collections to track to undo and redo changes.
+    ///   - origin: The origin of the local user's transactions.
     /// - Returns: A reference to the undo manager to control those actions.
-    public func undoManager<T: AnyObject>(trackedRefs: [YCollection]) -> YUndoManager<T> {
+    public func localUndoManager<T: AnyObject>(trackedRefs: [YCollection], origin: Origin) -> YUndoManager<T> {
         let mapped = trackedRefs.map { $0.pointer() }
-        return YUndoManager(manager: document.undoManager(trackedRefs: mapped))
+        return YUndoManager(manager: document.localUndoManager(trackedRefs: mapped, origin: origin.origin))
     }
 
     // MARK: - JSON Path Queries (Async)
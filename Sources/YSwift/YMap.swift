@@ -465,8 +465,8 @@ class YMapObservationDelegate<T: Codable>: YrsMapObservationDelegate {
         self.callback = callback
     }
 
-    func call(value: [YrsMapChange]) {
-        let result: [YMapChange<T>] = value.map { rsChange -> YMapChange<T> in
+    func call(value: [YrsMapChange], origin _: YrsOrigin?, target _: YrsCollectionPtr) {
+        let result: [YMapChange<T>] = value.compactMap { rsChange -> YMapChange<T>? in
             switch rsChange.change {
             case let .inserted(value):
                 return YMapChange.inserted(key: rsChange.key, value: decoded(value))
@@ -474,6 +474,9 @@ class YMapObservationDelegate<T: Codable>: YrsMapObservationDelegate {
                 return YMapChange.updated(key: rsChange.key, oldValue: decoded(oldValue), newValue: decoded(newValue))
             case let .removed(value):
                 return YMapChange.removed(key: rsChange.key, value: decoded(value))
+            case .insertedShared, .updatedShared, .removedShared:
+                // Nested shared types can't be decoded as `T`.
+                return nil
             }
         }
         callback(result)
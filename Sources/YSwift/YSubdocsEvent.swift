@@ -3,19 +3,41 @@ import Yniffi
 
 /// An event emitted when subdocuments are added, loaded, or removed from a parent document.
 public struct YSubdocsEvent {
+    /// A subdocument affected by a ``YSubdocsEvent``.
+    public struct Subdoc {
+        /// The subdocument itself.
+        public let document: YDocument
+
+        /// The unique identifier of the subdocument.
+        public let guid: String
+
+        /// Whether the subdocument should be loaded automatically.
+        public let shouldLoad: Bool
+
+        init(from info: YrsSubdocInfo) {
+            self.document = YDocument(wrapping: info.doc)
+            self.guid = info.guid
+            self.shouldLoad = info.shouldLoad
+        }
+    }
+
     /// Subdocuments that were added to the parent document.
-    public let added: [YDocument]
+    public let added: [Subdoc]
 
     /// Subdocuments that were loaded.
-    public let loaded: [YDocument]
+    public let loaded: [Subdoc]
 
     /// Subdocuments that were removed from the parent document.
-    public let removed: [YDocument]
+    public let removed: [Subdoc]
+
+    /// The origin of the transaction that changed the subdocuments.
+    public let origin: Origin?
 
     init(from event: YrsSubdocsEvent) {
-        self.added = event.added.map { YDocument(wrapping: $0) }
-        self.loaded = event.loaded.map { YDocument(wrapping: $0) }
-        self.removed = event.removed.map { YDocument(wrapping: $0) }
+        self.added = event.added.map { Subdoc(from: $0) }
+        self.loaded = event.loaded.map { Subdoc(from: $0) }
+        self.removed = event.removed.map { Subdoc(from: $0) }
+        self.origin = event.origin.map { Origin($0) }
     }
 }
 
@@ -11,7 +11,16 @@ public final class YSubscription {
         self.subscription = subscription
     }
 
+    /// A Boolean value that indicates whether the subscription hasn't been cancelled.
+    ///
+    /// It doesn't tell whether the observed document is still alive: a subscription whose
+    /// document was deallocated stays active, although it no longer delivers changes.
+    public var isActive: Bool {
+        subscription?.isActive() ?? false
+    }
+
     public func cancel() {
+        subscription?.cancel()
         subscription = nil
     }
 
@@ -28,9 +28,10 @@ public final class YText: Transactable, YCollection, @unchecked Sendable {
     /// - Parameters:
     ///   - text: The string to insert.
     ///   - index: The position, within the UTF-8 buffer view, to insert the string.
-    public func insert(_ text: String, at index: UInt32) async {
-        await document.transact { txn in
-            self._text.insert(tx: txn, index: index, chunk: text)
+    /// - Throws: `YrsTextError` when the position is out of bounds, or splits a surrogate pair in a document that rejects such writes.
+    public func insert(_ text: String, at index: UInt32) async throws {
+        try await document.transact { txn in
+            try self._text.insert(tx: txn, index: index, chunk: text)
         }
     }
 
@@ -39,9 +40,10 @@ public final class YText: Transactable, YCollection, @unchecked Sendable {
     ///   - text: The string to insert.
     ///   - attributes: The attributes to associate with the string.
     ///   - index: The position to insert at.
-    public func insertWithAttributes(_ text: String, attributes: [String: Any], at index: UInt32) async {
-        await document.transact { txn in
-            self._text.insertWithAttributes(tx: txn, index: index, chunk: text, attrs: Coder.encoded(attributes))
+    /// - Throws: `YrsTextError` when the position is out of bounds, or splits a surrogate pair in a document that rejects such writes.
+    public func insertWithAttributes(_ text: String, attributes: [String: Any], at index: UInt32) async throws {
+        try await document.transact { txn in
+            try self._text.insertWithAttributes(tx: txn, index: index, chunk: text, attrs: Coder.encoded(attributes))
         }
     }
 
@@ -49,9 +51,10 @@ public final class YText: Transactable, YCollection, @unchecked Sendable {
     /// - Parameters:
     ///   - embed: The codable type to embed.
     ///   - index: The position to embed at.
-    public func insertEmbed<T: Encodable & Sendable>(_ embed: T, at index: UInt32) async {
-        await document.transact { txn in
-            self._text.insertEmbed(tx: txn, index: index, content: Coder.encoded(embed))
+    /// - Throws: `YrsTextError` when the position is out of bounds, or splits a surrogate pair in a document that rejects such writes.
+    public func insertEmbed<T: Encodable & Sendable>(_ embed: T, at index: UInt32) async throws {
+        try await document.transact { txn in
+            try self._text.insertEmbed(tx: txn, index: index, content: Coder.encoded(embed))
         }
     }
 
@@ -60,9 +63,10 @@ public final class YText: Transactable, YCollection, @unchecked Sendable {
     ///   - embed: The codable type to embed.
     ///   - attributes: The attributes to associate with the embedded type.
     ///   - index: The position to embed at.
-    public func insertEmbedWithAttributes<T: Encodable & Sendable>(_ embed: T, attributes: [String: Any], at index: UInt32) async {
-        await document.transact { txn in
-            self._text.insertEmbedWithAttributes(tx: txn, index: index, content: Coder.encoded(embed), attrs: Coder.encoded(attributes))
+    /// - Throws: `YrsTextError` when the position is out of bounds, or splits a surrogate pair in a document that rejects such writes.
+    public func insertEmbedWithAttributes<T: Encodable & Sendable>(_ embed: T, attributes: [String: Any], at index: UInt32) async throws {
+        try await document.transact { txn in
+            try self._text.insertEmbedWithAttributes(tx: txn, index: index, content: Coder.encoded(embed), attrs: Coder.encoded(attributes))
         }
     }
 
@@ -71,9 +75,10 @@ public final class YText: Transactable, YCollection, @unchecked Sendable {
     ///   - index: The index position to start formatting.
     ///   - length: The length of characters to update.
     ///   - attributes: The attributes to associate.
-    public func format(at index: UInt32, length: UInt32, attributes: [String: Any]) async {
-        await document.transact { txn in
-            self._text.format(tx: txn, index: index, length: length, attrs: Coder.encoded(attributes))
+    /// - Throws: `YrsTextError` when the position is out of bounds, or splits a surrogate pair in a document that rejects such writes.
+    public func format(at index: UInt32, length: UInt32, attributes: [String: Any]) async throws {
+        try await document.transact { txn in
+            try self._text.format(tx: txn, index: index, length: length, attrs: Coder.encoded(attributes))
         }
     }
 
@@ -81,9 +86,10 @@ public final class YText: Transactable, YCollection, @unchecked Sendable {
     /// - Parameters:
     ///   - start: The index position to start removing.
     ///   - length: The length of characters to remove.
-    public func removeRange(start: UInt32, length: UInt32) async {
-        await document.transact { txn in
-            self._text.removeRange(tx: txn, start: start, length: length)
+    /// - Throws: `YrsTextError` when the position is out of bounds, or splits a surrogate pair in a document that rejects such writes.
+    public func removeRange(start: UInt32, length: UInt32) async throws {
+        try await document.transact { txn in
+            try self._text.removeRange(tx: txn, start: start, length: length)
         }
     }
 
@@ -114,20 +120,14 @@ public final class YText: Transactable, YCollection, @unchecked Sendable {
     }
 
     /// Applies a delta asynchronously.
-    /// - Parameter delta: An array of text changes to apply.
-    public func applyDelta(_ delta: [YTextChange]) async {
-        let yrsDelta: [YrsDelta] = delta.map { change in
-            switch change {
-            case let .inserted(value, attributes):
-                return YrsDelta.inserted(value: value, attrs: Coder.encoded(attributes))
-            case let .deleted(index):
-                return YrsDelta.deleted(index: index)
-            case let .retained(index, attributes):
-                return YrsDelta.retained(index: index, attrs: Coder.encoded(attributes))
-            }
-        }
-        await document.transact { txn in
-            self._text.applyDelta(tx: txn, delta: yrsDelta)
+    /// - Parameter delta: An array of text changes to apply. Inserted values must be JSON.
+    /// - Returns: The delta that was applied, with adjacent operations merged and empty ones dropped.
+    /// - Throws: `YrsDeltaError` when an inserted value isn't JSON, or attributes aren't a JSON object.
+    @discardableResult
+    public func applyDelta(_ delta: [YTextChange]) async throws -> [YTextChange] {
+        let yrsDelta = YText.yrsDelta(delta)
+        return try await document.transact { txn in
+            YText.textChanges(try self._text.applyDelta(tx: txn, delta: yrsDelta))
         }
     }
 
@@ -171,18 +171,15 @@ public final class YText: Transactable, YCollection, @unchecked Sendable {
     ///   - text: The string to insert.
     ///   - index: The position, within the UTF-8 buffer view, to insert the string.
     ///   - transaction: An optional transaction to use when appending the string.
+    /// - Throws: `YrsTextError` when the position is out of bounds, or splits a surrogate pair in a document that rejects such writes.
     @available(*, deprecated, message: "Use async insert(_:at:) or pass explicit transaction")
     public func insert(
         _ text: String,
         at index: UInt32,
         in transaction: YrsTransaction? = nil
-    ) {
-        if let transaction {
-            self._text.insert(tx: transaction, index: index, chunk: text)
-        } else {
-            withTransaction(transaction) { txn in
-                self._text.insert(tx: txn, index: index, chunk: text)
-            }
+    ) throws {
+        try withThrowingTransaction(transaction) { txn in
+            try self._text.insert(tx: txn, index: index, chunk: text)
         }
     }
 
@@ -193,19 +190,16 @@ public final class YText: Transactable, YCollection, @unchecked Sendable {
     ///   - attributes: The attributes to associate with the appended string.
     ///   - index: The position, within the UTF-8 buffer view, to insert the string.
     ///   - transaction: An optional transaction to use when appending the string.
+    /// - Throws: `YrsTextError` when the position is out of bounds, or splits a surrogate pair in a document that rejects such writes.
     @available(*, deprecated, message: "Use async insertWithAttributes(_:attributes:at:) or pass explicit transaction")
     public func insertWithAttributes(
         _ text: String,
         attributes: [String: Any],
         at index: UInt32,
         in transaction: YrsTransaction? = nil
-    ) {
-        if let transaction {
-            self._text.insertWithAttributes(tx: transaction, index: index, chunk: text, attrs: Coder.encoded(attributes))
-        } else {
-            withTransaction(transaction) { txn in
-                self._text.insertWithAttributes(tx: txn, index: index, chunk: text, attrs: Coder.encoded(attributes))
-            }
+    ) throws {
+        try withThrowingTransaction(transaction) { txn in
+            try self._text.insertWithAttributes(tx: txn, index: index, chunk: text, attrs: Coder.encoded(attributes))
         }
     }
 
@@ -215,18 +209,15 @@ public final class YText: Transactable, YCollection, @unchecked Sendable {
     ///   - embed: The codable type to embed.
     ///   - index: The position, within the UTF-8 buffer view, to embed the object.
     ///   - transaction: An optional transaction to use when appending the string.
+    /// - Throws: `YrsTextError` when the position is out of bounds, or splits a surrogate pair in a document that rejects such writes.
     @available(*, deprecated, message: "Use async insertEmbed(_:at:) or pass explicit transaction")
     public func insertEmbed<T: Encodable>(
         _ embed: T,
         at index: UInt32,
         in transaction: YrsTransaction? = nil
-    ) {
-        if let transaction {
-            self._text.insertEmbed(tx: transaction, index: index, content: Coder.encoded(embed))
-        } else {
-            withTransaction(transaction) { txn in
-                self._text.insertEmbed(tx: txn, index: index, content: Coder.encoded(embed))
-            }
+    ) throws {
+        try withThrowingTransaction(transaction) { txn in
+            try self._text.insertEmbed(tx: txn, index: index, content: Coder.encoded(embed))
         }
     }
 
@@ -237,19 +228,16 @@ public final class YText: Transactable, YCollection, @unchecked Sendable {
     ///   - attributes: The attributes to associate with the embedded type.
     ///   - index: The position, within the UTF-8 buffer view, to embed the object.
     ///   - transaction: An optional transaction to use when appending the string.
+    /// - Throws: `YrsTextError` when the position is out of bounds, or splits a surrogate pair in a document that rejects such writes.
     @available(*, deprecated, message: "Use async insertEmbedWithAttributes(_:attributes:at:) or pass explicit transaction")
     public func insertEmbedWithAttributes<T: Encodable>(
         _ embed: T,
         attributes: [String: Any],
         at index: UInt32,
         in transaction: YrsTransaction? = nil
-    ) {
-        if let transaction {
-            self._text.insertEmbedWithAttributes(tx: transaction, index: index, content: Coder.encoded(embed), attrs: Coder.encoded(attributes))
-        } else {
-            withTransaction(transaction) { txn in
-                self._text.insertEmbedWithAttributes(tx: txn, index: index, content: Coder.encoded(embed), attrs: Coder.encoded(attributes))
-            }
+    ) throws {
+        try withThrowingTransaction(transaction) { txn in
+            try self._text.insertEmbedWithAttributes(tx: txn, index: index, content: Coder.encoded(embed), attrs: Coder.encoded(attributes))
         }
     }
 
@@ -260,19 +248,16 @@ public final class YText: Transactable, YCollection, @unchecked Sendable {
     ///   - length: The length of characters to update.
     ///   - attributes: The attributes to associate with the string.
     ///   - transaction: An optional transaction to use when appending the string.
+    /// - Throws: `YrsTextError` when the position is out of bounds, or splits a surrogate pair in a document that rejects such writes.
     @available(*, deprecated, message: "Use async format(at:length:attributes:) or pass explicit transaction")
     public func format(
         at index: UInt32,
         length: UInt32,
         attributes: [String: Any],
         in transaction: YrsTransaction? = nil
-    ) {
-        if let transaction {
-            self._text.format(tx: transaction, index: index, length: length, attrs: Coder.encoded(attributes))
-        } else {
-            withTransaction(transaction) { txn in
-                self._text.format(tx: txn, index: index, length: length, attrs: Coder.encoded(attributes))
-            }
+    ) throws {
+        try withThrowingTransaction(transaction) { txn in
+            try self._text.format(tx: txn, index: index, length: length, attrs: Coder.encoded(attributes))
         }
     }
 
@@ -282,18 +267,15 @@ public final class YText: Transactable, YCollection, @unchecked Sendable {
     ///   - start: The index position, in the UTF-8 view of the string, to start removing characters.
     ///   - length: The length of characters to remove.
     ///   - transaction: An optional transaction to use when appending the string.
+    /// - Throws: `YrsTextError` when the position is out of bounds, or splits a surrogate pair in a document that rejects such writes.
     @available(*, deprecated, message: "Use async removeRange(start:length:) or pass explicit transaction")
     public func removeRange(
         start: UInt32,
         length: UInt32,
         in transaction: YrsTransaction? = nil
-    ) {
-        if let transaction {
-            self._text.removeRange(tx: transaction, start: start, length: length)
-        } else {
-            withTransaction(transaction) { txn in
-                self._text.removeRange(tx: txn, start: start, length: length)
-            }
+    ) throws {
+        try withThrowingTransaction(transaction) { txn in
+            try self._text.removeRange(tx: txn, start: start, length: length)
         }
     }
 
@@ -362,11 +344,21 @@ public final class YText: Transactable, YCollection, @unchecked Sendable {
     /// Applies a delta to the text.
     /// - Warning: Deprecated. Use async `applyDelta(_:)` or pass an explicit transaction.
     /// - Parameters:
-    ///   - delta: An array of text changes to apply.
+    ///   - delta: An array of text changes to apply. Inserted values must be JSON.
     ///   - transaction: An optional transaction to use.
+    /// - Returns: The delta that was applied, with adjacent operations merged and empty ones dropped.
+    /// - Throws: `YrsDeltaError` when an inserted value isn't JSON, or attributes aren't a JSON object.
     @available(*, deprecated, message: "Use async applyDelta(_:) or pass explicit transaction")
-    public func applyDelta(_ delta: [YTextChange], in transaction: YrsTransaction? = nil) {
-        let yrsDelta: [YrsDelta] = delta.map { change in
+    @discardableResult
+    public func applyDelta(_ delta: [YTextChange], in transaction: YrsTransaction? = nil) throws -> [YTextChange] {
+        let yrsDelta = YText.yrsDelta(delta)
+        return try withThrowingTransaction(transaction) { txn in
+            YText.textChanges(try self._text.applyDelta(tx: txn, delta: yrsDelta))
+        }
+    }
+
+    private static func yrsDelta(_ delta: [YTextChange]) -> [YrsDelta] {
+        delta.map { change in
             switch change {
             case let .inserted(value, attributes):
                 return YrsDelta.inserted(value: value, attrs: Coder.encoded(attributes))
@@ -376,8 +368,18 @@ public final class YText: Transactable, YCollection, @unchecked Sendable {
                 return YrsDelta.retained(index: index, attrs: Coder.encoded(attributes))
             }
         }
-        withTransaction(transaction) { txn in
-            self._text.applyDelta(tx: txn, delta: yrsDelta)
+    }
+
+    private static func textChanges(_ delta: [YrsDelta]) -> [YTextChange] {
+        delta.map { change in
+            switch change {
+            case let .inserted(value, attrs):
+                return YTextChange.inserted(value: value, attributes: Coder.decoded(attrs))
+            case let .retained(index, attrs):
+                return YTextChange.retained(index: index, attributes: Coder.decoded(attrs))
+            case let .deleted(index):
+                return YTextChange.deleted(index: index)
+            }
         }
     }
 
@@ -438,7 +440,7 @@ class YTextObservationDelegate: YrsTextObservationDelegate {
         self.decoded = decoded
     }
 
-    func call(value: [YrsDelta]) {
+    func call(value: [YrsDelta], origin _: YrsOrigin?, path _: [YrsPathSegment]) {
         let result: [YTextChange] = value.map { rsChange -> YTextChange in
             switch rsChange {
             case let .inserted(value, attrs):
@@ -37,13 +37,13 @@ public final class YUndoManager<T: AnyObject> {
     /// Additional calls will undo to points further back on the stack, if set.
     /// - Returns: A Boolean value that indicates wether the change was undone.
     public func undo() throws -> Bool {
-        return try _manager.undo()
+        return try _manager.undo() != nil
     }
 
     /// Replays a change forward from the Undo managers stack.
     /// - Returns: A Boolean value that indicates wether the change was replayed.
     public func redo() throws -> Bool {
-        return try _manager.redo()
+        return try _manager.redo() != nil
     }
 
     /// Mark a point in time that you want to be able to reverse back to.
@@ -54,6 +54,24 @@ public final class YUndoManager<T: AnyObject> {
         _manager.wrapChanges()
     }
 
+    /// The period, in milliseconds, within which consecutive changes are merged into a single undo step.
+    ///
+    /// Set it to `0` to make every change its own undo step.
+    public var captureTimeout: UInt64 {
+        get { _manager.captureTimeout() }
+        set { _manager.setCaptureTimeout(millis: newValue) }
+    }
+
+    /// The number of changes that can be undone.
+    public var undoCount: Int {
+        Int(_manager.undoStackLen())
+    }
+
+    /// The number of changes that can be replayed.
+    public var redoCount: Int {
+        Int(_manager.redoStackLen())
+    }
+
     /// Clears the stack of undo/redo actions.
     public func clear() throws {
         try _manager.clear()
@@ -170,8 +170,10 @@ class YSubdocTests: XCTestCase {
         let array: YArray<String> = parentDoc.getOrCreateArray(named: "docs")
 
         var addedCount = 0
+        var addedGuids: [String] = []
         let subscription = parentDoc.observeSubdocs { event in
             addedCount += event.added.count
+            addedGuids += event.added.map(\.guid)
         }
 
         let subdoc = YDocument(options: YDocumentOptions(guid: "observed-doc"))
@@ -181,6 +183,7 @@ class YSubdocTests: XCTestCase {
 
         // Give a brief moment for the event to propagate
         XCTAssertGreaterThanOrEqual(addedCount, 1)
+        XCTAssertEqual(addedGuids, ["observed-doc"])
 
         subscription.cancel()
     }
@@ -24,12 +24,12 @@ class YTextTests: XCTestCase {
 
     func test_appendAndInsert() throws {
         text.append("trailing text")
-        text.insert("leading text, ", at: 0)
+        try text.insert("leading text, ", at: 0)
 
         XCTAssertEqual(String(text), "leading text, trailing text")
     }
 
-    func test_format() {
+    func test_format() throws {
         let expectedAttributes = ["weight": "bold"]
         var actualAttributes: [String: String] = [:]
 
@@ -48,14 +48,14 @@ class YTextTests: XCTestCase {
         }
 
         text.append("abc")
-        text.format(at: 0, length: 3, attributes: expectedAttributes)
+        try text.format(at: 0, length: 3, attributes: expectedAttributes)
 
         subscription.cancel()
 
         XCTAssertEqual(expectedAttributes, actualAttributes)
     }
 
-    func test_insertWithAttributes() {
+    func test_insertWithAttributes() throws {
         let expectedAttributes = ["weight": "bold"]
         var actualAttributes: [String: String] = [:]
 
@@ -73,7 +73,7 @@ class YTextTests: XCTestCase {
             }
         }
 
-        text.insertWithAttributes("abc", attributes: expectedAttributes, at: 0)
+        try text.insertWithAttributes("abc", attributes: expectedAttributes, at: 0)
 
         subscription.cancel()
 
@@ -81,7 +81,7 @@ class YTextTests: XCTestCase {
         XCTAssertEqual(expectedAttributes, actualAttributes)
     }
 
-    func test_insertEmbed() {
+    func test_insertEmbed() throws {
         let embed = TestType(name: "Aidar", age: 24)
         var insertedEmbed: TestType?
 
@@ -95,7 +95,7 @@ class YTextTests: XCTestCase {
             }
         }
 
-        text.insertEmbed(embed, at: 0)
+        try text.insertEmbed(embed, at: 0)
 
         subscription.cancel()
 
@@ -103,7 +103,7 @@ class YTextTests: XCTestCase {
         XCTAssertEqual(text.length(), 1)
     }
 
-    func test_insertEmbedWithAttributes() {
+    func test_insertEmbedWithAttributes() throws {
         let embed = TestType(name: "Aidar", age: 24)
         var insertedEmbed: TestType?
 
@@ -125,7 +125,7 @@ class YTextTests: XCTestCase {
             }
         }
 
-        text.insertEmbedWithAttributes(embed, attributes: expectedAttributes, at: 0)
+        try text.insertEmbedWithAttributes(embed, attributes: expectedAttributes, at: 0)
 
         subscription.cancel()
 
@@ -140,7 +140,7 @@ class YTextTests: XCTestCase {
 
     func test_removeRange() throws {
         text.append("few apples")
-        text.removeRange(start: 0, length: 4)
+        try text.removeRange(start: 0, length: 4)
 
         XCTAssertEqual(String(text), "apples")
     }
@@ -303,28 +303,55 @@ class YTextTests: XCTestCase {
         XCTAssertEqual(result, "hello, world!")
     }
 
-    func test_asyncInsert() async {
+    func test_asyncInsert() async throws {
         let doc = YDocument()
         let text = doc.getOrCreateText(named: "test")
 
         await text.append("world!")
-        await text.insert("hello, ", at: 0)
+        try await text.insert("hello, ", at: 0)
 
         let result = await text.getStringAsync()
         XCTAssertEqual(result, "hello, world!")
     }
 
-    func test_asyncRemoveRange() async {
+    func test_asyncRemoveRange() async throws {
         let doc = YDocument()
         let text = doc.getOrCreateText(named: "test")
 
         await text.append("hello, world!")
-        await text.removeRange(start: 5, length: 7)
+        try await text.removeRange(start: 5, length: 7)
 
         let result = await text.getStringAsync()
         XCTAssertEqual(result, "hello!")
     }
 
+    func test_asyncApplyDelta() async throws {
+        let doc = YDocument()
+        let text = doc.getOrCreateText(named: "test")
+
+        await text.append("world")
+        let applied = try await text.applyDelta([
+            .inserted(value: "\"hello\"", attributes: [:]),
+            .inserted(value: "\", \"", attributes: [:]),
+            .retained(index: 0, attributes: [:]),
+        ])
+
+        let result = await text.getStringAsync()
+        XCTAssertEqual(result, "hello, world")
+        XCTAssertEqual(applied.count, 1)
+        guard case let .inserted(value, _) = applied.first else {
+            return XCTFail("expected a single insert, got \(applied)")
+        }
+        XCTAssertEqual(value, "\"hello, \"")
+
+        do {
+            try await text.applyDelta([.inserted(value: "not json", attributes: [:])])
+            XCTFail("applying an insert that isn't JSON should throw")
+        } catch {
+            XCTAssertTrue(error is YrsDeltaError)
+        }
+    }
+
     func test_asyncLength() async {
         let doc = YDocument()
         let text = doc.getOrCreateText(named: "test")
@@ -352,22 +379,22 @@ class YTextTests: XCTestCase {
         task.cancel()
     }
 
-    func test_asyncFormat() async {
+    func test_asyncFormat() async throws {
         let doc = YDocument()
         let text = doc.getOrCreateText(named: "test")
 
         await text.append("bold text")
-        await text.format(at: 0, length: 4, attributes: ["weight": "bold"])
+        try await text.format(at: 0, length: 4, attributes: ["weight": "bold"])
 
         let diff = await text.diffAsync()
         XCTAssertFalse(diff.isEmpty)
     }
 
-    func test_asyncInsertWithAttributes() async {
+    func test_asyncInsertWithAttributes() async throws {
         let doc = YDocument()
         let text = doc.getOrCreateText(named: "test")
 
-        await text.insertWithAttributes("styled", attributes: ["color": "red"], at: 0)
+        try await text.insertWithAttributes("styled", attributes: ["color": "red"], at: 0)
 
         let result = await text.getStringAsync()
         XCTAssertEqual(result, "styled")
@@ -389,14 +416,14 @@ class YTextTests: XCTestCase {
         }
     }
 
-    func test_asyncMultipleOperations() async {
+    func test_asyncMultipleOperations() async throws {
         let doc = YDocument()
         let text = doc.getOrCreateText(named: "test")
 
         // Test multiple sequential async operations
         await text.append("hello")
-        await text.insert(" world", at: 5)
-        await text.removeRange(start: 0, length: 6)
+        try await text.insert(" world", at: 5)
+        try await text.removeRange(start: 0, length: 6)
 
         let result = await text.getStringAsync()
         XCTAssertEqual(result, "world")
@@ -49,11 +49,11 @@ class YUndoManagerTests: XCTestCase {
     
     
     func test_undoTextBasic() throws {
-        text.insert("1221", at: 0)
+        try text.insert("1221", at: 0)
         manager.wrap()
-        text.insert("3", at: 2)
+        try text.insert("3", at: 2)
         manager.wrap()
-        text.insert("3", at: 3)
+        try text.insert("3", at: 3)
         manager.wrap()
         
         XCTAssertEqual(text.getString(), "123321")
@@ -64,7 +64,7 @@ class YUndoManagerTests: XCTestCase {
         XCTAssert (try manager.undo())
         XCTAssertEqual(text.getString(), "1221")
         
-        text.insert("3", at: 2)
+        try text.insert("3", at: 2)
         XCTAssertEqual(text.getString(), "12321")
     }
     
@@ -81,7 +81,7 @@ class YUndoManagerTests: XCTestCase {
             return metadata
         })
         
-        text.insert("abc", at: 0)
+        try text.insert("abc", at: 0)
         
         XCTAssert (try manager.undo())
         XCTAssertEqual(received.value, "A")
@@ -102,7 +102,7 @@ class YUndoManagerTests: XCTestCase {
         
         // create some changes locally
         document.transactSync(origin: localOrigin) { txn in
-            self.text.insert("hello", at: 0, in: txn)
+            XCTAssertNoThrow(try self.text.insert("hello", at: 0, in: txn))
         }
         self.manager.wrap() // add changes on a stack: they will be undone as one
         
@@ -110,10 +110,10 @@ class YUndoManagerTests: XCTestCase {
         
         // concurrent change on the remote replica
         document.transactSync(origin: localOrigin) { txn in
-            self.text.insert(" world", at: 5, in: txn)
+            XCTAssertNoThrow(try self.text.insert(" world", at: 5, in: txn))
         }
         self.manager.wrap() // add next batch of changes on a stack
-        remoteText.insert("<break>", at: 1)
+        try remoteText.insert("<break>", at: 1)
         
         XCTAssertEqual(text.getString(), "hello world")
         
@@ -1,15 +1,22 @@
-use crate::doc::{YrsCollectionPtr, YrsDoc};
+use crate::delivery::YrsObservationDelivery;
+use crate::doc::{YrsCollectionPtr, YrsDoc, YrsOrigin};
+use crate::event::{YrsDeepObservationDelegate, YrsEvent};
+use crate::path::{path_segments, root_path, YrsPathSegment};
 use crate::map::YrsMap;
 use crate::subscription::YSubscription;
 use crate::text::YrsText;
 use crate::transaction::YrsTransaction;
+use crate::value::YrsValue;
+use crate::weak::{YrsQuoteError, YrsWeakLink, YrsWeakPrelim};
 use crate::{change::YrsChange, error::CodingError};
-use parking_lot::ReentrantMutex;
+use parking_lot::{Mutex, ReentrantMutex};
 use std::cell::UnsafeCell;
 use std::fmt::Debug;
 use std::sync::Arc;
 use yrs::branch::Branch;
-use yrs::{Any, Array, ArrayRef, Observable, Out};
+use yrs::types::array::ArrayIter;
+use yrs::types::ToJson;
+use yrs::{Any, Array, ArrayRef, DeepObservable, Observable, Out, Quotable, ReadTxn, Store};
 
 pub(crate) struct YrsArray(ReentrantMutex<UnsafeCell<ArrayRef>>);
 
@@ -56,12 +63,57 @@ impl From<ArrayRef> for YrsArray {
     }
 }
 
+/// Read access to a transaction kept alive by an iterator.
+struct SharedTransaction(Arc<YrsTransaction>);
+
+impl ReadTxn for SharedTransaction {
+    fn store(&self) -> &Store {
+        self.0.store()
+    }
+}
+
+/// Lazily yields the values of an array within a transaction held for its lifetime.
+pub(crate) struct YrsArrayIterator {
+    transaction: Arc<YrsTransaction>,
+    inner: Mutex<ArrayIter<SharedTransaction, SharedTransaction>>,
+}
+
+// SAFETY: `ArrayIter` holds raw pointers to the branch of the array and to the block it
+// stopped at. They point into the document store, which the `TransactionMut` inside
+// `YrsTransaction` keeps locked for as long as it is open, and yrs only releases or
+// merges blocks when a transaction commits. `next` first takes the lock of the
+// transaction and returns `None` once it has been freed, so the pointers are only read
+// while the transaction is open, by one thread at a time, which also holds the `Mutex`
+// around the iterator. Writes made through the same transaction between two calls may
+// split blocks, which stay allocated, so values inserted or removed meanwhile may be
+// skipped or yielded, but no pointer dangles.
+unsafe impl Send for YrsArrayIterator {}
+unsafe impl Sync for YrsArrayIterator {}
+
+impl YrsArrayIterator {
+    /// Returns the next value, or `None` once the array is exhausted or the transaction
+    /// has been freed.
+    pub(crate) fn next(&self) -> Option<YrsValue> {
+        let tx = self.transaction.transaction();
+        tx.as_ref()?;
+        self.inner.lock().next().map(YrsValue::from)
+    }
+}
+
 pub(crate) trait YrsArrayEachDelegate: Send + Sync + Debug {
     fn call(&self, value: String);
 }
 
+pub(crate) trait YrsArrayEachValueDelegate: Send + Sync + Debug {
+    fn call(&self, index: u32, value: YrsValue);
+}
+
+pub(crate) trait YrsArrayStreamDelegate: Send + Sync + Debug {
+    fn call(&self, chunk: Vec<YrsValue>);
+}
+
 pub(crate) trait YrsArrayObservationDelegate: Send + Sync + Debug {
-    fn call(&self, value: Vec<YrsChange>);
+    fn call(&self, value: Vec<YrsChange>, origin: Option<YrsOrigin>, path: Vec<YrsPathSegment>);
 }
 
 impl YrsArray {
@@ -91,6 +143,22 @@ impl YrsArray {
         });
     }
 
+    /// Calls the delegate with the index and typed value of every element, including
+    /// references to nested shared types.
+    pub(crate) fn each_value(
+        &self,
+        transaction: &YrsTransaction,
+        delegate: Box<dyn YrsArrayEachValueDelegate>,
+    ) {
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+
+        let arr = self.inner();
+        for (index, value) in arr.as_ref().iter(tx).enumerate() {
+            delegate.call(index as u32, YrsValue::from(value));
+        }
+    }
+
     pub(crate) fn get(
         &self,
         transaction: &YrsTransaction,
@@ -105,14 +173,132 @@ impl YrsArray {
                 any.to_json(&mut buf);
                 Ok(buf)
             } else {
-                Err(CodingError::EncodingError)
+                Err(CodingError::UnexpectedType)
             }
         } else {
-            // Actually there is no element here, so it shouldn't be EncodingErro
-            Err(CodingError::EncodingError)
+            Err(CodingError::OutOfBounds)
+        }
+    }
+
+    /// Returns the value at the given index, or `None` if the index is out of bounds.
+    pub(crate) fn get_value(&self, transaction: &YrsTransaction, index: u32) -> Option<YrsValue> {
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+        let arr = self.inner();
+        arr.as_ref().get(tx, index).map(YrsValue::from)
+    }
+
+    /// Inserts a typed value at the given index. Fails if the value contains a shared reference.
+    pub(crate) fn insert_value(
+        &self,
+        transaction: &YrsTransaction,
+        index: u32,
+        value: YrsValue,
+    ) -> Result<(), CodingError> {
+        let avalue = Any::try_from(value)?;
+
+        let mut tx = transaction.transaction();
+        let tx = tx.as_mut().unwrap();
+
+        let mut arr = self.inner();
+        arr.as_mut().insert(tx, index, avalue);
+        Ok(())
+    }
+
+    /// Returns all values of the array, including references to nested shared types.
+    pub(crate) fn to_values(&self, transaction: &YrsTransaction) -> Vec<YrsValue> {
+        let arr = self.inner();
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+
+        arr.as_ref().iter(tx).map(YrsValue::from).collect()
+    }
+
+    /// Returns up to `len` values starting at `start`. The window is clamped to the
+    /// bounds of the array.
+    pub(crate) fn get_range(
+        &self,
+        transaction: &YrsTransaction,
+        start: u32,
+        len: u32,
+    ) -> Vec<YrsValue> {
+        let arr = self.inner();
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+
+        arr.as_ref()
+            .iter(tx)
+            .skip(start as usize)
+            .take(len as usize)
+            .map(YrsValue::from)
+            .collect()
+    }
+
+    /// Returns the index of the first element equal to the given JSON value, or `None`
+    /// if there is no such element. Nested shared types never match.
+    pub(crate) fn index_of(
+        &self,
+        transaction: &YrsTransaction,
+        value: String,
+    ) -> Result<Option<u32>, CodingError> {
+        let needle = Any::from_json(value.as_str()).map_err(|_| CodingError::DecodingError)?;
+
+        let arr = self.inner();
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+
+        let index = arr
+            .as_ref()
+            .iter(tx)
+            .position(|v| matches!(v, Out::Any(any) if any == needle));
+        Ok(index.map(|i| i as u32))
+    }
+
+    /// Returns whether the array contains an element equal to the given JSON value.
+    pub(crate) fn contains(
+        &self,
+        transaction: &YrsTransaction,
+        value: String,
+    ) -> Result<bool, CodingError> {
+        Ok(self.index_of(transaction, value)?.is_some())
+    }
+
+    /// Delivers the contents of the array to the delegate in chunks of at most
+    /// `chunk_size` values, so that only one chunk is materialized at a time.
+    pub(crate) fn stream(
+        &self,
+        transaction: &YrsTransaction,
+        chunk_size: u32,
+        delegate: Box<dyn YrsArrayStreamDelegate>,
+    ) {
+        let chunk_size = chunk_size.max(1) as usize;
+        let arr = self.inner();
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+
+        let mut chunk = Vec::with_capacity(chunk_size);
+        for value in arr.as_ref().iter(tx) {
+            chunk.push(YrsValue::from(value));
+            if chunk.len() == chunk_size {
+                delegate.call(std::mem::replace(&mut chunk, Vec::with_capacity(chunk_size)));
+            }
+        }
+        if !chunk.is_empty() {
+            delegate.call(chunk);
         }
     }
 
+    /// Returns an iterator over the values of the array. The iterator holds on to the
+    /// transaction, and stops yielding values once it is freed.
+    pub(crate) fn iter(&self, transaction: Arc<YrsTransaction>) -> Arc<YrsArrayIterator> {
+        let arr = self.inner();
+        let iter = ArrayIter::from(arr.as_ref(), SharedTransaction(transaction.clone()));
+        Arc::new(YrsArrayIterator {
+            transaction,
+            inner: Mutex::new(iter),
+        })
+    }
+
     pub(crate) fn insert(&self, transaction: &YrsTransaction, index: u32, value: String) {
         let avalue = Any::from_json(value.as_str()).unwrap();
 
@@ -123,6 +309,35 @@ impl YrsArray {
         arr.as_mut().insert(tx, index, avalue);
     }
 
+    /// Inserts a binary value at the given index, stored as a byte buffer.
+    pub(crate) fn insert_buffer(&self, transaction: &YrsTransaction, index: u32, value: Vec<u8>) {
+        let mut tx = transaction.transaction();
+        let tx = tx.as_mut().unwrap();
+
+        let mut arr = self.inner();
+        arr.as_mut().insert(tx, index, Any::Buffer(value.into()));
+    }
+
+    /// Appends a binary value to the end of the array, stored as a byte buffer.
+    pub(crate) fn push_back_buffer(&self, transaction: &YrsTransaction, value: Vec<u8>) {
+        let mut tx = transaction.transaction();
+        let tx = tx.as_mut().unwrap();
+
+        self.inner().as_mut().push_back(tx, Any::Buffer(value.into()));
+    }
+
+    /// Returns the binary value at the given index, or `None` if the index is out of
+    /// bounds or the value isn't a byte buffer.
+    pub(crate) fn get_buffer(&self, transaction: &YrsTransaction, index: u32) -> Option<Vec<u8>> {
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+        let arr = self.inner();
+        match arr.as_ref().get(tx, index) {
+            Some(Out::Any(Any::Buffer(buf))) => Some(buf.to_vec()),
+            _ => None,
+        }
+    }
+
     pub(crate) fn insert_range(
         &self,
         transaction: &YrsTransaction,
@@ -141,6 +356,27 @@ impl YrsArray {
         arr.as_mut().insert_range(tx, index, add_values)
     }
 
+    /// Appends all values to the end of the array as a single block. Nothing is
+    /// appended if any of the values isn't valid JSON.
+    pub(crate) fn push_back_range(
+        &self,
+        transaction: &YrsTransaction,
+        values: Vec<String>,
+    ) -> Result<(), CodingError> {
+        let add_values = values
+            .iter()
+            .map(|value| Any::from_json(value.as_str()).map_err(|_| CodingError::DecodingError))
+            .collect::<Result<Vec<Any>, _>>()?;
+
+        let mut arr = self.inner();
+        let mut tx = transaction.transaction();
+        let tx = tx.as_mut().unwrap();
+
+        let len = arr.as_ref().len(tx);
+        arr.as_mut().insert_range(tx, len, add_values);
+        Ok(())
+    }
+
     pub(crate) fn length(&self, transaction: &YrsTransaction) -> u32 {
         let arr = self.inner();
         let tx = transaction.transaction();
@@ -175,6 +411,73 @@ impl YrsArray {
         arr.as_mut().remove(tx, index)
     }
 
+    /// Removes all elements from the array.
+    pub(crate) fn clear(&self, transaction: &YrsTransaction) {
+        let mut tx = transaction.transaction();
+        let tx = tx.as_mut().unwrap();
+
+        let mut arr = self.inner();
+        let len = arr.as_ref().len(tx);
+        if len > 0 {
+            arr.as_mut().remove_range(tx, 0, len);
+        }
+    }
+
+    /// Removes `delete_count` elements starting at `index` and inserts `values` in
+    /// their place, within the same transaction. Fails without changing the array when
+    /// the removed range goes past its end or any of the values isn't valid JSON.
+    pub(crate) fn splice(
+        &self,
+        transaction: &YrsTransaction,
+        index: u32,
+        delete_count: u32,
+        values: Vec<String>,
+    ) -> Result<(), CodingError> {
+        let add_values = values
+            .iter()
+            .map(|value| Any::from_json(value.as_str()).map_err(|_| CodingError::DecodingError))
+            .collect::<Result<Vec<Any>, _>>()?;
+
+        let mut arr = self.inner();
+        let mut tx = transaction.transaction();
+        let tx = tx.as_mut().unwrap();
+
+        let len = arr.as_ref().len(tx);
+        if index.checked_add(delete_count).is_none_or(|end| end > len) {
+            return Err(CodingError::OutOfBounds);
+        }
+        if delete_count > 0 {
+            arr.as_mut().remove_range(tx, index, delete_count);
+        }
+        if !add_values.is_empty() {
+            arr.as_mut().insert_range(tx, index, add_values);
+        }
+        Ok(())
+    }
+
+    /// Removes the last element and returns it, or `None` if the array is empty.
+    pub(crate) fn pop_back(&self, transaction: &YrsTransaction) -> Option<YrsValue> {
+        let mut tx = transaction.transaction();
+        let tx = tx.as_mut().unwrap();
+
+        let mut arr = self.inner();
+        let index = arr.as_ref().len(tx).checked_sub(1)?;
+        let value = arr.as_ref().get(tx, index)?;
+        arr.as_mut().remove(tx, index);
+        Some(YrsValue::from(value))
+    }
+
+    /// Removes the first element and returns it, or `None` if the array is empty.
+    pub(crate) fn pop_front(&self, transaction: &YrsTransaction) -> Option<YrsValue> {
+        let mut tx = transaction.transaction();
+        let tx = tx.as_mut().unwrap();
+
+        let mut arr = self.inner();
+        let value = arr.as_ref().get(tx, 0)?;
+        arr.as_mut().remove(tx, 0);
+        Some(YrsValue::from(value))
+    }
+
     pub(crate) fn remove_range(&self, transaction: &YrsTransaction, index: u32, len: u32) {
         let mut tx = transaction.transaction();
         let tx = tx.as_mut().unwrap();
@@ -184,17 +487,45 @@ impl YrsArray {
     }
 
     pub(crate) fn observe(&self, delegate: Box<dyn YrsArrayObservationDelegate>) -> Arc<YSubscription> {
+        self.observe_with_delivery(delegate, YrsObservationDelivery::Synchronous)
+    }
+
+    /// Observes changes made to the array, calling the delegate as `delivery` specifies.
+    pub(crate) fn observe_with_delivery(
+        &self,
+        delegate: Box<dyn YrsArrayObservationDelegate>,
+        delivery: YrsObservationDelivery,
+    ) -> Arc<YSubscription> {
+        let delegate: Arc<dyn YrsArrayObservationDelegate> = Arc::from(delegate);
         let mut arr = self.inner();
-        let subscription = arr
-            .as_mut()
-            .observe(move |transaction, text_event| {
-                let delta = text_event.delta(transaction);
-                let result: Vec<YrsChange> =
-                    delta.iter().map(|change| YrsChange::from(change)).collect();
-                delegate.call(result)
-            });
+        let subscription = arr.as_mut().observe(move |transaction, text_event| {
+            let delta = text_event.delta(transaction);
+            let result: Vec<YrsChange> = delta.iter().map(YrsChange::from).collect();
+            let origin = transaction.origin().cloned().map(YrsOrigin::from);
+            let path = path_segments(root_path(transaction, text_event.target().as_ref()));
+            let delegate = delegate.clone();
+            delivery.deliver(move || delegate.call(result, origin, path))
+        });
 
-            Arc::new(YSubscription::new(subscription))
+        Arc::new(YSubscription::new(subscription))
+    }
+
+    /// Observes changes made to the array and to any shared type nested inside it.
+    pub(crate) fn observe_deep(
+        &self,
+        delegate: Box<dyn YrsDeepObservationDelegate>,
+    ) -> Arc<YSubscription> {
+        let arr = self.inner();
+        let subscription = arr.as_ref().observe_deep(move |transaction, events| {
+            let result: Vec<YrsEvent> = events
+                .iter()
+                .map(|event| YrsEvent::new(transaction, event))
+                .collect();
+            let origin = transaction.origin().cloned().map(YrsOrigin::from);
+            delegate.call(result, origin)
+        });
+
+        Arc::new(YSubscription::new(subscription))
     }
 
     pub(crate) fn to_a(&self, transaction: &YrsTransaction) -> Vec<String> {
@@ -218,6 +549,19 @@ impl YrsArray {
         arr
     }
 
+    /// Serializes the whole array to a JSON string, including nested shared types.
+    /// Maps and arrays become JSON objects and arrays, texts become strings and
+    /// subdocuments become `{"guid": ...}` objects.
+    pub(crate) fn to_json(&self, transaction: &YrsTransaction) -> String {
+        let arr = self.inner();
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+
+        let mut buf = String::new();
+        ToJson::to_json(arr.as_ref(), tx).to_json(&mut buf);
+        buf
+    }
+
     // MARK: - Subdoc methods
 
     /// Gets a subdocument at the specified index.
@@ -256,6 +600,31 @@ impl YrsArray {
         Arc::new(YrsDoc::from_doc(inserted))
     }
 
+    /// Appends a subdocument to the end of the array.
+    /// Returns a reference to the integrated subdocument.
+    pub(crate) fn push_back_doc(&self, transaction: &YrsTransaction, doc: &YrsDoc) -> Arc<YrsDoc> {
+        let mut tx = transaction.transaction();
+        let tx = tx.as_mut().unwrap();
+        let mut arr = self.inner();
+
+        let inserted = arr.as_mut().push_back(tx, doc.inner());
+        Arc::new(YrsDoc::from_doc(inserted))
+    }
+
+    /// Returns the indexes of all subdocument elements, in ascending order.
+    pub(crate) fn doc_indexes(&self, transaction: &YrsTransaction) -> Vec<u32> {
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+        let arr = self.inner();
+
+        arr.as_ref()
+            .iter(tx)
+            .enumerate()
+            .filter(|(_, value)| matches!(value, Out::YDoc(_)))
+            .map(|(index, _)| index as u32)
+            .collect()
+    }
+
     // MARK: - Nested shared type methods
 
     /// Gets a nested YMap at the specified index.
@@ -386,4 +755,592 @@ impl YrsArray {
         let mut arr = self.inner();
         arr.as_mut().move_range_to(tx, start, Assoc::After, end, Assoc::Before, target);
     }
+
+    // MARK: - Weak link methods
+
+    /// Inserts a quotation created by `quote` at the specified index.
+    /// Returns a reference to the integrated weak link.
+    pub(crate) fn insert_weak_link(
+        &self,
+        transaction: &YrsTransaction,
+        index: u32,
+        prelim: &YrsWeakPrelim,
+    ) -> Arc<YrsWeakLink> {
+        let mut tx = transaction.transaction();
+        let tx = tx.as_mut().unwrap();
+        let mut arr = self.inner();
+        let link = arr.as_mut().insert(tx, index, prelim.prelim());
+        Arc::new(YrsWeakLink::from(link))
+    }
+
+    /// Quotes `length` elements starting at `index`. The returned prelim can be
+    /// inserted into a map or an array, where it resolves to the current elements of
+    /// the quoted range as the source array changes.
+    pub(crate) fn quote(
+        &self,
+        transaction: &YrsTransaction,
+        index: u32,
+        length: u32,
+    ) -> Result<Arc<YrsWeakPrelim>, YrsQuoteError> {
+        if length == 0 {
+            return Err(YrsQuoteError::OutOfBounds);
+        }
+        let end = index.checked_add(length - 1).ok_or(YrsQuoteError::OutOfBounds)?;
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+
+        let prelim = self.inner().as_ref().quote(tx, index..=end)?;
+        Ok(Arc::new(YrsWeakPrelim::from(prelim)))
+    }
+
+    /// Gets a weak link at the specified index.
+    pub(crate) fn get_weak_link(
+        &self,
+        transaction: &YrsTransaction,
+        index: u32,
+    ) -> Option<Arc<YrsWeakLink>> {
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+        let arr = self.inner();
+        if let Some(Out::YWeakLink(link)) = arr.as_ref().get(tx, index) {
+            Some(Arc::new(YrsWeakLink::from(link)))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{YrsArrayEachValueDelegate, YrsArrayObservationDelegate, YrsArrayStreamDelegate};
+    use crate::event::{YrsDeepObservationDelegate, YrsEvent};
+    use crate::change::YrsChange;
+    use crate::doc::YrsOrigin;
+    use crate::error::CodingError;
+    use crate::path::YrsPathSegment;
+    use crate::value::{YrsSharedKind, YrsValue};
+    use crate::YrsDoc;
+    use parking_lot::Mutex;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    #[test]
+    fn typed_values_round_trip() {
+        let doc = YrsDoc::new();
+        let array = doc.get_array("array".to_string());
+
+        let txn = doc.transact(None);
+        let values = vec![
+            YrsValue::BigInt { value: 1 },
+            YrsValue::Number { value: 1.5 },
+            YrsValue::Buffer { value: vec![0, 1, 2] },
+            YrsValue::Map {
+                entries: HashMap::from([("a".to_string(), YrsValue::Null)]),
+            },
+        ];
+        for (i, value) in values.iter().enumerate() {
+            array.insert_value(&txn, i as u32, value.clone()).unwrap();
+        }
+        array.push_text(&txn);
+
+        assert_eq!(array.get_value(&txn, 0), Some(YrsValue::BigInt { value: 1 }));
+        assert_eq!(array.get_value(&txn, 5), None);
+
+        let mut expected = values;
+        expected.push(YrsValue::SharedRef { kind: YrsSharedKind::Text });
+        assert_eq!(array.to_values(&txn), expected);
+
+        let shared = YrsValue::SharedRef { kind: YrsSharedKind::Map };
+        assert!(array.insert_value(&txn, 0, shared).is_err());
+    }
+
+    #[test]
+    fn concurrent_moves_do_not_duplicate_elements() {
+        let doc1 = YrsDoc::new();
+        let doc2 = YrsDoc::new();
+        let array1 = doc1.get_array("array".to_string());
+        let array2 = doc2.get_array("array".to_string());
+
+        let txn = doc1.transact(None);
+        array1.insert_range(&txn, 0, vec!["\"a\"".into(), "\"b\"".into(), "\"c\"".into()]);
+        let update = txn.transaction_encode_state_as_update();
+        txn.free();
+        let txn = doc2.transact(None);
+        txn.transaction_apply_update(update).unwrap();
+        txn.free();
+
+        let txn1 = doc1.transact(None);
+        array1.move_to(&txn1, 0, 3);
+        assert_eq!(array1.to_a(&txn1), vec!["\"b\"", "\"c\"", "\"a\""]);
+        let txn2 = doc2.transact(None);
+        array2.move_to(&txn2, 0, 2);
+        assert_eq!(array2.to_a(&txn2), vec!["\"b\"", "\"a\"", "\"c\""]);
+
+        let update1 = txn1.transaction_encode_state_as_update();
+        let update2 = txn2.transaction_encode_state_as_update();
+        txn1.transaction_apply_update(update2).unwrap();
+        txn2.transaction_apply_update(update1).unwrap();
+
+        assert_eq!(array1.length(&txn1), 3);
+        assert_eq!(array1.to_a(&txn1), array2.to_a(&txn2));
+    }
+
+    #[test]
+    fn nested_maps_are_collaborative_objects() {
+        let doc = YrsDoc::new();
+        let todos = doc.get_array("todos".to_string());
+
+        let txn = doc.transact(None);
+        let first = todos.push_map(&txn);
+        first.insert(&txn, "title".to_string(), "\"write tests\"".to_string());
+        let second = todos.insert_map(&txn, 0);
+        second.insert(&txn, "title".to_string(), "\"fix bug\"".to_string());
+        let notes = todos.push_text(&txn);
+        notes.append(&txn, "remember milk".to_string());
+
+        assert_eq!(todos.length(&txn), 3);
+        let todo = todos.get_map(&txn, 1).unwrap();
+        assert_eq!(todo.get(&txn, "title".to_string()).unwrap(), "\"write tests\"");
+        assert!(todos.get_text(&txn, 0).is_none());
+        assert_eq!(todos.get_text(&txn, 2).unwrap().get_string(&txn), "remember milk");
+
+        todo.insert(&txn, "done".to_string(), "true".to_string());
+        assert_eq!(first.get(&txn, "done".to_string()).unwrap(), "true");
+    }
+
+    #[test]
+    fn iterator_yields_values_lazily() {
+        let doc = YrsDoc::new();
+        let array = doc.get_array("array".to_string());
+
+        let txn = doc.transact(None);
+        array.insert_range(&txn, 0, vec!["1".into(), "2".into()]);
+        array.push_map(&txn);
+
+        let iter = array.iter(txn.clone());
+        assert_eq!(iter.next(), Some(YrsValue::Number { value: 1.0 }));
+        assert_eq!(iter.next(), Some(YrsValue::Number { value: 2.0 }));
+        assert_eq!(iter.next(), Some(YrsValue::SharedRef { kind: YrsSharedKind::Map }));
+        assert_eq!(iter.next(), None);
+
+        let iter = array.iter(txn.clone());
+        txn.free();
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn get_range_returns_clamped_window() {
+        let doc = YrsDoc::new();
+        let array = doc.get_array("array".to_string());
+
+        let txn = doc.transact(None);
+        let values: Vec<String> = (0..10).map(|i| i.to_string()).collect();
+        array.insert_range(&txn, 0, values);
+
+        let window = array.get_range(&txn, 3, 2);
+        assert_eq!(
+            window,
+            vec![YrsValue::Number { value: 3.0 }, YrsValue::Number { value: 4.0 }]
+        );
+        assert_eq!(array.get_range(&txn, 8, 5).len(), 2);
+        assert!(array.get_range(&txn, 20, 5).is_empty());
+    }
+
+    #[test]
+    fn buffers_round_trip_without_json() {
+        let doc = YrsDoc::new();
+        let array = doc.get_array("array".to_string());
+        let map = doc.get_map("map".to_string());
+
+        let txn = doc.transact(None);
+        array.push_back_buffer(&txn, vec![0xff, 0x00, 0x10]);
+        array.insert_buffer(&txn, 0, vec![]);
+        array.push_back(&txn, "\"text\"".to_string());
+        map.insert_buffer(&txn, "thumb".to_string(), vec![1, 2, 3]);
+        txn.free();
+
+        let txn = doc.transact(None);
+        assert_eq!(array.get_buffer(&txn, 0), Some(vec![]));
+        assert_eq!(array.get_buffer(&txn, 1), Some(vec![0xff, 0x00, 0x10]));
+        assert_eq!(array.get_buffer(&txn, 2), None);
+        assert_eq!(map.get_buffer(&txn, "thumb".to_string()), Some(vec![1, 2, 3]));
+        assert_eq!(map.get_buffer(&txn, "missing".to_string()), None);
+    }
+
+    type ObservedEvent = (Option<Vec<u8>>, Vec<String>);
+
+    #[derive(Debug, Default)]
+    struct RecordingDelegate(Arc<Mutex<Vec<ObservedEvent>>>);
+
+    impl YrsArrayObservationDelegate for RecordingDelegate {
+        fn call(&self, _value: Vec<YrsChange>, origin: Option<YrsOrigin>, path: Vec<YrsPathSegment>) {
+            let origin = origin.map(|o| yrs::Origin::from(o).as_ref().to_vec());
+            let path = path
+                .into_iter()
+                .map(|segment| match segment {
+                    YrsPathSegment::Key { key } => key,
+                    YrsPathSegment::Index { index } => index.to_string(),
+                })
+                .collect();
+            self.0.lock().push((origin, path));
+        }
+    }
+
+    #[test]
+    fn observe_reports_origin_and_path_from_root() {
+        let doc = YrsDoc::new();
+        let board = doc.get_map("board".to_string());
+
+        let txn = doc.transact(None);
+        board.insert(&txn, "title".to_string(), "\"Sprint\"".to_string());
+        let columns = board.insert_array(&txn, "columns".to_string());
+        columns.push_back(&txn, "\"backlog\"".to_string());
+        let cards = columns.push_array(&txn);
+        txn.free();
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let _subscription = cards.observe(Box::new(RecordingDelegate(events.clone())));
+
+        let txn = doc.transact(Some(yrs::Origin::from("remote").into()));
+        cards.push_back(&txn, "1".to_string());
+        txn.free();
+
+        let events = events.lock();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].0.as_deref(), Some(&b"remote"[..]));
+        assert_eq!(events[0].1, vec!["board", "columns", "1"]);
+    }
+
+    #[test]
+    fn get_distinguishes_out_of_bounds_and_shared_values() {
+        let doc = YrsDoc::new();
+        let array = doc.get_array("array".to_string());
+
+        let txn = doc.transact(None);
+        array.push_back(&txn, "1".to_string());
+        array.push_map(&txn);
+
+        assert_eq!(array.get(&txn, 0).unwrap(), "1");
+        assert!(matches!(array.get(&txn, 1), Err(CodingError::UnexpectedType)));
+        assert!(matches!(array.get(&txn, 2), Err(CodingError::OutOfBounds)));
+    }
+
+    #[test]
+    fn index_of_compares_json_values() {
+        let doc = YrsDoc::new();
+        let array = doc.get_array("array".to_string());
+
+        let txn = doc.transact(None);
+        array.push_map(&txn);
+        array.insert_range(&txn, 1, vec!["\"a\"".into(), "{\"id\":2}".into(), "\"a\"".into()]);
+
+        assert_eq!(array.index_of(&txn, "\"a\"".to_string()).unwrap(), Some(1));
+        assert_eq!(array.index_of(&txn, "{\"id\":2}".to_string()).unwrap(), Some(2));
+        assert!(!array.contains(&txn, "\"b\"".to_string()).unwrap());
+        assert!(matches!(
+            array.contains(&txn, "not json".to_string()),
+            Err(CodingError::DecodingError)
+        ));
+    }
+
+    #[test]
+    fn to_json_serializes_nested_types() {
+        let doc = YrsDoc::new();
+        let array = doc.get_array("array".to_string());
+
+        let txn = doc.transact(None);
+        array.push_back(&txn, "1".to_string());
+        let map = array.push_map(&txn);
+        map.insert(&txn, "done".to_string(), "true".to_string());
+        let nested = map.insert_array(&txn, "tags".to_string());
+        nested.push_back(&txn, "\"a\"".to_string());
+        array.push_text(&txn).append(&txn, "hi".to_string());
+
+        assert_eq!(array.to_a(&txn).len(), 1);
+        let json = array.to_json(&txn);
+        let parsed = yrs::Any::from_json(&json).unwrap();
+        let expected = yrs::Any::from_json(r#"[1,{"done":true,"tags":["a"]},"hi"]"#).unwrap();
+        assert_eq!(parsed, expected);
+    }
+
+    #[test]
+    fn push_back_range_appends_in_order() {
+        let doc = YrsDoc::new();
+        let array = doc.get_array("array".to_string());
+
+        let txn = doc.transact(None);
+        array.push_back(&txn, "0".to_string());
+        let rows: Vec<String> = (1..1000).map(|i| i.to_string()).collect();
+        array.push_back_range(&txn, rows).unwrap();
+
+        assert_eq!(array.length(&txn), 1000);
+        assert_eq!(array.get(&txn, 999).unwrap(), "999");
+
+        assert!(matches!(
+            array.push_back_range(&txn, vec!["1000".into(), "{".into()]),
+            Err(CodingError::DecodingError)
+        ));
+        assert_eq!(array.length(&txn), 1000);
+    }
+
+    #[derive(Debug, Default)]
+    struct CollectingDelegate(Arc<Mutex<Vec<(u32, YrsValue)>>>);
+
+    impl YrsArrayEachValueDelegate for CollectingDelegate {
+        fn call(&self, index: u32, value: YrsValue) {
+            self.0.lock().push((index, value));
+        }
+    }
+
+    #[test]
+    fn each_value_passes_index_and_typed_value() {
+        let doc = YrsDoc::new();
+        let array = doc.get_array("array".to_string());
+
+        let txn = doc.transact(None);
+        array.push_back(&txn, "true".to_string());
+        array.push_map(&txn);
+
+        let values = Arc::new(Mutex::new(Vec::new()));
+        array.each_value(&txn, Box::new(CollectingDelegate(values.clone())));
+        assert_eq!(
+            *values.lock(),
+            vec![
+                (0, YrsValue::Bool { value: true }),
+                (1, YrsValue::SharedRef { kind: YrsSharedKind::Map }),
+            ]
+        );
+    }
+
+    #[derive(Debug, Default)]
+    struct DeepDelegate(Arc<Mutex<Vec<String>>>);
+
+    impl YrsDeepObservationDelegate for DeepDelegate {
+        fn call(&self, events: Vec<YrsEvent>, _origin: Option<YrsOrigin>) {
+            let mut log = self.0.lock();
+            for event in events {
+                let (kind, path) = match event {
+                    YrsEvent::Array { path, .. } => ("array", path),
+                    YrsEvent::Map { path, .. } => ("map", path),
+                    YrsEvent::Text { path, .. } => ("text", path),
+                    YrsEvent::Xml { path, .. } => ("xml", path),
+                    YrsEvent::XmlText { path, .. } => ("xml-text", path),
+                    YrsEvent::Other { path } => ("other", path),
+                };
+                let path: Vec<String> = path
+                    .into_iter()
+                    .map(|segment| match segment {
+                        YrsPathSegment::Key { key } => key,
+                        YrsPathSegment::Index { index } => index.to_string(),
+                    })
+                    .collect();
+                log.push(format!("{kind}:{}", path.join("/")));
+            }
+        }
+    }
+
+    #[test]
+    fn observe_deep_reports_nested_changes() {
+        let doc = YrsDoc::new();
+        let rows = doc.get_array("rows".to_string());
+
+        let txn = doc.transact(None);
+        rows.push_back(&txn, "0".to_string());
+        let row = rows.push_map(&txn);
+        txn.free();
+
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let _subscription = rows.observe_deep(Box::new(DeepDelegate(log.clone())));
+
+        let txn = doc.transact(None);
+        row.insert(&txn, "title".to_string(), "\"a\"".to_string());
+        txn.free();
+
+        let txn = doc.transact(None);
+        rows.push_back(&txn, "2".to_string());
+        row.insert_text(&txn, "notes".to_string());
+        txn.free();
+
+        let mut log = log.lock().clone();
+        log[1..].sort();
+        assert_eq!(log, vec!["map:1", "array:", "map:1"]);
+    }
+
+    type AddedMap = (u32, Arc<crate::map::YrsMap>);
+
+    #[derive(Default)]
+    struct AddedMapsDelegate(Arc<Mutex<Vec<AddedMap>>>);
+
+    impl std::fmt::Debug for AddedMapsDelegate {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("AddedMapsDelegate")
+        }
+    }
+
+    impl YrsArrayObservationDelegate for AddedMapsDelegate {
+        fn call(&self, value: Vec<YrsChange>, _origin: Option<YrsOrigin>, _path: Vec<YrsPathSegment>) {
+            for change in value {
+                if let YrsChange::Added { shared, .. } = change {
+                    for item in shared {
+                        assert_eq!(item.handle.kind, YrsSharedKind::Map);
+                        self.0.lock().push((item.offset, item.handle.map.unwrap()));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn added_changes_carry_shared_handles() {
+        let doc = YrsDoc::new();
+        let rows = doc.get_array("rows".to_string());
+
+        let added = Arc::new(Mutex::new(Vec::new()));
+        let _subscription = rows.observe(Box::new(AddedMapsDelegate(added.clone())));
+
+        let txn = doc.transact(None);
+        rows.push_back(&txn, "1".to_string());
+        rows.push_map(&txn);
+        txn.free();
+
+        let added = added.lock();
+        assert_eq!(added.len(), 1);
+        assert_eq!(added[0].0, 1);
+
+        let txn = doc.transact(None);
+        added[0].1.insert(&txn, "title".to_string(), "\"bound\"".to_string());
+        let row = rows.get_map(&txn, 1).unwrap();
+        assert_eq!(row.get(&txn, "title".to_string()).unwrap(), "\"bound\"");
+    }
+
+    #[test]
+    fn pop_removes_and_returns_elements() {
+        let doc = YrsDoc::new();
+        let queue = doc.get_array("queue".to_string());
+
+        let txn = doc.transact(None);
+        queue.insert_range(&txn, 0, vec!["1".into(), "2".into(), "3".into()]);
+
+        assert_eq!(queue.pop_front(&txn), Some(YrsValue::Number { value: 1.0 }));
+        assert_eq!(queue.pop_back(&txn), Some(YrsValue::Number { value: 3.0 }));
+        assert_eq!(queue.pop_back(&txn), Some(YrsValue::Number { value: 2.0 }));
+        assert_eq!(queue.pop_back(&txn), None);
+        assert_eq!(queue.pop_front(&txn), None);
+    }
+
+    #[test]
+    fn doc_indexes_lists_subdocuments() {
+        let doc = YrsDoc::new();
+        let pages = doc.get_array("pages".to_string());
+
+        let txn = doc.transact(None);
+        pages.push_back_doc(&txn, &YrsDoc::new());
+        pages.push_back(&txn, "\"separator\"".to_string());
+        let second = YrsDoc::new();
+        let inserted = pages.push_back_doc(&txn, &second);
+
+        assert_eq!(pages.doc_indexes(&txn), vec![0, 2]);
+        assert_eq!(pages.get_doc(&txn, 2).unwrap().guid(), inserted.guid());
+        assert_eq!(inserted.guid(), second.guid());
+    }
+
+    #[derive(Debug, Default)]
+    struct ChunkSizes(Arc<Mutex<Vec<usize>>>);
+
+    impl YrsArrayStreamDelegate for ChunkSizes {
+        fn call(&self, chunk: Vec<YrsValue>) {
+            self.0.lock().push(chunk.len());
+        }
+    }
+
+    #[test]
+    fn stream_delivers_bounded_chunks() {
+        let doc = YrsDoc::new();
+        let array = doc.get_array("array".to_string());
+        let empty = doc.get_array("empty".to_string());
+
+        let txn = doc.transact(None);
+        array.push_back_range(&txn, (0..10).map(|i| i.to_string()).collect()).unwrap();
+
+        let sizes = Arc::new(Mutex::new(Vec::new()));
+        array.stream(&txn, 4, Box::new(ChunkSizes(sizes.clone())));
+        assert_eq!(*sizes.lock(), vec![4, 4, 2]);
+
+        let sizes = Arc::new(Mutex::new(Vec::new()));
+        empty.stream(&txn, 4, Box::new(ChunkSizes(sizes.clone())));
+        assert!(sizes.lock().is_empty());
+    }
+
+    #[test]
+    fn splice_replaces_elements_in_place() {
+        let doc = YrsDoc::new();
+        let array = doc.get_array("array".to_string());
+
+        let txn = doc.transact(None);
+        array.insert_range(&txn, 0, vec!["1".into(), "2".into(), "3".into(), "4".into()]);
+
+        array.splice(&txn, 1, 2, vec!["\"a\"".into()]).unwrap();
+        assert_eq!(array.to_a(&txn), vec!["1", "\"a\"", "4"]);
+
+        array.splice(&txn, 3, 0, vec!["5".into(), "6".into()]).unwrap();
+        array.splice(&txn, 0, 1, vec![]).unwrap();
+        assert_eq!(array.to_a(&txn), vec!["\"a\"", "4", "5", "6"]);
+
+        assert!(matches!(
+            array.splice(&txn, 5, 0, vec!["7".into()]),
+            Err(CodingError::OutOfBounds)
+        ));
+        assert!(matches!(
+            array.splice(&txn, 2, 3, vec![]),
+            Err(CodingError::OutOfBounds)
+        ));
+        assert!(matches!(
+            array.splice(&txn, 1, u32::MAX, vec![]),
+            Err(CodingError::OutOfBounds)
+        ));
+        assert!(matches!(
+            array.splice(&txn, 0, 1, vec!["{".into()]),
+            Err(CodingError::DecodingError)
+        ));
+        assert_eq!(array.to_a(&txn), vec!["\"a\"", "4", "5", "6"]);
+    }
+
+    #[test]
+    fn concurrent_writers_share_one_array() {
+        let doc = Arc::new(YrsDoc::new());
+        let array = doc.get_array("array".to_string());
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let doc = doc.clone();
+                let array = array.clone();
+                std::thread::spawn(move || {
+                    for i in 0..25 {
+                        let txn = doc.transact(None);
+                        array.push_back(&txn, i.to_string());
+                        txn.free();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let txn = doc.transact(None);
+        assert_eq!(array.length(&txn), 100);
+    }
+
+    #[test]
+    fn clear_removes_all_elements() {
+        let doc = YrsDoc::new();
+        let array = doc.get_array("array".to_string());
+
+        let txn = doc.transact(None);
+        array.clear(&txn);
+        array.push_back_range(&txn, vec!["1".into(), "2".into()]).unwrap();
+        array.push_map(&txn);
+        array.clear(&txn);
+        assert_eq!(array.length(&txn), 0);
+    }
 }
@@ -25,6 +25,18 @@ impl From<String> for YrsAttrs {
     }
 }
 
+impl YrsAttrs {
+    /// Parses attributes from a JSON object, returning `None` if the input is not one.
+    pub(crate) fn from_json(value: &str) -> Option<YrsAttrs> {
+        match Any::from_json(value) {
+            Ok(Any::Map(m)) => Some(YrsAttrs(
+                m.iter().map(|(k, v)| (Arc::from(k.as_str()), v.clone())).collect(),
+            )),
+            _ => None,
+        }
+    }
+}
+
 impl From<YrsAttrs> for String {
     fn from(value: YrsAttrs) -> String {
         let mut buf = String::new();
@@ -0,0 +1,423 @@
+use crate::doc::YrsOrigin;
+use crate::subscription::YSubscription;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+use yrs::sync::awareness::{AwarenessUpdateEntry, Error};
+use yrs::sync::time::{Clock, SystemClock};
+use yrs::sync::{Awareness, AwarenessUpdate};
+use yrs::updates::decoder::Decode;
+use yrs::updates::encoder::Encode;
+use yrs::{Any, Doc, Origin};
+
+/// Error that can occur when changing the awareness state.
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum YrsAwarenessError {
+    #[error("Awareness state is not valid JSON")]
+    InvalidState,
+    #[error("Client {client_id} has no known awareness state")]
+    UnknownClient { client_id: u64 },
+    #[error("Awareness update could not be decoded")]
+    DecodingError,
+}
+
+/// Clients whose awareness state changed, along with the origin of the change.
+pub(crate) struct YrsAwarenessEvent {
+    /// Clients whose state is now known for the first time.
+    pub added: Vec<u64>,
+    /// Clients whose known state changed.
+    pub updated: Vec<u64>,
+    /// Clients whose state was cleared or timed out.
+    pub removed: Vec<u64>,
+    /// Origin given when applying the remote update, if any.
+    pub origin: Option<YrsOrigin>,
+}
+
+/// Delegate for observing awareness changes.
+pub(crate) trait YrsAwarenessObservationDelegate: Send + Sync + Debug {
+    fn call(&self, event: YrsAwarenessEvent);
+}
+
+/// Presence information (names, colors, cursors, ...) of every client editing a
+/// document, replicated with the y-protocols awareness CRDT. Unlike the document
+/// itself, awareness state is not persisted.
+pub(crate) struct YrsAwareness {
+    awareness: Arc<Awareness>,
+    /// Timeout of the running auto-expiry, with the flag stopping it.
+    expiry: Mutex<Option<(u64, Arc<AtomicBool>)>>,
+}
+
+impl YrsAwareness {
+    pub(crate) fn new(doc: Doc) -> Self {
+        YrsAwareness {
+            awareness: Arc::new(Awareness::new(doc)),
+            expiry: Mutex::new(None),
+        }
+    }
+
+    pub(crate) fn inner(&self) -> &Awareness {
+        &self.awareness
+    }
+
+    /// Returns the id of the local client, which is the client id of the document.
+    pub(crate) fn client_id(&self) -> u64 {
+        self.awareness.client_id()
+    }
+
+    /// Sets the state of the local client. A JSON `null` clears it, marking the local
+    /// client as gone to its peers.
+    pub(crate) fn set_local_state(&self, json: String) -> Result<(), YrsAwarenessError> {
+        match Any::from_json(&json).map_err(|_e| YrsAwarenessError::InvalidState)? {
+            Any::Null => self.awareness.clean_local_state(),
+            _ => self.awareness.set_local_state_raw(json),
+        }
+        Ok(())
+    }
+
+    /// Returns the JSON state of the local client, if it has one.
+    pub(crate) fn get_local_state(&self) -> Option<String> {
+        self.awareness.local_state_raw().map(|json| json.to_string())
+    }
+
+    /// Returns the JSON states of all clients known to have one, by client id.
+    pub(crate) fn states(&self) -> HashMap<u64, String> {
+        self.awareness
+            .iter()
+            .filter_map(|(client_id, state)| Some((client_id, state.data?.to_string())))
+            .collect()
+    }
+
+    /// Encodes the states of the given clients, or of every client with a state, in the
+    /// y-protocols awareness update format.
+    pub(crate) fn encode_update(
+        &self,
+        client_ids: Option<Vec<u64>>,
+    ) -> Result<Vec<u8>, YrsAwarenessError> {
+        let update = match client_ids {
+            Some(client_ids) => self.awareness.update_with_clients(client_ids),
+            None => self.awareness.update(),
+        };
+        let update = update.map_err(|e| match e {
+            Error::ClientNotFound(client_id) => YrsAwarenessError::UnknownClient { client_id },
+            Error::Serde(_) => YrsAwarenessError::InvalidState,
+        })?;
+        Ok(update.encode_v1())
+    }
+
+    /// Applies an update received from a peer. The origin is passed on to observers.
+    pub(crate) fn apply_update(
+        &self,
+        update: Vec<u8>,
+        origin: Option<YrsOrigin>,
+    ) -> Result<(), YrsAwarenessError> {
+        let update =
+            AwarenessUpdate::decode_v1(&update).map_err(|_e| YrsAwarenessError::DecodingError)?;
+        let result = match origin {
+            Some(origin) => self.awareness.apply_update_with(update, Origin::from(origin)),
+            None => self.awareness.apply_update(update),
+        };
+        result.map_err(|_e| YrsAwarenessError::InvalidState)
+    }
+
+    /// Removes the states of remote clients that haven't been renewed for `timeout_ms`,
+    /// returning their ids. Observers see them as removed.
+    pub(crate) fn remove_outdated(&self, timeout_ms: u64) -> Vec<u64> {
+        remove_outdated(&self.awareness, timeout_ms)
+    }
+
+    /// Returns the timeout after which remote clients are removed automatically, or 0
+    /// when auto-expiry is off.
+    pub(crate) fn outdated_timeout(&self) -> u64 {
+        self.expiry.lock().as_ref().map_or(0, |(timeout, _)| *timeout)
+    }
+
+    /// Removes remote clients automatically once they haven't renewed their state for
+    /// `timeout_ms`, checking ten times per timeout like y-protocols. Peers renew their
+    /// state by sending it again, so the local state should be re-sent well within the
+    /// timeout. A timeout of 0 turns auto-expiry off.
+    pub(crate) fn set_outdated_timeout(&self, timeout_ms: u64) {
+        let mut expiry = self.expiry.lock();
+        if let Some((_, stopped)) = expiry.take() {
+            stopped.store(true, Ordering::Relaxed);
+        }
+        if timeout_ms == 0 {
+            return;
+        }
+        let stopped = Arc::new(AtomicBool::new(false));
+        let awareness = Arc::downgrade(&self.awareness);
+        let interval = Duration::from_millis((timeout_ms / 10).max(1));
+        {
+            let stopped = stopped.clone();
+            std::thread::Builder::new()
+                .name("yniffi-awareness".to_string())
+                .spawn(move || expire(awareness, timeout_ms, interval, stopped))
+                .expect("Failed to spawn awareness expiry thread");
+        }
+        *expiry = Some((timeout_ms, stopped));
+    }
+
+    /// Observes clients joining, changing their state, or leaving. Updates that renew a
+    /// client's state without changing it aren't reported.
+    pub(crate) fn observe(
+        &self,
+        delegate: Box<dyn YrsAwarenessObservationDelegate>,
+    ) -> Arc<YSubscription> {
+        let subscription = self.awareness.on_change(move |_awareness, event, origin| {
+            delegate.call(YrsAwarenessEvent {
+                added: event.added().to_vec(),
+                updated: event.updated().to_vec(),
+                removed: event.removed().to_vec(),
+                origin: origin.cloned().map(YrsOrigin::from),
+            })
+        });
+        Arc::new(YSubscription::new(subscription))
+    }
+}
+
+impl Drop for YrsAwareness {
+    fn drop(&mut self) {
+        if let Some((_, stopped)) = self.expiry.lock().take() {
+            stopped.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Periodically removes outdated clients until stopped or the awareness is gone.
+fn expire(awareness: Weak<Awareness>, timeout_ms: u64, interval: Duration, stopped: Arc<AtomicBool>) {
+    loop {
+        std::thread::sleep(interval);
+        if stopped.load(Ordering::Relaxed) {
+            return;
+        }
+        let Some(awareness) = awareness.upgrade() else {
+            return;
+        };
+        remove_outdated(&awareness, timeout_ms);
+    }
+}
+
+fn remove_outdated(awareness: &Awareness, timeout_ms: u64) -> Vec<u64> {
+    let now = SystemClock.now();
+    let outdated: HashMap<_, _> = awareness
+        .iter()
+        .filter(|(client_id, state)| {
+            *client_id != awareness.client_id()
+                && state.data.is_some()
+                && now.saturating_sub(state.last_updated) >= timeout_ms
+        })
+        .map(|(client_id, state)| {
+            // A null state at the current clock removes the client without bumping its
+            // clock, so that its next heartbeat brings it back.
+            let entry = AwarenessUpdateEntry {
+                clock: state.clock,
+                json: "null".into(),
+            };
+            (client_id, entry)
+        })
+        .collect();
+    let removed = outdated.keys().copied().collect();
+    if !outdated.is_empty() {
+        // Entries are built from the current states, so applying them can't fail.
+        let _ = awareness.apply_update(AwarenessUpdate { clients: outdated });
+    }
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{YrsAwareness, YrsAwarenessError, YrsAwarenessEvent, YrsAwarenessObservationDelegate};
+    use crate::UniffiCustomTypeConverter;
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+    use yrs::{Doc, Options};
+
+    type Changes = Arc<Mutex<Vec<(Vec<u64>, Vec<u64>, Vec<u64>)>>>;
+
+    #[derive(Debug, Default)]
+    struct Recorder(Changes, Arc<Mutex<Vec<Vec<u8>>>>);
+
+    impl YrsAwarenessObservationDelegate for Recorder {
+        fn call(&self, event: YrsAwarenessEvent) {
+            if let Some(origin) = event.origin {
+                self.1.lock().push(UniffiCustomTypeConverter::from_custom(origin));
+            }
+            self.0.lock().push((event.added, event.updated, event.removed));
+        }
+    }
+
+    fn awareness(client_id: u64) -> YrsAwareness {
+        YrsAwareness::new(Doc::with_options(Options {
+            client_id,
+            ..Options::default()
+        }))
+    }
+
+    #[test]
+    fn local_state_round_trips() {
+        let awareness = awareness(1);
+        assert_eq!(awareness.get_local_state(), None);
+        assert!(awareness.states().is_empty());
+
+        awareness
+            .set_local_state(r#"{"name":"Ada","color":"red"}"#.to_string())
+            .unwrap();
+        assert_eq!(
+            awareness.get_local_state().as_deref(),
+            Some(r#"{"name":"Ada","color":"red"}"#)
+        );
+        assert_eq!(awareness.states().len(), 1);
+        assert!(awareness.states().contains_key(&1));
+
+        awareness.set_local_state("null".to_string()).unwrap();
+        assert_eq!(awareness.get_local_state(), None);
+        assert!(awareness.states().is_empty());
+    }
+
+    #[test]
+    fn document_awareness_is_shared() {
+        let doc = crate::doc::YrsDoc::new();
+        let first = doc.awareness();
+        first.set_local_state(r#"{"name":"Ada"}"#.to_string()).unwrap();
+
+        let second = doc.awareness();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(second.get_local_state().as_deref(), Some(r#"{"name":"Ada"}"#));
+
+        // Another handle to the same document, as returned for subdocuments.
+        let handle = crate::doc::YrsDoc::from_doc(doc.inner());
+        assert!(Arc::ptr_eq(&first, &handle.awareness()));
+
+        // A distinct document, even though it has the same guid.
+        let other = crate::doc::YrsDoc::new_with_options(crate::subdoc::YrsDocOptions {
+            auto_load: false,
+            client_id: Some(doc.client_id()),
+            guid: Some(doc.guid()),
+            should_load: true,
+            skip_gc: false,
+        });
+        assert!(!Arc::ptr_eq(&first, &other.awareness()));
+    }
+
+    #[test]
+    fn local_state_rejects_invalid_json() {
+        let awareness = awareness(1);
+        assert!(matches!(
+            awareness.set_local_state("{name".to_string()),
+            Err(YrsAwarenessError::InvalidState)
+        ));
+        assert_eq!(awareness.get_local_state(), None);
+    }
+
+    #[test]
+    fn observe_reports_local_state_changes() {
+        let awareness = awareness(1);
+        let changes = Changes::default();
+        let subscription = awareness.observe(Box::new(Recorder(changes.clone(), Default::default())));
+
+        awareness.set_local_state(r#"{"name":"Ada"}"#.to_string()).unwrap();
+        // Setting the same state again only renews it.
+        awareness.set_local_state(r#"{"name":"Ada"}"#.to_string()).unwrap();
+        awareness.set_local_state(r#"{"name":"Grace"}"#.to_string()).unwrap();
+        awareness.set_local_state("null".to_string()).unwrap();
+        assert_eq!(
+            *changes.lock(),
+            vec![
+                (vec![1], vec![], vec![]),
+                (vec![], vec![1], vec![]),
+                (vec![], vec![], vec![1]),
+            ]
+        );
+
+        subscription.cancel();
+        awareness.set_local_state(r#"{"name":"Ada"}"#.to_string()).unwrap();
+        assert_eq!(changes.lock().len(), 3);
+    }
+
+    #[test]
+    fn updates_replicate_states_between_peers() {
+        let local = awareness(1);
+        let remote = awareness(2);
+        local.set_local_state(r#"{"name":"Ada"}"#.to_string()).unwrap();
+        remote.set_local_state(r#"{"name":"Grace"}"#.to_string()).unwrap();
+
+        let changes = Changes::default();
+        let origins = Arc::new(Mutex::new(Vec::new()));
+        let _subscription = local.observe(Box::new(Recorder(changes.clone(), origins.clone())));
+        let update = remote.encode_update(None).unwrap();
+        local.apply_update(update, Some(yrs::Origin::from("remote").into())).unwrap();
+        assert_eq!(*changes.lock(), vec![(vec![2], vec![], vec![])]);
+        assert_eq!(*origins.lock(), vec![b"remote".to_vec()]);
+        assert_eq!(local.states().get(&2).map(String::as_str), Some(r#"{"name":"Grace"}"#));
+
+        // A client leaving is replicated as a null state.
+        remote.set_local_state("null".to_string()).unwrap();
+        let update = remote.encode_update(Some(vec![2])).unwrap();
+        local.apply_update(update, None).unwrap();
+        assert_eq!(changes.lock().last(), Some(&(vec![], vec![], vec![2])));
+        assert!(!local.states().contains_key(&2));
+    }
+
+    #[test]
+    fn update_encoding_matches_y_protocols_format() {
+        let awareness = awareness(1);
+        awareness.set_local_state("{}".to_string()).unwrap();
+        // One entry: client 1, clock 1, then the JSON state as a length-prefixed string.
+        assert_eq!(
+            awareness.encode_update(None).unwrap(),
+            vec![1, 1, 1, 2, b'{', b'}']
+        );
+        assert!(matches!(
+            awareness.encode_update(Some(vec![7])),
+            Err(YrsAwarenessError::UnknownClient { client_id: 7 })
+        ));
+        assert!(matches!(
+            awareness.apply_update(vec![1, 1], None),
+            Err(YrsAwarenessError::DecodingError)
+        ));
+    }
+
+    #[test]
+    fn remove_outdated_drops_silent_remote_clients() {
+        let local = awareness(1);
+        let remote = awareness(2);
+        local.set_local_state(r#"{"name":"Ada"}"#.to_string()).unwrap();
+        remote.set_local_state(r#"{"name":"Grace"}"#.to_string()).unwrap();
+        local.apply_update(remote.encode_update(None).unwrap(), None).unwrap();
+
+        let changes = Changes::default();
+        let _subscription = local.observe(Box::new(Recorder(changes.clone(), Default::default())));
+        assert!(local.remove_outdated(60_000).is_empty());
+        assert_eq!(local.remove_outdated(0), vec![2]);
+        assert_eq!(*changes.lock(), vec![(vec![], vec![], vec![2])]);
+        // The local client is never considered outdated.
+        assert_eq!(local.states().keys().copied().collect::<Vec<_>>(), vec![1]);
+
+        // The next heartbeat of the removed client brings it back.
+        remote.set_local_state(r#"{"name":"Grace"}"#.to_string()).unwrap();
+        local.apply_update(remote.encode_update(None).unwrap(), None).unwrap();
+        assert!(local.states().contains_key(&2));
+    }
+
+    #[test]
+    fn outdated_clients_expire_automatically() {
+        let local = awareness(1);
+        let remote = awareness(2);
+        remote.set_local_state(r#"{"name":"Grace"}"#.to_string()).unwrap();
+        local.apply_update(remote.encode_update(None).unwrap(), None).unwrap();
+        assert_eq!(local.outdated_timeout(), 0);
+
+        local.set_outdated_timeout(20);
+        assert_eq!(local.outdated_timeout(), 20);
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while local.states().contains_key(&2) {
+            assert!(std::time::Instant::now() < deadline, "client didn't expire");
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+
+        local.set_outdated_timeout(0);
+        assert_eq!(local.outdated_timeout(), 0);
+    }
+}
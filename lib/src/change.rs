@@ -1,29 +1,101 @@
+use crate::array::YrsArray;
+use crate::doc::YrsDoc;
+use crate::map::YrsMap;
+use crate::text::YrsText;
+use crate::value::{YrsSharedKind, YrsValue};
+use crate::weak::YrsWeakLink;
+use crate::xml::{YrsXmlElement, YrsXmlFragment, YrsXmlText};
+use std::sync::Arc;
 use yrs::types::Change;
 use yrs::Out;
 
 pub enum YrsChange {
-    Added { elements: Vec<String> },
+    Added {
+        elements: Vec<String>,
+        shared: Vec<YrsAddedShared>,
+    },
     Removed { range: u32 },
     Retained { range: u32 },
 }
 
-// Watch out for XML types here, because underlying
-// elements from Change::added event could XMLElement instances as well
-// and things might break due to that
+/// A shared type added to an array. `offset` is its position within the added
+/// run, counting every added element, so observers can bind to new rows directly.
+pub struct YrsAddedShared {
+    pub offset: u32,
+    pub handle: YrsSharedHandle,
+}
+
+/// A handle to a nested shared type reported by an observer. Only the field
+/// matching `kind` is set.
+pub struct YrsSharedHandle {
+    pub kind: YrsSharedKind,
+    pub array: Option<Arc<YrsArray>>,
+    pub map: Option<Arc<YrsMap>>,
+    pub text: Option<Arc<YrsText>>,
+    pub doc: Option<Arc<YrsDoc>>,
+    pub xml_element: Option<Arc<YrsXmlElement>>,
+    pub xml_fragment: Option<Arc<YrsXmlFragment>>,
+    pub xml_text: Option<Arc<YrsXmlText>>,
+    pub weak_link: Option<Arc<YrsWeakLink>>,
+}
+
+impl YrsSharedHandle {
+    /// Returns a handle for a shared type, or `None` for plain values.
+    pub(crate) fn new(value: &Out) -> Option<Self> {
+        let YrsValue::SharedRef { kind } = YrsValue::from(value.clone()) else {
+            return None;
+        };
+        let mut handle = YrsSharedHandle {
+            kind,
+            array: None,
+            map: None,
+            text: None,
+            doc: None,
+            xml_element: None,
+            xml_fragment: None,
+            xml_text: None,
+            weak_link: None,
+        };
+        match value {
+            Out::YArray(v) => handle.array = Some(Arc::new(YrsArray::from(v.clone()))),
+            Out::YMap(v) => handle.map = Some(Arc::new(YrsMap::from(v.clone()))),
+            Out::YText(v) => handle.text = Some(Arc::new(YrsText::from(v.clone()))),
+            Out::YDoc(v) => handle.doc = Some(Arc::new(YrsDoc::from_doc(v.clone()))),
+            Out::YXmlElement(v) => handle.xml_element = Some(Arc::new(YrsXmlElement::from(v.clone()))),
+            Out::YXmlFragment(v) => handle.xml_fragment = Some(Arc::new(YrsXmlFragment::from(v.clone()))),
+            Out::YXmlText(v) => handle.xml_text = Some(Arc::new(YrsXmlText::from(v.clone()))),
+            Out::YWeakLink(v) => handle.weak_link = Some(Arc::new(YrsWeakLink::from(v.clone()))),
+            _ => {}
+        }
+        Some(handle)
+    }
+}
+
+// Elements added to XML fragments and elements are XML nodes, reported as
+// shared handles like any other nested type.
 
 impl From<&Change> for YrsChange {
     fn from(item: &Change) -> Self {
         match item {
             Change::Added(added) => {
                 let mut res = Vec::new();
-                added.iter().for_each(|v| {
+                let mut shared = Vec::new();
+                added.iter().enumerate().for_each(|(offset, v)| {
                     let mut buf = String::new();
                     if let Out::Any(any) = v {
                         any.to_json(&mut buf);
                         res.push(buf);
+                    } else if let Some(handle) = YrsSharedHandle::new(v) {
+                        shared.push(YrsAddedShared {
+                            offset: offset as u32,
+                            handle,
+                        });
                     }
                 });
-                YrsChange::Added { elements: res }
+                YrsChange::Added {
+                    elements: res,
+                    shared,
+                }
             }
             Change::Removed(range) => YrsChange::Removed { range: *range },
             Change::Retain(range) => YrsChange::Retained { range: *range },
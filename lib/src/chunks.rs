@@ -0,0 +1,233 @@
+use crate::transaction::YrsTransaction;
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+/// Every chunk starts with its sequence number as a little endian u32, followed by its kind.
+const HEADER_LEN: usize = 5;
+/// Carries the next slice of the encoded state.
+const KIND_DATA: u8 = 0;
+/// Ends the transfer, carrying the length of the encoded state as a little endian u64.
+const KIND_END: u8 = 1;
+/// The end chunk is the largest chunk that can't be split, so chunks must fit it.
+const MIN_CHUNK_SIZE: u32 = (HEADER_LEN + 8) as u32;
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum YrsChunkError {
+    #[error("Chunks must be at least {MIN_CHUNK_SIZE} bytes long, got {size}")]
+    InvalidChunkSize { size: u32 },
+    #[error("Chunk isn't well formed")]
+    Malformed,
+    #[error("Expected chunk {expected}, got chunk {received}")]
+    OutOfOrder { expected: u32, received: u32 },
+    #[error("The transfer hasn't ended yet")]
+    Incomplete,
+    #[error("The transferred update couldn't be applied")]
+    DecodingError,
+}
+
+fn chunk(seq: u32, kind: u8, payload: &[u8]) -> Vec<u8> {
+    let mut chunk = Vec::with_capacity(HEADER_LEN + payload.len());
+    chunk.extend_from_slice(&seq.to_le_bytes());
+    chunk.push(kind);
+    chunk.extend_from_slice(payload);
+    chunk
+}
+
+struct ChunkerState {
+    seq: u32,
+    offset: usize,
+    done: bool,
+}
+
+/// Splits an encoded document state into chunks of at most `max_chunk_size` bytes,
+/// handed out one at a time and ended by a terminator chunk, so that large documents
+/// can cross transports limiting the size of messages.
+pub(crate) struct YrsUpdateChunker {
+    update: Vec<u8>,
+    payload_size: usize,
+    state: Mutex<ChunkerState>,
+}
+
+impl YrsUpdateChunker {
+    pub(crate) fn new(update: Vec<u8>, max_chunk_size: u32) -> Result<Self, YrsChunkError> {
+        if max_chunk_size < MIN_CHUNK_SIZE {
+            return Err(YrsChunkError::InvalidChunkSize {
+                size: max_chunk_size,
+            });
+        }
+        Ok(YrsUpdateChunker {
+            update,
+            payload_size: max_chunk_size as usize - HEADER_LEN,
+            state: Mutex::new(ChunkerState {
+                seq: 0,
+                offset: 0,
+                done: false,
+            }),
+        })
+    }
+
+    /// Returns the number of chunks, terminator included.
+    pub(crate) fn chunk_count(&self) -> u32 {
+        self.update.len().div_ceil(self.payload_size) as u32 + 1
+    }
+
+    /// Returns the next chunk, or nothing once the terminator was returned.
+    pub(crate) fn next(&self) -> Option<Vec<u8>> {
+        let mut state = self.state.lock();
+        if state.done {
+            return None;
+        }
+        let seq = state.seq;
+        state.seq += 1;
+        if state.offset < self.update.len() {
+            let end = self.update.len().min(state.offset + self.payload_size);
+            let payload = &self.update[state.offset..end];
+            state.offset = end;
+            Some(chunk(seq, KIND_DATA, payload))
+        } else {
+            state.done = true;
+            Some(chunk(
+                seq,
+                KIND_END,
+                &(self.update.len() as u64).to_le_bytes(),
+            ))
+        }
+    }
+}
+
+#[derive(Default)]
+struct ApplierState {
+    expected: u32,
+    update: Vec<u8>,
+    complete: bool,
+}
+
+/// Collects the chunks produced by `YrsUpdateChunker` in order and applies the update
+/// they carry once the terminator arrived.
+pub(crate) struct YrsChunkApplier(Mutex<ApplierState>);
+
+impl YrsChunkApplier {
+    pub(crate) fn new() -> Self {
+        YrsChunkApplier(Mutex::new(ApplierState::default()))
+    }
+
+    /// Adds the next chunk. Returns whether it was the terminator, after which the
+    /// update can be applied.
+    pub(crate) fn push(&self, chunk: Vec<u8>) -> Result<bool, YrsChunkError> {
+        if chunk.len() < HEADER_LEN {
+            return Err(YrsChunkError::Malformed);
+        }
+        let (header, payload) = chunk.split_at(HEADER_LEN);
+        let seq = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+
+        let mut state = self.0.lock();
+        if state.complete || seq != state.expected {
+            let expected = state.expected;
+            return Err(YrsChunkError::OutOfOrder {
+                expected,
+                received: seq,
+            });
+        }
+        match header[4] {
+            KIND_DATA => state.update.extend_from_slice(payload),
+            KIND_END => {
+                let len: [u8; 8] = payload.try_into().map_err(|_| YrsChunkError::Malformed)?;
+                if u64::from_le_bytes(len) != state.update.len() as u64 {
+                    return Err(YrsChunkError::Malformed);
+                }
+                state.complete = true;
+            }
+            _ => return Err(YrsChunkError::Malformed),
+        }
+        state.expected += 1;
+        Ok(state.complete)
+    }
+
+    pub(crate) fn is_complete(&self) -> bool {
+        self.0.lock().complete
+    }
+
+    /// Applies the transferred update, then starts over to receive another transfer.
+    pub(crate) fn apply(&self, transaction: &YrsTransaction) -> Result<(), YrsChunkError> {
+        let mut state = self.0.lock();
+        if !state.complete {
+            return Err(YrsChunkError::Incomplete);
+        }
+        let update = std::mem::take(&mut *state).update;
+        transaction
+            .transaction_apply_update(update)
+            .map_err(|_| YrsChunkError::DecodingError)
+    }
+}
+
+impl YrsTransaction {
+    /// Encodes the whole document state as chunks of at most `max_chunk_size` bytes.
+    pub(crate) fn transaction_encode_state_as_chunks(
+        &self,
+        max_chunk_size: u32,
+    ) -> Result<Arc<YrsUpdateChunker>, YrsChunkError> {
+        YrsUpdateChunker::new(self.transaction_encode_state_as_update(), max_chunk_size)
+            .map(Arc::new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{YrsChunkApplier, YrsChunkError, YrsUpdateChunker};
+    use crate::YrsDoc;
+
+    #[test]
+    fn chunks_transfer_the_document_state() {
+        let doc = YrsDoc::new();
+        let text = doc.get_text("text".to_string());
+        let txn = doc.transact(None);
+        text.append(&txn, "chunked ".repeat(40));
+        let chunker = txn.transaction_encode_state_as_chunks(32).unwrap();
+        txn.free();
+
+        let chunks: Vec<Vec<u8>> = std::iter::from_fn(|| chunker.next()).collect();
+        assert_eq!(chunks.len() as u32, chunker.chunk_count());
+        assert!(chunks.len() > 10);
+        assert!(chunks.iter().all(|c| c.len() <= 32));
+
+        let copy = YrsDoc::new();
+        let copy_text = copy.get_text("text".to_string());
+        let applier = YrsChunkApplier::new();
+        let (last, rest) = chunks.split_last().unwrap();
+        for chunk in rest {
+            assert!(!applier.push(chunk.clone()).unwrap());
+        }
+        let txn = copy.transact(None);
+        assert!(matches!(
+            applier.apply(&txn),
+            Err(YrsChunkError::Incomplete)
+        ));
+        assert!(applier.push(last.clone()).unwrap());
+        applier.apply(&txn).unwrap();
+        assert_eq!(copy_text.get_string(&txn), "chunked ".repeat(40));
+        assert!(!applier.is_complete());
+    }
+
+    #[test]
+    fn chunk_applier_rejects_gaps_and_bad_sizes() {
+        let chunker = YrsUpdateChunker::new(vec![1; 20], 13).unwrap();
+        let applier = YrsChunkApplier::new();
+        applier.push(chunker.next().unwrap()).unwrap();
+        chunker.next();
+        assert!(matches!(
+            applier.push(chunker.next().unwrap()),
+            Err(YrsChunkError::OutOfOrder {
+                expected: 1,
+                received: 2
+            })
+        ));
+        assert!(matches!(
+            applier.push(vec![0, 0]),
+            Err(YrsChunkError::Malformed)
+        ));
+        assert!(matches!(
+            YrsUpdateChunker::new(vec![], 12),
+            Err(YrsChunkError::InvalidChunkSize { size: 12 })
+        ));
+    }
+}
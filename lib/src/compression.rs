@@ -0,0 +1,148 @@
+use crate::error::CodingError;
+
+/// Compressed payloads start with a byte naming the method, so that new methods can be
+/// added without breaking older payloads.
+const METHOD_NONE: u8 = 0;
+/// Raw DEFLATE (RFC 1951) data, preceded by the uncompressed length as a little endian
+/// u32. Readable by zlib with negative window bits and by Apple's `COMPRESSION_ZLIB`.
+const METHOD_DEFLATE: u8 = 1;
+
+/// DEFLATE can't expand data more than 1032 times, so a payload claiming a larger
+/// length is rejected before anything gets decoded.
+const MAX_RATIO: usize = 1032;
+/// Compression level handed to the DEFLATE encoder, from 0 to 10.
+const LEVEL: u8 = 6;
+
+/// Compresses `data`, storing it as is when compression wouldn't make it smaller.
+pub(crate) fn compress(data: &[u8]) -> Vec<u8> {
+    let deflated = miniz_oxide::deflate::compress_to_vec(data, LEVEL);
+    match u32::try_from(data.len()) {
+        Ok(len) if deflated.len() + 4 < data.len() => {
+            let mut out = Vec::with_capacity(deflated.len() + 5);
+            out.push(METHOD_DEFLATE);
+            out.extend_from_slice(&len.to_le_bytes());
+            out.extend_from_slice(&deflated);
+            out
+        }
+        _ => {
+            let mut out = Vec::with_capacity(data.len() + 1);
+            out.push(METHOD_NONE);
+            out.extend_from_slice(data);
+            out
+        }
+    }
+}
+
+/// Reverses `compress`.
+pub(crate) fn decompress(data: &[u8]) -> Result<Vec<u8>, CodingError> {
+    match data.split_first() {
+        Some((&METHOD_NONE, rest)) => Ok(rest.to_vec()),
+        Some((&METHOD_DEFLATE, rest)) if rest.len() >= 4 => {
+            let (len, deflated) = rest.split_at(4);
+            let len = u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize;
+            if len > deflated.len().saturating_mul(MAX_RATIO) {
+                return Err(CodingError::DecodingError);
+            }
+            let out = inflate(deflated, len)?;
+            if out.len() == len {
+                Ok(out)
+            } else {
+                Err(CodingError::DecodingError)
+            }
+        }
+        _ => Err(CodingError::DecodingError),
+    }
+}
+
+/// Decodes raw DEFLATE data, failing once the output would exceed `limit` bytes. The
+/// output grows as it gets decoded rather than being allocated from `limit` up front.
+fn inflate(data: &[u8], limit: usize) -> Result<Vec<u8>, CodingError> {
+    miniz_oxide::inflate::decompress_to_vec_with_limit(data, limit).map_err(|_| CodingError::DecodingError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compress, decompress, inflate, METHOD_DEFLATE, METHOD_NONE};
+    use crate::error::CodingError;
+    use crate::YrsDoc;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn compression_round_trips_and_falls_back_to_stored() {
+        let data: Vec<u8> = (0..5000u32).flat_map(|i| format!("item {} ", i % 97).into_bytes()).collect();
+        let compressed = compress(&data);
+        assert_eq!(compressed[0], METHOD_DEFLATE);
+        assert!(compressed.len() < data.len() / 4);
+        assert_eq!(decompress(&compressed).unwrap(), data);
+
+        let tiny = compress(b"ab");
+        assert_eq!(tiny, vec![METHOD_NONE, b'a', b'b']);
+        assert_eq!(decompress(&tiny).unwrap(), b"ab");
+        assert_eq!(decompress(&compress(&[])).unwrap(), Vec::<u8>::new());
+
+        assert!(matches!(decompress(&[9, 1, 2]), Err(CodingError::DecodingError)));
+        let mut truncated = compressed.clone();
+        truncated.truncate(compressed.len() / 2);
+        assert!(decompress(&truncated).is_err());
+    }
+
+    #[test]
+    fn decompress_rejects_lengths_the_payload_cannot_hold() {
+        let bomb = [METHOD_DEFLATE, 0xff, 0xff, 0xff, 0xff, 0x03, 0x00];
+        assert!(matches!(decompress(&bomb), Err(CodingError::DecodingError)));
+
+        let mut lying = compress(&[7; 4096]);
+        lying[1..5].copy_from_slice(&8192u32.to_le_bytes());
+        assert!(matches!(decompress(&lying), Err(CodingError::DecodingError)));
+    }
+
+    #[test]
+    fn inflate_reads_dynamic_blocks_from_zlib() {
+        // produced by zlib at level 9 with raw deflate framing
+        let deflated = hex(concat!(
+            "4dd4d971d8300c45d196f0b86061aa89dd849dea6393d86634127f704612af44e7efc7e7cff1f5fdef0f",
+            "4e2ec789d53cbe58e75df7b9173ebf6769e35ae396e3a098071cc07802e625b0fa2dec42c0a5482a1a8a",
+            "3d65d055069a32467b9299ca58a18cedca6057e429da152b6552bd108432872b733e65aeabccdd94c94d",
+            "915234157365d15316aeb24653d62c65ad54d6cedde150c4157d8a356553291ba9ec11ca9eb1c9eb297b",
+            "5f657357a4295a8a85c2e40ac35b1957e1d9145ea5f04e85391509455db1971c35455ab352d14a562b91",
+            "ad78b7e2e1f672a5a52bd5ae66bb1aedaab7abaf5deded6a6b57ab5dcd7635da556fd75ebbd6dbb5d6ae",
+            "55bb96ed5ab46bdeaebd76adb76bad5d10b5cf31eb0545be20ef17f40206f58241dc316998161615039e",
+            "31f03a067ac8402b19a894815d7f0a4e4c0253c77acfff01",
+        ));
+        let expected: Vec<u8> = (0..120usize)
+            .flat_map(|i| format!("{}:{};", i, &"abcabcxyz"[i % 7..]).into_bytes())
+            .collect();
+        assert_eq!(inflate(&deflated, expected.len()).unwrap(), expected);
+        assert!(inflate(&deflated, expected.len() - 1).is_err());
+    }
+
+    #[test]
+    fn compressed_state_updates_apply_to_other_documents() {
+        let doc = YrsDoc::new();
+        let text = doc.get_text("text".to_string());
+        let txn = doc.transact(None);
+        for _ in 0..50 {
+            text.append(&txn, "all work and no play ".to_string());
+        }
+        let plain = txn.transaction_encode_state_as_update();
+        let compressed = txn.transaction_encode_state_as_update_compressed();
+        txn.free();
+        assert!(compressed.len() < plain.len());
+
+        let copy = YrsDoc::new();
+        let copy_text = copy.get_text("text".to_string());
+        let txn = copy.transact(None);
+        txn.transaction_apply_update_compressed(compressed).unwrap();
+        assert_eq!(copy_text.get_string(&txn), "all work and no play ".repeat(50));
+        assert!(matches!(
+            txn.transaction_apply_update_compressed(vec![METHOD_DEFLATE]),
+            Err(CodingError::DecodingError)
+        ));
+    }
+}
@@ -0,0 +1,69 @@
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::mpsc::{channel, SendError, Sender};
+use std::sync::OnceLock;
+
+/// When observation delegates are called.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum YrsObservationDelivery {
+    /// Inside the transaction commit, before the committing call returns.
+    Synchronous,
+    /// After the commit, on a shared worker thread. Callbacks of all deferred
+    /// observers are delivered one at a time, in commit order.
+    Deferred,
+}
+
+type Callback = Box<dyn FnOnce() + Send>;
+
+impl YrsObservationDelivery {
+    pub(crate) fn deliver<F>(self, callback: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        match self {
+            YrsObservationDelivery::Synchronous => callback(),
+            YrsObservationDelivery::Deferred => {
+                // The worker only stops if it panicked outside of a callback. Rather
+                // than dropping the callback then, it is called on this thread.
+                if let Err(SendError(callback)) = worker().send(Box::new(callback)) {
+                    callback();
+                }
+            }
+        }
+    }
+}
+
+/// Returns the sender feeding the worker calling deferred callbacks.
+///
+/// A panicking callback is caught so that the worker keeps delivering the callbacks
+/// after it. This only helps where panics unwind: release builds abort on panic, so
+/// a delegate panicking there takes down the app, as it would when synchronous.
+fn worker() -> &'static Sender<Callback> {
+    static WORKER: OnceLock<Sender<Callback>> = OnceLock::new();
+    WORKER.get_or_init(|| {
+        let (sender, receiver) = channel::<Callback>();
+        std::thread::Builder::new()
+            .name("yniffi-observers".to_string())
+            .spawn(move || {
+                for callback in receiver {
+                    let _ = catch_unwind(AssertUnwindSafe(callback));
+                }
+            })
+            .expect("Failed to spawn observer delivery thread");
+        sender
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::YrsObservationDelivery;
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
+
+    #[test]
+    fn deferred_delivery_survives_a_panicking_callback() {
+        let (sender, receiver) = channel();
+        YrsObservationDelivery::Deferred.deliver(|| panic!("delegate failed"));
+        YrsObservationDelivery::Deferred.deliver(move || sender.send(()).unwrap());
+        assert!(receiver.recv_timeout(Duration::from_secs(5)).is_ok());
+    }
+}
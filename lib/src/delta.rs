@@ -1,6 +1,6 @@
 use crate::attrs::YrsAttrs;
-use yrs::types::Delta;
-use yrs::Out;
+use yrs::types::{Attrs, Delta};
+use yrs::{Any, Out};
 
 pub enum YrsDelta {
     Inserted { value: String, attrs: String },
@@ -43,3 +43,162 @@ impl From<&Delta> for YrsDelta {
         }
     }
 }
+
+impl From<&Delta<Any>> for YrsDelta {
+    fn from(item: &Delta<Any>) -> Self {
+        match item {
+            Delta::Inserted(value, attrs) => {
+                let mut buf = String::new();
+                value.to_json(&mut buf);
+                let attrs = YrsAttrs::from(*attrs.clone().unwrap_or_default());
+                YrsDelta::Inserted {
+                    value: buf,
+                    attrs: attrs.into(),
+                }
+            }
+            Delta::Retain(index, attrs) => {
+                let attrs = YrsAttrs::from(*attrs.clone().unwrap_or_default());
+                YrsDelta::Retained {
+                    index: *index,
+                    attrs: attrs.into(),
+                }
+            }
+            Delta::Deleted(index) => YrsDelta::Deleted { index: *index },
+        }
+    }
+}
+
+/// Error returned when a delta cannot be applied. `index` is the position of the
+/// offending operation within the delta.
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum YrsDeltaError {
+    #[error("Operation {index} inserts a value that is missing or not valid JSON")]
+    InvalidValue { index: u32 },
+    #[error("Operation {index} has attributes that are not a JSON object")]
+    InvalidAttributes { index: u32 },
+}
+
+fn parse_attrs(attrs: &str, index: usize) -> Result<Option<Box<Attrs>>, YrsDeltaError> {
+    if attrs.is_empty() {
+        return Ok(None);
+    }
+    match YrsAttrs::from_json(attrs) {
+        Some(a) if a.is_empty() => Ok(None),
+        Some(a) => Ok(Some(Box::new(a.0))),
+        None => Err(YrsDeltaError::InvalidAttributes {
+            index: index as u32,
+        }),
+    }
+}
+
+/// Parses a delta and normalizes it: no-op operations are dropped, adjacent operations
+/// of the same kind and attributes are merged, and trailing plain retains are removed.
+pub(crate) fn normalize(delta: Vec<YrsDelta>) -> Result<Vec<Delta<Any>>, YrsDeltaError> {
+    let mut result: Vec<Delta<Any>> = Vec::with_capacity(delta.len());
+    for (i, op) in delta.into_iter().enumerate() {
+        let op = match op {
+            YrsDelta::Inserted { value, attrs } => {
+                let value = match Any::from_json(value.as_str()) {
+                    Ok(Any::Null) | Ok(Any::Undefined) | Err(_) => {
+                        return Err(YrsDeltaError::InvalidValue { index: i as u32 })
+                    }
+                    Ok(Any::String(s)) if s.is_empty() => continue,
+                    Ok(value) => value,
+                };
+                Delta::Inserted(value, parse_attrs(&attrs, i)?)
+            }
+            YrsDelta::Deleted { index: 0 } => continue,
+            YrsDelta::Deleted { index } => Delta::Deleted(index),
+            YrsDelta::Retained { index, attrs } => {
+                let attrs = parse_attrs(&attrs, i)?;
+                if index == 0 {
+                    continue;
+                }
+                Delta::Retain(index, attrs)
+            }
+        };
+        match (result.last_mut(), op) {
+            (
+                Some(Delta::Inserted(Any::String(last), last_attrs)),
+                Delta::Inserted(Any::String(s), attrs),
+            ) if *last_attrs == attrs => {
+                *last = format!("{}{}", last, s).into();
+            }
+            (Some(Delta::Deleted(last)), Delta::Deleted(len)) => *last += len,
+            (Some(Delta::Retain(last, last_attrs)), Delta::Retain(len, attrs))
+                if *last_attrs == attrs =>
+            {
+                *last += len
+            }
+            (_, op) => result.push(op),
+        }
+    }
+    while matches!(result.last(), Some(Delta::Retain(_, None))) {
+        result.pop();
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize, YrsDelta, YrsDeltaError};
+
+    #[test]
+    fn normalize_merges_and_drops_noops() {
+        let delta = vec![
+            YrsDelta::Retained {
+                index: 0,
+                attrs: "".into(),
+            },
+            YrsDelta::Inserted {
+                value: r#""ab""#.into(),
+                attrs: "".into(),
+            },
+            YrsDelta::Inserted {
+                value: r#""cd""#.into(),
+                attrs: "{}".into(),
+            },
+            YrsDelta::Deleted { index: 1 },
+            YrsDelta::Deleted { index: 2 },
+            YrsDelta::Retained {
+                index: 4,
+                attrs: "".into(),
+            },
+        ];
+        let normalized: Vec<YrsDelta> = normalize(delta)
+            .unwrap()
+            .iter()
+            .map(YrsDelta::from)
+            .collect();
+        assert_eq!(normalized.len(), 2);
+        assert!(matches!(&normalized[0], YrsDelta::Inserted { value, .. } if value == r#""abcd""#));
+        assert!(matches!(&normalized[1], YrsDelta::Deleted { index: 3 }));
+    }
+
+    #[test]
+    fn normalize_rejects_invalid_operations() {
+        let delta = vec![
+            YrsDelta::Retained {
+                index: 1,
+                attrs: "".into(),
+            },
+            YrsDelta::Inserted {
+                value: "not json".into(),
+                attrs: "".into(),
+            },
+        ];
+        assert!(matches!(
+            normalize(delta),
+            Err(YrsDeltaError::InvalidValue { index: 1 })
+        ));
+
+        let delta = vec![YrsDelta::Retained {
+            index: 1,
+            attrs: "[1]".into(),
+        }];
+        assert!(matches!(
+            normalize(delta),
+            Err(YrsDeltaError::InvalidAttributes { index: 0 })
+        ));
+    }
+}
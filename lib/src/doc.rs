@@ -1,20 +1,37 @@
 use crate::array::YrsArray;
+use crate::awareness::YrsAwareness;
 use crate::error::CodingError;
 use crate::map::YrsMap;
-use crate::subdoc::{YrsDestroyObservationDelegate, YrsDocOptions, YrsSubdocsEvent, YrsSubdocsObservationDelegate};
+use crate::persistence::{YrsPersistence, YrsPersistenceError, YrsPersistenceFailureDelegate};
+use crate::subdoc::{
+    YrsDestroyObservationDelegate, YrsDocOptions, YrsLoadedObservationDelegate, YrsSubdocInfo, YrsSubdocLoadDelegate,
+    YrsSubdocUpdateDelegate, YrsSubdocsEvent, YrsSubdocsObservationDelegate,
+};
 use crate::subscription::YSubscription;
-use crate::text::YrsText;
+use crate::sync::YrsSyncSession;
+use crate::text::{YrsSurrogatePolicy, YrsText};
 use crate::transaction::YrsTransaction;
-use crate::undo::YrsUndoManager;
+use crate::undo::{YrsUndoManager, YrsUndoManagerOptions};
+use crate::xml::YrsXmlFragment;
 use crate::UniffiCustomTypeConverter;
-use parking_lot::ReentrantMutex;
+use parking_lot::{Mutex, ReentrantMutex};
 use std::cell::UnsafeCell;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock, Weak};
 use std::borrow::Borrow;
 use yrs::branch::Branch;
-use yrs::{updates::decoder::Decode, ArrayRef, Doc, MapRef, OffsetKind, Options, Origin, ReadTxn, StateVector, Transact};
-
-pub(crate) struct YrsDoc(ReentrantMutex<UnsafeCell<Doc>>);
+use yrs::{updates::decoder::Decode, ArrayRef, Doc, MapRef, Observer, OffsetKind, Options, Origin, ReadTxn, StateVector, Subscription, Transact};
+
+/// A handle to a yrs document. Several handles can wrap the same document, such as the
+/// ones returned for a subdocument, and they all share the state of the document.
+pub(crate) struct YrsDoc {
+    doc: ReentrantMutex<UnsafeCell<Doc>>,
+    /// Stores the updates of a document opened with `load_from`, while this handle is alive.
+    persistence: Mutex<Option<Subscription>>,
+    state: Arc<YrsDocState>,
+}
 
 // Safe because ReentrantMutex provides proper thread synchronization.
 unsafe impl Send for YrsDoc {}
@@ -39,17 +56,19 @@ impl<'a> DocGuard<'a> {
 
 impl YrsDoc {
     fn doc(&self) -> DocGuard<'_> {
-        let guard = self.0.lock();
-        let ptr = unsafe { (*self.0.data_ptr()).get() };
+        let guard = self.doc.lock();
+        let ptr = unsafe { (*self.doc.data_ptr()).get() };
         DocGuard { _guard: guard, ptr }
     }
 
     pub(crate) fn new() -> Self {
-        let mut options = Options::default();
-        options.offset_kind = OffsetKind::Utf16;
+        let options = Options {
+            offset_kind: OffsetKind::Utf16,
+            ..Options::default()
+        };
         let doc = yrs::Doc::with_options(options);
 
-        Self(ReentrantMutex::new(UnsafeCell::new(doc)))
+        Self::from_doc(doc)
     }
 
     pub(crate) fn encode_diff_v1(
@@ -71,34 +90,79 @@ impl YrsDoc {
     }
 
     pub(crate) fn get_array(&self, name: String) -> Arc<YrsArray> {
-        let array_ref: ArrayRef = self.doc().as_ref().get_or_insert_array(name.as_str()).into();
+        let array_ref: ArrayRef = self.doc().as_ref().get_or_insert_array(name.as_str());
         Arc::from(YrsArray::from(array_ref))
     }
 
     pub(crate) fn get_map(&self, name: String) -> Arc<YrsMap> {
-        let map_ref: MapRef = self.doc().as_ref().get_or_insert_map(name.as_str()).into();
+        let map_ref: MapRef = self.doc().as_ref().get_or_insert_map(name.as_str());
         Arc::from(YrsMap::from(map_ref))
     }
 
-    pub(crate) fn transact<'doc>(&self, origin: Option<YrsOrigin>) -> Arc<YrsTransaction> {
+    pub(crate) fn get_xml_fragment(&self, name: String) -> Arc<YrsXmlFragment> {
+        let fragment_ref = self.doc().as_ref().get_or_insert_xml_fragment(name.as_str());
+        Arc::from(YrsXmlFragment::from(fragment_ref))
+    }
+
+    pub(crate) fn transact(&self, origin: Option<YrsOrigin>) -> Arc<YrsTransaction> {
         let doc = self.doc();
         let tx = if let Some(origin) = origin {
             doc.as_ref().transact_mut_with(origin)
         } else {
             doc.as_ref().transact_mut()
         };
-        Arc::from(YrsTransaction::from(tx))
+        Arc::from(YrsTransaction::new(tx, *self.state.surrogate_policy.lock()))
+    }
+
+    /// Sets how text writes treat offsets that split a surrogate pair, for every text
+    /// of the document and through every handle to it. Writes are rejected by default.
+    /// Transactions started before keep the previous policy.
+    pub(crate) fn set_surrogate_policy(&self, policy: YrsSurrogatePolicy) {
+        *self.state.surrogate_policy.lock() = policy;
     }
 
-    pub(crate) fn undo_manager(&self, tracked_refs: Vec<YrsCollectionPtr>) -> Arc<YrsUndoManager> {
+    pub(crate) fn undo_manager(
+        &self,
+        tracked_refs: Vec<YrsCollectionPtr>,
+        options: YrsUndoManagerOptions,
+    ) -> Arc<YrsUndoManager> {
         let doc = self.doc();
-        let mut i = tracked_refs.into_iter();
-        let first = i.next().unwrap();
-        let mut undo_manager = yrs::undo::UndoManager::new(doc.as_ref(), &first);
-        while let Some(n) = i.next() {
-            undo_manager.expand_scope(&n);
+        Arc::new(YrsUndoManager::new(doc.as_ref(), tracked_refs, options))
+    }
+
+    /// Creates an undo manager that only tracks changes made in transactions started with
+    /// `transact(origin)`, leaving changes from other origins, such as updates applied
+    /// from remote peers, out of the undo history.
+    pub(crate) fn local_undo_manager(
+        &self,
+        tracked_refs: Vec<YrsCollectionPtr>,
+        origin: YrsOrigin,
+    ) -> Arc<YrsUndoManager> {
+        let options = YrsUndoManagerOptions {
+            tracked_origins: vec![origin],
+            ..YrsUndoManagerOptions::default()
+        };
+        self.undo_manager(tracked_refs, options)
+    }
+
+    /// Returns the awareness of this document, tracking the presence of its clients.
+    /// Every handle to the document returns the same instance for as long as it is held
+    /// somewhere, so local state and observers are shared. Once it no longer is, the next
+    /// call creates a new one.
+    pub(crate) fn awareness(&self) -> Arc<YrsAwareness> {
+        let mut awareness = self.state.awareness.lock();
+        if let Some(awareness) = awareness.upgrade() {
+            return awareness;
         }
-        Arc::new(YrsUndoManager::from(undo_manager))
+        let created = Arc::new(YrsAwareness::new(self.inner()));
+        *awareness = Arc::downgrade(&created);
+        created
+    }
+
+    /// Creates a session syncing this document with one peer over the y-sync protocol.
+    /// Changes received from the peer are applied with the given origin.
+    pub(crate) fn sync_session(&self, origin: Option<YrsOrigin>) -> Arc<YrsSyncSession> {
+        Arc::new(YrsSyncSession::new(self.inner(), origin))
     }
 
     // MARK: - Subdoc methods
@@ -136,8 +200,10 @@ impl YrsDoc {
 
     /// Creates a new document with the specified options.
     pub(crate) fn new_with_options(options: YrsDocOptions) -> Self {
-        let mut opts = Options::default();
-        opts.auto_load = options.auto_load;
+        let mut opts = Options {
+            auto_load: options.auto_load,
+            ..Options::default()
+        };
         if let Some(client_id) = options.client_id {
             opts.client_id = client_id;
         }
@@ -146,8 +212,38 @@ impl YrsDoc {
         }
         opts.offset_kind = OffsetKind::Utf16;
         opts.should_load = options.should_load;
+        opts.skip_gc = options.skip_gc;
+
+        Self::from_doc(Doc::with_options(opts))
+    }
 
-        Self(ReentrantMutex::new(UnsafeCell::new(Doc::with_options(opts))))
+    /// Opens the document with the given guid from persistence: creates it, applies
+    /// its stored updates, and stores every later update of it, for as long as the
+    /// returned document is alive. Updates that fail to be stored are reported to
+    /// `on_failure`, and can be stored again from the document state.
+    pub(crate) fn load_from(
+        persistence: Arc<YrsPersistence>,
+        guid: String,
+        on_failure: Box<dyn YrsPersistenceFailureDelegate>,
+    ) -> Result<Self, YrsPersistenceError> {
+        let doc = Self::new_with_options(YrsDocOptions {
+            auto_load: false,
+            client_id: None,
+            guid: Some(guid.clone()),
+            should_load: true,
+            skip_gc: false,
+        });
+        persistence.load(guid.clone(), &doc)?;
+        let subscription = doc
+            .inner()
+            .observe_update_v1(move |_txn, event| {
+                if let Err(e) = persistence.store_update(guid.clone(), event.update.clone()) {
+                    on_failure.call(guid.clone(), e.to_string());
+                }
+            })
+            .expect("Failed to observe updates");
+        *doc.persistence.lock() = Some(subscription);
+        Ok(doc)
     }
 
     /// Observes when this document is destroyed.
@@ -166,6 +262,35 @@ impl YrsDoc {
         Arc::new(YSubscription::new(subscription))
     }
 
+    /// Returns whether this document is loaded. Root documents always are, and
+    /// subdocuments once `mark_loaded` has been called on any handle to them. Requesting
+    /// the load of a subdocument doesn't make it loaded, since its content may not have
+    /// arrived yet.
+    pub(crate) fn is_loaded(&self) -> bool {
+        self.doc().as_ref().parent_doc().is_none() || self.state.loaded.load(Ordering::SeqCst)
+    }
+
+    /// Marks this document as loaded once its content has been applied, typically by the
+    /// delegate given to `observe_load_requests`, and calls the loaded observers. Does
+    /// nothing if it already was.
+    pub(crate) fn mark_loaded(&self) {
+        if !self.state.loaded.swap(true, Ordering::SeqCst) {
+            self.state.loaded_observers.trigger(|delegate| delegate.call());
+        }
+    }
+
+    /// Observes this subdocument being marked as loaded with `mark_loaded`. Returns
+    /// nothing for root documents, which are always loaded.
+    pub(crate) fn observe_loaded(
+        &self,
+        delegate: Box<dyn YrsLoadedObservationDelegate>,
+    ) -> Option<Arc<YSubscription>> {
+        self.doc().as_ref().parent_doc()?;
+        let subscription = self.state.loaded_observers.subscribe(delegate);
+
+        Some(Arc::new(YSubscription::new(subscription)))
+    }
+
     /// Observes subdocument lifecycle changes (added, loaded, removed).
     pub(crate) fn observe_subdocs(
         &self,
@@ -174,23 +299,12 @@ impl YrsDoc {
         let doc = self.doc();
         let subscription = doc
             .as_ref()
-            .observe_subdocs(move |_txn, event| {
-                let added: Vec<Arc<YrsDoc>> = event
-                    .added()
-                    .map(|d| Arc::new(YrsDoc::from_doc(d.clone())))
-                    .collect();
-                let loaded: Vec<Arc<YrsDoc>> = event
-                    .loaded()
-                    .map(|d| Arc::new(YrsDoc::from_doc(d.clone())))
-                    .collect();
-                let removed: Vec<Arc<YrsDoc>> = event
-                    .removed()
-                    .map(|d| Arc::new(YrsDoc::from_doc(d.clone())))
-                    .collect();
+            .observe_subdocs(move |txn, event| {
                 delegate.call(YrsSubdocsEvent {
-                    added,
-                    loaded,
-                    removed,
+                    added: event.added().map(YrsSubdocInfo::from).collect(),
+                    loaded: event.loaded().map(YrsSubdocInfo::from).collect(),
+                    removed: event.removed().map(YrsSubdocInfo::from).collect(),
+                    origin: txn.origin().cloned().map(YrsOrigin::from),
                 });
             })
             .expect("Failed to observe subdocs");
@@ -198,6 +312,70 @@ impl YrsDoc {
         Arc::new(YSubscription::new(subscription))
     }
 
+    /// Calls the delegate for every subdocument whose load is requested, either with
+    /// `load` or by adding it with `auto_load` set, once the requesting transaction commits.
+    pub(crate) fn observe_load_requests(
+        &self,
+        delegate: Box<dyn YrsSubdocLoadDelegate>,
+    ) -> Arc<YSubscription> {
+        let doc = self.doc();
+        let subscription = doc
+            .as_ref()
+            .observe_subdocs(move |_txn, event| {
+                for subdoc in event.loaded() {
+                    delegate.call(Arc::new(YrsDoc::from_doc(subdoc.clone())));
+                }
+            })
+            .expect("Failed to observe subdocs");
+
+        Arc::new(YSubscription::new(subscription))
+    }
+
+    /// Forwards the updates of every current and future subdocument to the delegate,
+    /// tagged with the guid of the subdocument they were made in.
+    pub(crate) fn observe_subdoc_updates(
+        &self,
+        delegate: Box<dyn YrsSubdocUpdateDelegate>,
+    ) -> Arc<YSubscription> {
+        let delegate: Arc<dyn YrsSubdocUpdateDelegate> = Arc::from(delegate);
+        let updates = Arc::new(Mutex::new(HashMap::new()));
+        let observe = {
+            let updates = updates.clone();
+            move |subdoc: &Doc| {
+                let guid = subdoc.guid().to_string();
+                let delegate = delegate.clone();
+                let subscription = {
+                    let guid = guid.clone();
+                    subdoc
+                        .observe_update_v1(move |_txn, event| delegate.call(guid.clone(), event.update.clone()))
+                        .expect("Failed to observe subdoc updates")
+                };
+                updates.lock().insert(guid, subscription);
+            }
+        };
+
+        let doc = self.doc();
+        for subdoc in doc.as_ref().transact().subdocs() {
+            observe(subdoc);
+        }
+        let subdocs = {
+            let updates = updates.clone();
+            doc.as_ref()
+                .observe_subdocs(move |_txn, event| {
+                    event.added().for_each(&observe);
+                    for subdoc in event.removed() {
+                        updates.lock().remove(subdoc.guid().as_ref());
+                    }
+                })
+                .expect("Failed to observe subdocs")
+        };
+
+        Arc::new(YSubscription::new(Arc::new(SubdocUpdateSubscriptions {
+            _subdocs: subdocs,
+            updates,
+        })))
+    }
+
     /// Returns the parent document if this is a subdocument.
     pub(crate) fn parent_doc(&self) -> Option<Arc<YrsDoc>> {
         self.doc()
@@ -220,7 +398,12 @@ impl YrsDoc {
 impl YrsDoc {
     /// Creates a YrsDoc from an existing yrs Doc.
     pub(crate) fn from_doc(doc: Doc) -> Self {
-        Self(ReentrantMutex::new(UnsafeCell::new(doc)))
+        let state = YrsDocState::of(&doc);
+        YrsDoc {
+            doc: ReentrantMutex::new(UnsafeCell::new(doc)),
+            persistence: Mutex::new(None),
+            state,
+        }
     }
 
     /// Returns a clone of the inner Doc for internal use.
@@ -229,7 +412,110 @@ impl YrsDoc {
     }
 }
 
+/// Key of the destroy observer keeping the state of a document alive along with it.
+const DOC_STATE_ORIGIN: &str = "yniffi-doc-state";
+
+/// Identifies a document by the allocation of its guid, which the clones of a document
+/// share while other documents with the same guid don't, and which stays the same when
+/// the client ID of a subdocument changes as it loads. Holding the key keeps the
+/// allocation from being reused by another document.
 #[derive(Clone)]
+struct YrsDocKey(Arc<str>);
+
+impl PartialEq for YrsDocKey {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for YrsDocKey {}
+
+impl Hash for YrsDocKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.0).cast::<u8>().hash(state)
+    }
+}
+
+/// State of a yrs document shared by all of its handles. Handles find it from the
+/// document guid, and the document itself keeps it alive, so it isn't lost while no
+/// handle exists.
+struct YrsDocState {
+    key: YrsDocKey,
+    /// Policy handed to new transactions for writes splitting a surrogate pair.
+    surrogate_policy: Mutex<YrsSurrogatePolicy>,
+    /// Whether the content of the document has been applied, as told by `mark_loaded`.
+    loaded: AtomicBool,
+    loaded_observers: Observer<Box<dyn YrsLoadedObservationDelegate>>,
+    /// The awareness of the document, while it is held somewhere.
+    awareness: Mutex<Weak<YrsAwareness>>,
+}
+
+/// Returns the state of every document that has one.
+fn doc_states() -> &'static Mutex<HashMap<YrsDocKey, Weak<YrsDocState>>> {
+    static STATES: OnceLock<Mutex<HashMap<YrsDocKey, Weak<YrsDocState>>>> = OnceLock::new();
+    STATES.get_or_init(Mutex::default)
+}
+
+impl YrsDocState {
+    /// Returns the state of the document, creating it the first time it is asked for.
+    fn of(doc: &Doc) -> Arc<Self> {
+        let key = YrsDocKey(doc.guid());
+        let state = {
+            let mut states = doc_states().lock();
+            if let Some(state) = states.get(&key).and_then(Weak::upgrade) {
+                return state;
+            }
+            let state = Arc::new(YrsDocState {
+                key: key.clone(),
+                surrogate_policy: Mutex::new(YrsSurrogatePolicy::Reject),
+                loaded: AtomicBool::new(false),
+                loaded_observers: Observer::new(),
+                awareness: Mutex::new(Weak::new()),
+            });
+            states.insert(key, Arc::downgrade(&state));
+            state
+        };
+
+        // The observer lives as long as the document. It can't be added while a
+        // transaction is open on the document, in which case the state only lives as
+        // long as the handles. A destroyed document is replaced by a new one sharing its
+        // guid, which mustn't find the state of the old one.
+        let kept = state.clone();
+        let _ = doc.observe_destroy_with(DOC_STATE_ORIGIN, move |_txn, _doc| kept.forget());
+        state
+    }
+
+    /// Stops handles created from now on from finding this state.
+    fn forget(self: &Arc<Self>) {
+        let mut states = doc_states().lock();
+        if states.get(&self.key).is_some_and(|state| state.as_ptr() == Arc::as_ptr(self)) {
+            states.remove(&self.key);
+        }
+    }
+}
+
+impl Drop for YrsDocState {
+    fn drop(&mut self) {
+        let mut states = doc_states().lock();
+        if states.get(&self.key).is_some_and(|state| state.strong_count() == 0) {
+            states.remove(&self.key);
+        }
+    }
+}
+
+/// Keeps the subscriptions made by `observe_subdoc_updates` alive together.
+struct SubdocUpdateSubscriptions {
+    _subdocs: Subscription,
+    updates: Arc<Mutex<HashMap<String, Subscription>>>,
+}
+
+impl Drop for SubdocUpdateSubscriptions {
+    fn drop(&mut self) {
+        self.updates.lock().clear();
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub(crate) struct YrsOrigin(Arc<[u8]>);
 
 impl From<Origin> for YrsOrigin {
@@ -238,9 +524,9 @@ impl From<Origin> for YrsOrigin {
     }
 }
 
-impl Into<Origin> for YrsOrigin {
-    fn into(self) -> Origin {
-        Origin::from(self.0.as_ref())
+impl From<YrsOrigin> for Origin {
+    fn from(value: YrsOrigin) -> Self {
+        Origin::from(value.0.as_ref())
     }
 }
 
@@ -256,7 +542,7 @@ impl UniffiCustomTypeConverter for YrsOrigin {
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 #[repr(transparent)]
 pub(crate) struct YrsCollectionPtr(*const Branch);
 
@@ -290,3 +576,261 @@ impl UniffiCustomTypeConverter for YrsCollectionPtr {
         obj.0 as usize as u64
     }
 }
+
+/// Unique identifier of a block, made of the ID of the client that created it and
+/// that client's logical clock at the time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct YrsId {
+    pub client: u64,
+    pub clock: u32,
+}
+
+impl From<yrs::ID> for YrsId {
+    fn from(value: yrs::ID) -> Self {
+        YrsId {
+            client: value.client,
+            clock: value.clock,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::YrsDoc;
+    use crate::persistence::YrsPersistenceFailureDelegate;
+    use crate::subdoc::{
+        YrsDocOptions, YrsLoadedObservationDelegate, YrsSubdocLoadDelegate, YrsSubdocUpdateDelegate, YrsSubdocsEvent,
+        YrsSubdocsObservationDelegate,
+    };
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+
+    /// Serves subdocument content from an in-memory store of updates.
+    #[derive(Debug)]
+    struct Provider(Vec<u8>);
+
+    impl YrsSubdocLoadDelegate for Provider {
+        fn call(&self, subdoc: Arc<YrsDoc>) {
+            let txn = subdoc.transact(None);
+            txn.transaction_apply_update(self.0.clone()).unwrap();
+            txn.free();
+            subdoc.mark_loaded();
+        }
+    }
+
+    type SubdocsSummary = (Vec<(String, bool)>, Vec<String>, Option<Vec<u8>>);
+
+    #[derive(Debug, Default)]
+    struct Subdocs(Arc<Mutex<Vec<SubdocsSummary>>>);
+
+    impl YrsSubdocsObservationDelegate for Subdocs {
+        fn call(&self, event: YrsSubdocsEvent) {
+            let added = event.added.iter().map(|d| (d.guid.clone(), d.should_load)).collect();
+            let loaded = event.loaded.iter().map(|d| d.guid.clone()).collect();
+            let origin = event.origin.map(crate::UniffiCustomTypeConverter::from_custom);
+            self.0.lock().push((added, loaded, origin));
+        }
+    }
+
+    #[test]
+    fn subdocs_event_identifies_subdocs() {
+        let doc = YrsDoc::new();
+        let pages = doc.get_map("pages".to_string());
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let _subscription = doc.observe_subdocs(Box::new(Subdocs(events.clone())));
+
+        let page = YrsDoc::new();
+        let txn = doc.transact(Some(yrs::Origin::from("local").into()));
+        pages.insert_doc(&txn, "page".to_string(), &page);
+        txn.free();
+
+        assert_eq!(
+            *events.lock(),
+            vec![(vec![(page.guid(), true)], vec![page.guid()], Some(b"local".to_vec()))]
+        );
+    }
+
+    #[derive(Debug, Default)]
+    struct Updates(Arc<Mutex<Vec<String>>>);
+
+    impl YrsSubdocUpdateDelegate for Updates {
+        fn call(&self, guid: String, _update: Vec<u8>) {
+            self.0.lock().push(guid);
+        }
+    }
+
+    #[test]
+    fn subdoc_updates_are_tagged_with_guid() {
+        let doc = YrsDoc::new();
+        let pages = doc.get_map("pages".to_string());
+        let txn = doc.transact(None);
+        let existing = pages.insert_doc(&txn, "existing".to_string(), &YrsDoc::new());
+        txn.free();
+
+        let updates = Arc::new(Mutex::new(Vec::new()));
+        let subscription = doc.observe_subdoc_updates(Box::new(Updates(updates.clone())));
+
+        let txn = doc.transact(None);
+        let added = pages.insert_doc(&txn, "added".to_string(), &YrsDoc::new());
+        txn.free();
+
+        for page in [&existing, &added] {
+            let text = page.get_text("content".to_string());
+            let txn = page.transact(None);
+            text.insert(&txn, 0, "hello".to_string()).unwrap();
+            txn.free();
+        }
+        assert_eq!(*updates.lock(), vec![existing.guid(), added.guid()]);
+
+        subscription.cancel();
+        let text = added.get_text("content".to_string());
+        let txn = added.transact(None);
+        text.insert(&txn, 0, "ignored".to_string()).unwrap();
+        txn.free();
+        assert_eq!(updates.lock().len(), 2);
+    }
+
+    #[derive(Debug, Default)]
+    struct Loaded(Arc<Mutex<u32>>);
+
+    impl YrsLoadedObservationDelegate for Loaded {
+        fn call(&self) {
+            *self.0.lock() += 1;
+        }
+    }
+
+    #[test]
+    fn subdoc_load_state_is_observable() {
+        let source = YrsDoc::new();
+        let pages = source.get_map("pages".to_string());
+        let txn = source.transact(None);
+        pages.insert_doc(&txn, "page".to_string(), &YrsDoc::new());
+        let update = txn.transaction_encode_state_as_update();
+        txn.free();
+
+        let doc = YrsDoc::new();
+        assert!(doc.is_loaded());
+        assert!(doc.observe_loaded(Box::new(Loaded::default())).is_none());
+
+        let pages = doc.get_map("pages".to_string());
+        let txn = doc.transact(None);
+        txn.transaction_apply_update(update).unwrap();
+        txn.free();
+        let txn = doc.transact(None);
+        let page = pages.get_doc(&txn, "page".to_string()).unwrap();
+        txn.free();
+        assert!(!page.is_loaded());
+
+        let loads = Arc::new(Mutex::new(0));
+        let _subscription = page.observe_loaded(Box::new(Loaded(loads.clone()))).unwrap();
+        let txn = doc.transact(None);
+        page.load(&txn);
+        txn.free();
+        // Requested, but the content hasn't arrived yet.
+        assert!(!page.is_loaded());
+        assert_eq!(*loads.lock(), 0);
+
+        // Marking any handle of the subdocument loads all of them.
+        let txn = doc.transact(None);
+        pages.get_doc(&txn, "page".to_string()).unwrap().mark_loaded();
+        txn.free();
+        assert!(page.is_loaded());
+        assert_eq!(*loads.lock(), 1);
+
+        page.mark_loaded();
+        assert_eq!(*loads.lock(), 1);
+
+        // The subdocument stays loaded while no handle to it exists.
+        drop(page);
+        let txn = doc.transact(None);
+        let page = pages.get_doc(&txn, "page".to_string()).unwrap();
+        txn.free();
+        assert!(page.is_loaded());
+    }
+
+    #[test]
+    fn load_requests_let_provider_fill_subdocs() {
+        let page = YrsDoc::new();
+        let text = page.get_text("content".to_string());
+        let txn = page.transact(None);
+        text.insert(&txn, 0, "hello".to_string()).unwrap();
+        let stored = txn.transaction_encode_state_as_update();
+        txn.free();
+
+        // A reference to the page with the same guid, but none of its content.
+        let reference = YrsDoc::new_with_options(YrsDocOptions {
+            auto_load: false,
+            client_id: None,
+            guid: Some(page.guid()),
+            should_load: false,
+            skip_gc: false,
+        });
+        let source = YrsDoc::new();
+        let pages = source.get_map("pages".to_string());
+        let txn = source.transact(None);
+        pages.insert_doc(&txn, "page".to_string(), &reference);
+        let update = txn.transaction_encode_state_as_update();
+        txn.free();
+
+        let doc = YrsDoc::new();
+        let pages = doc.get_map("pages".to_string());
+        let _subscription = doc.observe_load_requests(Box::new(Provider(stored)));
+        let txn = doc.transact(None);
+        txn.transaction_apply_update(update).unwrap();
+        let subdoc = pages.get_doc(&txn, "page".to_string()).unwrap();
+        assert!(!subdoc.should_load());
+        subdoc.load(&txn);
+        txn.free();
+
+        let content = subdoc.get_text("content".to_string());
+        let txn = subdoc.transact(None);
+        assert_eq!(content.get_string(&txn), "hello");
+        txn.free();
+        assert!(subdoc.is_loaded());
+    }
+
+    #[derive(Debug, Default, Clone)]
+    struct Failures(Arc<Mutex<Vec<(String, String)>>>);
+
+    impl YrsPersistenceFailureDelegate for Failures {
+        fn call(&self, guid: String, message: String) {
+            self.0.lock().push((guid, message));
+        }
+    }
+
+    #[test]
+    fn load_from_restores_and_keeps_storing_updates() {
+        let directory = std::env::temp_dir().join(format!("yniffi-{}-load-from", std::process::id()));
+        let persistence = Arc::new(
+            crate::persistence::YrsPersistence::with_directory(directory.to_string_lossy().into_owned())
+                .unwrap(),
+        );
+        let failures = Failures::default();
+
+        let doc = YrsDoc::load_from(persistence.clone(), "notes".to_string(), Box::new(failures.clone())).unwrap();
+        assert_eq!(doc.guid(), "notes");
+        let text = doc.get_text("text".to_string());
+        let txn = doc.transact(None);
+        text.insert(&txn, 0, "hello".to_string()).unwrap();
+        txn.free();
+        drop(doc);
+
+        let doc = YrsDoc::load_from(persistence.clone(), "notes".to_string(), Box::new(failures.clone())).unwrap();
+        let text = doc.get_text("text".to_string());
+        let txn = doc.transact(None);
+        assert_eq!(text.get_string(&txn), "hello");
+        text.insert(&txn, 5, " world".to_string()).unwrap();
+        txn.free();
+        assert_eq!(persistence.updates("notes".to_string()).unwrap().len(), 2);
+        assert!(failures.0.lock().is_empty());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+        let txn = doc.transact(None);
+        text.append(&txn, "!".to_string());
+        txn.free();
+        let failures = failures.0.lock();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, "notes");
+        assert!(failures[0].1.starts_with("Storage failed"));
+    }
+}
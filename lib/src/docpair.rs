@@ -0,0 +1,283 @@
+use crate::doc::YrsDoc;
+use parking_lot::{Condvar, Mutex};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use yrs::updates::decoder::Decode;
+use yrs::{Doc, Origin, Subscription, Transact, Update};
+
+/// How updates travel between the documents of a pair.
+pub(crate) struct YrsDocPairOptions {
+    /// Delay, in milliseconds, before an update reaches the other document.
+    pub latency_millis: u64,
+    /// Whether each update gets a random delay up to `latency_millis` instead, so
+    /// updates can arrive in a different order than they were made.
+    pub reorder: bool,
+    /// Seed of the random delays, so that a reordering can be reproduced.
+    pub seed: u64,
+}
+
+/// An update on its way to a document.
+struct Pending {
+    due: Instant,
+    target: Doc,
+    update: Vec<u8>,
+}
+
+struct Link {
+    origin: Origin,
+    latency: Duration,
+    reorder: bool,
+    state: Mutex<LinkState>,
+    /// Wakes the delivery thread when an update is sent or the pair is dropped.
+    wake: Condvar,
+}
+
+struct LinkState {
+    pending: Vec<Pending>,
+    random: u64,
+    /// Set once the pair is dropped, which stops the delivery thread.
+    closed: bool,
+}
+
+impl Link {
+    fn send(&self, target: &Doc, update: Vec<u8>) {
+        if self.latency.is_zero() {
+            self.deliver(target, &update);
+            return;
+        }
+        let mut state = self.state.lock();
+        let delay = if self.reorder {
+            // xorshift64, good enough to shuffle test traffic.
+            state.random ^= state.random << 13;
+            state.random ^= state.random >> 7;
+            state.random ^= state.random << 17;
+            let millis = self.latency.as_millis() as u64;
+            Duration::from_millis(state.random % (millis + 1))
+        } else {
+            self.latency
+        };
+        state.pending.push(Pending {
+            due: Instant::now() + delay,
+            target: target.clone(),
+            update,
+        });
+        self.wake.notify_one();
+    }
+
+    /// Delivers the pending updates that are due, or all of them, in order of arrival.
+    fn deliver_pending(&self, all: bool) {
+        let now = Instant::now();
+        let mut due = {
+            let mut state = self.state.lock();
+            let pending = std::mem::take(&mut state.pending);
+            let (due, waiting) = pending.into_iter().partition(|p| all || p.due <= now);
+            state.pending = waiting;
+            due
+        };
+        due.sort_by_key(|p: &Pending| p.due);
+        for pending in due {
+            self.deliver(&pending.target, &pending.update);
+        }
+    }
+
+    fn deliver(&self, target: &Doc, update: &[u8]) {
+        // Updates come from the other document, so they always decode and apply.
+        if let Ok(update) = Update::decode_v1(update) {
+            let _ = target.transact_mut_with(self.origin.clone()).apply_update(update);
+        }
+    }
+}
+
+/// Two documents exchanging their updates in memory, as if connected through a
+/// server, for tests of conflict resolution and convergence. Updates can be delayed
+/// and reordered.
+///
+/// Without latency, an update is applied to the other document while the transaction
+/// that made it commits, so that document must not have a transaction open then.
+pub(crate) struct YrsDocPair {
+    link: Arc<Link>,
+    _subscriptions: [Subscription; 2],
+}
+
+impl YrsDocPair {
+    pub(crate) fn new(left: &YrsDoc, right: &YrsDoc, options: YrsDocPairOptions) -> Self {
+        let link = Arc::new(Link {
+            origin: Origin::from("yniffi-doc-pair"),
+            latency: Duration::from_millis(options.latency_millis),
+            reorder: options.reorder,
+            state: Mutex::new(LinkState {
+                pending: Vec::new(),
+                // xorshift needs a non-zero state.
+                random: options.seed | 1,
+                closed: false,
+            }),
+            wake: Condvar::new(),
+        });
+        let connect = |from: Doc, to: Doc| {
+            let link = link.clone();
+            from.observe_update_v1(move |txn, event| {
+                if txn.origin() != Some(&link.origin) {
+                    link.send(&to, event.update.clone());
+                }
+            })
+            .expect("Failed to observe updates")
+        };
+        let subscriptions = [
+            connect(left.inner(), right.inner()),
+            connect(right.inner(), left.inner()),
+        ];
+        if !link.latency.is_zero() {
+            let link = link.clone();
+            std::thread::Builder::new()
+                .name("yniffi-doc-pair".to_string())
+                .spawn(move || deliver_until_closed(link))
+                .expect("Failed to spawn doc pair thread");
+        }
+        YrsDocPair {
+            link,
+            _subscriptions: subscriptions,
+        }
+    }
+
+    /// Returns the number of updates on their way.
+    pub(crate) fn pending(&self) -> u32 {
+        self.link.state.lock().pending.len() as u32
+    }
+
+    /// Delivers every update on its way right away, in the order they are due.
+    pub(crate) fn flush(&self) {
+        self.link.deliver_pending(true);
+    }
+}
+
+impl Drop for YrsDocPair {
+    fn drop(&mut self) {
+        self.link.state.lock().closed = true;
+        self.link.wake.notify_all();
+    }
+}
+
+/// Sleeps until the earliest pending update is due, then delivers the due updates,
+/// until the pair is dropped.
+fn deliver_until_closed(link: Arc<Link>) {
+    loop {
+        {
+            let mut state = link.state.lock();
+            if state.closed {
+                return;
+            }
+            match state.pending.iter().map(|p| p.due).min() {
+                None => {
+                    link.wake.wait(&mut state);
+                    continue;
+                }
+                Some(due) if due > Instant::now() => {
+                    link.wake.wait_until(&mut state, due);
+                    continue;
+                }
+                Some(_) => {}
+            }
+        }
+        link.deliver_pending(false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{YrsDocPair, YrsDocPairOptions};
+    use crate::doc::YrsDoc;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    fn insert(doc: &YrsDoc, index: u32, content: &str) {
+        let text = doc.get_text("text".to_string());
+        let txn = doc.transact(None);
+        text.insert(&txn, index, content.to_string()).unwrap();
+        txn.free();
+    }
+
+    fn content(doc: &YrsDoc) -> String {
+        let text = doc.get_text("text".to_string());
+        let txn = doc.transact(None);
+        let content = text.get_string(&txn);
+        txn.free();
+        content
+    }
+
+    #[test]
+    fn updates_reach_other_document_right_away() {
+        let (left, right) = (YrsDoc::new(), YrsDoc::new());
+        let options = YrsDocPairOptions {
+            latency_millis: 0,
+            reorder: false,
+            seed: 0,
+        };
+        let _pair = YrsDocPair::new(&left, &right, options);
+        insert(&left, 0, "left");
+        assert_eq!(content(&right), "left");
+        insert(&right, 4, " right");
+        assert_eq!(content(&left), "left right");
+    }
+
+    #[test]
+    fn concurrent_edits_converge_despite_reordering() {
+        let (left, right) = (YrsDoc::new(), YrsDoc::new());
+        let options = YrsDocPairOptions {
+            latency_millis: 60_000,
+            reorder: true,
+            seed: 7,
+        };
+        let pair = YrsDocPair::new(&left, &right, options);
+        for i in 0..5 {
+            insert(&left, 0, &format!("l{i}"));
+            insert(&right, 0, &format!("r{i}"));
+        }
+        assert_eq!(pair.pending(), 10);
+        assert_eq!(content(&left).len(), 10);
+
+        pair.flush();
+        assert_eq!(pair.pending(), 0);
+        assert_eq!(content(&left), content(&right));
+        assert_eq!(content(&left).len(), 20);
+    }
+
+    #[test]
+    fn dropping_the_pair_stops_delivery() {
+        let (left, right) = (YrsDoc::new(), YrsDoc::new());
+        let options = YrsDocPairOptions {
+            latency_millis: 60_000,
+            reorder: false,
+            seed: 0,
+        };
+        let pair = YrsDocPair::new(&left, &right, options);
+        insert(&left, 0, "hello");
+        let link = Arc::downgrade(&pair.link);
+        drop(pair);
+
+        // The delivery thread lets go of the link once it noticed the pair is gone.
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while link.strong_count() > 0 {
+            assert!(Instant::now() < deadline, "delivery thread kept running");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(content(&right), "");
+    }
+
+    #[test]
+    fn delayed_updates_arrive_on_their_own() {
+        let (left, right) = (YrsDoc::new(), YrsDoc::new());
+        let options = YrsDocPairOptions {
+            latency_millis: 5,
+            reorder: false,
+            seed: 0,
+        };
+        let pair = YrsDocPair::new(&left, &right, options);
+        insert(&left, 0, "hello");
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while pair.pending() > 0 {
+            assert!(std::time::Instant::now() < deadline, "update wasn't delivered");
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        assert_eq!(content(&right), "hello");
+    }
+}
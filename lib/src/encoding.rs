@@ -0,0 +1,117 @@
+use crate::error::CodingError;
+use parking_lot::Mutex;
+use yrs::encoding::read::{Cursor, Read};
+use yrs::encoding::write::Write;
+
+/// Writes values in the lib0 binary encoding used by Yjs message framing.
+pub(crate) struct YrsEncoder {
+    buf: Mutex<Vec<u8>>,
+}
+
+impl YrsEncoder {
+    pub(crate) fn new() -> Self {
+        YrsEncoder {
+            buf: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub(crate) fn write_var_uint(&self, value: u64) {
+        self.buf.lock().write_var(value);
+    }
+
+    /// Writes a string as its var-uint byte length followed by its UTF-8 bytes.
+    pub(crate) fn write_var_string(&self, value: String) {
+        self.buf.lock().write_string(&value);
+    }
+
+    /// Writes bytes prefixed with their var-uint length.
+    pub(crate) fn write_var_uint8_array(&self, value: Vec<u8>) {
+        self.buf.lock().write_buf(value);
+    }
+
+    /// Returns everything written so far.
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        self.buf.lock().clone()
+    }
+}
+
+/// Reads values in the lib0 binary encoding used by Yjs message framing, one after
+/// another. A failed read leaves the position unchanged.
+pub(crate) struct YrsDecoder {
+    buf: Vec<u8>,
+    position: Mutex<usize>,
+}
+
+impl YrsDecoder {
+    pub(crate) fn new(data: Vec<u8>) -> Self {
+        YrsDecoder {
+            buf: data,
+            position: Mutex::new(0),
+        }
+    }
+
+    pub(crate) fn read_var_uint(&self) -> Result<u64, CodingError> {
+        self.read(|cursor| cursor.read_var())
+    }
+
+    pub(crate) fn read_var_string(&self) -> Result<String, CodingError> {
+        self.read(|cursor| cursor.read_string().map(str::to_string))
+    }
+
+    pub(crate) fn read_var_uint8_array(&self) -> Result<Vec<u8>, CodingError> {
+        self.read(|cursor| cursor.read_buf().map(<[u8]>::to_vec))
+    }
+
+    /// Returns whether there are bytes left to read.
+    pub(crate) fn has_content(&self) -> bool {
+        *self.position.lock() < self.buf.len()
+    }
+
+    fn read<T, F>(&self, f: F) -> Result<T, CodingError>
+    where
+        F: FnOnce(&mut Cursor) -> Result<T, yrs::encoding::read::Error>,
+    {
+        let mut position = self.position.lock();
+        let mut cursor = Cursor::new(&self.buf);
+        cursor.next = *position;
+        let value = f(&mut cursor).map_err(|_e| CodingError::DecodingError)?;
+        *position = cursor.next;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{YrsDecoder, YrsEncoder};
+    use crate::error::CodingError;
+
+    #[test]
+    fn values_round_trip_in_lib0_encoding() {
+        let encoder = YrsEncoder::new();
+        encoder.write_var_uint(300);
+        encoder.write_var_string("hé".to_string());
+        encoder.write_var_uint8_array(vec![1, 2]);
+        let bytes = encoder.to_bytes();
+        // 300 as a var-uint, "hé" as its UTF-8 length and bytes, then the array.
+        assert_eq!(bytes, vec![0xac, 0x02, 3, b'h', 0xc3, 0xa9, 2, 1, 2]);
+
+        let decoder = YrsDecoder::new(bytes);
+        assert_eq!(decoder.read_var_uint().unwrap(), 300);
+        assert_eq!(decoder.read_var_string().unwrap(), "hé");
+        assert!(decoder.has_content());
+        assert_eq!(decoder.read_var_uint8_array().unwrap(), vec![1, 2]);
+        assert!(!decoder.has_content());
+    }
+
+    #[test]
+    fn failed_read_keeps_position() {
+        let decoder = YrsDecoder::new(vec![5, b'a']);
+        assert!(matches!(
+            decoder.read_var_string(),
+            Err(CodingError::DecodingError)
+        ));
+        assert_eq!(decoder.read_var_uint().unwrap(), 5);
+        assert_eq!(decoder.read_var_uint().unwrap(), u64::from(b'a'));
+        assert!(decoder.read_var_uint().is_err());
+    }
+}
@@ -0,0 +1,59 @@
+use parking_lot::RwLock;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+/// Encrypts updates before they are stored or sent, and decrypts them once read or
+/// received, e.g. with a key shared by the collaborators of a document.
+pub(crate) trait YrsEncryptionDelegate: Send + Sync + Debug {
+    fn encrypt(&self, data: Vec<u8>) -> Vec<u8>;
+    /// Returns nothing when `data` can't be decrypted, e.g. because it was encrypted
+    /// with another key.
+    fn decrypt(&self, data: Vec<u8>) -> Option<Vec<u8>>;
+}
+
+/// The encryption delegate of a subsystem, if one was set. Clones share the same
+/// delegate, so setting it later reaches everything the subsystem handed a clone to.
+/// Updates pass through unchanged while no delegate is set.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Encryption(Arc<RwLock<Option<Arc<dyn YrsEncryptionDelegate>>>>);
+
+impl Encryption {
+    pub(crate) fn set(&self, delegate: Box<dyn YrsEncryptionDelegate>) {
+        *self.0.write() = Some(Arc::from(delegate));
+    }
+
+    pub(crate) fn encrypt(&self, data: Vec<u8>) -> Vec<u8> {
+        match self.0.read().as_ref() {
+            Some(delegate) => delegate.encrypt(data),
+            None => data,
+        }
+    }
+
+    pub(crate) fn decrypt(&self, data: Vec<u8>) -> Option<Vec<u8>> {
+        match self.0.read().as_ref() {
+            Some(delegate) => delegate.decrypt(data),
+            None => Some(data),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::YrsEncryptionDelegate;
+
+    /// Toy cipher for tests: XORs every byte with the key, which is also prepended so
+    /// that decrypting with another key fails.
+    #[derive(Debug, Clone, Copy)]
+    pub(crate) struct XorCipher(pub(crate) u8);
+
+    impl YrsEncryptionDelegate for XorCipher {
+        fn encrypt(&self, data: Vec<u8>) -> Vec<u8> {
+            std::iter::once(self.0).chain(data.into_iter().map(|b| b ^ self.0)).collect()
+        }
+
+        fn decrypt(&self, data: Vec<u8>) -> Option<Vec<u8>> {
+            let (&key, data) = data.split_first()?;
+            (key == self.0).then(|| data.iter().map(|b| b ^ self.0).collect())
+        }
+    }
+}
@@ -4,4 +4,13 @@ pub enum CodingError {
     EncodingError,
     #[error("DecodingError")]
     DecodingError,
+    /// No element exists at the requested array index or map key.
+    #[error("OutOfBounds")]
+    OutOfBounds,
+    /// The stored element is a shared type rather than a JSON-encodable value.
+    #[error("UnexpectedType")]
+    UnexpectedType,
+    /// Content needed to answer was removed from the document by garbage collection.
+    #[error("ContentCollected")]
+    ContentCollected,
 }
@@ -0,0 +1,91 @@
+use crate::change::YrsChange;
+use crate::delta::YrsDelta;
+use crate::doc::YrsOrigin;
+use crate::mapchange::{from_entry_change, YrsMapChange};
+use crate::path::{path_segments, YrsPathSegment};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+use yrs::types::{EntryChange, Event};
+use yrs::TransactionMut;
+
+/// A change to one of the shared types reported by a deep observer. The path leads
+/// from the observed type to the type that changed, and is empty for changes made
+/// to the observed type itself.
+pub(crate) enum YrsEvent {
+    Array {
+        path: Vec<YrsPathSegment>,
+        changes: Vec<YrsChange>,
+    },
+    Map {
+        path: Vec<YrsPathSegment>,
+        changes: Vec<YrsMapChange>,
+    },
+    Text {
+        path: Vec<YrsPathSegment>,
+        delta: Vec<YrsDelta>,
+    },
+    /// A change to the children or attributes of an XML fragment or element.
+    Xml {
+        path: Vec<YrsPathSegment>,
+        changes: Vec<YrsChange>,
+        attributes: Vec<YrsMapChange>,
+    },
+    /// A change to the content or attributes of an XML text node.
+    XmlText {
+        path: Vec<YrsPathSegment>,
+        delta: Vec<YrsDelta>,
+        attributes: Vec<YrsMapChange>,
+    },
+    /// A change to a weak link, which only reports where it happened.
+    Other { path: Vec<YrsPathSegment> },
+}
+
+impl YrsEvent {
+    pub(crate) fn new(txn: &TransactionMut, event: &Event) -> Self {
+        let path = path_segments(event.path());
+        match event {
+            Event::Array(e) => YrsEvent::Array {
+                path,
+                changes: e.delta(txn).iter().map(YrsChange::from).collect(),
+            },
+            Event::Map(e) => YrsEvent::Map {
+                path,
+                changes: e
+                    .keys(txn)
+                    .iter()
+                    .map(|(key, change)| from_entry_change(key, change))
+                    .collect(),
+            },
+            Event::Text(e) => YrsEvent::Text {
+                path,
+                delta: e.delta(txn).iter().map(YrsDelta::from).collect(),
+            },
+            Event::XmlFragment(e) => YrsEvent::Xml {
+                path,
+                changes: e.delta(txn).iter().map(YrsChange::from).collect(),
+                attributes: attribute_changes(e.keys(txn)),
+            },
+            Event::XmlText(e) => YrsEvent::XmlText {
+                path,
+                delta: e.delta(txn).iter().map(YrsDelta::from).collect(),
+                attributes: attribute_changes(e.keys(txn)),
+            },
+            _ => YrsEvent::Other { path },
+        }
+    }
+}
+
+/// Converts the attribute changes of an XML event, sorted by attribute name.
+pub(crate) fn attribute_changes(keys: &HashMap<Arc<str>, EntryChange>) -> Vec<YrsMapChange> {
+    let mut changes: Vec<YrsMapChange> = keys
+        .iter()
+        .map(|(key, change)| from_entry_change(key, change))
+        .collect();
+    changes.sort_by(|a, b| a.key.cmp(&b.key));
+    changes
+}
+
+pub(crate) trait YrsDeepObservationDelegate: Send + Sync + Debug {
+    fn call(&self, events: Vec<YrsEvent>, origin: Option<YrsOrigin>);
+}
@@ -0,0 +1,397 @@
+use crate::persistence::{UpdateStore, YrsPersistenceError};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+/// Keeps the updates of each document in an append-only log file inside a directory,
+/// next to a snapshot file holding the compacted older updates, and a file holding
+/// the state vector. Files are named after the hex encoded
+/// document guid, so any guid makes a valid file name.
+///
+/// Every record is framed as its length and CRC-32 checksum, both little-endian u32,
+/// followed by its bytes. Reading stops at the first truncated or corrupted record,
+/// which is what an interrupted append leaves behind, and the next append overwrites it.
+/// A log is only validated the first time it's appended to or counted, after which its
+/// valid records are tracked, so neither reads the log again.
+pub(crate) struct FileStore {
+    directory: PathBuf,
+    logs: Mutex<HashMap<String, Log>>,
+}
+
+/// The valid records of a log validated since the store was opened.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Log {
+    /// Length of the valid records, after which an interrupted append may have left bytes.
+    len: u64,
+    records: usize,
+}
+
+impl FileStore {
+    pub(crate) fn open(path: String) -> Result<Self, YrsPersistenceError> {
+        let directory = PathBuf::from(path);
+        fs::create_dir_all(&directory)?;
+        Ok(FileStore {
+            directory,
+            logs: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn path(&self, guid: &str, extension: &str) -> PathBuf {
+        let name: String = guid.bytes().map(|b| format!("{b:02x}")).collect();
+        self.directory.join(format!("{name}.{extension}"))
+    }
+
+    fn validate_log(&self, guid: &str) -> Result<Log, YrsPersistenceError> {
+        let (records, len) = read_records(&read(self.path(guid, "log"))?);
+        Ok(Log {
+            len: len as u64,
+            records: records.len(),
+        })
+    }
+
+    /// Replaces a file with the given records, through a temporary file so that
+    /// readers see either the old or the new content.
+    fn write_file(&self, path: PathBuf, records: &[&[u8]]) -> Result<(), YrsPersistenceError> {
+        let temporary = path.with_extension("tmp");
+        let mut file = File::create(&temporary)?;
+        for record in records {
+            file.write_all(&frame(record))?;
+        }
+        file.sync_all()?;
+        fs::rename(temporary, path)?;
+        Ok(())
+    }
+}
+
+impl UpdateStore for FileStore {
+    fn updates(&self, guid: &str) -> Result<Vec<Vec<u8>>, YrsPersistenceError> {
+        let mut updates = read_records(&read(self.path(guid, "snapshot"))?).0;
+        updates.extend(read_records(&read(self.path(guid, "log"))?).0);
+        Ok(updates)
+    }
+
+    fn append(&self, guid: &str, update: Vec<u8>) -> Result<(), YrsPersistenceError> {
+        let mut logs = self.logs.lock();
+        // Forgotten until the append succeeds, so that a failed one gets validated again.
+        let log = match logs.remove(guid) {
+            Some(log) => log,
+            None => self.validate_log(guid)?,
+        };
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(self.path(guid, "log"))?;
+        // Drops whatever an interrupted append left after the last valid record.
+        file.set_len(log.len)?;
+        file.seek(SeekFrom::Start(log.len))?;
+        let record = frame(&update);
+        file.write_all(&record)?;
+        file.sync_data()?;
+        let log = Log {
+            len: log.len + record.len() as u64,
+            records: log.records + 1,
+        };
+        logs.insert(guid.to_string(), log);
+        Ok(())
+    }
+
+    fn replace(&self, guid: &str, update: Vec<u8>) -> Result<(), YrsPersistenceError> {
+        // The log is emptied once the snapshot is in place, so nothing is lost if the
+        // app stops in between. Applying updates twice is harmless.
+        self.write_file(self.path(guid, "snapshot"), &[&update])?;
+        let mut logs = self.logs.lock();
+        logs.remove(guid);
+        match OpenOptions::new().write(true).open(self.path(guid, "log")) {
+            Ok(file) => {
+                file.set_len(0)?;
+                file.sync_all()?;
+                logs.insert(guid.to_string(), Log { len: 0, records: 0 });
+                Ok(())
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn len(&self, guid: &str) -> Result<usize, YrsPersistenceError> {
+        let mut logs = self.logs.lock();
+        let log = match logs.get(guid) {
+            Some(&log) => log,
+            None => {
+                let log = self.validate_log(guid)?;
+                logs.insert(guid.to_string(), log);
+                log
+            }
+        };
+        // The snapshot holds a single record, written as a whole by `replace`.
+        let snapshot = match fs::metadata(self.path(guid, "snapshot")) {
+            Ok(metadata) => usize::from(metadata.len() > 0),
+            Err(e) if e.kind() == ErrorKind::NotFound => 0,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(snapshot + log.records)
+    }
+
+    fn state_vector(&self, guid: &str) -> Result<Option<Vec<u8>>, YrsPersistenceError> {
+        Ok(read_records(&read(self.path(guid, "sv"))?).0.pop())
+    }
+
+    fn set_state_vector(
+        &self,
+        guid: &str,
+        state_vector: Vec<u8>,
+    ) -> Result<(), YrsPersistenceError> {
+        self.write_file(self.path(guid, "sv"), &[&state_vector])
+    }
+
+    fn clear(&self, guid: &str) -> Result<(), YrsPersistenceError> {
+        self.logs.lock().remove(guid);
+        for extension in ["log", "snapshot", "sv"] {
+            match fs::remove_file(self.path(guid, extension)) {
+                Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads a file, which is empty when it doesn't exist.
+fn read(path: PathBuf) -> Result<Vec<u8>, YrsPersistenceError> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(bytes),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn frame(record: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(record.len() + 8);
+    buf.extend_from_slice(&(record.len() as u32).to_le_bytes());
+    buf.extend_from_slice(&crc32(record).to_le_bytes());
+    buf.extend_from_slice(record);
+    buf
+}
+
+/// Returns the valid records of a file, along with the length they span.
+fn read_records(bytes: &[u8]) -> (Vec<Vec<u8>>, usize) {
+    let mut records = Vec::new();
+    let mut offset = 0;
+    while let Some(header) = bytes.get(offset..offset + 8) {
+        let len = u32::from_le_bytes(header[0..4].try_into().unwrap()) as usize;
+        let checksum = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        let Some(record) = bytes.get(offset + 8..offset + 8 + len) else {
+            break;
+        };
+        if crc32(record) != checksum {
+            break;
+        }
+        records.push(record.to_vec());
+        offset += 8 + len;
+    }
+    (records, offset)
+}
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// CRC-32 as used by zlib and PNG.
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, &b| {
+        CRC32_TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc32, frame, read_records, FileStore, Log};
+    use crate::persistence::{UpdateStore, YrsPersistence};
+    use std::fs;
+    use std::path::PathBuf;
+    use yrs::{GetString, Text, Transact};
+
+    /// Returns an empty directory for a test, removed again when dropped.
+    struct TestDirectory(PathBuf);
+
+    impl TestDirectory {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("yniffi-{}-{name}", std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            TestDirectory(path)
+        }
+
+        fn path(&self) -> String {
+            self.0.to_string_lossy().into_owned()
+        }
+    }
+
+    impl Drop for TestDirectory {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn updates(count: usize) -> Vec<Vec<u8>> {
+        let doc = yrs::Doc::with_client_id(1);
+        let text = doc.get_or_insert_text("text");
+        (0..count)
+            .map(|i| {
+                let mut txn = doc.transact_mut();
+                text.insert(&mut txn, i as u32, "a");
+                txn.encode_update_v1()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn crc32_matches_reference_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn updates_survive_reopening_directory() {
+        let directory = TestDirectory::new("reopen");
+        let persistence = YrsPersistence::with_directory(directory.path()).unwrap();
+        for update in updates(3) {
+            persistence
+                .store_update("doc/1".to_string(), update)
+                .unwrap();
+        }
+        let state_vector = persistence.state_vector("doc/1".to_string()).unwrap();
+        drop(persistence);
+
+        let persistence = YrsPersistence::with_directory(directory.path()).unwrap();
+        assert_eq!(
+            persistence.updates("doc/1".to_string()).unwrap(),
+            updates(3)
+        );
+        assert_eq!(
+            persistence.state_vector("doc/1".to_string()).unwrap(),
+            state_vector
+        );
+        let doc = crate::doc::YrsDoc::new();
+        persistence.load("doc/1".to_string(), &doc).unwrap();
+        let text = doc.inner().get_or_insert_text("text");
+        assert_eq!(text.get_string(&doc.inner().transact()), "aaa");
+
+        persistence.clear("doc/1".to_string()).unwrap();
+        assert!(persistence.updates("doc/1".to_string()).unwrap().is_empty());
+        assert_eq!(fs::read_dir(&directory.0).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn interrupted_append_is_dropped() {
+        let directory = TestDirectory::new("torn");
+        let persistence = YrsPersistence::with_directory(directory.path()).unwrap();
+        let updates = updates(2);
+        persistence
+            .store_update("doc".to_string(), updates[0].clone())
+            .unwrap();
+
+        // Half of a record, as left by a crash in the middle of an append.
+        let log = fs::read_dir(&directory.0)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| path.extension().is_some_and(|e| e == "log"))
+            .unwrap();
+        let torn = frame(&updates[1]);
+        let mut bytes = fs::read(&log).unwrap();
+        bytes.extend_from_slice(&torn[..torn.len() / 2]);
+        fs::write(&log, bytes).unwrap();
+        assert_eq!(
+            persistence.updates("doc".to_string()).unwrap(),
+            vec![updates[0].clone()]
+        );
+
+        persistence
+            .store_update("doc".to_string(), updates[1].clone())
+            .unwrap();
+        assert_eq!(persistence.updates("doc".to_string()).unwrap(), updates);
+    }
+
+    #[test]
+    fn log_is_validated_once() {
+        let directory = TestDirectory::new("lens");
+        let updates = updates(3);
+        let store = FileStore::open(directory.path()).unwrap();
+        let log = store.path("doc", "log");
+        let torn = frame(&updates[1]);
+        let mut bytes = frame(&updates[0]);
+        bytes.extend_from_slice(&torn[..torn.len() / 2]);
+        fs::write(&log, bytes).unwrap();
+
+        assert_eq!(store.len("doc").unwrap(), 1);
+        store.append("doc", updates[1].clone()).unwrap();
+        store.append("doc", updates[2].clone()).unwrap();
+        let len = fs::metadata(&log).unwrap().len();
+        assert_eq!(store.logs.lock().get("doc"), Some(&Log { len, records: 3 }));
+        assert_eq!(store.len("doc").unwrap(), 3);
+        assert_eq!(store.updates("doc").unwrap(), updates);
+
+        store.replace("doc", updates[0].clone()).unwrap();
+        assert_eq!(store.len("doc").unwrap(), 1);
+        store.append("doc", updates[1].clone()).unwrap();
+        assert_eq!(store.len("doc").unwrap(), 2);
+        store.clear("doc").unwrap();
+        assert!(store.logs.lock().is_empty());
+        assert_eq!(store.len("doc").unwrap(), 0);
+    }
+
+    #[test]
+    fn corrupted_record_ends_log() {
+        let mut bytes = frame(b"first");
+        let mut second = frame(b"second");
+        second[10] ^= 0xff;
+        bytes.extend(second);
+        bytes.extend(frame(b"third"));
+        let (records, len) = read_records(&bytes);
+        assert_eq!(records, vec![b"first".to_vec()]);
+        assert_eq!(len, frame(b"first").len());
+    }
+
+    #[test]
+    fn compaction_writes_snapshot_and_empties_log() {
+        let directory = TestDirectory::new("compact");
+        let persistence = YrsPersistence::with_directory(directory.path()).unwrap();
+        let updates = updates(4);
+        for update in &updates[..3] {
+            persistence
+                .store_update("doc".to_string(), update.clone())
+                .unwrap();
+        }
+        persistence.compact("doc".to_string()).unwrap();
+        assert_eq!(persistence.updates("doc".to_string()).unwrap().len(), 1);
+        persistence
+            .store_update("doc".to_string(), updates[3].clone())
+            .unwrap();
+        assert_eq!(persistence.updates("doc".to_string()).unwrap().len(), 2);
+
+        let doc = crate::doc::YrsDoc::new();
+        persistence.load("doc".to_string(), &doc).unwrap();
+        let text = doc.inner().get_or_insert_text("text");
+        assert_eq!(text.get_string(&doc.inner().transact()), "aaaa");
+
+        persistence.clear("doc".to_string()).unwrap();
+        assert_eq!(fs::read_dir(&directory.0).unwrap().count(), 0);
+    }
+}
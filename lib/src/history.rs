@@ -0,0 +1,308 @@
+use crate::doc::YrsDoc;
+use crate::error::CodingError;
+use crate::snapshot::{materialize, YrsSnapshot};
+use parking_lot::Mutex;
+use std::sync::Arc;
+use yrs::sync::time::{Clock, SystemClock};
+use yrs::types::ToJson;
+use yrs::{Any, Doc, GetString, Out, ReadTxn, Subscription, Transact};
+
+/// Error that can occur when working with the version history of a document.
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum YrsVersionHistoryError {
+    #[error("Versions can only be restored from documents created with skip_gc")]
+    GcEnabled,
+    #[error("No version with id {id}")]
+    UnknownVersion { id: u32 },
+    #[error("Version {id} couldn't be restored")]
+    RestoreFailed { id: u32 },
+}
+
+/// When versions get recorded automatically. Zero disables a condition, and no
+/// version is recorded automatically when both are zero.
+pub(crate) struct YrsVersionPolicy {
+    /// Number of document updates after which a version is recorded.
+    pub every_updates: u32,
+    /// Minimum time, in milliseconds, between two automatically recorded versions.
+    pub min_interval_millis: u64,
+}
+
+/// A recorded version of a document.
+#[derive(Clone)]
+pub(crate) struct YrsVersion {
+    pub id: u32,
+    pub name: Option<String>,
+    /// When the version was recorded, in milliseconds since the Unix epoch.
+    pub timestamp_millis: u64,
+    pub snapshot: Arc<YrsSnapshot>,
+}
+
+/// A root collection whose contents differ between two versions, with both contents
+/// as JSON. Texts and XML fragments are reported as JSON strings.
+#[derive(Debug, PartialEq)]
+pub(crate) struct YrsCollectionDiff {
+    pub name: String,
+    pub before: String,
+    pub after: String,
+}
+
+#[derive(Default)]
+struct History {
+    versions: Vec<YrsVersion>,
+    next_id: u32,
+    /// Updates since the last recorded version.
+    pending_updates: u32,
+}
+
+impl History {
+    fn push(&mut self, name: Option<String>, snapshot: yrs::Snapshot) -> YrsVersion {
+        let version = YrsVersion {
+            id: self.next_id,
+            name,
+            timestamp_millis: SystemClock.now(),
+            snapshot: Arc::new(YrsSnapshot::from(snapshot)),
+        };
+        self.next_id += 1;
+        self.pending_updates = 0;
+        self.versions.push(version.clone());
+        version
+    }
+}
+
+/// Records snapshots of a document as named versions, either on demand or following
+/// a policy, and restores or compares them. The document has to be created with
+/// `skip_gc`, so that the content of past versions is kept around.
+pub(crate) struct YrsVersionHistory {
+    doc: Doc,
+    history: Arc<Mutex<History>>,
+    _subscription: Option<Subscription>,
+}
+
+impl YrsVersionHistory {
+    pub(crate) fn new(doc: &YrsDoc, policy: YrsVersionPolicy) -> Result<Self, YrsVersionHistoryError> {
+        let doc = doc.inner();
+        if !doc.skip_gc() {
+            return Err(YrsVersionHistoryError::GcEnabled);
+        }
+
+        let history = Arc::new(Mutex::new(History::default()));
+        let subscription = if policy.every_updates > 0 || policy.min_interval_millis > 0 {
+            let history = history.clone();
+            let subscription = doc
+                .observe_update_v1(move |txn, _event| {
+                    let mut history = history.lock();
+                    history.pending_updates += 1;
+                    let enough_updates = history.pending_updates >= policy.every_updates;
+                    let last = history.versions.last().map(|v| v.timestamp_millis);
+                    let enough_time = last.is_none_or(|last| {
+                        SystemClock.now().saturating_sub(last) >= policy.min_interval_millis
+                    });
+                    if enough_updates && enough_time {
+                        history.push(None, txn.snapshot());
+                    }
+                })
+                .expect("Failed to observe updates");
+            Some(subscription)
+        } else {
+            None
+        };
+
+        Ok(YrsVersionHistory {
+            doc,
+            history,
+            _subscription: subscription,
+        })
+    }
+
+    /// Records the current state of the document as a new version. Must not be called
+    /// while a transaction on the document is open.
+    pub(crate) fn record(&self, name: Option<String>) -> YrsVersion {
+        let snapshot = self.doc.transact().snapshot();
+        self.history.lock().push(name, snapshot)
+    }
+
+    /// Returns the recorded versions, oldest first.
+    pub(crate) fn versions(&self) -> Vec<YrsVersion> {
+        self.history.lock().versions.clone()
+    }
+
+    pub(crate) fn get(&self, id: u32) -> Option<YrsVersion> {
+        self.history.lock().versions.iter().find(|v| v.id == id).cloned()
+    }
+
+    /// Forgets a version. Returns whether it was recorded.
+    pub(crate) fn remove(&self, id: u32) -> bool {
+        let mut history = self.history.lock();
+        let len = history.versions.len();
+        history.versions.retain(|v| v.id != id);
+        history.versions.len() != len
+    }
+
+    /// Creates a new document holding the state of the document at the given version.
+    pub(crate) fn restore(&self, id: u32) -> Result<Arc<YrsDoc>, YrsVersionHistoryError> {
+        self.materialize(id).map(Arc::new)
+    }
+
+    /// Compares two versions and returns the root collections whose contents differ,
+    /// sorted by name. Collections that haven't been accessed on this document yet
+    /// can't be told apart by type and are left out.
+    pub(crate) fn diff(&self, from: u32, to: u32) -> Result<Vec<YrsCollectionDiff>, YrsVersionHistoryError> {
+        let before = self.materialize(from)?.inner();
+        let after = self.materialize(to)?.inner();
+
+        let mut roots: Vec<(String, Out)> = self
+            .doc
+            .transact()
+            .root_refs()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect();
+        roots.sort_by(|a, b| a.0.cmp(&b.0));
+
+        Ok(roots
+            .into_iter()
+            .filter_map(|(name, kind)| {
+                let before = root_json(&before, &name, &kind)?;
+                let after = root_json(&after, &name, &kind)?;
+                (before != after).then_some(YrsCollectionDiff { name, before, after })
+            })
+            .collect())
+    }
+
+    fn materialize(&self, id: u32) -> Result<YrsDoc, YrsVersionHistoryError> {
+        let version = self.get(id).ok_or(YrsVersionHistoryError::UnknownVersion { id })?;
+        materialize(&self.doc, version.snapshot.inner())
+            .map_err(|_e: CodingError| YrsVersionHistoryError::RestoreFailed { id })
+    }
+}
+
+/// Returns the contents of the root collection `name` of `doc` as JSON, reading it as
+/// the same type as `kind`.
+fn root_json(doc: &Doc, name: &str, kind: &Out) -> Option<String> {
+    let value = match kind {
+        Out::YText(_) => {
+            let text = doc.get_or_insert_text(name);
+            Any::from(text.get_string(&doc.transact()))
+        }
+        Out::YArray(_) => {
+            let array = doc.get_or_insert_array(name);
+            array.to_json(&doc.transact())
+        }
+        Out::YMap(_) => {
+            let map = doc.get_or_insert_map(name);
+            map.to_json(&doc.transact())
+        }
+        Out::YXmlFragment(_) => {
+            let fragment = doc.get_or_insert_xml_fragment(name);
+            Any::from(fragment.get_string(&doc.transact()))
+        }
+        _ => return None,
+    };
+    let mut buf = String::new();
+    value.to_json(&mut buf);
+    Some(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{YrsCollectionDiff, YrsVersionHistory, YrsVersionHistoryError, YrsVersionPolicy};
+    use crate::subdoc::YrsDocOptions;
+    use crate::YrsDoc;
+
+    fn doc_without_gc() -> YrsDoc {
+        YrsDoc::new_with_options(YrsDocOptions {
+            auto_load: false,
+            client_id: None,
+            guid: None,
+            should_load: true,
+            skip_gc: true,
+        })
+    }
+
+    fn manual() -> YrsVersionPolicy {
+        YrsVersionPolicy {
+            every_updates: 0,
+            min_interval_millis: 0,
+        }
+    }
+
+    #[test]
+    fn history_records_restores_and_diffs_versions() {
+        let doc = doc_without_gc();
+        let history = YrsVersionHistory::new(&doc, manual()).unwrap();
+        let text = doc.get_text("text".to_string());
+        let map = doc.get_map("map".to_string());
+
+        let txn = doc.transact(None);
+        text.insert(&txn, 0, "draft".to_string()).unwrap();
+        map.insert(&txn, "status".to_string(), "\"open\"".to_string());
+        txn.free();
+        let first = history.record(Some("first".to_string()));
+
+        let txn = doc.transact(None);
+        text.remove_range(&txn, 0, 5).unwrap();
+        text.insert(&txn, 0, "final".to_string()).unwrap();
+        txn.free();
+        let second = history.record(None);
+
+        let versions = history.versions();
+        assert_eq!(versions.len(), 2);
+        assert_eq!(versions[0].name.as_deref(), Some("first"));
+        assert!(versions[1].timestamp_millis >= versions[0].timestamp_millis);
+
+        let restored = history.restore(first.id).unwrap();
+        let restored_text = restored.get_text("text".to_string());
+        let txn = restored.transact(None);
+        assert_eq!(restored_text.get_string(&txn), "draft");
+        txn.free();
+
+        assert_eq!(
+            history.diff(first.id, second.id).unwrap(),
+            vec![YrsCollectionDiff {
+                name: "text".to_string(),
+                before: "\"draft\"".to_string(),
+                after: "\"final\"".to_string(),
+            }]
+        );
+
+        assert!(history.remove(first.id));
+        assert!(!history.remove(first.id));
+        assert!(matches!(
+            history.diff(first.id, second.id),
+            Err(YrsVersionHistoryError::UnknownVersion { id }) if id == first.id
+        ));
+    }
+
+    #[test]
+    fn history_records_versions_following_policy() {
+        let doc = doc_without_gc();
+        let history = YrsVersionHistory::new(
+            &doc,
+            YrsVersionPolicy {
+                every_updates: 2,
+                min_interval_millis: 0,
+            },
+        )
+        .unwrap();
+        let text = doc.get_text("text".to_string());
+        for chunk in ["a", "b", "c", "d", "e"] {
+            let txn = doc.transact(None);
+            text.append(&txn, chunk.to_string());
+            txn.free();
+        }
+
+        let versions = history.versions();
+        assert_eq!(versions.len(), 2);
+        let restored = history.restore(versions[1].id).unwrap();
+        let restored_text = restored.get_text("text".to_string());
+        let txn = restored.transact(None);
+        assert_eq!(restored_text.get_string(&txn), "abcd");
+    }
+
+    #[test]
+    fn history_requires_skip_gc() {
+        assert!(matches!(
+            YrsVersionHistory::new(&YrsDoc::new(), manual()),
+            Err(YrsVersionHistoryError::GcEnabled)
+        ));
+    }
+}
@@ -0,0 +1,291 @@
+use crate::text::YrsText;
+use crate::transaction::YrsTransaction;
+use yrs::types::text::Diff;
+use yrs::types::Attrs;
+use yrs::{Any, Out, Text};
+
+/// A single line of text, rendered as inline HTML, along with the attributes of the
+/// newline that terminated it.
+struct Line {
+    html: String,
+    attrs: Attrs,
+}
+
+fn escape(text: &str, buf: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => buf.push_str("&amp;"),
+            '<' => buf.push_str("&lt;"),
+            '>' => buf.push_str("&gt;"),
+            '"' => buf.push_str("&quot;"),
+            '\'' => buf.push_str("&#39;"),
+            c => buf.push(c),
+        }
+    }
+}
+
+fn is_set(attrs: &Attrs, key: &str) -> bool {
+    matches!(attrs.get(key), Some(value) if !matches!(value, Any::Null | Any::Undefined | Any::Bool(false)))
+}
+
+fn string_attr<'a>(attrs: &'a Attrs, key: &str) -> Option<&'a str> {
+    match attrs.get(key) {
+        Some(Any::String(value)) => Some(value.as_ref()),
+        _ => None,
+    }
+}
+
+/// Link schemes kept in the export. Links with any other scheme, such as `javascript:`,
+/// are dropped, while relative links are kept.
+const LINK_SCHEMES: [&str; 3] = ["http", "https", "mailto"];
+
+fn is_safe_href(href: &str) -> bool {
+    // Browsers skip leading spaces and control characters, and tabs and newlines anywhere.
+    let href: String = href
+        .trim_start_matches(|c: char| c <= ' ')
+        .chars()
+        .filter(|c| !matches!(c, '\t' | '\n' | '\r'))
+        .collect();
+    match href.find([':', '/', '?', '#']) {
+        Some(i) if href[i..].starts_with(':') => {
+            LINK_SCHEMES.iter().any(|scheme| href[..i].eq_ignore_ascii_case(scheme))
+        }
+        _ => true,
+    }
+}
+
+/// Inline formatting tags, from the outermost to the innermost one.
+const INLINE_TAGS: [(&str, &str); 5] = [
+    ("bold", "strong"),
+    ("italic", "em"),
+    ("underline", "u"),
+    ("strike", "s"),
+    ("code", "code"),
+];
+
+fn push_inline(content: &str, attrs: &Attrs, buf: &mut String) {
+    let link = string_attr(attrs, "link").filter(|href| is_safe_href(href));
+    if let Some(href) = link {
+        buf.push_str("<a href=\"");
+        escape(href, buf);
+        buf.push_str("\">");
+    }
+    let tags: Vec<&str> = INLINE_TAGS
+        .iter()
+        .filter(|(key, _)| is_set(attrs, key))
+        .map(|(_, tag)| *tag)
+        .collect();
+    for tag in tags.iter() {
+        buf.push('<');
+        buf.push_str(tag);
+        buf.push('>');
+    }
+    buf.push_str(content);
+    for tag in tags.iter().rev() {
+        buf.push_str("</");
+        buf.push_str(tag);
+        buf.push('>');
+    }
+    if link.is_some() {
+        buf.push_str("</a>");
+    }
+}
+
+fn push_embed(embed: &Any, attrs: &Attrs, buf: &mut String) {
+    let mut content = String::new();
+    match embed {
+        Any::Map(map) => {
+            if let Some(Any::String(src)) = map.get("image") {
+                content.push_str("<img src=\"");
+                escape(src, &mut content);
+                content.push_str("\">");
+            } else {
+                let mut json = String::new();
+                embed.to_json(&mut json);
+                content.push_str("<span data-embed=\"");
+                escape(&json, &mut content);
+                content.push_str("\"></span>");
+            }
+        }
+        other => {
+            let mut json = String::new();
+            other.to_json(&mut json);
+            content.push_str("<span data-embed=\"");
+            escape(&json, &mut content);
+            content.push_str("\"></span>");
+        }
+    }
+    push_inline(&content, attrs, buf);
+}
+
+fn split_lines(diffs: Vec<Diff<()>>) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    for diff in diffs {
+        let attrs = diff.attributes.map(|a| *a).unwrap_or_default();
+        match diff.insert {
+            Out::Any(Any::String(s)) => {
+                let mut parts = s.split('\n').peekable();
+                while let Some(part) = parts.next() {
+                    if !part.is_empty() {
+                        let mut escaped = String::new();
+                        escape(part, &mut escaped);
+                        push_inline(&escaped, &attrs, &mut current);
+                    }
+                    if parts.peek().is_some() {
+                        lines.push(Line {
+                            html: std::mem::take(&mut current),
+                            attrs: attrs.clone(),
+                        });
+                    }
+                }
+            }
+            Out::Any(embed) => push_embed(&embed, &attrs, &mut current),
+            _ => {}
+        }
+    }
+    if !current.is_empty() {
+        lines.push(Line {
+            html: current,
+            attrs: Attrs::new(),
+        });
+    }
+    lines
+}
+
+/// Returns the list tag of a line, if it is a list item.
+fn list_tag(line: &Line) -> Option<&'static str> {
+    match string_attr(&line.attrs, "list") {
+        Some("ordered") => Some("ol"),
+        Some(_) => Some("ul"),
+        None => None,
+    }
+}
+
+fn render(lines: Vec<Line>) -> String {
+    let mut buf = String::new();
+    let mut open_list: Option<&str> = None;
+    let mut in_code_block = false;
+    for line in lines {
+        let list = list_tag(&line);
+        if open_list.is_some() && open_list != list {
+            buf.push_str("</");
+            buf.push_str(open_list.unwrap());
+            buf.push('>');
+            open_list = None;
+        }
+        let code_block = is_set(&line.attrs, "code-block");
+        if in_code_block && !code_block {
+            buf.push_str("</pre>");
+            in_code_block = false;
+        }
+
+        if let Some(tag) = list {
+            if open_list.is_none() {
+                buf.push('<');
+                buf.push_str(tag);
+                buf.push('>');
+                open_list = Some(tag);
+            }
+            buf.push_str("<li>");
+            buf.push_str(&line.html);
+            buf.push_str("</li>");
+        } else if code_block {
+            if in_code_block {
+                buf.push('\n');
+            } else {
+                buf.push_str("<pre>");
+                in_code_block = true;
+            }
+            buf.push_str(&line.html);
+        } else {
+            let tag = match line.attrs.get("header") {
+                Some(Any::Number(level)) if (1.0..=6.0).contains(level) => {
+                    format!("h{}", *level as u32)
+                }
+                Some(Any::BigInt(level)) if (1..=6).contains(level) => format!("h{}", level),
+                _ if is_set(&line.attrs, "blockquote") => "blockquote".to_string(),
+                _ => "p".to_string(),
+            };
+            buf.push_str(&format!("<{}>", tag));
+            if line.html.is_empty() {
+                buf.push_str("<br>");
+            } else {
+                buf.push_str(&line.html);
+            }
+            buf.push_str(&format!("</{}>", tag));
+        }
+    }
+    if let Some(tag) = open_list {
+        buf.push_str("</");
+        buf.push_str(tag);
+        buf.push('>');
+    }
+    if in_code_block {
+        buf.push_str("</pre>");
+    }
+    buf
+}
+
+impl YrsText {
+    /// Renders the text as HTML, mapping formatting attributes to markup: inline `bold`,
+    /// `italic`, `underline`, `strike`, `code` and `link` attributes, and line-level
+    /// `header`, `list`, `blockquote` and `code-block` attributes. Only `http`, `https`,
+    /// `mailto` and relative links become anchors. Image embeds become
+    /// `<img>` elements, while other embeds are kept as JSON in a `data-embed` attribute.
+    pub(crate) fn to_html(&self, transaction: &YrsTransaction) -> String {
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+
+        let diffs: Vec<Diff<()>> = self.inner().as_ref().diff(tx, |_| ());
+        render(split_lines(diffs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::YrsDoc;
+
+    #[test]
+    fn html_export_maps_formatting() {
+        let doc = YrsDoc::new();
+        let text = doc.get_text("text".to_string());
+
+        let txn = doc.transact(None);
+        text.insert_markdown(
+            &txn,
+            0,
+            "# Title\n\nSome **bold** <text>\n\n- one\n- two\n".to_string(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            text.to_html(&txn),
+            "<h1>Title</h1><p>Some <strong>bold</strong> &lt;text&gt;</p><ul><li>one</li><li>two</li></ul>"
+        );
+    }
+
+    #[test]
+    fn html_export_drops_unsafe_links() {
+        let doc = YrsDoc::new();
+        let text = doc.get_text("text".to_string());
+
+        let txn = doc.transact(None);
+        let link = |href: &str| format!(r#"{{"link":"{href}"}}"#);
+        text.insert_with_attributes(&txn, 0, "a".to_string(), link("https://example.com")).unwrap();
+        text.insert_with_attributes(&txn, 1, "b".to_string(), link("/docs?q=1")).unwrap();
+        text.insert_with_attributes(&txn, 2, "c".to_string(), link("javascript:alert(1)")).unwrap();
+        text.insert_with_attributes(&txn, 3, "d".to_string(), link(" JavaScript:alert(1)")).unwrap();
+        text.insert_with_attributes(&txn, 4, "e".to_string(), link("java\\tscript:alert(1)")).unwrap();
+        text.insert_with_attributes(&txn, 5, "f".to_string(), link("data:text/html,x")).unwrap();
+        text.insert_with_attributes(&txn, 6, "g".to_string(), link("MAILTO:ada@example.com")).unwrap();
+
+        assert_eq!(
+            text.to_html(&txn),
+            concat!(
+                r#"<p><a href="https://example.com">a</a><a href="/docs?q=1">b</a>cdef"#,
+                r#"<a href="MAILTO:ada@example.com">g</a></p>"#
+            )
+        );
+    }
+}
@@ -64,7 +64,7 @@ impl YrsTransaction {
                             }
                             first = false;
                             buf.push('"');
-                            buf.push_str(&key);
+                            buf.push_str(key);
                             buf.push_str("\":");
                             if let Out::Any(any) = value {
                                 any.to_json(&mut buf);
@@ -97,7 +97,7 @@ impl YrsTransaction {
                     Out::YXmlElement(_) | Out::YXmlFragment(_) | Out::YXmlText(_) => {
                         buf.push_str("null");
                     }
-                    Out::UndefinedRef(_) => {
+                    Out::YWeakLink(_) | Out::UndefinedRef(_) => {
                         buf.push_str("null");
                     }
                 }
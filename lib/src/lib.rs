@@ -1,47 +1,150 @@
+// The scaffolding generated by uniffi leaves an empty line after one of its doc
+// comments, and has to be included at the crate root.
+#![allow(clippy::empty_line_after_doc_comments)]
+
 mod array;
 mod attrs;
+mod awareness;
 mod change;
+mod chunks;
+mod compression;
 mod delta;
+mod delivery;
 mod doc;
+mod docpair;
+mod encoding;
+mod encryption;
 mod error;
+mod event;
+mod filestore;
+mod history;
+mod html;
 mod jsonpath;
 mod map;
 mod mapchange;
+mod markdown;
+mod path;
+mod persistence;
+mod provider;
+mod snapshot;
 mod subdoc;
 mod subscription;
+mod sync;
 mod text;
+mod textdiff;
 mod transaction;
 mod undo;
+mod userdata;
+mod value;
+mod weak;
+mod xml;
+mod xmljson;
 
 use crate::array::YrsArray;
+use crate::array::YrsArrayIterator;
 use crate::array::YrsArrayEachDelegate;
+use crate::array::YrsArrayEachValueDelegate;
 use crate::array::YrsArrayObservationDelegate;
+use crate::array::YrsArrayStreamDelegate;
+use crate::awareness::YrsAwareness;
+use crate::awareness::YrsAwarenessError;
+use crate::awareness::YrsAwarenessEvent;
+use crate::awareness::YrsAwarenessObservationDelegate;
+use crate::change::YrsAddedShared;
 use crate::change::YrsChange;
+use crate::change::YrsSharedHandle;
+use crate::chunks::YrsChunkApplier;
+use crate::chunks::YrsChunkError;
+use crate::chunks::YrsUpdateChunker;
+use crate::delivery::YrsObservationDelivery;
 use crate::delta::YrsDelta;
+use crate::delta::YrsDeltaError;
 use crate::doc::YrsCollectionPtr;
 use crate::doc::YrsDoc;
+use crate::doc::YrsId;
 use crate::doc::YrsOrigin;
+use crate::docpair::YrsDocPair;
+use crate::docpair::YrsDocPairOptions;
+use crate::encoding::YrsDecoder;
+use crate::encoding::YrsEncoder;
+use crate::encryption::YrsEncryptionDelegate;
 use crate::error::CodingError;
+use crate::event::YrsDeepObservationDelegate;
+use crate::event::YrsEvent;
+use crate::history::YrsCollectionDiff;
+use crate::history::YrsVersion;
+use crate::history::YrsVersionHistory;
+use crate::history::YrsVersionHistoryError;
+use crate::history::YrsVersionPolicy;
 use crate::jsonpath::YrsJsonPathError;
 use crate::map::YrsMap;
 use crate::map::YrsMapIteratorDelegate;
+use crate::map::YrsMapEntryDelegate;
 use crate::map::YrsMapKVIteratorDelegate;
+use crate::map::YrsMapEntry;
+use crate::map::YrsMapIterator;
 use crate::map::YrsMapObservationDelegate;
+use crate::map::YrsMapKeyObservationDelegate;
+use crate::map::YrsMapValueObservationDelegate;
 use crate::mapchange::YrsEntryChange;
+use crate::mapchange::YrsEntryValueChange;
 use crate::mapchange::YrsMapChange;
+use crate::mapchange::YrsMapValueChange;
+use crate::path::YrsPathSegment;
+use crate::persistence::YrsPersistence;
+use crate::persistence::YrsPersistenceError;
+use crate::persistence::{YrsPersistenceFailureDelegate, YrsStorageDelegate};
+use crate::provider::YrsTransportDelegate;
+use crate::provider::YrsWebSocketProvider;
+use crate::snapshot::YrsSnapshot;
 use crate::subdoc::YrsDestroyObservationDelegate;
 use crate::subdoc::YrsDocOptions;
+use crate::subdoc::YrsLoadedObservationDelegate;
+use crate::subdoc::YrsSubdocInfo;
+use crate::subdoc::YrsSubdocLoadDelegate;
+use crate::subdoc::YrsSubdocNode;
+use crate::subdoc::YrsSubdocUpdateDelegate;
 use crate::subdoc::YrsSubdocsEvent;
 use crate::subdoc::YrsSubdocsObservationDelegate;
 use crate::subscription::YSubscription;
+use crate::subscription::YSubscriptionBag;
+use crate::sync::decode_sync_messages;
+use crate::sync::encode_sync_message;
+use crate::sync::YrsSyncError;
+use crate::sync::YrsSyncMessage;
+use crate::sync::YrsSyncSession;
+use crate::text::YrsAttributedDiff;
+use crate::text::YrsAttributionRun;
+use crate::text::YrsChangeDelegate;
+use crate::text::YrsChangeKind;
 use crate::text::YrsDiff;
+use crate::text::YrsSurrogatePolicy;
 use crate::text::YrsText;
+use crate::text::YrsTextChunk;
+use crate::text::YrsTextError;
 use crate::text::YrsTextObservationDelegate;
+use crate::text::YrsTextRun;
 use crate::transaction::YrsTransaction;
 use crate::undo::YrsUndoError;
 use crate::undo::YrsUndoEvent;
 use crate::undo::YrsUndoEventKind;
 use crate::undo::YrsUndoManager;
 use crate::undo::YrsUndoManagerObservationDelegate;
+use crate::undo::YrsUndoManagerOptions;
+use crate::undo::YrsUndoResult;
+use crate::undo::YrsUndoStackItem;
+use crate::userdata::YrsPermanentUserData;
+use crate::userdata::YrsUserMapping;
+use crate::value::YrsSharedKind;
+use crate::value::YrsValue;
+use crate::weak::YrsQuoteError;
+use crate::weak::YrsWeakLink;
+use crate::weak::YrsWeakPrelim;
+use crate::xml::YrsXmlElement;
+use crate::xml::YrsXmlFragment;
+use crate::xml::YrsXmlObservationDelegate;
+use crate::xml::YrsXmlText;
+use crate::xml::YrsXmlTextObservationDelegate;
+use crate::xml::YrsXmlTreeWalker;
 
 uniffi::include_scaffolding!("yniffi");
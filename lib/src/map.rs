@@ -1,16 +1,21 @@
 use crate::array::YrsArray;
-use crate::doc::{YrsCollectionPtr, YrsDoc};
+use crate::delivery::YrsObservationDelivery;
+use crate::doc::{YrsCollectionPtr, YrsDoc, YrsOrigin};
 use crate::error::CodingError;
-use crate::mapchange::{try_from_entry_change, YrsMapChange};
+use crate::event::{YrsDeepObservationDelegate, YrsEvent};
+use crate::mapchange::{from_entry_change, YrsEntryValueChange, YrsMapChange, YrsMapValueChange};
 use crate::subscription::YSubscription;
 use crate::text::YrsText;
 use crate::transaction::YrsTransaction;
-use parking_lot::ReentrantMutex;
+use crate::value::YrsValue;
+use crate::weak::{YrsWeakLink, YrsWeakPrelim};
+use parking_lot::{Mutex, ReentrantMutex};
 use std::cell::UnsafeCell;
 use std::fmt::Debug;
 use std::sync::Arc;
 use yrs::branch::Branch;
-use yrs::{Any, Map, MapRef, Observable, Out};
+use yrs::types::ToJson;
+use yrs::{Any, DeepObservable, Map, MapRef, Observable, Out};
 
 pub(crate) struct YrsMap(ReentrantMutex<UnsafeCell<MapRef>>);
 
@@ -62,6 +67,42 @@ impl From<MapRef> for YrsMap {
     }
 }
 
+/// A key-value pair of a map.
+pub(crate) struct YrsMapEntry {
+    pub key: String,
+    pub value: YrsValue,
+}
+
+/// Lazily yields the entries of a map within a transaction held for its lifetime.
+/// Keys are captured when the iterator is created; values are read on demand, and
+/// entries removed in the meantime are skipped.
+pub(crate) struct YrsMapIterator {
+    transaction: Arc<YrsTransaction>,
+    map: MapRef,
+    keys: Mutex<std::vec::IntoIter<String>>,
+}
+
+// Safe because Mutex provides proper thread synchronization.
+unsafe impl Send for YrsMapIterator {}
+unsafe impl Sync for YrsMapIterator {}
+
+impl YrsMapIterator {
+    /// Returns the next entry, or `None` once the map is exhausted or the transaction
+    /// has been freed.
+    pub(crate) fn next(&self) -> Option<YrsMapEntry> {
+        let tx = self.transaction.transaction();
+        let tx = tx.as_ref()?;
+        let mut keys = self.keys.lock();
+        keys.find_map(|key| {
+            let value = self.map.get(tx, key.as_str())?;
+            Some(YrsMapEntry {
+                key,
+                value: YrsValue::from(value),
+            })
+        })
+    }
+}
+
 // A representation of a callback that is invoked from the various
 // map iterators, specifically to provide the JSON-string of the iterated
 // value from the map (for example, with `values` or `iter`).
@@ -83,8 +124,20 @@ pub(crate) trait YrsMapKVIteratorDelegate: Send + Sync + Debug {
     fn call(&self, key: String, value: String);
 }
 
+pub(crate) trait YrsMapEntryDelegate: Send + Sync + Debug {
+    fn call(&self, key: String, value: YrsValue);
+}
+
 pub(crate) trait YrsMapObservationDelegate: Send + Sync + Debug {
-    fn call(&self, value: Vec<YrsMapChange>);
+    fn call(&self, value: Vec<YrsMapChange>, origin: Option<YrsOrigin>, target: YrsCollectionPtr);
+}
+
+pub(crate) trait YrsMapValueObservationDelegate: Send + Sync + Debug {
+    fn call(&self, value: Vec<YrsMapValueChange>);
+}
+
+pub(crate) trait YrsMapKeyObservationDelegate: Send + Sync + Debug {
+    fn call(&self, change: YrsEntryValueChange, origin: Option<YrsOrigin>);
 }
 
 /*
@@ -122,6 +175,27 @@ impl YrsMap {
         // nested.insert(&mut txn, "inner2", 100);
     }
 
+    /// Inserts a binary value under the key you provide, stored as a byte buffer.
+    pub(crate) fn insert_buffer(&self, transaction: &YrsTransaction, key: String, value: Vec<u8>) {
+        let mut binding = transaction.transaction();
+        let tx = binding.as_mut().unwrap();
+
+        let mut map = self.inner();
+        map.as_mut().insert(tx, key, Any::Buffer(value.into()));
+    }
+
+    /// Returns the binary value stored under the key you provide, or `None` if there is
+    /// no value or it isn't a byte buffer.
+    pub(crate) fn get_buffer(&self, transaction: &YrsTransaction, key: String) -> Option<Vec<u8>> {
+        let binding = transaction.transaction();
+        let tx = binding.as_ref().unwrap();
+        let map = self.inner();
+        match map.as_ref().get(tx, key.as_str()) {
+            Some(Out::Any(Any::Buffer(buf))) => Some(buf.to_vec()),
+            _ => None,
+        }
+    }
+
     /// Returns the size of the map.
     pub(crate) fn length(&self, transaction: &YrsTransaction) -> u32 {
         let map = self.inner();
@@ -154,16 +228,61 @@ impl YrsMap {
         let binding = transaction.transaction();
         let tx = binding.as_ref().unwrap();
         let map = self.inner();
-        let v = map.as_ref().get(tx, key.as_str()).unwrap();
+        let v = map.as_ref().get(tx, key.as_str()).ok_or(CodingError::OutOfBounds)?;
         let mut buf = String::new();
         if let Out::Any(any) = v {
             any.to_json(&mut buf);
             Ok(buf)
         } else {
-            Err(CodingError::EncodingError)
+            Err(CodingError::UnexpectedType)
         }
     }
 
+    /// Returns the value for the given key, or `None` if the key doesn't exist.
+    pub(crate) fn get_value(&self, transaction: &YrsTransaction, key: String) -> Option<YrsValue> {
+        let binding = transaction.transaction();
+        let tx = binding.as_ref().unwrap();
+        let map = self.inner();
+        map.as_ref().get(tx, key.as_str()).map(YrsValue::from)
+    }
+
+    /// Inserts a typed value for the given key. Fails if the value contains a shared reference.
+    pub(crate) fn insert_value(
+        &self,
+        transaction: &YrsTransaction,
+        key: String,
+        value: YrsValue,
+    ) -> Result<(), CodingError> {
+        let avalue = Any::try_from(value)?;
+
+        let mut binding = transaction.transaction();
+        let tx = binding.as_mut().unwrap();
+        let mut map = self.inner();
+        map.as_mut().insert(tx, key, avalue);
+        Ok(())
+    }
+
+    /// Inserts all the entries you provide within the same transaction. Values are
+    /// converted up front, so nothing is inserted if any of them contains a shared reference.
+    pub(crate) fn insert_all(
+        &self,
+        transaction: &YrsTransaction,
+        entries: Vec<YrsMapEntry>,
+    ) -> Result<(), CodingError> {
+        let entries = entries
+            .into_iter()
+            .map(|entry| Ok((entry.key, Any::try_from(entry.value)?)))
+            .collect::<Result<Vec<_>, CodingError>>()?;
+
+        let mut binding = transaction.transaction();
+        let tx = binding.as_mut().unwrap();
+        let mut map = self.inner();
+        for (key, value) in entries {
+            map.as_mut().insert(tx, key, value);
+        }
+        Ok(())
+    }
+
     pub(crate) fn remove(
         &self,
         transaction: &YrsTransaction,
@@ -184,19 +303,113 @@ impl YrsMap {
                 if let Out::Any(any) = v {
                     let mut buf = String::new();
                     any.to_json(&mut buf);
-                    return Ok(Some(buf));
+                    Ok(Some(buf))
                 } else {
-                    return Err(CodingError::EncodingError);
+                    Err(CodingError::UnexpectedType)
                 }
             }
             // No value returned from the map on remove, so return the Optional
             // string as None.
-            None => {
-                return Ok(None);
-            }
+            None => Ok(None),
         }
     }
 
+    /// Serializes the whole map to a JSON object, including nested shared types.
+    /// Maps and arrays become JSON objects and arrays, texts become strings and
+    /// subdocuments become `{"guid": ...}` objects.
+    pub(crate) fn to_json(&self, transaction: &YrsTransaction) -> String {
+        let binding = transaction.transaction();
+        let tx = binding.as_ref().unwrap();
+        let map = self.inner();
+
+        let mut buf = String::new();
+        ToJson::to_json(map.as_ref(), tx).to_json(&mut buf);
+        buf
+    }
+
+    /// Compares this map with another one, which may live in a different document, and
+    /// returns the per-key changes that turn this map into `other`, sorted by key.
+    /// Nested shared types are compared by their JSON contents.
+    pub(crate) fn diff(
+        &self,
+        transaction: &YrsTransaction,
+        other: Arc<YrsMap>,
+        other_transaction: &YrsTransaction,
+    ) -> Vec<YrsMapValueChange> {
+        let binding = transaction.transaction();
+        let tx = binding.as_ref().unwrap();
+        let other_binding = other_transaction.transaction();
+        let other_tx = other_binding.as_ref().unwrap();
+
+        let map = self.inner();
+        let other_map = other.inner();
+
+        let mut keys: Vec<String> = map
+            .as_ref()
+            .keys(tx)
+            .chain(other_map.as_ref().keys(other_tx))
+            .map(String::from)
+            .collect();
+        keys.sort();
+        keys.dedup();
+
+        keys.into_iter()
+            .filter_map(|key| {
+                let old_value = map.as_ref().get(tx, key.as_str());
+                let new_value = other_map.as_ref().get(other_tx, key.as_str());
+                let change = match (old_value, new_value) {
+                    (None, Some(value)) => YrsEntryValueChange::Inserted {
+                        value: YrsValue::from(value),
+                    },
+                    (Some(value), None) => YrsEntryValueChange::Removed {
+                        value: YrsValue::from(value),
+                    },
+                    (Some(old_value), Some(new_value))
+                        if old_value.to_json(tx) != new_value.to_json(other_tx) =>
+                    {
+                        YrsEntryValueChange::Updated {
+                            old_value: YrsValue::from(old_value),
+                            new_value: YrsValue::from(new_value),
+                        }
+                    }
+                    _ => return None,
+                };
+                Some(YrsMapValueChange { key, change })
+            })
+            .collect()
+    }
+
+    /// Upserts every top-level key of a JSON object. With `deep` set, nested objects are
+    /// merged into existing nested maps instead of replacing them.
+    pub(crate) fn merge_json(
+        &self,
+        transaction: &YrsTransaction,
+        json: String,
+        deep: bool,
+    ) -> Result<(), CodingError> {
+        let Any::Map(entries) =
+            Any::from_json(json.as_str()).map_err(|_| CodingError::DecodingError)?
+        else {
+            return Err(CodingError::UnexpectedType);
+        };
+
+        let mut binding = transaction.transaction();
+        let tx = binding.as_mut().unwrap();
+        let map = self.inner();
+        merge_entries(map.as_ref(), tx, &entries, deep);
+        Ok(())
+    }
+
+    /// Removes the entry for the given key and returns its previous value as a typed
+    /// value, including nested shared types, or `None` if the key didn't exist.
+    pub(crate) fn remove_value(&self, transaction: &YrsTransaction, key: String) -> Option<YrsValue> {
+        let mut binding = transaction.transaction();
+        let tx = binding.as_mut().unwrap();
+        let mut map = self.inner();
+
+        map.as_mut().remove(tx, key.as_str()).map(YrsValue::from)
+    }
+
     pub(crate) fn clear(&self, transaction: &YrsTransaction) {
         // acquire a *mutable* transaction
         let mut binding = transaction.transaction();
@@ -303,21 +516,119 @@ impl YrsMap {
         });
     }
 
+    /// Calls the delegate with the key and typed value of every entry, including
+    /// references to nested shared types.
+    pub(crate) fn each_entry(&self, transaction: &YrsTransaction, delegate: Box<dyn YrsMapEntryDelegate>) {
+        let binding = transaction.transaction();
+        let txn = binding.as_ref().unwrap();
+
+        let map = self.inner();
+        for (key, value) in map.as_ref().iter(txn) {
+            delegate.call(key.to_string(), YrsValue::from(value));
+        }
+    }
+
+    /// Returns an iterator over the entries of the map. The iterator holds on to the
+    /// transaction, and stops yielding entries once it is freed.
+    pub(crate) fn iter(&self, transaction: Arc<YrsTransaction>) -> Arc<YrsMapIterator> {
+        let map = self.inner().as_ref().clone();
+        let keys: Vec<String> = {
+            let tx = transaction.transaction();
+            let tx = tx.as_ref().unwrap();
+            map.keys(tx).map(String::from).collect()
+        };
+        Arc::new(YrsMapIterator {
+            transaction,
+            map,
+            keys: Mutex::new(keys.into_iter()),
+        })
+    }
+
     pub(crate) fn observe(&self, delegate: Box<dyn YrsMapObservationDelegate>) -> Arc<YSubscription> {
+        self.observe_with_delivery(delegate, YrsObservationDelivery::Synchronous)
+    }
+
+    /// Observes changes made to the map, calling the delegate as `delivery` specifies.
+    pub(crate) fn observe_with_delivery(
+        &self,
+        delegate: Box<dyn YrsMapObservationDelegate>,
+        delivery: YrsObservationDelivery,
+    ) -> Arc<YSubscription> {
+        let delegate: Arc<dyn YrsMapObservationDelegate> = Arc::from(delegate);
+        let mut map = self.inner();
+        let subscription = map.as_mut().observe(move |transaction, map_event| {
+            let delta = map_event.keys(transaction);
+            let result: Vec<YrsMapChange> = delta
+                .iter()
+                .map(|val| from_entry_change(val.0, val.1))
+                .collect();
+            let origin = transaction.origin().cloned().map(YrsOrigin::from);
+            let target = YrsCollectionPtr::from(map_event.target().as_ref());
+            let delegate = delegate.clone();
+            delivery.deliver(move || delegate.call(result, origin, target))
+        });
+
+        Arc::new(YSubscription::new(subscription))
+    }
+
+    /// Observes changes made to the map and to any shared type nested inside it.
+    pub(crate) fn observe_deep(
+        &self,
+        delegate: Box<dyn YrsDeepObservationDelegate>,
+    ) -> Arc<YSubscription> {
+        let map = self.inner();
+        let subscription = map.as_ref().observe_deep(move |transaction, events| {
+            let result: Vec<YrsEvent> = events
+                .iter()
+                .map(|event| YrsEvent::new(transaction, event))
+                .collect();
+            let origin = transaction.origin().cloned().map(YrsOrigin::from);
+            delegate.call(result, origin)
+        });
+
+        Arc::new(YSubscription::new(subscription))
+    }
+
+    /// Observes changes to the map, reporting old and new entries as typed values.
+    pub(crate) fn observe_values(
+        &self,
+        delegate: Box<dyn YrsMapValueObservationDelegate>,
+    ) -> Arc<YSubscription> {
         let mut map = self.inner();
         let subscription = map
             .as_mut()
             .observe(move |transaction, map_event| {
-                let delta = map_event.keys(transaction);
-                // Filter out nested shared types (YMap, YArray, YText, YDoc) which return None
-                let result: Vec<YrsMapChange> = delta
+                let result: Vec<YrsMapValueChange> = map_event
+                    .keys(transaction)
                     .iter()
-                    .filter_map(|val| try_from_entry_change(val.0, val.1))
+                    .map(|(key, change)| YrsMapValueChange {
+                        key: key.to_string(),
+                        change: YrsEntryValueChange::from(change),
+                    })
                     .collect();
                 delegate.call(result)
             });
 
-            Arc::new(YSubscription::new(subscription))
+        Arc::new(YSubscription::new(subscription))
+    }
+
+    /// Observes changes to a single key, ignoring changes to every other entry.
+    pub(crate) fn observe_key(
+        &self,
+        key: String,
+        delegate: Box<dyn YrsMapKeyObservationDelegate>,
+    ) -> Arc<YSubscription> {
+        let mut map = self.inner();
+        let subscription = map
+            .as_mut()
+            .observe(move |transaction, map_event| {
+                if let Some(change) = map_event.keys(transaction).get(key.as_str()) {
+                    let origin = transaction.origin().cloned().map(YrsOrigin::from);
+                    delegate.call(YrsEntryValueChange::from(change), origin)
+                }
+            });
+
+        Arc::new(YSubscription::new(subscription))
     }
 
     // MARK: - Subdoc methods
@@ -495,6 +806,26 @@ impl YrsMap {
         map.as_mut().try_update(tx, key, any_value)
     }
 
+    /// Returns the existing value at key, or inserts `default_value` and returns it.
+    /// Fails if `default_value` contains a shared reference.
+    pub(crate) fn get_or_insert_value(
+        &self,
+        transaction: &YrsTransaction,
+        key: String,
+        default_value: YrsValue,
+    ) -> Result<YrsValue, CodingError> {
+        let mut binding = transaction.transaction();
+        let tx = binding.as_mut().unwrap();
+        let mut map = self.inner();
+
+        if let Some(value) = map.as_ref().get(tx, key.as_str()) {
+            return Ok(YrsValue::from(value));
+        }
+        let avalue = Any::try_from(default_value.clone())?;
+        map.as_mut().insert(tx, key, avalue);
+        Ok(default_value)
+    }
+
     /// Gets existing nested map or creates new one at key.
     pub(crate) fn get_or_insert_map(
         &self,
@@ -536,11 +867,90 @@ impl YrsMap {
         let nested: TextRef = map.as_mut().get_or_init(tx, key.as_str());
         Arc::new(YrsText::from(nested))
     }
+
+    // MARK: - Weak link methods
+
+    /// Creates a link to the entry stored under the specified key, which keeps resolving
+    /// to the entry's current value once it is overwritten. Insert it elsewhere to obtain a
+    /// live `YrsWeakLink`. Returns None if the key doesn't exist.
+    pub(crate) fn link(&self, transaction: &YrsTransaction, key: String) -> Option<Arc<YrsWeakPrelim>> {
+        let binding = transaction.transaction();
+        let tx = binding.as_ref().unwrap();
+        let map = self.inner();
+
+        map.as_ref()
+            .link(tx, key.as_str())
+            .map(YrsWeakPrelim::from)
+            .map(Arc::new)
+    }
+
+    /// Inserts a quotation created by `quote` at the specified key.
+    /// Returns a reference to the integrated weak link.
+    pub(crate) fn insert_weak_link(
+        &self,
+        transaction: &YrsTransaction,
+        key: String,
+        prelim: &YrsWeakPrelim,
+    ) -> Arc<YrsWeakLink> {
+        let mut binding = transaction.transaction();
+        let tx = binding.as_mut().unwrap();
+        let mut map = self.inner();
+
+        let link = map.as_mut().insert(tx, key, prelim.prelim());
+        Arc::new(YrsWeakLink::from(link))
+    }
+
+    /// Gets a weak link for the specified key.
+    /// Returns None if the key doesn't exist or the value is not a weak link.
+    pub(crate) fn get_weak_link(
+        &self,
+        transaction: &YrsTransaction,
+        key: String,
+    ) -> Option<Arc<YrsWeakLink>> {
+        let binding = transaction.transaction();
+        let tx = binding.as_ref().unwrap();
+        let map = self.inner();
+
+        if let Some(Out::YWeakLink(link)) = map.as_ref().get(tx, key.as_str()) {
+            Some(Arc::new(YrsWeakLink::from(link)))
+        } else {
+            None
+        }
+    }
+}
+
+fn merge_entries(
+    map: &MapRef,
+    tx: &mut yrs::TransactionMut,
+    entries: &std::collections::HashMap<String, Any>,
+    deep: bool,
+) {
+    for (key, value) in entries {
+        if let (true, Any::Map(nested_entries)) = (deep, value) {
+            if let Some(Out::YMap(nested)) = map.get(tx, key.as_str()) {
+                merge_entries(&nested, tx, nested_entries, deep);
+                continue;
+            }
+        }
+        map.insert(tx, key.as_str(), value.clone());
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::{YrsMapEntry, YrsMapEntryDelegate, YrsMapKeyObservationDelegate, YrsMapObservationDelegate, YrsMapValueObservationDelegate};
+    use crate::delivery::YrsObservationDelivery;
+    use crate::doc::YrsCollectionPtr;
+    use crate::mapchange::{YrsEntryChange, YrsMapChange};
+    use crate::doc::YrsOrigin;
+    use crate::error::CodingError;
+    use crate::event::{YrsDeepObservationDelegate, YrsEvent};
+    use crate::path::YrsPathSegment;
+    use crate::mapchange::{YrsEntryValueChange, YrsMapValueChange};
+    use crate::value::{YrsSharedKind, YrsValue};
     use crate::YrsDoc;
+    use parking_lot::Mutex;
+    use std::sync::Arc;
 
     #[test]
     fn verify_new_map_has_zero_count() {
@@ -561,12 +971,43 @@ mod tests {
 
         let txn = doc.transact(None);
 
-        assert_eq!(map.contains_key(&txn, key_to_insert.clone()), false);
+        assert!(!map.contains_key(&txn, key_to_insert.clone()));
 
         map.insert(&txn, key_to_insert.clone(), value_to_insert);
         assert_eq!(map.length(&txn), 1);
 
-        assert_eq!(map.contains_key(&txn, key_to_insert), true);
+        assert!(map.contains_key(&txn, key_to_insert));
+    }
+
+    #[test]
+    fn map_contains_key_covers_shared_values() {
+        let doc = YrsDoc::new();
+        let map = doc.get_map("example_map".to_string());
+
+        let txn = doc.transact(None);
+        map.insert_text(&txn, "notes".to_string());
+        map.insert_buffer(&txn, "blob".to_string(), vec![0; 1024]);
+
+        assert!(map.contains_key(&txn, "notes".to_string()));
+        assert!(map.contains_key(&txn, "blob".to_string()));
+        map.remove_value(&txn, "notes".to_string());
+        assert!(!map.contains_key(&txn, "notes".to_string()));
+    }
+
+    #[test]
+    fn map_length_counts_every_entry_kind() {
+        let doc = YrsDoc::new();
+        let map = doc.get_map("example_map".to_string());
+
+        let txn = doc.transact(None);
+        map.insert(&txn, "a".to_string(), "1".to_string());
+        map.insert(&txn, "a".to_string(), "2".to_string());
+        map.insert_map(&txn, "b".to_string());
+        map.insert_doc(&txn, "c".to_string(), &YrsDoc::new());
+        assert_eq!(map.length(&txn), 3);
+
+        map.remove_value(&txn, "b".to_string());
+        assert_eq!(map.length(&txn), 2);
     }
 
     #[test]
@@ -579,7 +1020,7 @@ mod tests {
 
         let txn = doc.transact(None);
 
-        assert_eq!(map.contains_key(&txn, key_to_insert.clone()), false);
+        assert!(!map.contains_key(&txn, key_to_insert.clone()));
 
         map.insert(&txn, key_to_insert.clone(), value_to_insert.clone());
         assert_eq!(map.length(&txn), 1);
@@ -598,7 +1039,7 @@ mod tests {
 
         let txn = doc.transact(None);
 
-        assert_eq!(map.contains_key(&txn, key_to_insert.clone()), false);
+        assert!(!map.contains_key(&txn, key_to_insert.clone()));
 
         map.insert(&txn, key_to_insert.clone(), value_to_insert.clone());
 
@@ -608,6 +1049,363 @@ mod tests {
         assert_eq!(map.length(&txn), 0);
     }
 
+    #[test]
+    fn map_remove_value_returns_previous_value() {
+        let doc = YrsDoc::new();
+        let map = doc.get_map("example_map".to_string());
+
+        let txn = doc.transact(None);
+        map.insert(&txn, "count".to_string(), "2".to_string());
+        map.insert_array(&txn, "list".to_string());
+
+        assert_eq!(
+            map.remove_value(&txn, "count".to_string()),
+            Some(YrsValue::Number { value: 2.0 })
+        );
+        assert_eq!(
+            map.remove_value(&txn, "list".to_string()),
+            Some(YrsValue::SharedRef { kind: YrsSharedKind::Array })
+        );
+        assert_eq!(map.remove_value(&txn, "count".to_string()), None);
+        assert_eq!(map.length(&txn), 0);
+    }
+
+    #[test]
+    fn map_diff_compares_two_documents() {
+        let doc1 = YrsDoc::new();
+        let doc2 = YrsDoc::new();
+        let map1 = doc1.get_map("map".to_string());
+        let map2 = doc2.get_map("map".to_string());
+
+        let txn1 = doc1.transact(None);
+        map1.insert(&txn1, "same".to_string(), "1".to_string());
+        map1.insert(&txn1, "changed".to_string(), "1".to_string());
+        map1.insert(&txn1, "removed".to_string(), "true".to_string());
+        map1.insert_map(&txn1, "nested".to_string())
+            .insert(&txn1, "a".to_string(), "1".to_string());
+
+        let txn2 = doc2.transact(None);
+        map2.insert(&txn2, "same".to_string(), "1".to_string());
+        map2.insert(&txn2, "changed".to_string(), "2".to_string());
+        map2.insert(&txn2, "added".to_string(), "\"new\"".to_string());
+        map2.insert_map(&txn2, "nested".to_string())
+            .insert(&txn2, "a".to_string(), "1".to_string());
+
+        let changes: Vec<_> = map1
+            .diff(&txn1, map2.clone(), &txn2)
+            .into_iter()
+            .map(|c| (c.key, c.change))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("added".to_string(), YrsEntryValueChange::Inserted { value: YrsValue::String { value: "new".to_string() } }),
+                ("changed".to_string(), YrsEntryValueChange::Updated { old_value: YrsValue::Number { value: 1.0 }, new_value: YrsValue::Number { value: 2.0 } }),
+                ("removed".to_string(), YrsEntryValueChange::Removed { value: YrsValue::Bool { value: true } }),
+            ]
+        );
+        assert!(map2.diff(&txn2, map2.clone(), &txn2).is_empty());
+    }
+
+    #[test]
+    fn map_merge_json_upserts_keys() {
+        let doc = YrsDoc::new();
+        let map = doc.get_map("settings".to_string());
+
+        let txn = doc.transact(None);
+        map.insert(&txn, "theme".to_string(), "\"dark\"".to_string());
+        let editor = map.insert_map(&txn, "editor".to_string());
+        editor.insert(&txn, "tabs".to_string(), "2".to_string());
+
+        map.merge_json(&txn, r#"{"font":12,"editor":{"wrap":true}}"#.to_string(), true)
+            .unwrap();
+        assert_eq!(map.get(&txn, "theme".to_string()).unwrap(), "\"dark\"");
+        assert_eq!(map.get(&txn, "font".to_string()).unwrap(), "12");
+        assert_eq!(editor.get(&txn, "tabs".to_string()).unwrap(), "2");
+        assert_eq!(editor.get(&txn, "wrap".to_string()).unwrap(), "true");
+
+        map.merge_json(&txn, r#"{"editor":{"wrap":false}}"#.to_string(), false)
+            .unwrap();
+        assert!(map.get_map(&txn, "editor".to_string()).is_none());
+        assert_eq!(map.get(&txn, "editor".to_string()).unwrap(), r#"{"wrap":false}"#);
+
+        assert!(matches!(
+            map.merge_json(&txn, "[1]".to_string(), false),
+            Err(CodingError::UnexpectedType)
+        ));
+        assert!(matches!(
+            map.merge_json(&txn, "{".to_string(), false),
+            Err(CodingError::DecodingError)
+        ));
+    }
+
+    #[test]
+    fn map_to_json_serializes_nested_types() {
+        let doc = YrsDoc::new();
+        let map = doc.get_map("example_map".to_string());
+
+        let txn = doc.transact(None);
+        map.insert(&txn, "title".to_string(), "\"Doc\"".to_string());
+        map.insert_text(&txn, "body".to_string()).append(&txn, "Hi".to_string());
+        let tags = map.insert_array(&txn, "tags".to_string());
+        tags.push_back(&txn, "\"a\"".to_string());
+        let page = YrsDoc::new();
+        map.insert_doc(&txn, "page".to_string(), &page);
+
+        let parsed = yrs::Any::from_json(&map.to_json(&txn)).unwrap();
+        let expected = yrs::Any::from_json(&format!(
+            r#"{{"title":"Doc","body":"Hi","tags":["a"],"page":{{"guid":"{}"}}}}"#,
+            page.guid()
+        ))
+        .unwrap();
+        assert_eq!(parsed, expected);
+    }
+
+    type TargetEvent = (u64, Option<Vec<u8>>);
+
+    #[derive(Debug, Default)]
+    struct Targets(Arc<Mutex<Vec<TargetEvent>>>);
+
+    impl YrsMapObservationDelegate for Targets {
+        fn call(&self, _value: Vec<YrsMapChange>, origin: Option<YrsOrigin>, target: YrsCollectionPtr) {
+            let target = crate::UniffiCustomTypeConverter::from_custom(target);
+            let origin = origin.map(crate::UniffiCustomTypeConverter::from_custom);
+            self.0.lock().push((target, origin));
+        }
+    }
+
+    #[test]
+    fn map_observe_reports_origin_and_target() {
+        let doc = YrsDoc::new();
+        let first = doc.get_map("first".to_string());
+        let second = doc.get_map("second".to_string());
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let _s1 = first.observe(Box::new(Targets(events.clone())));
+        let _s2 = second.observe(Box::new(Targets(events.clone())));
+
+        let txn = doc.transact(Some(yrs::Origin::from("local").into()));
+        second.insert(&txn, "key".to_string(), "1".to_string());
+        txn.free();
+
+        let ptr = crate::UniffiCustomTypeConverter::from_custom(second.raw_ptr());
+        assert_eq!(*events.lock(), vec![(ptr, Some(b"local".to_vec()))]);
+    }
+
+    struct SelfReading {
+        doc: Arc<YrsDoc>,
+        map: Arc<crate::map::YrsMap>,
+        reads: Arc<Mutex<u32>>,
+    }
+
+    impl std::fmt::Debug for SelfReading {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("SelfReading")
+        }
+    }
+
+    impl YrsMapObservationDelegate for SelfReading {
+        fn call(&self, value: Vec<YrsMapChange>, _origin: Option<YrsOrigin>, target: YrsCollectionPtr) {
+            // Reads the observed map in a transaction of its own, while other threads
+            // keep writing to it.
+            let own = crate::UniffiCustomTypeConverter::from_custom(self.map.raw_ptr());
+            assert_eq!(own, crate::UniffiCustomTypeConverter::from_custom(target));
+            let txn = self.doc.transact(None);
+            for change in value {
+                assert!(self.map.contains_key(&txn, change.key));
+                *self.reads.lock() += 1;
+            }
+            txn.free();
+        }
+    }
+
+    #[test]
+    fn map_observer_reads_same_map_from_many_threads() {
+        let doc = Arc::new(YrsDoc::new());
+        let map = doc.get_map("map".to_string());
+
+        let reads = Arc::new(Mutex::new(0));
+        // The committing transaction holds the document, so reads happen once it's done.
+        let _subscription = map.observe_with_delivery(
+            Box::new(SelfReading {
+                doc: doc.clone(),
+                map: map.clone(),
+                reads: reads.clone(),
+            }),
+            YrsObservationDelivery::Deferred,
+        );
+
+        let handles: Vec<_> = (0..4)
+            .map(|t| {
+                let doc = doc.clone();
+                let map = map.clone();
+                std::thread::spawn(move || {
+                    for i in 0..25 {
+                        let txn = doc.transact(None);
+                        map.insert(&txn, format!("{t}-{i}"), i.to_string());
+                        txn.free();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let txn = doc.transact(None);
+        assert_eq!(map.length(&txn), 100);
+        txn.free();
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while *reads.lock() < 100 {
+            assert!(std::time::Instant::now() < deadline, "observer never read every change");
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        assert_eq!(*reads.lock(), 100);
+    }
+
+    #[derive(Debug)]
+    struct Blocking {
+        release: Mutex<std::sync::mpsc::Receiver<()>>,
+        done: Mutex<std::sync::mpsc::Sender<usize>>,
+    }
+
+    impl YrsMapObservationDelegate for Blocking {
+        fn call(&self, value: Vec<YrsMapChange>, _origin: Option<YrsOrigin>, _target: YrsCollectionPtr) {
+            self.release.lock().recv().unwrap();
+            self.done.lock().send(value.len()).unwrap();
+        }
+    }
+
+    #[test]
+    fn map_deferred_observer_does_not_block_commit() {
+        let doc = YrsDoc::new();
+        let map = doc.get_map("map".to_string());
+        let (release, release_rx) = std::sync::mpsc::channel();
+        let (done_tx, done) = std::sync::mpsc::channel();
+        let _subscription = map.observe_with_delivery(
+            Box::new(Blocking {
+                release: Mutex::new(release_rx),
+                done: Mutex::new(done_tx),
+            }),
+            YrsObservationDelivery::Deferred,
+        );
+
+        // The delegate waits for `release`, so committing would hang if it ran inline.
+        let txn = doc.transact(None);
+        map.insert(&txn, "a".to_string(), "1".to_string());
+        map.insert(&txn, "b".to_string(), "2".to_string());
+        txn.free();
+
+        release.send(()).unwrap();
+        let received = done.recv_timeout(std::time::Duration::from_secs(5)).unwrap();
+        assert_eq!(received, 2);
+    }
+
+    #[derive(Default)]
+    struct Changes(Arc<Mutex<Vec<YrsMapChange>>>);
+
+    impl std::fmt::Debug for Changes {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("Changes")
+        }
+    }
+
+    impl YrsMapObservationDelegate for Changes {
+        fn call(&self, value: Vec<YrsMapChange>, _origin: Option<YrsOrigin>, _target: YrsCollectionPtr) {
+            self.0.lock().extend(value);
+        }
+    }
+
+    #[test]
+    fn map_observe_reports_shared_values() {
+        let doc = YrsDoc::new();
+        let map = doc.get_map("example_map".to_string());
+
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let _subscription = map.observe(Box::new(Changes(changes.clone())));
+
+        let txn = doc.transact(None);
+        map.insert_map(&txn, "row".to_string());
+        txn.free();
+
+        let txn = doc.transact(None);
+        map.insert(&txn, "row".to_string(), "1".to_string());
+        txn.free();
+
+        let changes = changes.lock();
+        assert_eq!(changes.len(), 2);
+        let YrsEntryChange::InsertedShared { handle } = &changes[0].change else {
+            panic!("expected a shared insertion");
+        };
+        assert_eq!(handle.kind, YrsSharedKind::Map);
+        assert!(handle.map.is_some());
+        let YrsEntryChange::UpdatedShared {
+            old_value,
+            old_handle,
+            new_value,
+            new_handle,
+        } = &changes[1].change
+        else {
+            panic!("expected a shared update");
+        };
+        assert!(old_value.is_none() && new_handle.is_none());
+        assert_eq!(old_handle.as_ref().unwrap().kind, YrsSharedKind::Map);
+        assert_eq!(new_value.as_deref(), Some("1"));
+    }
+
+    #[test]
+    fn map_get_or_insert_value_keeps_existing() {
+        let doc = YrsDoc::new();
+        let map = doc.get_map("example_map".to_string());
+
+        let txn = doc.transact(None);
+        let first = map
+            .get_or_insert_value(&txn, "count".to_string(), YrsValue::Number { value: 0.0 })
+            .unwrap();
+        assert_eq!(first, YrsValue::Number { value: 0.0 });
+
+        map.insert(&txn, "count".to_string(), "5".to_string());
+        let second = map
+            .get_or_insert_value(&txn, "count".to_string(), YrsValue::Number { value: 0.0 })
+            .unwrap();
+        assert_eq!(second, YrsValue::Number { value: 5.0 });
+
+        let shared = YrsValue::SharedRef { kind: YrsSharedKind::Map };
+        assert!(map.get_or_insert_value(&txn, "other".to_string(), shared).is_err());
+        assert!(!map.contains_key(&txn, "other".to_string()));
+    }
+
+    #[derive(Debug, Default)]
+    struct Entries(Arc<Mutex<Vec<(String, YrsValue)>>>);
+
+    impl YrsMapEntryDelegate for Entries {
+        fn call(&self, key: String, value: YrsValue) {
+            self.0.lock().push((key, value));
+        }
+    }
+
+    #[test]
+    fn map_each_entry_passes_typed_values() {
+        let doc = YrsDoc::new();
+        let map = doc.get_map("example_map".to_string());
+
+        let txn = doc.transact(None);
+        map.insert(&txn, "name".to_string(), "\"yrs\"".to_string());
+        map.insert_array(&txn, "items".to_string());
+
+        let entries = Arc::new(Mutex::new(Vec::new()));
+        map.each_entry(&txn, Box::new(Entries(entries.clone())));
+        let mut entries = entries.lock().clone();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            entries,
+            vec![
+                ("items".to_string(), YrsValue::SharedRef { kind: YrsSharedKind::Array }),
+                ("name".to_string(), YrsValue::String { value: "yrs".to_string() }),
+            ]
+        );
+    }
+
     #[test]
     fn map_clear() {
         let doc = YrsDoc::new();
@@ -625,6 +1423,282 @@ mod tests {
         assert_eq!(map.length(&txn), 0);
     }
 
+    #[test]
+    fn map_of_subdocs_syncs_by_key() {
+        let doc1 = YrsDoc::new();
+        let doc2 = YrsDoc::new();
+        let pages1 = doc1.get_map("pages".to_string());
+        let pages2 = doc2.get_map("pages".to_string());
+
+        let page = YrsDoc::new();
+        let txn = doc1.transact(None);
+        let inserted = pages1.insert_doc(&txn, "page-1".to_string(), &page);
+        assert_eq!(inserted.guid(), page.guid());
+        assert!(pages1.get_doc(&txn, "page-2".to_string()).is_none());
+        let update = txn.transaction_encode_state_as_update();
+        txn.free();
+
+        let txn = doc2.transact(None);
+        txn.transaction_apply_update(update).unwrap();
+        let synced = pages2.get_doc(&txn, "page-1".to_string()).unwrap();
+        assert_eq!(synced.guid(), page.guid());
+    }
+
+    #[test]
+    fn map_builds_nested_hierarchies() {
+        let doc = YrsDoc::new();
+        let root = doc.get_map("root".to_string());
+
+        let txn = doc.transact(None);
+        let sections = root.insert_array(&txn, "sections".to_string());
+        let section = sections.push_map(&txn);
+        section.insert(&txn, "title".to_string(), "\"Intro\"".to_string());
+        section.insert_text(&txn, "body".to_string()).append(&txn, "Hello".to_string());
+        txn.free();
+
+        let txn = doc.transact(None);
+        let sections = root.get_array(&txn, "sections".to_string()).unwrap();
+        let section = sections.get_map(&txn, 0).unwrap();
+        assert_eq!(section.get(&txn, "title".to_string()).unwrap(), "\"Intro\"");
+        let body = section.get_text(&txn, "body".to_string()).unwrap();
+        assert_eq!(body.get_string(&txn), "Hello");
+        assert!(root.get_map(&txn, "sections".to_string()).is_none());
+    }
+
+    #[test]
+    fn map_insert_all_is_all_or_nothing() {
+        let doc = YrsDoc::new();
+        let map = doc.get_map("example_map".to_string());
+
+        let txn = doc.transact(None);
+        let entries = (0..100)
+            .map(|i| YrsMapEntry {
+                key: format!("row-{i}"),
+                value: YrsValue::BigInt { value: i },
+            })
+            .collect();
+        map.insert_all(&txn, entries).unwrap();
+        assert_eq!(map.length(&txn), 100);
+        assert_eq!(
+            map.get_value(&txn, "row-42".to_string()),
+            Some(YrsValue::BigInt { value: 42 })
+        );
+
+        let result = map.insert_all(
+            &txn,
+            vec![
+                YrsMapEntry {
+                    key: "valid".to_string(),
+                    value: YrsValue::Null,
+                },
+                YrsMapEntry {
+                    key: "shared".to_string(),
+                    value: YrsValue::SharedRef { kind: YrsSharedKind::Map },
+                },
+            ],
+        );
+        assert!(result.is_err());
+        assert!(!map.contains_key(&txn, "valid".to_string()));
+    }
+
+    #[test]
+    fn map_typed_values_round_trip() {
+        let doc = YrsDoc::new();
+        let map = doc.get_map("example_map".to_string());
+
+        let txn = doc.transact(None);
+        map.insert_value(&txn, "count".to_string(), YrsValue::BigInt { value: 3 })
+            .unwrap();
+        map.insert_value(&txn, "blob".to_string(), YrsValue::Buffer { value: vec![1, 2] })
+            .unwrap();
+        map.insert_map(&txn, "nested".to_string());
+
+        assert_eq!(
+            map.get_value(&txn, "count".to_string()),
+            Some(YrsValue::BigInt { value: 3 })
+        );
+        assert_eq!(
+            map.get_value(&txn, "blob".to_string()),
+            Some(YrsValue::Buffer { value: vec![1, 2] })
+        );
+        assert_eq!(
+            map.get_value(&txn, "nested".to_string()),
+            Some(YrsValue::SharedRef { kind: YrsSharedKind::Map })
+        );
+        assert_eq!(map.get_value(&txn, "missing".to_string()), None);
+    }
+
+    #[derive(Debug, Default)]
+    struct KeyChanges(Arc<Mutex<Vec<YrsEntryValueChange>>>);
+
+    impl YrsMapKeyObservationDelegate for KeyChanges {
+        fn call(&self, change: YrsEntryValueChange, _origin: Option<YrsOrigin>) {
+            self.0.lock().push(change);
+        }
+    }
+
+    #[test]
+    fn map_observe_key_ignores_other_keys() {
+        let doc = YrsDoc::new();
+        let map = doc.get_map("example_map".to_string());
+
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let _subscription = map.observe_key(
+            "title".to_string(),
+            Box::new(KeyChanges(changes.clone())),
+        );
+
+        let txn = doc.transact(None);
+        map.insert(&txn, "body".to_string(), "\"text\"".to_string());
+        txn.free();
+        assert!(changes.lock().is_empty());
+
+        let txn = doc.transact(None);
+        map.insert(&txn, "title".to_string(), "\"draft\"".to_string());
+        map.insert(&txn, "body".to_string(), "\"more\"".to_string());
+        txn.free();
+        let txn = doc.transact(None);
+        map.remove(&txn, "title".to_string()).unwrap();
+        txn.free();
+
+        assert_eq!(
+            *changes.lock(),
+            vec![
+                YrsEntryValueChange::Inserted { value: YrsValue::String { value: "draft".to_string() } },
+                YrsEntryValueChange::Removed { value: YrsValue::String { value: "draft".to_string() } },
+            ]
+        );
+    }
+
+    #[derive(Debug, Default)]
+    struct ValueChanges(Arc<Mutex<Vec<YrsMapValueChange>>>);
+
+    impl YrsMapValueObservationDelegate for ValueChanges {
+        fn call(&self, value: Vec<YrsMapValueChange>) {
+            self.0.lock().extend(value);
+        }
+    }
+
+    #[test]
+    fn map_observe_values_reports_typed_changes() {
+        let doc = YrsDoc::new();
+        let map = doc.get_map("example_map".to_string());
+
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let _subscription = map.observe_values(Box::new(ValueChanges(changes.clone())));
+
+        let txn = doc.transact(None);
+        map.insert_value(&txn, "count".to_string(), YrsValue::Number { value: 1.5 })
+            .unwrap();
+        map.insert_text(&txn, "notes".to_string());
+        txn.free();
+
+        let mut changes = changes.lock();
+        changes.sort_by(|a, b| a.key.cmp(&b.key));
+        assert_eq!(changes.len(), 2);
+        assert!(matches!(
+            &changes[0].change,
+            YrsEntryValueChange::Inserted { value: YrsValue::Number { value } } if *value == 1.5
+        ));
+        assert!(matches!(
+            &changes[1].change,
+            YrsEntryValueChange::Inserted {
+                value: YrsValue::SharedRef { kind: YrsSharedKind::Text }
+            }
+        ));
+    }
+
+    #[test]
+    fn map_iterator_yields_entries_lazily() {
+        let doc = YrsDoc::new();
+        let map = doc.get_map("example_map".to_string());
+
+        let txn = doc.transact(None);
+        map.insert(&txn, "a".to_string(), "1".to_string());
+        map.insert(&txn, "b".to_string(), "2".to_string());
+        map.insert(&txn, "c".to_string(), "3".to_string());
+
+        let iter = map.iter(txn.clone());
+        let first = iter.next().unwrap();
+        let removed = if first.key == "a" { "b" } else { "a" };
+        map.remove(&txn, removed.to_string()).unwrap();
+
+        let mut keys = vec![first.key];
+        while let Some(entry) = iter.next() {
+            keys.push(entry.key);
+        }
+        assert_eq!(keys.len(), 2);
+        assert!(!keys.contains(&removed.to_string()));
+
+        let iter = map.iter(txn.clone());
+        txn.free();
+        assert!(iter.next().is_none());
+    }
+
+    #[derive(Debug, Default)]
+    struct DeepPaths(Arc<Mutex<Vec<Vec<String>>>>);
+
+    impl YrsDeepObservationDelegate for DeepPaths {
+        fn call(&self, events: Vec<YrsEvent>, _origin: Option<YrsOrigin>) {
+            for event in events {
+                let path = match event {
+                    YrsEvent::Array { path, .. }
+                    | YrsEvent::Map { path, .. }
+                    | YrsEvent::Text { path, .. }
+                    | YrsEvent::Xml { path, .. }
+                    | YrsEvent::XmlText { path, .. }
+                    | YrsEvent::Other { path } => path,
+                };
+                let path = path
+                    .into_iter()
+                    .map(|segment| match segment {
+                        YrsPathSegment::Key { key } => key,
+                        YrsPathSegment::Index { index } => index.to_string(),
+                    })
+                    .collect();
+                self.0.lock().push(path);
+            }
+        }
+    }
+
+    #[test]
+    fn map_observe_deep_reports_nested_paths() {
+        let doc = YrsDoc::new();
+        let map = doc.get_map("example_map".to_string());
+
+        let txn = doc.transact(None);
+        let todos = map.insert_array(&txn, "todos".to_string());
+        let todo = todos.push_map(&txn);
+        txn.free();
+
+        let paths = Arc::new(Mutex::new(Vec::new()));
+        let _subscription = map.observe_deep(Box::new(DeepPaths(paths.clone())));
+
+        let txn = doc.transact(None);
+        todo.insert(&txn, "done".to_string(), "true".to_string());
+        txn.free();
+
+        assert_eq!(*paths.lock(), vec![vec!["todos".to_string(), "0".to_string()]]);
+    }
+
+    #[test]
+    fn map_get_distinguishes_missing_and_shared_values() {
+        let doc = YrsDoc::new();
+        let map = doc.get_map("example_map".to_string());
+
+        let txn = doc.transact(None);
+        map.insert_map(&txn, "nested".to_string());
+
+        assert!(matches!(
+            map.get(&txn, "missing".to_string()),
+            Err(CodingError::OutOfBounds)
+        ));
+        assert!(matches!(
+            map.get(&txn, "nested".to_string()),
+            Err(CodingError::UnexpectedType)
+        ));
+    }
+
     /*
         ## The section below is Joe trying to sort out the pieces to make a unit test
         that "works" the code structure when you invoke "keys" - which involves multiple
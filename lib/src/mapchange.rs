@@ -1,3 +1,5 @@
+use crate::change::YrsSharedHandle;
+use crate::value::YrsValue;
 use yrs::types::EntryChange;
 use yrs::Out;
 
@@ -17,57 +19,108 @@ pub enum YrsEntryChange {
     Removed {
         value: String,
     },
+    InsertedShared {
+        handle: YrsSharedHandle,
+    },
+    /// An update where the old value, the new value, or both are shared types.
+    /// Each side carries either its JSON value or its handle.
+    UpdatedShared {
+        old_value: Option<String>,
+        old_handle: Option<YrsSharedHandle>,
+        new_value: Option<String>,
+        new_handle: Option<YrsSharedHandle>,
+    },
+    RemovedShared {
+        handle: YrsSharedHandle,
+    },
 }
 
-/// Attempts to convert an EntryChange to YrsEntryChange.
-/// Returns None if the change involves nested shared types (YMap, YArray, YText, YDoc, etc.)
-/// which should be accessed via dedicated methods instead.
-pub fn try_from_entry_change(key: &str, item: &EntryChange) -> Option<YrsMapChange> {
+fn to_json(value: &Out) -> Option<String> {
+    if let Out::Any(any) = value {
+        let mut buf = String::new();
+        any.to_json(&mut buf);
+        Some(buf)
+    } else {
+        None
+    }
+}
+
+/// Converts an EntryChange to YrsEntryChange. Changes involving nested shared types
+/// (YMap, YArray, YText, YDoc, etc.) carry a handle to the shared value.
+pub fn from_entry_change(key: &str, item: &EntryChange) -> YrsMapChange {
     let change = match item {
-        EntryChange::Inserted(value) => match value {
-            Out::Any(val) => {
-                let mut buf = String::new();
-                val.to_json(&mut buf);
-                YrsEntryChange::Inserted { value: buf }
-            }
-            // Skip nested shared types - they should be accessed via dedicated methods
-            Out::YMap(_)
-            | Out::YArray(_)
-            | Out::YText(_)
-            | Out::YXmlElement(_)
-            | Out::YXmlFragment(_)
-            | Out::YXmlText(_)
-            | Out::YDoc(_)
-            | Out::UndefinedRef(_) => return None,
+        EntryChange::Inserted(value) => match to_json(value) {
+            Some(value) => YrsEntryChange::Inserted { value },
+            None => YrsEntryChange::InsertedShared {
+                handle: shared_handle(value),
+            },
         },
         EntryChange::Updated(old_value, new_value) => {
-            if let (Out::Any(old), Out::Any(new)) = (old_value, new_value) {
-                let mut old_string = String::new();
-                let mut new_string = String::new();
-                old.to_json(&mut old_string);
-                new.to_json(&mut new_string);
-                YrsEntryChange::Updated {
-                    old_value: old_string,
-                    new_value: new_string,
-                }
-            } else {
-                // Skip nested shared types
-                return None;
-            }
-        }
-        EntryChange::Removed(value) => {
-            if let Out::Any(val) = value {
-                let mut buf = String::new();
-                val.to_json(&mut buf);
-                YrsEntryChange::Removed { value: buf }
-            } else {
-                // Skip nested shared types
-                return None;
+            match (to_json(old_value), to_json(new_value)) {
+                (Some(old_value), Some(new_value)) => YrsEntryChange::Updated {
+                    old_value,
+                    new_value,
+                },
+                (old_json, new_json) => YrsEntryChange::UpdatedShared {
+                    old_handle: old_json.is_none().then(|| shared_handle(old_value)),
+                    old_value: old_json,
+                    new_handle: new_json.is_none().then(|| shared_handle(new_value)),
+                    new_value: new_json,
+                },
             }
         }
+        EntryChange::Removed(value) => match to_json(value) {
+            Some(value) => YrsEntryChange::Removed { value },
+            None => YrsEntryChange::RemovedShared {
+                handle: shared_handle(value),
+            },
+        },
     };
-    Some(YrsMapChange {
+    YrsMapChange {
         key: key.to_string(),
         change,
-    })
+    }
+}
+
+fn shared_handle(value: &Out) -> YrsSharedHandle {
+    YrsSharedHandle::new(value).expect("non-JSON values are shared types")
+}
+
+/// A change to a single map entry, carrying typed values. Unlike `YrsMapChange`,
+/// changes involving nested shared types are reported rather than skipped.
+#[derive(Debug)]
+pub struct YrsMapValueChange {
+    pub key: String,
+    pub change: YrsEntryValueChange,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum YrsEntryValueChange {
+    Inserted {
+        value: YrsValue,
+    },
+    Updated {
+        old_value: YrsValue,
+        new_value: YrsValue,
+    },
+    Removed {
+        value: YrsValue,
+    },
+}
+
+impl From<&EntryChange> for YrsEntryValueChange {
+    fn from(item: &EntryChange) -> Self {
+        match item {
+            EntryChange::Inserted(value) => YrsEntryValueChange::Inserted {
+                value: YrsValue::from(value.clone()),
+            },
+            EntryChange::Updated(old_value, new_value) => YrsEntryValueChange::Updated {
+                old_value: YrsValue::from(old_value.clone()),
+                new_value: YrsValue::from(new_value.clone()),
+            },
+            EntryChange::Removed(value) => YrsEntryValueChange::Removed {
+                value: YrsValue::from(value.clone()),
+            },
+        }
+    }
 }
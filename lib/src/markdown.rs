@@ -0,0 +1,275 @@
+use crate::text::{checked_index, YrsText, YrsTextError};
+use crate::transaction::YrsTransaction;
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use std::collections::HashMap;
+use std::sync::Arc;
+use yrs::types::Attrs;
+use yrs::{Any, Text};
+
+/// A piece of content produced while converting Markdown, ready to be inserted into a text.
+enum Segment {
+    Text(String, Attrs),
+    Embed(Any, Attrs),
+}
+
+/// Converts Markdown events into text segments carrying Quill-style formatting attributes:
+/// inline `bold`, `italic`, `strike`, `code` and `link` attributes, and line-level `header`,
+/// `list`, `blockquote` and `code-block` attributes set on the newline ending each line.
+#[derive(Default)]
+struct Importer {
+    segments: Vec<Segment>,
+    inline: Vec<(Arc<str>, Any)>,
+    heading: Option<u32>,
+    lists: Vec<Option<u64>>,
+    quote_depth: usize,
+    in_code_block: bool,
+    image_depth: usize,
+    line_open: bool,
+}
+
+impl Importer {
+    fn inline_attrs(&self) -> Attrs {
+        self.inline.iter().cloned().collect()
+    }
+
+    fn line_attrs(&self) -> Attrs {
+        let mut attrs = Attrs::new();
+        if let Some(level) = self.heading {
+            attrs.insert(Arc::from("header"), Any::from(level as f64));
+        }
+        if let Some(list) = self.lists.last() {
+            let kind = if list.is_some() { "ordered" } else { "bullet" };
+            attrs.insert(Arc::from("list"), Any::from(kind));
+        }
+        if self.quote_depth > 0 {
+            attrs.insert(Arc::from("blockquote"), Any::Bool(true));
+        }
+        if self.in_code_block {
+            attrs.insert(Arc::from("code-block"), Any::Bool(true));
+        }
+        attrs
+    }
+
+    fn push_text(&mut self, text: &str, attrs: Attrs) {
+        if text.is_empty() {
+            return;
+        }
+        self.line_open = true;
+        if let Some(Segment::Text(last, last_attrs)) = self.segments.last_mut() {
+            if *last_attrs == attrs {
+                last.push_str(text);
+                return;
+            }
+        }
+        self.segments.push(Segment::Text(text.to_string(), attrs));
+    }
+
+    fn end_line(&mut self) {
+        if self.line_open {
+            let attrs = self.line_attrs();
+            self.segments.push(Segment::Text("\n".to_string(), attrs));
+            self.line_open = false;
+        }
+    }
+
+    fn pop_inline(&mut self, key: &str) {
+        if let Some(pos) = self.inline.iter().rposition(|(k, _)| k.as_ref() == key) {
+            self.inline.remove(pos);
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Heading { level, .. } => {
+                self.end_line();
+                self.heading = Some(heading_level(level));
+            }
+            Tag::List(start) => {
+                self.end_line();
+                self.lists.push(start);
+            }
+            Tag::BlockQuote(_) => {
+                self.end_line();
+                self.quote_depth += 1;
+            }
+            Tag::CodeBlock(_) => {
+                self.end_line();
+                self.in_code_block = true;
+            }
+            Tag::Emphasis => self.inline.push((Arc::from("italic"), Any::Bool(true))),
+            Tag::Strong => self.inline.push((Arc::from("bold"), Any::Bool(true))),
+            Tag::Strikethrough => self.inline.push((Arc::from("strike"), Any::Bool(true))),
+            Tag::Link { dest_url, .. } => {
+                self.inline.push((Arc::from("link"), Any::from(dest_url.as_ref())))
+            }
+            Tag::Image { dest_url, .. } => {
+                if self.image_depth == 0 {
+                    let embed = Any::Map(Arc::new(HashMap::from([(
+                        "image".to_string(),
+                        Any::from(dest_url.as_ref()),
+                    )])));
+                    self.segments.push(Segment::Embed(embed, self.inline_attrs()));
+                    self.line_open = true;
+                }
+                self.image_depth += 1;
+            }
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph | TagEnd::Item => self.end_line(),
+            TagEnd::Heading(_) => {
+                self.end_line();
+                self.heading = None;
+            }
+            TagEnd::List(_) => {
+                self.end_line();
+                self.lists.pop();
+            }
+            TagEnd::BlockQuote(_) => {
+                self.end_line();
+                self.quote_depth = self.quote_depth.saturating_sub(1);
+            }
+            TagEnd::CodeBlock => {
+                self.end_line();
+                self.in_code_block = false;
+            }
+            TagEnd::Emphasis => self.pop_inline("italic"),
+            TagEnd::Strong => self.pop_inline("bold"),
+            TagEnd::Strikethrough => self.pop_inline("strike"),
+            TagEnd::Link => self.pop_inline("link"),
+            TagEnd::Image => self.image_depth = self.image_depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if self.image_depth > 0 {
+            // alt text of an image is not part of the document content
+            return;
+        }
+        if self.in_code_block {
+            for line in text.split_inclusive('\n') {
+                match line.strip_suffix('\n') {
+                    Some(content) => {
+                        self.push_text(content, Attrs::new());
+                        self.line_open = true;
+                        self.end_line();
+                    }
+                    None => self.push_text(line, Attrs::new()),
+                }
+            }
+        } else {
+            self.push_text(text, self.inline_attrs());
+        }
+    }
+
+    fn process(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) | Event::Html(text) | Event::InlineHtml(text) => self.text(&text),
+            Event::Code(code) => {
+                let mut attrs = self.inline_attrs();
+                attrs.insert(Arc::from("code"), Any::Bool(true));
+                self.push_text(&code, attrs);
+            }
+            Event::SoftBreak => self.push_text(" ", self.inline_attrs()),
+            Event::HardBreak => {
+                self.line_open = true;
+                self.end_line();
+            }
+            _ => {}
+        }
+    }
+}
+
+fn heading_level(level: HeadingLevel) -> u32 {
+    match level {
+        HeadingLevel::H1 => 1,
+        HeadingLevel::H2 => 2,
+        HeadingLevel::H3 => 3,
+        HeadingLevel::H4 => 4,
+        HeadingLevel::H5 => 5,
+        HeadingLevel::H6 => 6,
+    }
+}
+
+fn parse(markdown: &str) -> Vec<Segment> {
+    let mut importer = Importer::default();
+    for event in Parser::new_ext(markdown, Options::ENABLE_STRIKETHROUGH) {
+        importer.process(event);
+    }
+    importer.end_line();
+    importer.segments
+}
+
+impl YrsText {
+    /// Parses `markdown` and inserts the result at `index`, translating inline styles and
+    /// block structure into formatting attributes. Returns the length of inserted content.
+    pub(crate) fn insert_markdown(
+        &self,
+        transaction: &YrsTransaction,
+        index: u32,
+        markdown: String,
+    ) -> Result<u32, YrsTextError> {
+        let segments = parse(&markdown);
+
+        let mut tx = transaction.transaction();
+        let tx = tx.as_mut().unwrap();
+
+        let mut text = self.inner();
+        let index = checked_index(text.as_ref(), tx, transaction.surrogate_policy(), index)?;
+        let mut position = index;
+        for segment in segments {
+            match segment {
+                Segment::Text(chunk, attrs) => {
+                    text.as_mut()
+                        .insert_with_attributes(tx, position, chunk.as_str(), attrs);
+                    position += chunk.encode_utf16().count() as u32;
+                }
+                Segment::Embed(embed, attrs) => {
+                    text.as_mut()
+                        .insert_embed_with_attributes(tx, position, embed, attrs);
+                    position += 1;
+                }
+            }
+        }
+        Ok(position - index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::value::YrsValue;
+    use crate::YrsDoc;
+
+    #[test]
+    fn markdown_import_applies_formatting() {
+        let doc = YrsDoc::new();
+        let text = doc.get_text("text".to_string());
+
+        let txn = doc.transact(None);
+        let len = text.insert_markdown(
+            &txn,
+            0,
+            "# Title\n\nSome **bold** and [a link](https://example.com).".to_string(),
+        ).unwrap();
+
+        assert_eq!(text.get_string(&txn), "Title\nSome bold and a link.\n");
+        assert_eq!(len, text.length(&txn));
+
+        let runs = text.runs(&txn);
+        let header = runs.iter().find(|r| r.text == "\n").unwrap();
+        assert_eq!(header.attrs.get("header"), Some(&YrsValue::Number { value: 1.0 }));
+        let bold = runs.iter().find(|r| r.text == "bold").unwrap();
+        assert_eq!(bold.attrs.get("bold"), Some(&YrsValue::Bool { value: true }));
+        let link = runs.iter().find(|r| r.text == "a link").unwrap();
+        assert_eq!(
+            link.attrs.get("link"),
+            Some(&YrsValue::String { value: "https://example.com".to_string() })
+        );
+    }
+}
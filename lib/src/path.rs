@@ -0,0 +1,69 @@
+use yrs::branch::{Branch, BranchID};
+use yrs::types::{Path, PathSegment};
+use yrs::{Assoc, IndexScope, Map, MapRef, Out, ReadTxn, StickyIndex};
+
+/// A single segment of a path from a root shared type to a nested one.
+pub(crate) enum YrsPathSegment {
+    Key { key: String },
+    Index { index: u32 },
+}
+
+impl From<PathSegment> for YrsPathSegment {
+    fn from(value: PathSegment) -> Self {
+        match value {
+            PathSegment::Key(key) => YrsPathSegment::Key { key: key.to_string() },
+            PathSegment::Index(index) => YrsPathSegment::Index { index },
+        }
+    }
+}
+
+/// Converts a yrs event path into its FFI representation, ordered from the root.
+pub(crate) fn path_segments(path: Path) -> Vec<YrsPathSegment> {
+    path.into_iter().map(YrsPathSegment::from).collect()
+}
+
+/// Returns the path from the document root to `target`, starting with the name of
+/// the root type. yrs only exposes paths relative to an observed type, so the path is
+/// built by walking up from `target` through the parents of the types it's nested in.
+pub(crate) fn root_path<T: ReadTxn>(txn: &T, target: &Branch) -> Path {
+    let mut path = Path::new();
+    let mut id = target.id();
+    loop {
+        match id {
+            BranchID::Root(name) => {
+                path.push_front(PathSegment::Key(name));
+                return path;
+            }
+            BranchID::Nested(item) => {
+                // the offset of a type's own item is its position within its parent
+                let position = StickyIndex::new(IndexScope::Relative(item), Assoc::After);
+                let Some(offset) = position.get_offset(txn) else {
+                    return path;
+                };
+                let parent = MapRef::from(offset.branch);
+                let key = parent
+                    .iter(txn)
+                    .find(|(_, value)| is_branch(value, &id))
+                    .map(|(key, _)| key.into());
+                path.push_front(match key {
+                    Some(key) => PathSegment::Key(key),
+                    None => PathSegment::Index(offset.index),
+                });
+                id = offset.branch.id();
+            }
+        }
+    }
+}
+
+fn is_branch(value: &Out, id: &BranchID) -> bool {
+    let branch: &Branch = match value {
+        Out::YText(text) => text.as_ref(),
+        Out::YArray(array) => array.as_ref(),
+        Out::YMap(map) => map.as_ref(),
+        Out::YXmlElement(element) => element.as_ref(),
+        Out::YXmlFragment(fragment) => fragment.as_ref(),
+        Out::YXmlText(text) => text.as_ref(),
+        _ => return false,
+    };
+    branch.id() == *id
+}
@@ -0,0 +1,486 @@
+use crate::doc::YrsDoc;
+use crate::encryption::{Encryption, YrsEncryptionDelegate};
+use crate::filestore::FileStore;
+use parking_lot::Mutex;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU32, Ordering};
+use yrs::updates::decoder::Decode;
+use yrs::updates::encoder::Encode;
+use yrs::{merge_updates_v1, DeleteSet, StateVector, Transact, Update};
+
+/// Error that can occur when storing or loading document updates.
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum YrsPersistenceError {
+    #[error("Stored data could not be decoded")]
+    DecodingError,
+    #[error("Storage failed: {message}")]
+    IoError { message: String },
+    #[error("Stored update could not be decrypted")]
+    DecryptionFailed,
+}
+
+impl From<std::io::Error> for YrsPersistenceError {
+    fn from(value: std::io::Error) -> Self {
+        YrsPersistenceError::IoError {
+            message: value.to_string(),
+        }
+    }
+}
+
+/// Key-value storage provided by the app, e.g. backed by files or a database.
+pub(crate) trait YrsStorageDelegate: Send + Sync + Debug {
+    fn get(&self, key: String) -> Option<Vec<u8>>;
+    fn put(&self, key: String, value: Vec<u8>);
+    fn delete(&self, key: String);
+    /// Returns the keys starting with `prefix`, in any order.
+    fn keys(&self, prefix: String) -> Vec<String>;
+}
+
+/// Told about the updates of a document that couldn't be stored, along with why.
+pub(crate) trait YrsPersistenceFailureDelegate: Send + Sync + Debug {
+    fn call(&self, guid: String, message: String);
+}
+
+/// Where the updates of each document are kept. Writes are serialized by the caller.
+pub(crate) trait UpdateStore: Send + Sync {
+    /// Returns the stored updates of the document, oldest first.
+    fn updates(&self, guid: &str) -> Result<Vec<Vec<u8>>, YrsPersistenceError>;
+    fn append(&self, guid: &str, update: Vec<u8>) -> Result<(), YrsPersistenceError>;
+    /// Replaces all the stored updates of the document with a single one.
+    fn replace(&self, guid: &str, update: Vec<u8>) -> Result<(), YrsPersistenceError>;
+    /// Returns the number of stored updates of the document.
+    fn len(&self, guid: &str) -> Result<usize, YrsPersistenceError> {
+        Ok(self.updates(guid)?.len())
+    }
+    fn state_vector(&self, guid: &str) -> Result<Option<Vec<u8>>, YrsPersistenceError>;
+    fn set_state_vector(&self, guid: &str, state_vector: Vec<u8>) -> Result<(), YrsPersistenceError>;
+    fn clear(&self, guid: &str) -> Result<(), YrsPersistenceError>;
+}
+
+/// Stores the updates of documents, keyed by document guid, so they can be loaded
+/// again when the documents are reopened. Along with the updates, the state vector
+/// of everything stored for each document is kept.
+pub(crate) struct YrsPersistence {
+    store: Box<dyn UpdateStore>,
+    /// Serializes writes, which read the stored state before changing it.
+    writing: Mutex<()>,
+    compact_after: AtomicU32,
+    encryption: Encryption,
+}
+
+impl YrsPersistence {
+    pub(crate) fn new(storage: Box<dyn YrsStorageDelegate>) -> Self {
+        Self::with_store(Box::new(DelegateStore { storage }))
+    }
+
+    /// Creates a persistence keeping update logs in files inside `path`, which is
+    /// created if needed.
+    pub(crate) fn with_directory(path: String) -> Result<Self, YrsPersistenceError> {
+        Ok(Self::with_store(Box::new(FileStore::open(path)?)))
+    }
+
+    fn with_store(store: Box<dyn UpdateStore>) -> Self {
+        YrsPersistence {
+            store,
+            writing: Mutex::new(()),
+            compact_after: AtomicU32::new(0),
+            encryption: Encryption::default(),
+        }
+    }
+
+    /// Encrypts updates from now on before they are stored, and decrypts them when they
+    /// are read. Updates stored before are expected to be encrypted with the same
+    /// delegate. State vectors are stored as they are.
+    pub(crate) fn set_encryption(&self, delegate: Box<dyn YrsEncryptionDelegate>) {
+        self.encryption.set(delegate);
+    }
+
+    /// Appends an update of the document with the given guid, compacting the stored
+    /// updates once there are as many as the `compact_after` policy allows.
+    pub(crate) fn store_update(
+        &self,
+        guid: String,
+        update: Vec<u8>,
+    ) -> Result<(), YrsPersistenceError> {
+        let decoded =
+            Update::decode_v1(&update).map_err(|_e| YrsPersistenceError::DecodingError)?;
+        let _writing = self.writing.lock();
+        let mut state_vector = self.stored_state_vector(&guid)?;
+        state_vector.merge(upper_state_vector(&decoded));
+
+        self.store.append(&guid, self.encryption.encrypt(update))?;
+        self.store.set_state_vector(&guid, state_vector.encode_v1())?;
+
+        let compact_after = self.compact_after.load(Ordering::Relaxed) as usize;
+        if compact_after > 0 && self.store.len(&guid)? >= compact_after {
+            self.compact_stored(&guid)?;
+        }
+        Ok(())
+    }
+
+    /// Merges all the stored updates of the document into a single one.
+    pub(crate) fn compact(&self, guid: String) -> Result<(), YrsPersistenceError> {
+        let _writing = self.writing.lock();
+        self.compact_stored(&guid)
+    }
+
+    /// Returns the number of stored updates after which a document is compacted, or 0
+    /// when documents are only compacted on request.
+    pub(crate) fn compact_after(&self) -> u32 {
+        self.compact_after.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_compact_after(&self, count: u32) {
+        self.compact_after.store(count, Ordering::Relaxed);
+    }
+
+    fn compact_stored(&self, guid: &str) -> Result<(), YrsPersistenceError> {
+        let updates = self.decrypted_updates(guid)?;
+        if updates.len() < 2 {
+            return Ok(());
+        }
+        let merged =
+            merge_updates_v1(&updates).map_err(|_e| YrsPersistenceError::DecodingError)?;
+        self.store.replace(guid, self.encryption.encrypt(merged))
+    }
+
+    /// Returns the stored updates of the document, oldest first.
+    pub(crate) fn updates(&self, guid: String) -> Result<Vec<Vec<u8>>, YrsPersistenceError> {
+        self.decrypted_updates(&guid)
+    }
+
+    fn decrypted_updates(&self, guid: &str) -> Result<Vec<Vec<u8>>, YrsPersistenceError> {
+        self.store
+            .updates(guid)?
+            .into_iter()
+            .map(|update| {
+                self.encryption
+                    .decrypt(update)
+                    .ok_or(YrsPersistenceError::DecryptionFailed)
+            })
+            .collect()
+    }
+
+    /// Applies every stored update of the document with the given guid to `doc`. Must
+    /// not be called while a transaction on the document is open.
+    pub(crate) fn load(&self, guid: String, doc: &YrsDoc) -> Result<(), YrsPersistenceError> {
+        let updates = self
+            .updates(guid)?
+            .iter()
+            .map(|update| {
+                Update::decode_v1(update).map_err(|_e| YrsPersistenceError::DecodingError)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let doc = doc.inner();
+        let mut txn = doc.transact_mut();
+        for update in updates {
+            txn.apply_update(update)
+                .map_err(|_e| YrsPersistenceError::DecodingError)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the encoded state vector of everything stored for the document.
+    pub(crate) fn state_vector(&self, guid: String) -> Result<Vec<u8>, YrsPersistenceError> {
+        Ok(self.stored_state_vector(&guid)?.encode_v1())
+    }
+
+    /// Deletes everything stored for the document.
+    pub(crate) fn clear(&self, guid: String) -> Result<(), YrsPersistenceError> {
+        let _writing = self.writing.lock();
+        self.store.clear(&guid)
+    }
+
+    fn stored_state_vector(&self, guid: &str) -> Result<StateVector, YrsPersistenceError> {
+        match self.store.state_vector(guid)? {
+            Some(bytes) => {
+                StateVector::decode_v1(&bytes).map_err(|_e| YrsPersistenceError::DecodingError)
+            }
+            None => Ok(StateVector::default()),
+        }
+    }
+}
+
+/// Keeps each update under its own key, `<guid>/update/<sequence number>`, next to
+/// `<guid>/state_vector`.
+struct DelegateStore {
+    storage: Box<dyn YrsStorageDelegate>,
+}
+
+impl DelegateStore {
+    /// Returns the keys of the stored updates with their sequence numbers, in order.
+    fn update_keys(&self, guid: &str) -> Vec<(u64, String)> {
+        let prefix = update_key_prefix(guid);
+        let mut keys: Vec<_> = self
+            .storage
+            .keys(prefix.clone())
+            .into_iter()
+            .filter_map(|key| Some((key.strip_prefix(&prefix)?.parse().ok()?, key)))
+            .collect();
+        keys.sort();
+        keys
+    }
+}
+
+impl UpdateStore for DelegateStore {
+    fn updates(&self, guid: &str) -> Result<Vec<Vec<u8>>, YrsPersistenceError> {
+        Ok(self
+            .update_keys(guid)
+            .into_iter()
+            .filter_map(|(_, key)| self.storage.get(key))
+            .collect())
+    }
+
+    fn append(&self, guid: &str, update: Vec<u8>) -> Result<(), YrsPersistenceError> {
+        let next = self.update_keys(guid).last().map_or(0, |(seq, _)| seq + 1);
+        self.storage.put(update_key(guid, next), update);
+        Ok(())
+    }
+
+    fn replace(&self, guid: &str, update: Vec<u8>) -> Result<(), YrsPersistenceError> {
+        // The merged update is stored before the ones it replaces are deleted, so that
+        // nothing is lost if the app stops in between. Applying updates twice is harmless.
+        let keys = self.update_keys(guid);
+        let next = keys.last().map_or(0, |(seq, _)| seq + 1);
+        self.storage.put(update_key(guid, next), update);
+        for (_, key) in keys {
+            self.storage.delete(key);
+        }
+        Ok(())
+    }
+
+    fn len(&self, guid: &str) -> Result<usize, YrsPersistenceError> {
+        Ok(self.update_keys(guid).len())
+    }
+
+    fn state_vector(&self, guid: &str) -> Result<Option<Vec<u8>>, YrsPersistenceError> {
+        Ok(self.storage.get(state_vector_key(guid)))
+    }
+
+    fn set_state_vector(&self, guid: &str, state_vector: Vec<u8>) -> Result<(), YrsPersistenceError> {
+        self.storage.put(state_vector_key(guid), state_vector);
+        Ok(())
+    }
+
+    fn clear(&self, guid: &str) -> Result<(), YrsPersistenceError> {
+        // Only this document's own keys are deleted: a prefix scan on `{guid}/` would
+        // also match documents whose guid starts with `{guid}/`.
+        for (_, key) in self.update_keys(guid) {
+            self.storage.delete(key);
+        }
+        self.storage.delete(state_vector_key(guid));
+        Ok(())
+    }
+}
+
+/// Returns the clocks following the last blocks inserted by the update. Unlike
+/// `Update::state_vector`, this covers incremental updates, which don't start at clock 0.
+fn upper_state_vector(update: &Update) -> StateVector {
+    let mut state_vector = StateVector::default();
+    for (client, range) in DeleteSet::from(update.insertions(true)).iter() {
+        if let Some(end) = range.iter().map(|r| r.end).max() {
+            state_vector.set_max(*client, end);
+        }
+    }
+    state_vector
+}
+
+fn update_key_prefix(guid: &str) -> String {
+    format!("{guid}/update/")
+}
+
+fn update_key(guid: &str, seq: u64) -> String {
+    format!("{}{seq}", update_key_prefix(guid))
+}
+
+fn state_vector_key(guid: &str) -> String {
+    format!("{guid}/state_vector")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{YrsPersistence, YrsPersistenceError, YrsStorageDelegate};
+    use crate::doc::YrsDoc;
+    use crate::encryption::tests::XorCipher;
+    use parking_lot::Mutex;
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use yrs::updates::decoder::Decode;
+    use yrs::{ReadTxn, StateVector, Transact};
+
+    #[derive(Debug, Default, Clone)]
+    struct MemoryStorage(Arc<Mutex<BTreeMap<String, Vec<u8>>>>);
+
+    impl YrsStorageDelegate for MemoryStorage {
+        fn get(&self, key: String) -> Option<Vec<u8>> {
+            self.0.lock().get(&key).cloned()
+        }
+
+        fn put(&self, key: String, value: Vec<u8>) {
+            self.0.lock().insert(key, value);
+        }
+
+        fn delete(&self, key: String) {
+            self.0.lock().remove(&key);
+        }
+
+        fn keys(&self, prefix: String) -> Vec<String> {
+            let keys = self.0.lock();
+            // Reversed, to check that callers don't rely on the order.
+            keys.keys().rev().filter(|key| key.starts_with(&prefix)).cloned().collect()
+        }
+    }
+
+    /// Inserts text at the end of the document's "text", returning the update.
+    fn edit(doc: &YrsDoc, content: &str) -> Vec<u8> {
+        let text = doc.get_text("text".to_string());
+        let txn = doc.transact(None);
+        let len = text.length(&txn);
+        text.insert(&txn, len, content.to_string()).unwrap();
+        let update = txn.transaction_encode_update();
+        txn.free();
+        update
+    }
+
+    #[test]
+    fn stored_updates_load_into_new_document() {
+        let storage = MemoryStorage::default();
+        let persistence = YrsPersistence::new(Box::new(storage.clone()));
+        let doc = YrsDoc::new();
+        // More than ten updates, so that sequence numbers don't sort as strings.
+        for i in 0..12 {
+            let update = edit(&doc, &i.to_string());
+            persistence.store_update("doc".to_string(), update).unwrap();
+        }
+        assert_eq!(persistence.updates("doc".to_string()).unwrap().len(), 12);
+        assert!(persistence.updates("other".to_string()).unwrap().is_empty());
+
+        let loaded = YrsDoc::new();
+        persistence.load("doc".to_string(), &loaded).unwrap();
+        let text = loaded.get_text("text".to_string());
+        let txn = loaded.transact(None);
+        assert_eq!(text.get_string(&txn), "01234567891011");
+        txn.free();
+
+        let stored = persistence.state_vector("doc".to_string()).unwrap();
+        assert_eq!(
+            StateVector::decode_v1(&stored).unwrap(),
+            doc.inner().transact().state_vector()
+        );
+
+        persistence.clear("doc".to_string()).unwrap();
+        assert!(storage.0.lock().is_empty());
+        assert_eq!(
+            StateVector::decode_v1(&persistence.state_vector("doc".to_string()).unwrap()).unwrap(),
+            StateVector::default()
+        );
+    }
+
+    #[test]
+    fn clear_keeps_documents_sharing_the_guid_prefix() {
+        let storage = MemoryStorage::default();
+        let persistence = YrsPersistence::new(Box::new(storage.clone()));
+        let doc = YrsDoc::new();
+        let nested = YrsDoc::new();
+        persistence.store_update("doc".to_string(), edit(&doc, "a")).unwrap();
+        persistence.store_update("doc/1".to_string(), edit(&nested, "b")).unwrap();
+
+        persistence.clear("doc".to_string()).unwrap();
+        assert!(persistence.updates("doc".to_string()).unwrap().is_empty());
+        assert_eq!(persistence.updates("doc/1".to_string()).unwrap().len(), 1);
+        assert_eq!(
+            StateVector::decode_v1(&persistence.state_vector("doc/1".to_string()).unwrap()).unwrap(),
+            nested.inner().transact().state_vector()
+        );
+    }
+
+    #[test]
+    fn malformed_update_is_not_stored() {
+        let storage = MemoryStorage::default();
+        let persistence = YrsPersistence::new(Box::new(storage.clone()));
+        assert!(persistence
+            .store_update("doc".to_string(), vec![0xff])
+            .is_err());
+        assert!(storage.0.lock().is_empty());
+    }
+
+    #[test]
+    fn compaction_merges_stored_updates() {
+        let storage = MemoryStorage::default();
+        let persistence = YrsPersistence::new(Box::new(storage.clone()));
+        let doc = YrsDoc::new();
+        for content in ["a", "b", "c"] {
+            let update = edit(&doc, content);
+            persistence.store_update("doc".to_string(), update).unwrap();
+        }
+        let state_vector = persistence.state_vector("doc".to_string()).unwrap();
+
+        persistence.compact("doc".to_string()).unwrap();
+        assert_eq!(persistence.updates("doc".to_string()).unwrap().len(), 1);
+        assert_eq!(persistence.state_vector("doc".to_string()).unwrap(), state_vector);
+        let update = edit(&doc, "d");
+        persistence.store_update("doc".to_string(), update).unwrap();
+
+        let loaded = YrsDoc::new();
+        persistence.load("doc".to_string(), &loaded).unwrap();
+        let text = loaded.get_text("text".to_string());
+        let txn = loaded.transact(None);
+        assert_eq!(text.get_string(&txn), "abcd");
+        txn.free();
+    }
+
+    #[test]
+    fn compact_after_policy_bounds_stored_updates() {
+        let persistence = YrsPersistence::new(Box::new(MemoryStorage::default()));
+        assert_eq!(persistence.compact_after(), 0);
+        persistence.set_compact_after(3);
+        let doc = YrsDoc::new();
+        let mut counts = Vec::new();
+        for i in 0..7 {
+            let update = edit(&doc, &i.to_string());
+            persistence.store_update("doc".to_string(), update).unwrap();
+            counts.push(persistence.updates("doc".to_string()).unwrap().len());
+        }
+        assert_eq!(counts, vec![1, 2, 1, 2, 1, 2, 1]);
+    }
+
+    #[test]
+    fn encrypted_updates_are_stored_as_ciphertext() {
+        let storage = MemoryStorage::default();
+        let persistence = YrsPersistence::new(Box::new(storage.clone()));
+        persistence.set_encryption(Box::new(XorCipher(0x5a)));
+        let doc = YrsDoc::new();
+        let mut updates = Vec::new();
+        for content in ["secret", " notes"] {
+            let update = edit(&doc, content);
+            updates.push(update.clone());
+            persistence.store_update("doc".to_string(), update).unwrap();
+        }
+
+        let stored: Vec<Vec<u8>> = storage
+            .0
+            .lock()
+            .iter()
+            .filter(|(key, _)| key.starts_with("doc/update/"))
+            .map(|(_, value)| value.clone())
+            .collect();
+        assert_eq!(stored.len(), 2);
+        assert!(stored.iter().all(|s| !s.windows(6).any(|w| w == b"secret")));
+        assert_eq!(persistence.updates("doc".to_string()).unwrap(), updates);
+
+        persistence.compact("doc".to_string()).unwrap();
+        let loaded = YrsDoc::new();
+        persistence.load("doc".to_string(), &loaded).unwrap();
+        let text = loaded.get_text("text".to_string());
+        let txn = loaded.transact(None);
+        assert_eq!(text.get_string(&txn), "secret notes");
+        txn.free();
+
+        let other_key = YrsPersistence::new(Box::new(storage));
+        other_key.set_encryption(Box::new(XorCipher(0x33)));
+        assert!(matches!(
+            other_key.updates("doc".to_string()),
+            Err(YrsPersistenceError::DecryptionFailed)
+        ));
+    }
+}
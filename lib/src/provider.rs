@@ -0,0 +1,499 @@
+use crate::awareness::YrsAwareness;
+use crate::doc::{YrsDoc, YrsOrigin};
+use crate::encryption::{Encryption, YrsEncryptionDelegate};
+use crate::sync::{
+    decode_messages, encode_sync_message, YrsSyncError, YrsSyncMessage, YrsSyncSession,
+};
+use parking_lot::Mutex;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+use yrs::sync::Message;
+use yrs::updates::encoder::Encode;
+use yrs::{Origin, Subscription};
+
+/// How often the local awareness state is sent again while connected: half the 30
+/// seconds after which y-protocols peers drop a client they haven't heard from.
+const RENEW_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Network connection of a provider, implemented by the app on top of a WebSocket.
+pub(crate) trait YrsTransportDelegate: Send + Sync + Debug {
+    /// Sends a binary message to the server.
+    fn send(&self, data: Vec<u8>);
+}
+
+/// Keeps a document and its awareness in sync with a y-websocket server, speaking
+/// the y-sync protocol through a transport owned by the app. The app reports the
+/// connection opening and closing, and hands over the messages it receives.
+///
+/// Every time the connection opens, the handshake runs again, so changes made while
+/// disconnected are exchanged on reconnect.
+pub(crate) struct YrsWebSocketProvider {
+    doc: Arc<YrsDoc>,
+    awareness: Arc<YrsAwareness>,
+    transport: Arc<dyn YrsTransportDelegate>,
+    origin: Origin,
+    connected: Arc<AtomicBool>,
+    session: Mutex<Option<YrsSyncSession>>,
+    encryption: Encryption,
+    renew_interval: Duration,
+    /// Flag stopping the renewal of the local awareness state for the current connection.
+    renewal: Mutex<Option<Arc<AtomicBool>>>,
+    _subscriptions: [Subscription; 2],
+}
+
+impl YrsWebSocketProvider {
+    pub(crate) fn new(doc: Arc<YrsDoc>, transport: Box<dyn YrsTransportDelegate>) -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let origin = Origin::from(format!("yniffi-websocket-provider-{id}").as_str());
+        let transport: Arc<dyn YrsTransportDelegate> = Arc::from(transport);
+        let connected = Arc::new(AtomicBool::new(false));
+        let awareness = doc.awareness();
+        let encryption = Encryption::default();
+
+        let updates = {
+            let transport = transport.clone();
+            let connected = connected.clone();
+            let origin = origin.clone();
+            let encryption = encryption.clone();
+            doc.inner()
+                .observe_update_v1(move |txn, event| {
+                    if connected.load(Ordering::Acquire) && txn.origin() != Some(&origin) {
+                        let update = encryption.encrypt(event.update.clone());
+                        transport.send(encode_sync_message(YrsSyncMessage::Update { update }));
+                    }
+                })
+                .expect("Failed to observe updates")
+        };
+        let presence = {
+            let transport = transport.clone();
+            let connected = connected.clone();
+            let origin = origin.clone();
+            awareness.inner().on_update(move |awareness, event, event_origin| {
+                if !connected.load(Ordering::Acquire) || event_origin == Some(&origin) {
+                    return;
+                }
+                if let Ok(update) = awareness.update_with_clients(event.all_changes()) {
+                    transport.send(Message::Awareness(update).encode_v1());
+                }
+            })
+        };
+
+        YrsWebSocketProvider {
+            doc,
+            awareness,
+            transport,
+            origin,
+            connected,
+            session: Mutex::new(None),
+            encryption,
+            renew_interval: RENEW_INTERVAL,
+            renewal: Mutex::new(None),
+            _subscriptions: [updates, presence],
+        }
+    }
+
+    pub(crate) fn doc(&self) -> Arc<YrsDoc> {
+        self.doc.clone()
+    }
+
+    pub(crate) fn awareness(&self) -> Arc<YrsAwareness> {
+        self.awareness.clone()
+    }
+
+    /// Returns the origin of the transactions applying changes received from the server.
+    pub(crate) fn origin(&self) -> YrsOrigin {
+        self.origin.clone().into()
+    }
+
+    /// Encrypts the document updates sent from now on, and decrypts the ones received.
+    /// Awareness states and state vectors are sent as they are.
+    ///
+    /// Every other peer has to encrypt with the same key, and the server has to relay
+    /// messages between peers without reading them. A y-websocket server can't: it
+    /// applies updates to its own copy of the document and answers handshakes from it
+    /// in plaintext, which fails here with `YrsSyncError::DecryptionFailed`.
+    pub(crate) fn set_encryption(&self, delegate: Box<dyn YrsEncryptionDelegate>) {
+        self.encryption.set(delegate);
+    }
+
+    pub(crate) fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Acquire)
+    }
+
+    /// Returns whether the server has answered the handshake of the current connection.
+    pub(crate) fn is_synced(&self) -> bool {
+        self.session.lock().as_ref().is_some_and(YrsSyncSession::is_synced)
+    }
+
+    /// Starts a new handshake, to be called once the connection is open. Sends the
+    /// state vector of the document and the local awareness state, which is then sent
+    /// again every 15 seconds for as long as the connection is open, so that peers
+    /// don't drop it as outdated.
+    pub(crate) fn on_open(&self) {
+        let session = YrsSyncSession::new(self.doc.inner(), Some(self.origin.clone().into()))
+            .with_encryption(self.encryption.clone());
+        let start = session.start();
+        *self.session.lock() = Some(session);
+        self.connected.store(true, Ordering::Release);
+
+        self.transport.send(start);
+        let client_id = self.awareness.client_id();
+        if let Ok(update) = self.awareness.inner().update_with_clients([client_id]) {
+            self.transport.send(Message::Awareness(update).encode_v1());
+        }
+
+        let stopped = Arc::new(AtomicBool::new(false));
+        if let Some(previous) = self.renewal.lock().replace(stopped.clone()) {
+            previous.store(true, Ordering::Relaxed);
+        }
+        let awareness = Arc::downgrade(&self.awareness);
+        let interval = self.renew_interval;
+        std::thread::Builder::new()
+            .name("yniffi-provider-awareness".to_string())
+            .spawn(move || renew(awareness, interval, stopped))
+            .expect("Failed to spawn awareness renewal thread");
+    }
+
+    /// Handles a message received from the server, sending back any replies. Messages
+    /// received while the connection isn't open are ignored.
+    pub(crate) fn on_receive(&self, data: Vec<u8>) -> Result<(), YrsSyncError> {
+        let mut replies = Vec::new();
+        {
+            let session = self.session.lock();
+            let Some(session) = session.as_ref() else {
+                return Ok(());
+            };
+            let mut sync = Vec::new();
+            for message in decode_messages(&data)? {
+                match message {
+                    Message::Awareness(update) => {
+                        // Updates concerning unknown clients are dropped by the awareness.
+                        let _ = self.awareness.inner().apply_update_with(update, self.origin.clone());
+                    }
+                    Message::AwarenessQuery => {
+                        if let Ok(update) = self.awareness.inner().update() {
+                            replies.push(Message::Awareness(update).encode_v1());
+                        }
+                    }
+                    message => sync.push(message),
+                }
+            }
+            replies.extend(session.handle(sync)?);
+        }
+        // Sent once the session is released, so that the transport can call back into
+        // the provider, e.g. to report the connection closing.
+        for reply in replies {
+            self.transport.send(reply);
+        }
+        Ok(())
+    }
+
+    /// Ends the current connection, to be called once it is closed. The states of
+    /// remote clients are removed from the awareness, since they can't be renewed.
+    pub(crate) fn on_close(&self) {
+        self.connected.store(false, Ordering::Release);
+        *self.session.lock() = None;
+        if let Some(stopped) = self.renewal.lock().take() {
+            stopped.store(true, Ordering::Relaxed);
+        }
+        self.awareness.remove_outdated(0);
+    }
+}
+
+impl Drop for YrsWebSocketProvider {
+    fn drop(&mut self) {
+        if let Some(stopped) = self.renewal.lock().take() {
+            stopped.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Periodically sets the local awareness state again, which bumps its clock and gets
+/// it sent to the server, until stopped or the awareness is gone.
+fn renew(awareness: Weak<YrsAwareness>, interval: Duration, stopped: Arc<AtomicBool>) {
+    loop {
+        std::thread::sleep(interval);
+        if stopped.load(Ordering::Relaxed) {
+            return;
+        }
+        let Some(awareness) = awareness.upgrade() else {
+            return;
+        };
+        if let Some(state) = awareness.inner().local_state_raw() {
+            awareness.inner().set_local_state_raw(state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{YrsTransportDelegate, YrsWebSocketProvider};
+    use crate::doc::YrsDoc;
+    use crate::encryption::tests::XorCipher;
+    use crate::sync::{decode_sync_messages, YrsSyncMessage, YrsSyncSession};
+    use parking_lot::Mutex;
+    use std::sync::{Arc, Weak};
+    use std::time::{Duration, Instant};
+    use yrs::sync::Message;
+    use yrs::updates::encoder::Encode;
+
+    #[derive(Debug, Default, Clone)]
+    struct Outbox(Arc<Mutex<Vec<Vec<u8>>>>);
+
+    impl YrsTransportDelegate for Outbox {
+        fn send(&self, data: Vec<u8>) {
+            self.0.lock().push(data);
+        }
+    }
+
+    impl Outbox {
+        fn take(&self) -> Vec<Vec<u8>> {
+            std::mem::take(&mut *self.0.lock())
+        }
+    }
+
+    fn edit(doc: &YrsDoc, content: &str) {
+        let text = doc.get_text("text".to_string());
+        let txn = doc.transact(None);
+        let len = text.length(&txn);
+        text.insert(&txn, len, content.to_string()).unwrap();
+        txn.free();
+    }
+
+    fn content(doc: &YrsDoc) -> String {
+        let text = doc.get_text("text".to_string());
+        let txn = doc.transact(None);
+        let content = text.get_string(&txn);
+        txn.free();
+        content
+    }
+
+    /// Plays the server: a document with a sync session answering the provider.
+    struct Server {
+        doc: Arc<YrsDoc>,
+        session: YrsSyncSession,
+    }
+
+    impl Server {
+        fn new() -> Self {
+            let doc = Arc::new(YrsDoc::new());
+            let session = YrsSyncSession::new(doc.inner(), None);
+            Server { doc, session }
+        }
+
+        /// Handles messages of the provider, returning the replies of the server.
+        fn receive(&self, messages: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
+            messages
+                .into_iter()
+                .flat_map(|message| self.session.handle_message(message).unwrap())
+                .collect()
+        }
+    }
+
+    #[test]
+    fn provider_syncs_and_resyncs_after_reconnect() {
+        let server = Server::new();
+        edit(&server.doc, "server ");
+        let outbox = Outbox::default();
+        let doc = Arc::new(YrsDoc::new());
+        let provider = YrsWebSocketProvider::new(doc.clone(), Box::new(outbox.clone()));
+
+        provider.on_open();
+        assert!(provider.is_connected());
+        let mut replies = server.receive(outbox.take());
+        // The server also opens its own handshake.
+        replies.push(server.session.start());
+        for reply in replies {
+            provider.on_receive(reply).unwrap();
+        }
+        assert!(provider.is_synced());
+        assert_eq!(content(&doc), "server ");
+        server.receive(outbox.take());
+
+        // Local changes are sent as they happen while connected.
+        edit(&doc, "online ");
+        let sent = outbox.take();
+        assert!(matches!(
+            decode_sync_messages(sent[0].clone()).unwrap()[..],
+            [YrsSyncMessage::Update { .. }]
+        ));
+        server.receive(sent);
+        assert_eq!(content(&server.doc), "server online ");
+
+        // Changes made while disconnected are exchanged by the next handshake.
+        provider.on_close();
+        assert!(!provider.is_synced());
+        edit(&doc, "offline");
+        assert!(outbox.take().is_empty());
+        provider.on_open();
+        let mut replies = server.receive(outbox.take());
+        replies.push(server.session.start());
+        for reply in replies {
+            provider.on_receive(reply).unwrap();
+        }
+        server.receive(outbox.take());
+        assert_eq!(content(&server.doc), "server online offline");
+    }
+
+    #[test]
+    fn provider_exchanges_awareness() {
+        let outbox = Outbox::default();
+        let provider =
+            YrsWebSocketProvider::new(Arc::new(YrsDoc::new()), Box::new(outbox.clone()));
+        provider.awareness().set_local_state(r#"{"name":"Ada"}"#.to_string()).unwrap();
+        provider.on_open();
+        // The handshake, then the local awareness state.
+        assert_eq!(outbox.take().len(), 2);
+
+        let peer = crate::awareness::YrsAwareness::new(YrsDoc::new().inner());
+        peer.set_local_state(r#"{"name":"Grace"}"#.to_string()).unwrap();
+        let update = Message::Awareness(peer.inner().update().unwrap()).encode_v1();
+        provider.on_receive(update).unwrap();
+        assert_eq!(provider.awareness().states().len(), 2);
+        // Remote states aren't echoed back to the server.
+        assert!(outbox.take().is_empty());
+
+        provider.awareness().set_local_state(r#"{"name":"Ada L."}"#.to_string()).unwrap();
+        assert_eq!(outbox.take().len(), 1);
+
+        provider.on_close();
+        assert_eq!(provider.awareness().states().len(), 1);
+    }
+
+    #[test]
+    fn provider_encrypts_live_updates() {
+        let server = Server::new();
+        server.session.set_encryption(Box::new(XorCipher(3)));
+        let outbox = Outbox::default();
+        let doc = Arc::new(YrsDoc::new());
+        let provider = YrsWebSocketProvider::new(doc.clone(), Box::new(outbox.clone()));
+        provider.set_encryption(Box::new(XorCipher(3)));
+        provider.on_open();
+        for reply in server.receive(outbox.take()) {
+            provider.on_receive(reply).unwrap();
+        }
+
+        edit(&doc, "private");
+        let sent = outbox.take();
+        assert!(!sent[0].windows(7).any(|w| w == b"private"));
+        server.receive(sent);
+        assert_eq!(content(&server.doc), "private");
+    }
+
+    #[test]
+    fn encrypted_providers_sync_through_a_blind_relay() {
+        fn readable(messages: &[Vec<u8>], plaintext: &[u8]) -> bool {
+            messages.iter().any(|m| m.windows(plaintext.len()).any(|w| w == plaintext))
+        }
+
+        let (alice_outbox, bob_outbox) = (Outbox::default(), Outbox::default());
+        let alice_doc = Arc::new(YrsDoc::new());
+        let bob_doc = Arc::new(YrsDoc::new());
+        edit(&alice_doc, "secret ");
+        edit(&bob_doc, "hidden ");
+        let alice = YrsWebSocketProvider::new(alice_doc.clone(), Box::new(alice_outbox.clone()));
+        let bob = YrsWebSocketProvider::new(bob_doc.clone(), Box::new(bob_outbox.clone()));
+        alice.set_encryption(Box::new(XorCipher(9)));
+        bob.set_encryption(Box::new(XorCipher(9)));
+
+        // The relay forwards every message to the other peer without looking into it,
+        // until neither peer has anything left to send.
+        let relay = || {
+            let mut relayed = Vec::new();
+            loop {
+                let (from_alice, from_bob) = (alice_outbox.take(), bob_outbox.take());
+                if from_alice.is_empty() && from_bob.is_empty() {
+                    return relayed;
+                }
+                for message in &from_alice {
+                    bob.on_receive(message.clone()).unwrap();
+                }
+                for message in &from_bob {
+                    alice.on_receive(message.clone()).unwrap();
+                }
+                relayed.extend(from_alice.into_iter().chain(from_bob));
+            }
+        };
+
+        alice.on_open();
+        bob.on_open();
+        let relayed = relay();
+        assert!(alice.is_synced() && bob.is_synced());
+        assert!(!readable(&relayed, b"secret") && !readable(&relayed, b"hidden"));
+        assert_eq!(content(&alice_doc).len(), "secret hidden ".len());
+        assert_eq!(content(&alice_doc), content(&bob_doc));
+
+        edit(&bob_doc, "words");
+        let relayed = relay();
+        assert!(!readable(&relayed, b"words"));
+        assert_eq!(content(&alice_doc), content(&bob_doc));
+        assert!(content(&alice_doc).ends_with("words"));
+
+        // A server answering from its own, plaintext copy can't be read.
+        let server = Server::new();
+        edit(&server.doc, "plain");
+        let replies = server.receive(vec![server.session.start()]);
+        assert!(matches!(
+            alice.on_receive(replies[0].clone()),
+            Err(crate::sync::YrsSyncError::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn provider_renews_local_awareness_while_connected() {
+        let outbox = Outbox::default();
+        let mut provider =
+            YrsWebSocketProvider::new(Arc::new(YrsDoc::new()), Box::new(outbox.clone()));
+        provider.renew_interval = Duration::from_millis(10);
+        provider.awareness().set_local_state(r#"{"name":"Ada"}"#.to_string()).unwrap();
+        provider.on_open();
+        outbox.take();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while outbox.take().is_empty() {
+            assert!(Instant::now() < deadline, "local state wasn't renewed");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+
+        provider.on_close();
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(outbox.take().is_empty());
+    }
+
+    /// Asks the provider whether it's synced from within `send`, like a transport
+    /// reporting state changes to the app would.
+    #[derive(Debug, Default, Clone)]
+    struct Reentrant(Arc<Mutex<Weak<YrsWebSocketProvider>>>, Outbox);
+
+    impl YrsTransportDelegate for Reentrant {
+        fn send(&self, data: Vec<u8>) {
+            if let Some(provider) = self.0.lock().upgrade() {
+                provider.is_synced();
+            }
+            self.1.send(data);
+        }
+    }
+
+    #[test]
+    fn provider_sends_replies_after_releasing_the_session() {
+        let server = Server::new();
+        edit(&server.doc, "server");
+        let transport = Reentrant::default();
+        let provider = Arc::new(YrsWebSocketProvider::new(
+            Arc::new(YrsDoc::new()),
+            Box::new(transport.clone()),
+        ));
+        *transport.0.lock() = Arc::downgrade(&provider);
+
+        provider.on_open();
+        server.receive(transport.1.take());
+        provider.on_receive(server.session.start()).unwrap();
+        assert!(matches!(
+            decode_sync_messages(transport.1.take()[0].clone()).unwrap()[..],
+            [YrsSyncMessage::SyncStep2 { .. }]
+        ));
+    }
+}
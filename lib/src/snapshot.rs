@@ -0,0 +1,157 @@
+use crate::doc::YrsDoc;
+use crate::error::CodingError;
+use crate::transaction::YrsTransaction;
+use std::sync::Arc;
+use yrs::updates::decoder::Decode;
+use yrs::updates::encoder::{Encode, Encoder, EncoderV1};
+use yrs::{Doc, ReadTxn, Snapshot, Transact, Update};
+
+/// The state of a document at a point in time: its state vector together with its
+/// delete set. Much smaller than the document state itself, but it can only be
+/// materialized against a document that kept its deleted content.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct YrsSnapshot(Snapshot);
+
+impl YrsSnapshot {
+    pub(crate) fn decode_v1(data: Vec<u8>) -> Result<Self, CodingError> {
+        Snapshot::decode_v1(data.as_slice())
+            .map(YrsSnapshot)
+            .map_err(|_e| CodingError::DecodingError)
+    }
+
+    pub(crate) fn encode_v1(&self) -> Vec<u8> {
+        self.0.encode_v1()
+    }
+
+    pub(crate) fn inner(&self) -> &Snapshot {
+        &self.0
+    }
+
+    /// Returns the state vector of the snapshot, encoded with lib0 v1 encoding.
+    pub(crate) fn state_vector(&self) -> Vec<u8> {
+        self.0.state_map.encode_v1()
+    }
+}
+
+impl From<Snapshot> for YrsSnapshot {
+    fn from(value: Snapshot) -> Self {
+        YrsSnapshot(value)
+    }
+}
+
+/// Creates a new document holding the state of `doc` as it was at the snapshot.
+/// Fails with `CodingError::EncodingError` unless `doc` was created with `skip_gc`,
+/// since the content deleted since the snapshot would be gone otherwise.
+pub(crate) fn materialize(doc: &Doc, snapshot: &Snapshot) -> Result<YrsDoc, CodingError> {
+    let mut encoder = EncoderV1::new();
+    doc.transact()
+        .encode_state_from_snapshot(snapshot, &mut encoder)
+        .map_err(|_e| CodingError::EncodingError)?;
+    let update = Update::decode_v1(encoder.to_vec().as_slice()).map_err(|_e| CodingError::DecodingError)?;
+
+    let restored = YrsDoc::new();
+    restored
+        .inner()
+        .transact_mut()
+        .apply_update(update)
+        .map_err(|_e| CodingError::DecodingError)?;
+    Ok(restored)
+}
+
+impl YrsDoc {
+    /// Creates a new document holding the state of this one as it was at the snapshot.
+    /// Only works on documents created with `skip_gc`.
+    pub(crate) fn materialize_snapshot(&self, snapshot: &YrsSnapshot) -> Result<Arc<YrsDoc>, CodingError> {
+        materialize(&self.inner(), &snapshot.0).map(Arc::new)
+    }
+}
+
+impl YrsTransaction {
+    /// Captures the current state of the document.
+    pub(crate) fn snapshot(&self) -> Arc<YrsSnapshot> {
+        let tx = self.transaction();
+        Arc::new(YrsSnapshot::from(tx.as_ref().unwrap().snapshot()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::YrsSnapshot;
+    use crate::error::CodingError;
+    use crate::subdoc::YrsDocOptions;
+    use crate::YrsDoc;
+
+    fn doc_without_gc() -> YrsDoc {
+        YrsDoc::new_with_options(YrsDocOptions {
+            auto_load: false,
+            client_id: None,
+            guid: None,
+            should_load: true,
+            skip_gc: true,
+        })
+    }
+
+    #[test]
+    fn materialized_snapshot_matches_past_state() {
+        let doc = doc_without_gc();
+        let text = doc.get_text("text".to_string());
+        let map = doc.get_map("map".to_string());
+        let txn = doc.transact(None);
+        text.insert(&txn, 0, "hello world".to_string()).unwrap();
+        map.insert(&txn, "count".to_string(), "1".to_string());
+        let snapshot = txn.snapshot();
+        txn.free();
+
+        let txn = doc.transact(None);
+        text.remove_range(&txn, 0, 6).unwrap();
+        text.insert(&txn, 5, "!".to_string()).unwrap();
+        map.insert(&txn, "count".to_string(), "2".to_string());
+        txn.free();
+
+        let past = doc.materialize_snapshot(&snapshot).unwrap();
+        let past_text = past.get_text("text".to_string());
+        let past_map = past.get_map("map".to_string());
+        let txn = past.transact(None);
+        assert_eq!(past_text.get_string(&txn), "hello world");
+        assert_eq!(past_map.get(&txn, "count".to_string()).unwrap(), "1");
+        txn.free();
+
+        let txn = doc.transact(None);
+        assert_eq!(text.get_string(&txn), "world!");
+    }
+
+    #[test]
+    fn materialize_snapshot_requires_skip_gc() {
+        let doc = YrsDoc::new();
+        let txn = doc.transact(None);
+        let snapshot = txn.snapshot();
+        txn.free();
+
+        assert!(matches!(
+            doc.materialize_snapshot(&snapshot),
+            Err(CodingError::EncodingError)
+        ));
+    }
+
+    #[test]
+    fn snapshot_round_trips_and_compares() {
+        let doc = YrsDoc::new();
+        let text = doc.get_text("text".to_string());
+        let txn = doc.transact(None);
+        text.insert(&txn, 0, "hello".to_string()).unwrap();
+        let first = txn.snapshot();
+        assert_eq!(*txn.snapshot(), *first);
+
+        text.remove_range(&txn, 0, 2).unwrap();
+        let second = txn.snapshot();
+        assert_ne!(*second, *first);
+        assert_eq!(second.state_vector(), txn.transaction_state_vector());
+
+        let decoded = YrsSnapshot::decode_v1(second.encode_v1()).unwrap();
+        assert_eq!(decoded, *second);
+        assert!(matches!(
+            YrsSnapshot::decode_v1(vec![1, 2]),
+            Err(CodingError::DecodingError)
+        ));
+    }
+}
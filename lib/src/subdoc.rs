@@ -1,7 +1,8 @@
 use std::fmt::Debug;
 use std::sync::Arc;
 
-use crate::doc::YrsDoc;
+use crate::doc::{YrsDoc, YrsOrigin};
+use yrs::Doc;
 
 /// Options for creating a YrsDoc with specific configuration.
 #[derive(Debug)]
@@ -10,13 +11,40 @@ pub(crate) struct YrsDocOptions {
     pub client_id: Option<u64>,
     pub guid: Option<String>,
     pub should_load: bool,
+    pub skip_gc: bool,
+}
+
+/// A subdocument reported by a subdocs event, along with what identifies it.
+pub(crate) struct YrsSubdocInfo {
+    pub guid: String,
+    pub should_load: bool,
+    pub doc: Arc<YrsDoc>,
+}
+
+impl From<&Doc> for YrsSubdocInfo {
+    fn from(doc: &Doc) -> Self {
+        YrsSubdocInfo {
+            guid: doc.guid().to_string(),
+            should_load: doc.should_load(),
+            doc: Arc::new(YrsDoc::from_doc(doc.clone())),
+        }
+    }
+}
+
+/// A subdocument found while walking a document tree, along with the guid of the
+/// document containing it.
+pub(crate) struct YrsSubdocNode {
+    pub guid: String,
+    pub parent_guid: String,
 }
 
 /// Event emitted when subdocuments are added, loaded, or removed.
 pub(crate) struct YrsSubdocsEvent {
-    pub added: Vec<Arc<YrsDoc>>,
-    pub loaded: Vec<Arc<YrsDoc>>,
-    pub removed: Vec<Arc<YrsDoc>>,
+    pub added: Vec<YrsSubdocInfo>,
+    pub loaded: Vec<YrsSubdocInfo>,
+    pub removed: Vec<YrsSubdocInfo>,
+    /// Origin of the transaction that changed the subdocuments.
+    pub origin: Option<YrsOrigin>,
 }
 
 /// Delegate for observing subdocument lifecycle changes.
@@ -24,6 +52,23 @@ pub(crate) trait YrsSubdocsObservationDelegate: Send + Sync + Debug {
     fn call(&self, event: YrsSubdocsEvent);
 }
 
+/// Delegate asked to provide the content of a subdocument once its load has been
+/// requested. It typically fetches the subdocument's stored updates, applies them in a
+/// transaction on the given document, and then marks it loaded with `mark_loaded`.
+pub(crate) trait YrsSubdocLoadDelegate: Send + Sync + Debug {
+    fn call(&self, subdoc: Arc<YrsDoc>);
+}
+
+/// Delegate receiving the updates of every subdocument, tagged with the subdocument guid.
+pub(crate) trait YrsSubdocUpdateDelegate: Send + Sync + Debug {
+    fn call(&self, guid: String, update: Vec<u8>);
+}
+
+/// Delegate for observing a subdocument being marked as loaded.
+pub(crate) trait YrsLoadedObservationDelegate: Send + Sync + Debug {
+    fn call(&self);
+}
+
 /// Delegate for observing document destruction.
 pub(crate) trait YrsDestroyObservationDelegate: Send + Sync + Debug {
     fn call(&self);
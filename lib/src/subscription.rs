@@ -1,14 +1,126 @@
+use parking_lot::Mutex;
+use std::sync::Arc;
 use yrs::Subscription;
 
 pub(crate) struct YSubscription {
-  #[allow(dead_code)]
-  value: Subscription
+  value: Mutex<Option<Subscription>>
 }
 
 impl YSubscription {
   pub(crate)fn new(value: Subscription) -> YSubscription {
       YSubscription {
-        value
+        value: Mutex::new(Some(value))
       }
   }
-}
\ No newline at end of file
+
+  /// Stops the observation right away. Calling it again has no effect.
+  pub(crate) fn cancel(&self) {
+      let value = self.value.lock().take();
+      drop(value);
+  }
+
+  /// Returns whether the subscription hasn't been cancelled. Whether the document is
+  /// still alive can't be told: yrs subscriptions can't be queried, and most are made
+  /// from shared types, which hold no reference to their document. A subscription whose
+  /// document was dropped stays active, although it no longer delivers changes.
+  pub(crate) fn is_active(&self) -> bool {
+      self.value.lock().is_some()
+  }
+}
+
+/// Owns a group of subscriptions so they can be cancelled all at once.
+pub(crate) struct YSubscriptionBag {
+  subscriptions: Mutex<Vec<Arc<YSubscription>>>
+}
+
+impl YSubscriptionBag {
+  pub(crate) fn new() -> YSubscriptionBag {
+      YSubscriptionBag {
+        subscriptions: Mutex::new(Vec::new())
+      }
+  }
+
+  pub(crate) fn insert(&self, subscription: Arc<YSubscription>) {
+      self.subscriptions.lock().push(subscription);
+  }
+
+  pub(crate) fn len(&self) -> u32 {
+      self.subscriptions.lock().len() as u32
+  }
+
+  /// Cancels every subscription in the bag and empties it.
+  pub(crate) fn cancel_all(&self) {
+      let subscriptions = std::mem::take(&mut *self.subscriptions.lock());
+      for subscription in subscriptions {
+          subscription.cancel();
+      }
+  }
+}
+
+impl Drop for YSubscriptionBag {
+  fn drop(&mut self) {
+      self.cancel_all();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::map::YrsMapValueObservationDelegate;
+  use crate::mapchange::YrsMapValueChange;
+  use crate::subscription::YSubscriptionBag;
+  use crate::YrsDoc;
+  use std::sync::atomic::{AtomicU32, Ordering};
+  use std::sync::Arc;
+
+  #[derive(Debug, Default)]
+  struct Counter(Arc<AtomicU32>);
+
+  impl YrsMapValueObservationDelegate for Counter {
+      fn call(&self, _value: Vec<YrsMapValueChange>) {
+          self.0.fetch_add(1, Ordering::SeqCst);
+      }
+  }
+
+  #[test]
+  fn cancel_stops_observation() {
+      let doc = YrsDoc::new();
+      let map = doc.get_map("map".to_string());
+      let calls = Arc::new(AtomicU32::new(0));
+      let subscription = map.observe_values(Box::new(Counter(calls.clone())));
+
+      let txn = doc.transact(None);
+      map.insert(&txn, "a".to_string(), "1".to_string());
+      txn.free();
+      assert!(subscription.is_active());
+      subscription.cancel();
+      subscription.cancel();
+      assert!(!subscription.is_active());
+
+      let txn = doc.transact(None);
+      map.insert(&txn, "b".to_string(), "2".to_string());
+      txn.free();
+      assert_eq!(calls.load(Ordering::SeqCst), 1);
+  }
+
+  #[test]
+  fn bag_cancels_all_subscriptions() {
+      let doc = YrsDoc::new();
+      let map = doc.get_map("map".to_string());
+      let calls = Arc::new(AtomicU32::new(0));
+
+      let bag = YSubscriptionBag::new();
+      let first = map.observe_values(Box::new(Counter(calls.clone())));
+      bag.insert(first.clone());
+      bag.insert(map.observe_values(Box::new(Counter(calls.clone()))));
+      assert_eq!(bag.len(), 2);
+
+      bag.cancel_all();
+      assert_eq!(bag.len(), 0);
+      assert!(!first.is_active());
+
+      let txn = doc.transact(None);
+      map.insert(&txn, "a".to_string(), "1".to_string());
+      txn.free();
+      assert_eq!(calls.load(Ordering::SeqCst), 0);
+  }
+}
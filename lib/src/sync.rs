@@ -0,0 +1,480 @@
+use crate::doc::YrsOrigin;
+use crate::encryption::{Encryption, YrsEncryptionDelegate};
+use std::sync::atomic::{AtomicBool, Ordering};
+use yrs::encoding::read::{self, Cursor};
+use yrs::encoding::write::Write;
+use yrs::sync::protocol::{
+    MSG_AUTH, MSG_AWARENESS, MSG_QUERY_AWARENESS, MSG_SYNC, MSG_SYNC_STEP_1, MSG_SYNC_STEP_2,
+    MSG_SYNC_UPDATE, PERMISSION_DENIED, PERMISSION_GRANTED,
+};
+use yrs::sync::{Message, MessageReader, SyncMessage};
+use yrs::updates::decoder::{Decode, DecoderV1};
+use yrs::updates::encoder::Encode;
+use yrs::{Doc, Origin, ReadTxn, StateVector, Transact, Update};
+
+/// Error that can occur when decoding y-sync protocol messages.
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum YrsSyncError {
+    #[error("Sync message could not be decoded")]
+    DecodingError,
+    #[error("Unsupported message type {tag}")]
+    UnsupportedMessage { tag: u8 },
+    #[error("Permission denied: {reason}")]
+    PermissionDenied { reason: String },
+    #[error("Update could not be decrypted")]
+    DecryptionFailed,
+}
+
+/// A message of the y-sync protocol, as exchanged with y-websocket servers.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum YrsSyncMessage {
+    /// Asks the peer for the changes missing from the given state vector.
+    SyncStep1 { state_vector: Vec<u8> },
+    /// Replies to `SyncStep1` with the missing changes.
+    SyncStep2 { update: Vec<u8> },
+    /// Carries changes made after the initial sync.
+    Update { update: Vec<u8> },
+    /// Tells whether the peer may access the document, with the reason when it may not.
+    Auth { denied_reason: Option<String> },
+    /// Asks the peer for the awareness states it knows.
+    QueryAwareness,
+    /// Carries awareness states, encoded as by `YrsAwareness::encode_update`.
+    Awareness { update: Vec<u8> },
+}
+
+impl TryFrom<Message> for YrsSyncMessage {
+    type Error = YrsSyncError;
+
+    fn try_from(message: Message) -> Result<Self, Self::Error> {
+        match message {
+            Message::Sync(SyncMessage::SyncStep1(state_vector)) => Ok(YrsSyncMessage::SyncStep1 {
+                state_vector: state_vector.encode_v1(),
+            }),
+            Message::Sync(SyncMessage::SyncStep2(update)) => {
+                Ok(YrsSyncMessage::SyncStep2 { update })
+            }
+            Message::Sync(SyncMessage::Update(update)) => Ok(YrsSyncMessage::Update { update }),
+            Message::Awareness(update) => Ok(YrsSyncMessage::Awareness {
+                update: update.encode_v1(),
+            }),
+            Message::Auth(denied_reason) => Ok(YrsSyncMessage::Auth { denied_reason }),
+            Message::AwarenessQuery => Ok(YrsSyncMessage::QueryAwareness),
+            Message::Custom(tag, _) => Err(YrsSyncError::UnsupportedMessage { tag }),
+        }
+    }
+}
+
+/// Encodes a message in the y-protocols wire format: the message type as a var-uint,
+/// followed by the sync step and length-prefixed payload for sync messages, the
+/// permission and denial reason for auth messages, or the length-prefixed update for
+/// awareness messages.
+pub(crate) fn encode_sync_message(message: YrsSyncMessage) -> Vec<u8> {
+    let mut buf = Vec::new();
+    let (step, payload) = match message {
+        YrsSyncMessage::SyncStep1 { state_vector } => (MSG_SYNC_STEP_1, state_vector),
+        YrsSyncMessage::SyncStep2 { update } => (MSG_SYNC_STEP_2, update),
+        YrsSyncMessage::Update { update } => (MSG_SYNC_UPDATE, update),
+        YrsSyncMessage::Auth { denied_reason } => {
+            buf.write_var(MSG_AUTH);
+            match denied_reason {
+                Some(reason) => {
+                    buf.write_var(PERMISSION_DENIED);
+                    buf.write_string(&reason);
+                }
+                None => buf.write_var(PERMISSION_GRANTED),
+            }
+            return buf;
+        }
+        YrsSyncMessage::QueryAwareness => {
+            buf.write_var(MSG_QUERY_AWARENESS);
+            return buf;
+        }
+        YrsSyncMessage::Awareness { update } => {
+            buf.write_var(MSG_AWARENESS);
+            buf.write_buf(update);
+            return buf;
+        }
+    };
+    buf.write_var(MSG_SYNC);
+    buf.write_var(step);
+    buf.write_buf(payload);
+    buf
+}
+
+/// Decodes every message stored one after another in `data`.
+pub(crate) fn decode_sync_messages(data: Vec<u8>) -> Result<Vec<YrsSyncMessage>, YrsSyncError> {
+    decode_messages(&data)?
+        .into_iter()
+        .map(YrsSyncMessage::try_from)
+        .collect()
+}
+
+/// Decodes every message stored one after another in `data`, of any type.
+pub(crate) fn decode_messages(data: &[u8]) -> Result<Vec<Message>, YrsSyncError> {
+    let mut decoder = DecoderV1::new(Cursor::new(data));
+    MessageReader::new(&mut decoder)
+        .collect::<Result<Vec<_>, read::Error>>()
+        .map_err(|_e| YrsSyncError::DecodingError)
+}
+
+/// The y-sync handshake and update exchange with one peer, applied to a document.
+/// Incoming messages are fed to `handle_message`, which returns the replies to send.
+pub(crate) struct YrsSyncSession {
+    doc: Doc,
+    origin: Option<Origin>,
+    synced: AtomicBool,
+    encryption: Encryption,
+}
+
+impl YrsSyncSession {
+    pub(crate) fn new(doc: Doc, origin: Option<YrsOrigin>) -> Self {
+        YrsSyncSession {
+            doc,
+            origin: origin.map(Origin::from),
+            synced: AtomicBool::new(false),
+            encryption: Encryption::default(),
+        }
+    }
+
+    /// Makes the session share the encryption delegate of its owner.
+    pub(crate) fn with_encryption(mut self, encryption: Encryption) -> Self {
+        self.encryption = encryption;
+        self
+    }
+
+    /// Encrypts the updates sent to the peer from now on, and decrypts the ones it
+    /// sends. State vectors are exchanged as they are, so the peer must be a relay
+    /// that doesn't need to read updates.
+    pub(crate) fn set_encryption(&self, delegate: Box<dyn YrsEncryptionDelegate>) {
+        self.encryption.set(delegate);
+    }
+
+    /// Returns the message opening the handshake, asking the peer for missing changes.
+    pub(crate) fn start(&self) -> Vec<u8> {
+        let state_vector = self.doc.transact().state_vector().encode_v1();
+        encode_sync_message(YrsSyncMessage::SyncStep1 { state_vector })
+    }
+
+    /// Returns whether the peer has answered the handshake, so the document holds
+    /// everything the peer had when it replied.
+    pub(crate) fn is_synced(&self) -> bool {
+        self.synced.load(Ordering::Acquire)
+    }
+
+    /// Applies the messages stored in `data`, returning the replies to send back in
+    /// order. Nothing is applied if any message or update can't be decoded or decrypted,
+    /// or if the peer denies permission, which fails with `PermissionDenied`. Other
+    /// message types are ignored.
+    ///
+    /// Updates are applied in a transaction of their own, with the origin of the
+    /// session, so this must not be called while a transaction on the document is open.
+    /// Only decoding is all or nothing: when an update fails to apply, the updates
+    /// before it in `data` stay applied.
+    pub(crate) fn handle_message(&self, data: Vec<u8>) -> Result<Vec<Vec<u8>>, YrsSyncError> {
+        self.handle(decode_messages(&data)?)
+    }
+
+    /// Handles decoded messages like `handle_message`. Every update is decrypted and
+    /// decoded, and permission denials are looked for, before anything is applied.
+    pub(crate) fn handle(&self, messages: Vec<Message>) -> Result<Vec<Vec<u8>>, YrsSyncError> {
+        let mut steps = Vec::with_capacity(messages.len());
+        for message in messages {
+            match message {
+                Message::Sync(SyncMessage::SyncStep1(state_vector)) => {
+                    steps.push(Step::Answer(state_vector))
+                }
+                Message::Sync(SyncMessage::SyncStep2(update)) => steps.push(Step::Apply {
+                    update: self.decode(update)?,
+                    completes_handshake: true,
+                }),
+                Message::Sync(SyncMessage::Update(update)) => steps.push(Step::Apply {
+                    update: self.decode(update)?,
+                    completes_handshake: false,
+                }),
+                Message::Auth(Some(reason)) => {
+                    return Err(YrsSyncError::PermissionDenied { reason });
+                }
+                _ => {}
+            }
+        }
+
+        let mut replies = Vec::new();
+        for step in steps {
+            match step {
+                Step::Answer(state_vector) => {
+                    let update = self.doc.transact().encode_state_as_update_v1(&state_vector);
+                    let update = self.encryption.encrypt(update);
+                    replies.push(encode_sync_message(YrsSyncMessage::SyncStep2 { update }));
+                }
+                Step::Apply {
+                    update,
+                    completes_handshake,
+                } => {
+                    self.apply(update)?;
+                    if completes_handshake {
+                        self.synced.store(true, Ordering::Release);
+                    }
+                }
+            }
+        }
+        Ok(replies)
+    }
+
+    fn decode(&self, update: Vec<u8>) -> Result<Update, YrsSyncError> {
+        let update = self
+            .encryption
+            .decrypt(update)
+            .ok_or(YrsSyncError::DecryptionFailed)?;
+        Update::decode_v1(&update).map_err(|_e| YrsSyncError::DecodingError)
+    }
+
+    fn apply(&self, update: Update) -> Result<(), YrsSyncError> {
+        let mut txn = match &self.origin {
+            Some(origin) => self.doc.transact_mut_with(origin.clone()),
+            None => self.doc.transact_mut(),
+        };
+        txn.apply_update(update)
+            .map_err(|_e| YrsSyncError::DecodingError)
+    }
+}
+
+/// What handling a message comes down to, once its payload was decoded.
+enum Step {
+    /// Replies to `SyncStep1` with the changes missing from the state vector.
+    Answer(StateVector),
+    Apply { update: Update, completes_handshake: bool },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        decode_sync_messages, encode_sync_message, YrsSyncError, YrsSyncMessage, YrsSyncSession,
+    };
+    use crate::awareness::YrsAwareness;
+    use crate::encryption::tests::XorCipher;
+    use yrs::updates::decoder::Decode;
+    use yrs::updates::encoder::Encode;
+    use yrs::{Doc, GetString, ReadTxn, Text, Transact};
+
+    #[test]
+    fn messages_use_y_protocols_framing() {
+        let message = YrsSyncMessage::Update {
+            update: vec![7, 8, 9],
+        };
+        // messageSync, messageYjsUpdate, then the update as a var-uint8 array.
+        assert_eq!(encode_sync_message(message.clone()), vec![0, 2, 3, 7, 8, 9]);
+        assert_eq!(
+            decode_sync_messages(vec![0, 2, 3, 7, 8, 9]).unwrap(),
+            vec![message]
+        );
+    }
+
+    #[test]
+    fn sync_steps_round_trip_document_state() {
+        let source = Doc::with_client_id(1);
+        let text = source.get_or_insert_text("text");
+        text.insert(&mut source.transact_mut(), 0, "hello");
+
+        let target = Doc::with_client_id(2);
+        let step1 = YrsSyncMessage::SyncStep1 {
+            state_vector: target.transact().state_vector().encode_v1(),
+        };
+        let step2 = YrsSyncMessage::SyncStep2 {
+            update: source
+                .transact()
+                .encode_state_as_update_v1(&Default::default()),
+        };
+        let mut data = encode_sync_message(step1.clone());
+        data.extend(encode_sync_message(step2.clone()));
+
+        let messages = decode_sync_messages(data).unwrap();
+        assert_eq!(messages, vec![step1, step2]);
+        let YrsSyncMessage::SyncStep2 { update } = &messages[1] else {
+            panic!("expected sync step 2");
+        };
+        target
+            .transact_mut()
+            .apply_update(yrs::Update::decode_v1(update).unwrap())
+            .unwrap();
+        let text = target.get_or_insert_text("text");
+        assert_eq!(text.get_string(&target.transact()), "hello");
+    }
+
+    #[test]
+    fn decoding_rejects_malformed_and_unsupported_messages() {
+        assert!(matches!(
+            decode_sync_messages(vec![0, 7, 0]),
+            Err(YrsSyncError::DecodingError)
+        ));
+        assert!(matches!(
+            decode_sync_messages(vec![7, 1, 0]),
+            Err(YrsSyncError::UnsupportedMessage { tag: 7 })
+        ));
+    }
+
+    #[test]
+    fn awareness_messages_round_trip() {
+        let awareness = YrsAwareness::new(Doc::new());
+        awareness.set_local_state(r#"{"name":"sam"}"#.to_string()).unwrap();
+        let update = awareness.encode_update(Some(vec![awareness.client_id()])).unwrap();
+        let message = YrsSyncMessage::Awareness { update: update.clone() };
+
+        let mut data = encode_sync_message(message.clone());
+        data.extend(encode_sync_message(YrsSyncMessage::QueryAwareness));
+        let messages = decode_sync_messages(data).unwrap();
+        assert_eq!(messages, vec![message, YrsSyncMessage::QueryAwareness]);
+
+        let remote = YrsAwareness::new(Doc::new());
+        remote.apply_update(update, None).unwrap();
+        assert_eq!(
+            remote.states().get(&awareness.client_id()).map(String::as_str),
+            Some(r#"{"name":"sam"}"#)
+        );
+    }
+
+    #[test]
+    fn sessions_complete_handshake_in_both_directions() {
+        let a = Doc::with_client_id(1);
+        let b = Doc::with_client_id(2);
+        a.get_or_insert_text("text").insert(&mut a.transact_mut(), 0, "from a ");
+        b.get_or_insert_text("text").insert(&mut b.transact_mut(), 0, "from b");
+
+        let a_session = YrsSyncSession::new(a.clone(), None);
+        let b_session = YrsSyncSession::new(b.clone(), None);
+        let a_step1 = a_session.start();
+        let b_step1 = b_session.start();
+        assert!(!a_session.is_synced());
+
+        for reply in b_session.handle_message(a_step1).unwrap() {
+            assert!(a_session.handle_message(reply).unwrap().is_empty());
+        }
+        assert!(a_session.is_synced());
+        assert!(!b_session.is_synced());
+        for reply in a_session.handle_message(b_step1).unwrap() {
+            b_session.handle_message(reply).unwrap();
+        }
+        assert!(b_session.is_synced());
+
+        let a_text = a.get_or_insert_text("text").get_string(&a.transact());
+        let b_text = b.get_or_insert_text("text").get_string(&b.transact());
+        assert_eq!(a_text, b_text);
+        assert_eq!(a_text.len(), "from a from b".len());
+    }
+
+    #[test]
+    fn session_applies_updates_with_its_origin() {
+        let source = Doc::with_client_id(1);
+        let text = source.get_or_insert_text("text");
+        let mut txn = source.transact_mut();
+        text.insert(&mut txn, 0, "hello");
+        let update = txn.encode_update_v1();
+        drop(txn);
+
+        let doc = Doc::with_client_id(2);
+        let origins = std::sync::Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let _subscription = {
+            let origins = origins.clone();
+            doc.observe_update_v1(move |txn, _| origins.lock().push(txn.origin().cloned()))
+                .unwrap()
+        };
+        let session = YrsSyncSession::new(doc.clone(), Some(yrs::Origin::from("remote").into()));
+        let replies = session
+            .handle_message(encode_sync_message(YrsSyncMessage::Update { update }))
+            .unwrap();
+        assert!(replies.is_empty());
+        // Updates alone don't complete the handshake.
+        assert!(!session.is_synced());
+        assert_eq!(*origins.lock(), vec![Some(yrs::Origin::from("remote"))]);
+        assert_eq!(doc.get_or_insert_text("text").get_string(&doc.transact()), "hello");
+
+        assert!(matches!(
+            session.handle_message(vec![0, 2, 1, 0xff]),
+            Err(YrsSyncError::DecodingError)
+        ));
+    }
+
+    #[test]
+    fn session_applies_nothing_when_a_message_fails() {
+        let source = Doc::with_client_id(1);
+        let text = source.get_or_insert_text("text");
+        let mut txn = source.transact_mut();
+        text.insert(&mut txn, 0, "hello");
+        let update = txn.encode_update_v1();
+        drop(txn);
+
+        let doc = Doc::with_client_id(2);
+        let session = YrsSyncSession::new(doc.clone(), None);
+        let valid = encode_sync_message(YrsSyncMessage::Update { update });
+
+        let mut data = valid.clone();
+        data.extend(encode_sync_message(YrsSyncMessage::Update { update: vec![0xff] }));
+        assert!(matches!(session.handle_message(data), Err(YrsSyncError::DecodingError)));
+        let mut data = valid.clone();
+        data.extend(encode_sync_message(YrsSyncMessage::Auth {
+            denied_reason: Some("no".to_string()),
+        }));
+        assert!(matches!(
+            session.handle_message(data),
+            Err(YrsSyncError::PermissionDenied { .. })
+        ));
+        assert_eq!(doc.get_or_insert_text("text").get_string(&doc.transact()), "");
+
+        session.handle_message(valid).unwrap();
+        assert_eq!(doc.get_or_insert_text("text").get_string(&doc.transact()), "hello");
+    }
+
+    #[test]
+    fn auth_and_awareness_query_messages_round_trip() {
+        let denied = YrsSyncMessage::Auth {
+            denied_reason: Some("no".to_string()),
+        };
+        // messageAuth, messagePermissionDenied, then the reason as a var-string.
+        assert_eq!(encode_sync_message(denied.clone()), vec![2, 0, 2, b'n', b'o']);
+        let granted = YrsSyncMessage::Auth { denied_reason: None };
+        assert_eq!(encode_sync_message(granted.clone()), vec![2, 1]);
+        assert_eq!(encode_sync_message(YrsSyncMessage::QueryAwareness), vec![3]);
+
+        let mut data = encode_sync_message(denied.clone());
+        data.extend(encode_sync_message(granted.clone()));
+        data.extend(encode_sync_message(YrsSyncMessage::QueryAwareness));
+        assert_eq!(
+            decode_sync_messages(data).unwrap(),
+            vec![denied.clone(), granted, YrsSyncMessage::QueryAwareness]
+        );
+
+        let session = YrsSyncSession::new(Doc::new(), None);
+        assert!(matches!(
+            session.handle_message(encode_sync_message(denied)),
+            Err(YrsSyncError::PermissionDenied { reason }) if reason == "no"
+        ));
+    }
+
+    #[test]
+    fn encrypted_sessions_exchange_ciphertext() {
+        let a = Doc::with_client_id(1);
+        let b = Doc::with_client_id(2);
+        a.get_or_insert_text("text").insert(&mut a.transact_mut(), 0, "classified");
+
+        let a_session = YrsSyncSession::new(a.clone(), None);
+        let b_session = YrsSyncSession::new(b.clone(), None);
+        a_session.set_encryption(Box::new(XorCipher(7)));
+        b_session.set_encryption(Box::new(XorCipher(7)));
+
+        let replies = a_session.handle_message(b_session.start()).unwrap();
+        assert!(replies
+            .iter()
+            .all(|reply| !reply.windows(10).any(|w| w == b"classified")));
+        for reply in replies {
+            b_session.handle_message(reply).unwrap();
+        }
+        assert_eq!(b.get_or_insert_text("text").get_string(&b.transact()), "classified");
+
+        let eavesdropper = YrsSyncSession::new(Doc::new(), None);
+        eavesdropper.set_encryption(Box::new(XorCipher(8)));
+        let replies = a_session.handle_message(eavesdropper.start()).unwrap();
+        assert!(matches!(
+            eavesdropper.handle_message(replies[0].clone()),
+            Err(YrsSyncError::DecryptionFailed)
+        ));
+    }
+}
@@ -1,15 +1,27 @@
 use crate::attrs::YrsAttrs;
-use crate::delta::YrsDelta;
+use crate::delta::{normalize, YrsDelta, YrsDeltaError};
+use crate::error::CodingError;
 use crate::subscription::YSubscription;
+use crate::textdiff::{self, Edit};
 use crate::transaction::YrsTransaction;
+use crate::value::YrsValue;
+use yrs::types::Attrs;
 use yrs::Any;
 use parking_lot::ReentrantMutex;
 use std::cell::UnsafeCell;
+use std::collections::HashMap;
 use std::fmt::Debug;
 use std::sync::Arc;
-use yrs::{GetString, Observable, Text, TextRef};
+use yrs::updates::decoder::Decode;
+use yrs::{
+    Assoc, GetString, IndexedSequence, Observable, Quotable, ReadTxn, Snapshot, StateVector, Text, TextRef, TransactionMut,
+};
 use yrs::branch::Branch;
-use crate::doc::YrsCollectionPtr;
+use crate::delivery::YrsObservationDelivery;
+use crate::doc::{YrsCollectionPtr, YrsId, YrsOrigin};
+use crate::path::{path_segments, root_path, YrsPathSegment};
+use crate::snapshot::YrsSnapshot;
+use crate::weak::{YrsQuoteError, YrsWeakPrelim};
 
 pub(crate) struct YrsText(ReentrantMutex<UnsafeCell<TextRef>>);
 
@@ -34,7 +46,7 @@ impl<'a> TextRefGuard<'a> {
 }
 
 impl YrsText {
-    fn inner(&self) -> TextRefGuard<'_> {
+    pub(crate) fn inner(&self) -> TextRefGuard<'_> {
         let guard = self.0.lock();
         let ptr = unsafe { (*self.0.data_ptr()).get() };
         TextRefGuard { _guard: guard, ptr }
@@ -56,8 +68,161 @@ impl From<TextRef> for YrsText {
     }
 }
 
+/// Error that can occur when writing to a text at a given offset.
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum YrsTextError {
+    #[error("Offset {index} splits a UTF-16 surrogate pair")]
+    SurrogateSplit { index: u32 },
+    #[error("Range of {length} starting at {index} is out of bounds")]
+    OutOfBounds { index: u32, length: u32 },
+}
+
+/// Decides what happens when a write offset falls between the two halves of a
+/// UTF-16 surrogate pair. Set per document, which rejects by default.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum YrsSurrogatePolicy {
+    /// Fail the write with `YrsTextError::SurrogateSplit`.
+    Reject,
+    /// Move the offset to the nearest boundary that keeps the pair intact.
+    Snap,
+}
+
+/// Returns the text content, with every embed represented by a single U+FFFC character,
+/// so that its UTF-16 offsets line up with the offsets used by the text itself.
+fn content_with_placeholders<T: ReadTxn>(text: &TextRef, txn: &T) -> String {
+    use yrs::types::text::Diff;
+    use yrs::Out;
+    let diffs: Vec<Diff<()>> = text.diff(txn, |_| ());
+    let mut content = String::new();
+    for d in diffs {
+        match d.insert {
+            Out::Any(Any::String(s)) => content.push_str(&s),
+            _ => content.push(OBJECT_REPLACEMENT),
+        }
+    }
+    content
+}
+
+/// Tells for each offset whether it points at the low half of a surrogate pair, with
+/// every embed counting as a single unit. Offsets at either end of the text can't, and
+/// are answered from its length alone.
+///
+/// The two halves of a pair are always inserted together, so they carry consecutive
+/// clocks of the same client. The ids of the units around each offset are found by
+/// walking the blocks up to it, and only offsets whose neighbours were inserted together
+/// need their content read, which yrs only exposes as a delta of the whole text.
+fn splits_surrogates<T: ReadTxn, const N: usize>(text: &TextRef, txn: &T, offsets: [u32; N]) -> [bool; N] {
+    use yrs::Out;
+    let mut splits = [false; N];
+    let len = text.len(txn);
+    let candidates = offsets.map(|offset| offset > 0 && offset < len && inserted_together(text, txn, offset));
+    if !candidates.contains(&true) {
+        return splits;
+    }
+    let last = offsets.iter().copied().max().unwrap_or_default();
+    let mut start = 0;
+    for chunk in text.diff(txn, |_| ()) {
+        let Out::Any(Any::String(s)) = chunk.insert else {
+            start += 1;
+            continue;
+        };
+        for c in s.chars() {
+            let end = start + c.len_utf16() as u32;
+            for ((split, &offset), &candidate) in splits.iter_mut().zip(&offsets).zip(&candidates) {
+                *split |= candidate && start < offset && offset < end;
+            }
+            start = end;
+            if start >= last {
+                return splits;
+            }
+        }
+    }
+    splits
+}
+
+/// Tells whether the units on both sides of `offset` have consecutive clocks of the same
+/// client, which a surrogate pair split by the offset would.
+fn inserted_together<T: ReadTxn>(text: &TextRef, txn: &T, offset: u32) -> bool {
+    let id = |index| {
+        text.sticky_index(txn, index, Assoc::After)
+            .and_then(|sticky| sticky.id().cloned())
+    };
+    match (id(offset - 1), id(offset)) {
+        (Some(before), Some(after)) => {
+            before.client == after.client && before.clock + 1 == after.clock
+        }
+        _ => true,
+    }
+}
+
+/// Validates that `index` is within the text and doesn't split a surrogate pair. When
+/// snapping, the index moves to the start of the pair.
+pub(crate) fn checked_index(
+    text: &TextRef,
+    txn: &TransactionMut,
+    policy: YrsSurrogatePolicy,
+    index: u32,
+) -> Result<u32, YrsTextError> {
+    if index > text.len(txn) {
+        return Err(YrsTextError::OutOfBounds { index, length: 0 });
+    }
+    let [split] = splits_surrogates(text, txn, [index]);
+    match (split, policy) {
+        (false, _) => Ok(index),
+        (true, YrsSurrogatePolicy::Reject) => Err(YrsTextError::SurrogateSplit { index }),
+        (true, YrsSurrogatePolicy::Snap) => Ok(index - 1),
+    }
+}
+
+/// Validates that a range is within the text and that neither of its ends splits a
+/// surrogate pair. When snapping, the range grows to cover the pairs at both ends.
+fn checked_range(
+    text: &TextRef,
+    txn: &TransactionMut,
+    policy: YrsSurrogatePolicy,
+    index: u32,
+    length: u32,
+) -> Result<(u32, u32), YrsTextError> {
+    let end = index
+        .checked_add(length)
+        .filter(|&end| end <= text.len(txn))
+        .ok_or(YrsTextError::OutOfBounds { index, length })?;
+    let [start_splits, end_splits] = splits_surrogates(text, txn, [index, end]);
+    match policy {
+        YrsSurrogatePolicy::Reject if start_splits => Err(YrsTextError::SurrogateSplit { index }),
+        YrsSurrogatePolicy::Reject if end_splits => Err(YrsTextError::SurrogateSplit { index: end }),
+        _ => {
+            let start = index - start_splits as u32;
+            let end = end + end_splits as u32;
+            Ok((start, end - start))
+        }
+    }
+}
+
 pub(crate) trait YrsTextObservationDelegate: Send + Sync + Debug {
-    fn call(&self, value: Vec<YrsDelta>);
+    fn call(&self, value: Vec<YrsDelta>, origin: Option<YrsOrigin>, path: Vec<YrsPathSegment>);
+}
+
+/// Kind of change a text chunk went through, as reported by `diff_with_changes`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum YrsChangeKind {
+    Added,
+    Removed,
+}
+
+impl From<yrs::types::text::ChangeKind> for YrsChangeKind {
+    fn from(value: yrs::types::text::ChangeKind) -> Self {
+        match value {
+            yrs::types::text::ChangeKind::Added => YrsChangeKind::Added,
+            yrs::types::text::ChangeKind::Removed => YrsChangeKind::Removed,
+        }
+    }
+}
+
+/// Maps the block behind a changed chunk to caller-defined metadata, e.g. an author
+/// name or a timestamp.
+pub(crate) trait YrsChangeDelegate: Send + Sync + Debug {
+    fn call(&self, kind: YrsChangeKind, id: YrsId) -> String;
 }
 
 impl YrsText {
@@ -72,13 +237,38 @@ impl YrsText {
         index: u32,
         length: u32,
         attrs: String,
-    ) {
+    ) -> Result<(), YrsTextError> {
         let mut tx = transaction.transaction();
         let tx = tx.as_mut().unwrap();
 
         let a = YrsAttrs::from(attrs);
 
-        self.inner().as_mut().format(tx, index, length, a.0)
+        let mut text = self.inner();
+        let (index, length) = checked_range(text.as_ref(), tx, transaction.surrogate_policy(), index, length)?;
+        text.as_mut().format(tx, index, length, a.0);
+        Ok(())
+    }
+
+    /// Clears the listed formatting attributes over the given range.
+    pub(crate) fn remove_format(
+        &self,
+        transaction: &YrsTransaction,
+        index: u32,
+        length: u32,
+        attr_names: Vec<String>,
+    ) -> Result<(), YrsTextError> {
+        let mut tx = transaction.transaction();
+        let tx = tx.as_mut().unwrap();
+
+        let attrs: Attrs = attr_names
+            .into_iter()
+            .map(|name| (Arc::from(name.as_str()), Any::Null))
+            .collect();
+
+        let mut text = self.inner();
+        let (index, length) = checked_range(text.as_ref(), tx, transaction.surrogate_policy(), index, length)?;
+        text.as_mut().format(tx, index, length, attrs);
+        Ok(())
     }
 
     pub(crate) fn append(&self, tx: &YrsTransaction, text: String) {
@@ -88,11 +278,19 @@ impl YrsText {
         self.inner().as_mut().push(tx, text.as_str());
     }
 
-    pub(crate) fn insert(&self, tx: &YrsTransaction, index: u32, chunk: String) {
-        let mut tx = tx.transaction();
+    pub(crate) fn insert(
+        &self,
+        transaction: &YrsTransaction,
+        index: u32,
+        chunk: String,
+    ) -> Result<(), YrsTextError> {
+        let mut tx = transaction.transaction();
         let tx = tx.as_mut().unwrap();
 
-        self.inner().as_mut().insert(tx, index, chunk.as_str())
+        let mut text = self.inner();
+        let index = checked_index(text.as_ref(), tx, transaction.surrogate_policy(), index)?;
+        text.as_mut().insert(tx, index, chunk.as_str());
+        Ok(())
     }
 
     pub(crate) fn insert_with_attributes(
@@ -101,24 +299,34 @@ impl YrsText {
         index: u32,
         chunk: String,
         attrs: String,
-    ) {
+    ) -> Result<(), YrsTextError> {
         let mut tx = transaction.transaction();
         let tx = tx.as_mut().unwrap();
 
         let a = YrsAttrs::from(attrs);
 
-        self.inner()
-            .as_mut()
-            .insert_with_attributes(tx, index, chunk.as_str(), a.0)
+        let mut text = self.inner();
+        let index = checked_index(text.as_ref(), tx, transaction.surrogate_policy(), index)?;
+        text.as_mut()
+            .insert_with_attributes(tx, index, chunk.as_str(), a.0);
+        Ok(())
     }
 
-    pub(crate) fn insert_embed(&self, transaction: &YrsTransaction, index: u32, content: String) {
+    pub(crate) fn insert_embed(
+        &self,
+        transaction: &YrsTransaction,
+        index: u32,
+        content: String,
+    ) -> Result<(), YrsTextError> {
         let mut tx = transaction.transaction();
         let tx = tx.as_mut().unwrap();
 
         let avalue = Any::from_json(content.as_str()).unwrap();
 
-        self.inner().as_mut().insert_embed(tx, index, avalue);
+        let mut text = self.inner();
+        let index = checked_index(text.as_ref(), tx, transaction.surrogate_policy(), index)?;
+        text.as_mut().insert_embed(tx, index, avalue);
+        Ok(())
     }
 
     pub(crate) fn insert_embed_with_attributes(
@@ -127,7 +335,7 @@ impl YrsText {
         index: u32,
         content: String,
         attrs: String,
-    ) {
+    ) -> Result<(), YrsTextError> {
         let mut tx = transaction.transaction();
         let tx = tx.as_mut().unwrap();
 
@@ -135,9 +343,11 @@ impl YrsText {
 
         let a = YrsAttrs::from(attrs);
 
-        self.inner()
-            .as_mut()
+        let mut text = self.inner();
+        let index = checked_index(text.as_ref(), tx, transaction.surrogate_policy(), index)?;
+        text.as_mut()
             .insert_embed_with_attributes(tx, index, avalue, a.0);
+        Ok(())
     }
 
     pub(crate) fn get_string(&self, tx: &YrsTransaction) -> String {
@@ -147,11 +357,52 @@ impl YrsText {
         self.inner().as_ref().get_string(tx)
     }
 
-    pub(crate) fn remove_range(&self, transaction: &YrsTransaction, start: u32, length: u32) {
+    /// Replaces the content of the text with `new_value`, applying only the minimal
+    /// set of inserts and removals so that unchanged ranges keep their formatting and
+    /// concurrent edits merge cleanly. Embeds count as a single U+FFFC character.
+    pub(crate) fn set_string(&self, transaction: &YrsTransaction, new_value: String) {
         let mut tx = transaction.transaction();
         let tx = tx.as_mut().unwrap();
 
-        self.inner().as_mut().remove_range(tx, start, length)
+        let mut text = self.inner();
+        let current = content_with_placeholders(text.as_ref(), tx);
+
+        let chars: Vec<char> = current.chars().collect();
+        let utf16_len = |chars: &[char]| chars.iter().map(|c| c.len_utf16() as u32).sum::<u32>();
+        let mut pos = 0;
+        let mut index = 0;
+        for edit in textdiff::diff(&current, &new_value) {
+            match edit {
+                Edit::Equal(n) => {
+                    index += utf16_len(&chars[pos..pos + n]);
+                    pos += n;
+                }
+                Edit::Delete(n) => {
+                    let len = utf16_len(&chars[pos..pos + n]);
+                    text.as_mut().remove_range(tx, index, len);
+                    pos += n;
+                }
+                Edit::Insert(s) => {
+                    text.as_mut().insert(tx, index, s.as_str());
+                    index += s.encode_utf16().count() as u32;
+                }
+            }
+        }
+    }
+
+    pub(crate) fn remove_range(
+        &self,
+        transaction: &YrsTransaction,
+        start: u32,
+        length: u32,
+    ) -> Result<(), YrsTextError> {
+        let mut tx = transaction.transaction();
+        let tx = tx.as_mut().unwrap();
+
+        let mut text = self.inner();
+        let (start, length) = checked_range(text.as_ref(), tx, transaction.surrogate_policy(), start, length)?;
+        text.as_mut().remove_range(tx, start, length);
+        Ok(())
     }
 
     pub(crate) fn length(&self, transaction: &YrsTransaction) -> u32 {
@@ -162,49 +413,44 @@ impl YrsText {
     }
 
     pub(crate) fn observe(&self, delegate: Box<dyn YrsTextObservationDelegate>) -> Arc<YSubscription> {
+        self.observe_with_delivery(delegate, YrsObservationDelivery::Synchronous)
+    }
+
+    /// Observes changes made to the text, calling the delegate as `delivery` specifies.
+    pub(crate) fn observe_with_delivery(
+        &self,
+        delegate: Box<dyn YrsTextObservationDelegate>,
+        delivery: YrsObservationDelivery,
+    ) -> Arc<YSubscription> {
+        let delegate: Arc<dyn YrsTextObservationDelegate> = Arc::from(delegate);
         let mut text = self.inner();
-        let subscription = text
-            .as_mut()
-            .observe(move |transaction, text_event| {
-                let delta = text_event.delta(transaction);
-                let result: Vec<YrsDelta> =
-                    delta.iter().map(|change| YrsDelta::from(change)).collect();
-                delegate.call(result)
-            });
+        let subscription = text.as_mut().observe(move |transaction, text_event| {
+            let delta = text_event.delta(transaction);
+            let result: Vec<YrsDelta> = delta.iter().map(YrsDelta::from).collect();
+            let origin = transaction.origin().cloned().map(YrsOrigin::from);
+            let path = path_segments(root_path(transaction, text_event.target().as_ref()));
+            let delegate = delegate.clone();
+            delivery.deliver(move || delegate.call(result, origin, path))
+        });
 
-            Arc::new(YSubscription::new(subscription))
+        Arc::new(YSubscription::new(subscription))
     }
 
-    /// Applies a delta to the text. Delta is a JSON array of operations.
-    pub(crate) fn apply_delta(&self, transaction: &YrsTransaction, delta: Vec<YrsDelta>) {
-        use yrs::types::Delta;
+    /// Applies a delta to the text, returning the normalized delta that was actually
+    /// applied. Inserted values must be JSON and attributes JSON objects.
+    pub(crate) fn apply_delta(
+        &self,
+        transaction: &YrsTransaction,
+        delta: Vec<YrsDelta>,
+    ) -> Result<Vec<YrsDelta>, YrsDeltaError> {
+        let deltas = normalize(delta)?;
+
         let mut tx = transaction.transaction();
         let tx = tx.as_mut().unwrap();
 
-        let deltas: Vec<Delta<Any>> = delta.into_iter().map(|d| {
-            match d {
-                YrsDelta::Inserted { value, attrs } => {
-                    let any_value = Any::from_json(value.as_str()).unwrap();
-                    let attrs_parsed = if attrs.is_empty() {
-                        None
-                    } else {
-                        Some(Box::new(YrsAttrs::from(attrs).0))
-                    };
-                    Delta::Inserted(any_value, attrs_parsed)
-                }
-                YrsDelta::Deleted { index } => Delta::Deleted(index),
-                YrsDelta::Retained { index, attrs } => {
-                    let attrs_parsed = if attrs.is_empty() {
-                        None
-                    } else {
-                        Some(Box::new(YrsAttrs::from(attrs).0))
-                    };
-                    Delta::Retain(index, attrs_parsed)
-                }
-            }
-        }).collect();
-
+        let applied = deltas.iter().map(YrsDelta::from).collect();
         self.inner().as_mut().apply_delta(tx, deltas);
+        Ok(applied)
     }
 
     /// Returns the text content as a list of diff chunks with formatting.
@@ -216,6 +462,362 @@ impl YrsText {
         let diffs: Vec<Diff<()>> = self.inner().as_ref().diff(tx, |_| ());
         diffs.into_iter().map(|d| YrsDiff::from(&d)).collect()
     }
+
+    /// Returns the text content as diff chunks, attributing the chunks inserted since the
+    /// given state vector (or all of them, if none is given) with the metadata returned
+    /// by `delegate` for the block they come from.
+    pub(crate) fn diff_with_changes(
+        &self,
+        transaction: &YrsTransaction,
+        state_vector: Option<Vec<u8>>,
+        delegate: Box<dyn YrsChangeDelegate>,
+    ) -> Result<Vec<YrsAttributedDiff>, CodingError> {
+        use yrs::types::text::YChange;
+
+        let sv = match state_vector {
+            Some(sv) => StateVector::decode_v1(sv.as_slice())
+                .map_err(|_e| CodingError::DecodingError)?,
+            None => StateVector::default(),
+        };
+
+        let mut tx = transaction.transaction();
+        let tx = tx.as_mut().unwrap();
+
+        let hi = tx.snapshot();
+        let lo = Snapshot::new(sv, hi.delete_set.clone());
+        let compute = |change: YChange| delegate.call(change.kind.into(), change.id.into());
+        let diffs = self
+            .inner()
+            .as_ref()
+            .diff_range(tx, Some(&hi), Some(&lo), compute);
+
+        Ok(diffs
+            .iter()
+            .map(|d| YrsAttributedDiff {
+                diff: YrsDiff::from(d),
+                change: d.ychange.clone(),
+            })
+            .collect())
+    }
+
+    /// Returns the text content as a flat list of formatted runs. Embeds are reported
+    /// as a single object replacement character with the embedded value attached.
+    pub(crate) fn runs(&self, transaction: &YrsTransaction) -> Vec<YrsTextRun> {
+        use yrs::types::text::Diff;
+        use yrs::Out;
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+
+        let diffs: Vec<Diff<()>> = self.inner().as_ref().diff(tx, |_| ());
+        diffs
+            .into_iter()
+            .map(|d| {
+                let attrs = d
+                    .attributes
+                    .map(|a| {
+                        a.iter()
+                            .map(|(k, v)| (k.to_string(), YrsValue::from(v)))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                match d.insert {
+                    Out::Any(Any::String(s)) => YrsTextRun {
+                        text: s.to_string(),
+                        embed: None,
+                        attrs,
+                    },
+                    Out::Any(any) => YrsTextRun {
+                        text: OBJECT_REPLACEMENT.to_string(),
+                        embed: Some(YrsValue::from(any)),
+                        attrs,
+                    },
+                    _ => YrsTextRun {
+                        text: OBJECT_REPLACEMENT.to_string(),
+                        embed: None,
+                        attrs,
+                    },
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the blocks backing this text in document order, including deleted ones whose
+    /// content hasn't been garbage collected yet. Meant for inspecting the CRDT structure
+    /// while debugging merges; embeds are listed without an ID, as yrs doesn't expose it.
+    pub(crate) fn chunks(&self, transaction: &YrsTransaction) -> Vec<YrsTextChunk> {
+        use yrs::types::text::YChange;
+        use yrs::{DeleteSet, Out};
+
+        let mut tx = transaction.transaction();
+        let tx = tx.as_mut().unwrap();
+
+        let current = tx.snapshot();
+        // every known block is visible in `hi` and none in `lo`, so each text block is
+        // reported as a separate insertion carrying its ID
+        let hi = Snapshot::new(current.state_map.clone(), DeleteSet::new());
+        let lo = Snapshot::new(StateVector::default(), DeleteSet::new());
+        let diffs = self
+            .inner()
+            .as_ref()
+            .diff_range(tx, Some(&hi), Some(&lo), YChange::identity);
+
+        diffs
+            .into_iter()
+            .map(|diff| {
+                let id = diff.ychange.map(|change| change.id);
+                let deleted = id
+                    .map(|id| current.delete_set.is_deleted(&id))
+                    .unwrap_or(false);
+                let (text, embed) = match diff.insert {
+                    Out::Any(Any::String(s)) => (s.to_string(), None),
+                    Out::Any(any) => (OBJECT_REPLACEMENT.to_string(), Some(YrsValue::from(any))),
+                    _ => (OBJECT_REPLACEMENT.to_string(), None),
+                };
+                YrsTextChunk {
+                    id: id.map(YrsId::from),
+                    text,
+                    embed,
+                    deleted,
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the text as runs of characters inserted by the same client, for rendering
+    /// author colors or a blame gutter. Without a snapshot the current text is described,
+    /// otherwise the text as it was at the snapshot. Deleted runs are included as long as
+    /// their content hasn't been garbage collected. Yjs doesn't record who deleted
+    /// content, so deleted runs are only attributed to the client that inserted them.
+    /// Embeds are left out.
+    pub(crate) fn attribution(
+        &self,
+        transaction: &YrsTransaction,
+        snapshot: Option<Arc<YrsSnapshot>>,
+    ) -> Vec<YrsAttributionRun> {
+        use yrs::types::text::YChange;
+        use yrs::{DeleteSet, Out};
+
+        let mut tx = transaction.transaction();
+        let tx = tx.as_mut().unwrap();
+
+        let at = match snapshot {
+            Some(snapshot) => snapshot.inner().clone(),
+            None => tx.snapshot(),
+        };
+        // same trick as `chunks`: every block known at `at` is reported with its ID
+        let hi = Snapshot::new(at.state_map.clone(), DeleteSet::new());
+        let lo = Snapshot::new(StateVector::default(), DeleteSet::new());
+        let diffs = self
+            .inner()
+            .as_ref()
+            .diff_range(tx, Some(&hi), Some(&lo), YChange::identity);
+
+        let mut runs: Vec<YrsAttributionRun> = Vec::new();
+        for diff in diffs {
+            let (Out::Any(Any::String(text)), Some(change)) = (diff.insert, diff.ychange) else {
+                continue;
+            };
+            let deleted = at.delete_set.is_deleted(&change.id);
+            match runs.last_mut() {
+                Some(last) if last.client_id == change.id.client && last.deleted == deleted => {
+                    last.text.push_str(&text);
+                }
+                _ => runs.push(YrsAttributionRun {
+                    text: text.to_string(),
+                    client_id: change.id.client,
+                    deleted,
+                }),
+            }
+        }
+        runs
+    }
+
+    /// Returns the delta operations needed to bring a view of this text, captured along
+    /// with `since`, up to date with its current content, including formatting applied
+    /// or removed since, which is reported as retains carrying the changed attributes,
+    /// with `null` for removed ones.
+    ///
+    /// Callers have to keep the `YrsSnapshot` taken when capturing the view; a state
+    /// vector isn't enough. The delete set of the snapshot tells content removed after
+    /// the view was captured, which is reported as deleted, from content that was
+    /// already gone from the view. Fails with `CodingError::ContentCollected` when
+    /// content or formatting known to the view was removed from a document that doesn't
+    /// `skip_gc`, since the length of that content and the replaced formatting are gone;
+    /// the view has to be re-fetched then.
+    pub(crate) fn delta_since(
+        &self,
+        transaction: &YrsTransaction,
+        since: &YrsSnapshot,
+    ) -> Result<Vec<YrsDelta>, CodingError> {
+        use yrs::types::text::{ChangeKind, YChange};
+        use yrs::Out;
+
+        let mut tx = transaction.transaction();
+        let tx = tx.as_mut().unwrap();
+
+        let hi = tx.snapshot();
+        let lo = since.inner();
+        if !tx.doc().skip_gc() && removed_since(&hi, lo) {
+            return Err(CodingError::ContentCollected);
+        }
+        let text = self.inner();
+        let diffs = text.as_ref().diff_range(tx, Some(&hi), Some(lo), YChange::identity);
+        // The content of the view, along with the attributes it had then.
+        let mut before = text
+            .as_ref()
+            .diff_range(tx, Some(lo), Some(lo), YChange::identity)
+            .into_iter()
+            .map(|diff| (diff_len(&diff.insert), diff.attributes.map(|a| *a).unwrap_or_default()));
+        let mut run: (u32, Attrs) = (0, Attrs::new());
+
+        let mut result: Vec<YrsDelta> = Vec::new();
+        let mut retain = 0;
+        for diff in diffs {
+            let kind = diff.ychange.as_ref().map(|change| change.kind);
+            let mut len = diff_len(&diff.insert);
+            if kind == Some(ChangeKind::Added) {
+                if retain > 0 {
+                    result.push(YrsDelta::Retained { index: retain, attrs: "".into() });
+                    retain = 0;
+                }
+                let mut value = String::new();
+                if let Out::Any(any) = &diff.insert {
+                    any.to_json(&mut value);
+                }
+                let attrs = diff
+                    .attributes
+                    .map(|a| YrsAttrs::from(*a).into())
+                    .unwrap_or_default();
+                result.push(YrsDelta::Inserted { value, attrs });
+                continue;
+            }
+
+            // Removed and retained content was part of the view, and the retained
+            // content may have been formatted since.
+            let attrs = diff.attributes.map(|a| *a).unwrap_or_default();
+            while len > 0 {
+                if run.0 == 0 {
+                    run = before.next().unwrap_or((u32::MAX, Attrs::new()));
+                }
+                let n = len.min(run.0);
+                run.0 -= n;
+                len -= n;
+                if kind == Some(ChangeKind::Removed) {
+                    continue;
+                }
+                let changed = attrs_changed(&run.1, &attrs);
+                if changed.is_empty() {
+                    retain += n;
+                    continue;
+                }
+                if retain > 0 {
+                    result.push(YrsDelta::Retained { index: retain, attrs: "".into() });
+                    retain = 0;
+                }
+                let changed: String = YrsAttrs::from(changed).into();
+                match result.last_mut() {
+                    Some(YrsDelta::Retained { index, attrs }) if *attrs == changed => *index += n,
+                    _ => result.push(YrsDelta::Retained { index: n, attrs: changed }),
+                }
+            }
+            if kind == Some(ChangeKind::Removed) {
+                if retain > 0 {
+                    result.push(YrsDelta::Retained { index: retain, attrs: "".into() });
+                    retain = 0;
+                }
+                result.push(YrsDelta::Deleted { index: diff_len(&diff.insert) });
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Quotes `length` characters starting at `index`. The returned prelim can be
+    /// inserted into a map or an array, where it resolves to the current content of
+    /// the quoted range as the source text changes.
+    pub(crate) fn quote(
+        &self,
+        transaction: &YrsTransaction,
+        index: u32,
+        length: u32,
+    ) -> Result<Arc<YrsWeakPrelim>, YrsQuoteError> {
+        if length == 0 {
+            return Err(YrsQuoteError::OutOfBounds);
+        }
+        let end = index.checked_add(length - 1).ok_or(YrsQuoteError::OutOfBounds)?;
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+
+        let prelim = self.inner().as_ref().quote(tx, index..=end)?;
+        Ok(Arc::new(YrsWeakPrelim::from(prelim)))
+    }
+}
+
+/// Returns the length of a diff chunk, in UTF-16 code units for text.
+fn diff_len(insert: &yrs::Out) -> u32 {
+    match insert {
+        yrs::Out::Any(Any::String(s)) => s.encode_utf16().count() as u32,
+        _ => 1,
+    }
+}
+
+/// Returns the attributes to apply to content formatted with `before` so that it's
+/// formatted with `after`, with `null` for the ones to remove.
+fn attrs_changed(before: &Attrs, after: &Attrs) -> Attrs {
+    let mut changed: Attrs = after
+        .iter()
+        .filter(|(key, value)| before.get(*key) != Some(*value))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    for key in before.keys() {
+        if !after.contains_key(key) {
+            changed.insert(key.clone(), Any::Null);
+        }
+    }
+    changed
+}
+
+/// Returns whether content that was visible at `lo` has been removed by `hi`.
+fn removed_since(hi: &Snapshot, lo: &Snapshot) -> bool {
+    hi.delete_set.iter().any(|(client, ranges)| {
+        let known = lo.state_map.get(client);
+        ranges.iter().any(|range| {
+            (range.start..range.end.min(known))
+                .any(|clock| !lo.delete_set.is_deleted(&yrs::ID::new(*client, clock)))
+        })
+    })
+}
+
+/// Placeholder character used in place of embedded content within text runs.
+const OBJECT_REPLACEMENT: char = '\u{FFFC}';
+
+/// A span of text sharing the same formatting attributes.
+pub(crate) struct YrsTextRun {
+    pub text: String,
+    pub embed: Option<YrsValue>,
+    pub attrs: HashMap<String, YrsValue>,
+}
+
+/// A single block of text content, as stored in the underlying CRDT structure.
+pub(crate) struct YrsTextChunk {
+    pub id: Option<YrsId>,
+    pub text: String,
+    pub embed: Option<YrsValue>,
+    pub deleted: bool,
+}
+
+/// Consecutive characters inserted by the same client, as reported by `attribution`.
+#[derive(Debug, PartialEq)]
+pub(crate) struct YrsAttributionRun {
+    pub text: String,
+    pub client_id: u64,
+    pub deleted: bool,
+}
+
+/// A diff chunk along with the metadata computed for the change that produced it.
+pub(crate) struct YrsAttributedDiff {
+    pub diff: YrsDiff,
+    pub change: Option<String>,
 }
 
 /// Represents a diff chunk from YText.
@@ -225,8 +827,8 @@ pub(crate) enum YrsDiff {
     Other { attrs: String },
 }
 
-impl From<&yrs::types::text::Diff<()>> for YrsDiff {
-    fn from(diff: &yrs::types::text::Diff<()>) -> Self {
+impl<T> From<&yrs::types::text::Diff<T>> for YrsDiff {
+    fn from(diff: &yrs::types::text::Diff<T>) -> Self {
         use yrs::Out;
         let attrs = diff.attributes.as_ref()
             .map(|a| YrsAttrs::from(*a.clone()).into())
@@ -242,3 +844,333 @@ impl From<&yrs::types::text::Diff<()>> for YrsDiff {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::delta::YrsDelta;
+    use crate::error::CodingError;
+    use crate::subdoc::YrsDocOptions;
+    use crate::text::YrsDiff;
+    use crate::value::YrsValue;
+    use crate::YrsDoc;
+
+    #[test]
+    fn remove_format_clears_listed_attributes() {
+        let doc = YrsDoc::new();
+        let text = doc.get_text("text".to_string());
+
+        let txn = doc.transact(None);
+        text.insert_with_attributes(
+            &txn,
+            0,
+            "hello".to_string(),
+            r#"{"bold":true,"italic":true}"#.to_string(),
+        )
+        .unwrap();
+        text.remove_format(&txn, 0, 5, vec!["bold".to_string()])
+            .unwrap();
+
+        let diff = text.diff(&txn);
+        assert_eq!(diff.len(), 1);
+        assert!(matches!(&diff[0], YrsDiff::Text { attrs, .. } if attrs == r#"{"italic":true}"#));
+    }
+
+    #[test]
+    fn set_string_preserves_formatting_of_unchanged_text() {
+        let doc = YrsDoc::new();
+        let text = doc.get_text("text".to_string());
+
+        let txn = doc.transact(None);
+        text.insert(&txn, 0, "hello world".to_string()).unwrap();
+        text.format(&txn, 0, 5, r#"{"bold":true}"#.to_string())
+            .unwrap();
+
+        text.set_string(&txn, "hello 🌍 world!".to_string());
+        assert_eq!(text.get_string(&txn), "hello 🌍 world!");
+
+        let runs = text.runs(&txn);
+        assert_eq!(runs[0].text, "hello");
+        assert_eq!(runs[0].attrs.get("bold"), Some(&YrsValue::Bool { value: true }));
+    }
+
+    #[test]
+    fn runs_split_on_formatting() {
+        let doc = YrsDoc::new();
+        let text = doc.get_text("text".to_string());
+
+        let txn = doc.transact(None);
+        text.insert(&txn, 0, "hello world".to_string()).unwrap();
+        text.format(&txn, 6, 5, r#"{"bold":true}"#.to_string())
+            .unwrap();
+        text.insert_embed(&txn, 5, r#"{"image":"cat.png"}"#.to_string())
+            .unwrap();
+
+        let runs = text.runs(&txn);
+        assert_eq!(runs.len(), 4);
+        assert_eq!(runs[0].text, "hello");
+        assert!(runs[0].attrs.is_empty());
+        assert_eq!(runs[1].text, "\u{FFFC}");
+        assert!(runs[1].embed.is_some());
+        assert_eq!(runs[2].text, " ");
+        assert_eq!(runs[3].text, "world");
+        assert_eq!(runs[3].attrs.get("bold"), Some(&YrsValue::Bool { value: true }));
+    }
+
+    #[test]
+    fn delta_since_reports_new_inserts() {
+        let doc = YrsDoc::new();
+        let text = doc.get_text("text".to_string());
+
+        let txn = doc.transact(None);
+        text.insert(&txn, 0, "hello world".to_string()).unwrap();
+        let since = txn.snapshot();
+        txn.free();
+
+        let txn = doc.transact(None);
+        text.insert(&txn, 5, ",".to_string()).unwrap();
+        text.append(&txn, "!".to_string());
+
+        let delta = text.delta_since(&txn, &since).unwrap();
+        assert_eq!(delta.len(), 4);
+        assert!(matches!(delta[0], YrsDelta::Retained { index: 5, .. }));
+        assert!(matches!(&delta[1], YrsDelta::Inserted { value, .. } if value == "\",\""));
+        assert!(matches!(delta[2], YrsDelta::Retained { index: 6, .. }));
+        assert!(matches!(&delta[3], YrsDelta::Inserted { value, .. } if value == "\"!\""));
+    }
+
+    #[test]
+    fn delta_since_reports_removals() {
+        let doc = YrsDoc::new_with_options(YrsDocOptions {
+            auto_load: false,
+            client_id: None,
+            guid: None,
+            should_load: true,
+            skip_gc: true,
+        });
+        let text = doc.get_text("text".to_string());
+
+        let txn = doc.transact(None);
+        text.insert(&txn, 0, "hello big world".to_string()).unwrap();
+        text.remove_range(&txn, 0, 1).unwrap();
+        let since = txn.snapshot();
+        txn.free();
+
+        let txn = doc.transact(None);
+        text.remove_range(&txn, 5, 4).unwrap();
+        text.append(&txn, "!".to_string());
+
+        let delta = text.delta_since(&txn, &since).unwrap();
+        assert_eq!(delta.len(), 4);
+        assert!(matches!(delta[0], YrsDelta::Retained { index: 5, .. }));
+        assert!(matches!(delta[1], YrsDelta::Deleted { index: 4 }));
+        assert!(matches!(delta[2], YrsDelta::Retained { index: 5, .. }));
+        assert!(matches!(&delta[3], YrsDelta::Inserted { value, .. } if value == "\"!\""));
+    }
+
+    #[test]
+    fn delta_since_reports_formatting() {
+        let doc = YrsDoc::new_with_options(YrsDocOptions {
+            auto_load: false,
+            client_id: None,
+            guid: None,
+            should_load: true,
+            skip_gc: true,
+        });
+        let text = doc.get_text("text".to_string());
+
+        let txn = doc.transact(None);
+        text.insert(&txn, 0, "hello big world".to_string()).unwrap();
+        text.format(&txn, 6, 3, r#"{"italic":true}"#.to_string()).unwrap();
+        let since = txn.snapshot();
+        txn.free();
+
+        let txn = doc.transact(None);
+        text.format(&txn, 0, 5, r#"{"bold":true}"#.to_string()).unwrap();
+        text.remove_format(&txn, 6, 3, vec!["italic".to_string()]).unwrap();
+
+        let delta = text.delta_since(&txn, &since).unwrap();
+        assert_eq!(delta.len(), 3);
+        assert!(matches!(&delta[0], YrsDelta::Retained { index: 5, attrs } if attrs == r#"{"bold":true}"#));
+        assert!(matches!(&delta[1], YrsDelta::Retained { index: 1, attrs } if attrs.is_empty()));
+        assert!(matches!(&delta[2], YrsDelta::Retained { index: 3, attrs } if attrs == r#"{"italic":null}"#));
+    }
+
+    #[test]
+    fn delta_since_fails_once_removed_content_was_collected() {
+        let doc = YrsDoc::new();
+        let text = doc.get_text("text".to_string());
+
+        let txn = doc.transact(None);
+        text.insert(&txn, 0, "hello world".to_string()).unwrap();
+        let since = txn.snapshot();
+        txn.free();
+
+        let txn = doc.transact(None);
+        text.insert(&txn, 0, "oh ".to_string()).unwrap();
+        text.remove_range(&txn, 0, 1).unwrap();
+        assert_eq!(text.delta_since(&txn, &since).unwrap().len(), 1);
+        text.remove_range(&txn, 2, 5).unwrap();
+        txn.free();
+
+        let txn = doc.transact(None);
+        assert!(matches!(text.delta_since(&txn, &since), Err(CodingError::ContentCollected)));
+    }
+
+    #[test]
+    fn writes_inside_surrogate_pair_are_rejected_or_snapped() {
+        use crate::text::{YrsSurrogatePolicy, YrsTextError};
+
+        let doc = YrsDoc::new();
+        let text = doc.get_text("text".to_string());
+
+        let txn = doc.transact(None);
+        text.insert(&txn, 0, "a🌍b".to_string()).unwrap();
+        let err = text.insert(&txn, 2, "x".to_string()).unwrap_err();
+        assert!(matches!(err, YrsTextError::SurrogateSplit { index: 2 }));
+        let err = text.remove_range(&txn, 1, 1).unwrap_err();
+        assert!(matches!(err, YrsTextError::SurrogateSplit { index: 2 }));
+        assert_eq!(text.get_string(&txn), "a🌍b");
+        txn.free();
+
+        // the policy belongs to the document, so it is shared by every handle to it
+        YrsDoc::from_doc(doc.inner()).set_surrogate_policy(YrsSurrogatePolicy::Snap);
+        let text = doc.get_text("text".to_string());
+        let txn = doc.transact(None);
+        text.insert(&txn, 2, "x".to_string()).unwrap();
+        assert_eq!(text.get_string(&txn), "ax🌍b");
+
+        text.remove_range(&txn, 3, 1).unwrap();
+        assert_eq!(text.get_string(&txn), "axb");
+
+        assert!(matches!(
+            text.insert(&txn, 4, "y".to_string()),
+            Err(YrsTextError::OutOfBounds { index: 4, length: 0 })
+        ));
+        assert!(matches!(
+            text.remove_range(&txn, 2, u32::MAX),
+            Err(YrsTextError::OutOfBounds { index: 2, .. })
+        ));
+        assert!(matches!(
+            text.format(&txn, 1, 3, "{}".to_string()),
+            Err(YrsTextError::OutOfBounds { index: 1, length: 3 })
+        ));
+    }
+
+    #[test]
+    fn surrogate_checks_only_read_pairs_inserted_together() {
+        use crate::text::{inserted_together, YrsTextError};
+
+        let doc = YrsDoc::new();
+        let text = doc.get_text("text".to_string());
+        let txn = doc.transact(None);
+        text.insert(&txn, 0, "ab".to_string()).unwrap();
+        text.insert(&txn, 1, "🌍".to_string()).unwrap();
+        {
+            let inner = text.inner();
+            let t = txn.transaction();
+            let t = t.as_ref().unwrap();
+            assert!(!inserted_together(inner.as_ref(), t, 1));
+            assert!(inserted_together(inner.as_ref(), t, 2));
+            assert!(!inserted_together(inner.as_ref(), t, 3));
+        }
+        text.insert(&txn, 1, "x".to_string()).unwrap();
+        let err = text.insert(&txn, 3, "y".to_string()).unwrap_err();
+        assert!(matches!(err, YrsTextError::SurrogateSplit { index: 3 }));
+        assert_eq!(text.get_string(&txn), "ax🌍b");
+        txn.free();
+    }
+
+    #[test]
+    fn chunks_list_blocks_with_ids_and_deletions() {
+        let doc = YrsDoc::new();
+        let client = doc.client_id();
+        let text = doc.get_text("text".to_string());
+
+        let txn = doc.transact(None);
+        text.insert(&txn, 0, "hello".to_string()).unwrap();
+        text.insert(&txn, 5, " world".to_string()).unwrap();
+        text.remove_range(&txn, 0, 2).unwrap();
+
+        let chunks = text.chunks(&txn);
+        let summary: Vec<_> = chunks
+            .iter()
+            .map(|c| (c.id.unwrap().client, c.id.unwrap().clock, c.text.as_str(), c.deleted))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (client, 0, "he", true),
+                (client, 2, "llo", false),
+                (client, 5, " world", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn attribution_reports_runs_by_inserting_client() {
+        let alice = YrsDoc::new();
+        let bob = YrsDoc::new();
+        let alice_text = alice.get_text("text".to_string());
+        let bob_text = bob.get_text("text".to_string());
+
+        let txn = alice.transact(None);
+        alice_text.insert(&txn, 0, "hello".to_string()).unwrap();
+        let update = txn.transaction_encode_state_as_update();
+        let before = txn.snapshot();
+        txn.free();
+
+        let txn = bob.transact(None);
+        txn.transaction_apply_update(update).unwrap();
+        bob_text.insert(&txn, 5, " world".to_string()).unwrap();
+        bob_text.remove_range(&txn, 0, 2).unwrap();
+
+        let summary = |runs: Vec<crate::text::YrsAttributionRun>| -> Vec<(String, u64, bool)> {
+            runs.into_iter().map(|r| (r.text, r.client_id, r.deleted)).collect()
+        };
+        assert_eq!(
+            summary(bob_text.attribution(&txn, None)),
+            vec![
+                ("he".to_string(), alice.client_id(), true),
+                ("llo".to_string(), alice.client_id(), false),
+                (" world".to_string(), bob.client_id(), false),
+            ]
+        );
+        assert_eq!(
+            summary(bob_text.attribution(&txn, Some(before))),
+            vec![("hello".to_string(), alice.client_id(), false)]
+        );
+    }
+
+    #[derive(Debug)]
+    struct ClientNames;
+
+    impl crate::text::YrsChangeDelegate for ClientNames {
+        fn call(&self, kind: crate::text::YrsChangeKind, id: crate::doc::YrsId) -> String {
+            format!("{:?} by {}", kind, id.client)
+        }
+    }
+
+    #[test]
+    fn diff_with_changes_attributes_new_chunks() {
+        let doc = YrsDoc::new();
+        let client = doc.client_id();
+        let text = doc.get_text("text".to_string());
+
+        let txn = doc.transact(None);
+        text.insert(&txn, 0, "hello".to_string()).unwrap();
+        let sv = txn.transaction_state_vector();
+        txn.free();
+
+        let txn = doc.transact(None);
+        text.append(&txn, " world".to_string());
+
+        let diff = text
+            .diff_with_changes(&txn, Some(sv), Box::new(ClientNames))
+            .unwrap();
+        assert_eq!(diff.len(), 2);
+        assert!(matches!(&diff[0].diff, YrsDiff::Text { value, .. } if value == r#""hello""#));
+        assert_eq!(diff[0].change, None);
+        assert!(matches!(&diff[1].diff, YrsDiff::Text { value, .. } if value == r#"" world""#));
+        assert_eq!(diff[1].change, Some(format!("Added by {}", client)));
+    }
+}
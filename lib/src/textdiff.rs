@@ -0,0 +1,210 @@
+/// Upper bound of the number of trace entries kept while searching for the shortest
+/// edit script. Once exceeded, the remaining middle section is replaced as a whole.
+const MAX_TRACE_ENTRIES: usize = 8_000_000;
+
+/// A single step of an edit script turning one sequence of characters into another.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Edit {
+    /// Keep the given number of characters.
+    Equal(usize),
+    /// Remove the given number of characters.
+    Delete(usize),
+    /// Insert the given string.
+    Insert(String),
+}
+
+enum Step {
+    Equal,
+    Delete,
+    Insert(char),
+}
+
+/// Computes a minimal edit script between `old` and `new` using Myers' algorithm,
+/// after trimming their common prefix and suffix.
+pub(crate) fn diff(old: &str, new: &str) -> Vec<Edit> {
+    let a: Vec<char> = old.chars().collect();
+    let b: Vec<char> = new.chars().collect();
+
+    let prefix = a.iter().zip(b.iter()).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let a_mid = &a[prefix..a.len() - suffix];
+    let b_mid = &b[prefix..b.len() - suffix];
+
+    let mut edits = Vec::new();
+    push_equal(&mut edits, prefix);
+    match shortest_edit(a_mid, b_mid) {
+        Some(steps) => {
+            for step in steps {
+                match step {
+                    Step::Equal => push_equal(&mut edits, 1),
+                    Step::Delete => push_delete(&mut edits, 1),
+                    Step::Insert(c) => push_insert(&mut edits, c),
+                }
+            }
+        }
+        None => {
+            push_delete(&mut edits, a_mid.len());
+            if !b_mid.is_empty() {
+                edits.push(Edit::Insert(b_mid.iter().collect()));
+            }
+        }
+    }
+    push_equal(&mut edits, suffix);
+    edits
+}
+
+fn push_equal(edits: &mut Vec<Edit>, n: usize) {
+    if n == 0 {
+        return;
+    }
+    if let Some(Edit::Equal(len)) = edits.last_mut() {
+        *len += n;
+    } else {
+        edits.push(Edit::Equal(n));
+    }
+}
+
+fn push_delete(edits: &mut Vec<Edit>, n: usize) {
+    if n == 0 {
+        return;
+    }
+    if let Some(Edit::Delete(len)) = edits.last_mut() {
+        *len += n;
+    } else {
+        edits.push(Edit::Delete(n));
+    }
+}
+
+fn push_insert(edits: &mut Vec<Edit>, c: char) {
+    if let Some(Edit::Insert(s)) = edits.last_mut() {
+        s.push(c);
+    } else {
+        edits.push(Edit::Insert(c.to_string()));
+    }
+}
+
+/// Returns the shortest edit script between `a` and `b`, or `None` if finding it
+/// would require more memory than allowed by `MAX_TRACE_ENTRIES`.
+fn shortest_edit(a: &[char], b: &[char]) -> Option<Vec<Step>> {
+    let n = a.len() as isize;
+    let m = b.len() as isize;
+    let max = n + m;
+    if max == 0 {
+        return Some(Vec::new());
+    }
+    let width = 2 * max as usize + 2;
+    let max_d = (MAX_TRACE_ENTRIES / width).max(1) as isize;
+    let offset = max + 1;
+
+    let mut v = vec![0isize; width];
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+    for d in 0..=max.min(max_d) {
+        trace.push(v.clone());
+        let mut k = -d;
+        while k <= d {
+            let i = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[i - 1] < v[i + 1]) {
+                v[i + 1]
+            } else {
+                v[i - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[i] = x;
+            if x >= n && y >= m {
+                return Some(backtrack(b, &trace, n, m, offset));
+            }
+            k += 2;
+        }
+    }
+    None
+}
+
+fn backtrack(b: &[char], trace: &[Vec<isize>], n: isize, m: isize, offset: isize) -> Vec<Step> {
+    let mut steps = Vec::new();
+    let mut x = n;
+    let mut y = m;
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let i = (k + offset) as usize;
+        let prev_k = if k == -d || (k != d && v[i - 1] < v[i + 1]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = v[(prev_k + offset) as usize];
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            steps.push(Step::Equal);
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            if x == prev_x {
+                steps.push(Step::Insert(b[(y - 1) as usize]));
+            } else {
+                steps.push(Step::Delete);
+            }
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    steps.reverse();
+    steps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff, Edit};
+
+    #[test]
+    fn diff_keeps_common_parts() {
+        let edits = diff("hello world", "hello brave new world");
+        assert_eq!(
+            edits,
+            vec![
+                Edit::Equal(6),
+                Edit::Insert("brave new ".to_string()),
+                Edit::Equal(5),
+            ]
+        );
+    }
+
+    #[test]
+    fn diff_replaces_changed_characters() {
+        let edits = diff("kitten", "sitting");
+        let applied = apply("kitten", &edits);
+        assert_eq!(applied, "sitting");
+        let deleted: usize = edits
+            .iter()
+            .map(|e| if let Edit::Delete(n) = e { *n } else { 0 })
+            .sum();
+        assert_eq!(deleted, 2);
+    }
+
+    fn apply(old: &str, edits: &[Edit]) -> String {
+        let chars: Vec<char> = old.chars().collect();
+        let mut pos = 0;
+        let mut result = String::new();
+        for edit in edits {
+            match edit {
+                Edit::Equal(n) => {
+                    result.extend(&chars[pos..pos + n]);
+                    pos += n;
+                }
+                Edit::Delete(n) => pos += n,
+                Edit::Insert(s) => result.push_str(s),
+            }
+        }
+        result
+    }
+}
@@ -1,17 +1,22 @@
 use crate::array::YrsArray;
+use crate::compression;
 use crate::doc::{YrsDoc, YrsOrigin};
 use crate::error::CodingError;
 use crate::map::YrsMap;
-use crate::text::YrsText;
+use crate::subdoc::YrsSubdocNode;
+use crate::text::{YrsSurrogatePolicy, YrsText};
 use parking_lot::ReentrantMutex;
 use std::borrow::Borrow;
 use std::cell::UnsafeCell;
+use std::collections::HashMap;
 use std::sync::Arc;
+use yrs::encoding::read::{Cursor, Read};
+use yrs::encoding::write::Write;
 use yrs::{
     updates::decoder::Decode, updates::encoder::Encode, ReadTxn, StateVector, TransactionMut,
     Update,
 };
-use yrs::{Store, WriteTxn};
+use yrs::{Array, ArrayRef, Doc, Map, MapRef, Out, Store, Transact, WriteTxn};
 
 /// YrsTransaction wraps a yrs TransactionMut for use across the FFI boundary.
 ///
@@ -20,7 +25,13 @@ use yrs::{Store, WriteTxn};
 /// 2. ReentrantMutex allows same-thread re-entry for observer callbacks
 /// 3. UnsafeCell provides interior mutability - safe because ReentrantMutex
 ///    ensures exclusive access (only one thread at a time)
-pub(crate) struct YrsTransaction(pub(crate) ReentrantMutex<UnsafeCell<Option<TransactionMut<'static>>>>);
+///
+/// The second field is the surrogate policy of the document, as it was when the
+/// transaction started.
+pub(crate) struct YrsTransaction(
+    pub(crate) ReentrantMutex<UnsafeCell<Option<TransactionMut<'static>>>>,
+    YrsSurrogatePolicy,
+);
 
 // Safe because ReentrantMutex provides proper thread synchronization
 unsafe impl Send for YrsTransaction {}
@@ -38,10 +49,15 @@ impl ReadTxn for YrsTransaction {
     }
 }
 
-impl<'doc> From<TransactionMut<'doc>> for YrsTransaction {
-    fn from(txn: TransactionMut<'doc>) -> Self {
+impl YrsTransaction {
+    pub(crate) fn new(txn: TransactionMut<'_>, surrogate_policy: YrsSurrogatePolicy) -> Self {
         let txn: TransactionMut<'static> = unsafe { std::mem::transmute(txn) };
-        YrsTransaction(ReentrantMutex::new(UnsafeCell::new(Some(txn))))
+        YrsTransaction(ReentrantMutex::new(UnsafeCell::new(Some(txn))), surrogate_policy)
+    }
+
+    /// How writes in this transaction treat offsets that split a surrogate pair.
+    pub(crate) fn surrogate_policy(&self) -> YrsSurrogatePolicy {
+        self.1
     }
 }
 
@@ -102,6 +118,11 @@ impl YrsTransaction {
         tx.encode_state_as_update_v1(&StateVector::default())
     }
 
+    /// Like `transaction_encode_state_as_update`, but compressed with `compression::compress`.
+    pub(crate) fn transaction_encode_state_as_update_compressed(&self) -> Vec<u8> {
+        compression::compress(&self.transaction_encode_state_as_update())
+    }
+
     pub(crate) fn transaction_state_vector(&self) -> Vec<u8> {
         let guard = self.transaction();
         guard.as_ref().unwrap().state_vector().encode_v1()
@@ -119,6 +140,11 @@ impl YrsTransaction {
             })
     }
 
+    /// Applies an update produced by `transaction_encode_state_as_update_compressed`.
+    pub(crate) fn transaction_apply_update_compressed(&self, update: Vec<u8>) -> Result<(), CodingError> {
+        self.transaction_apply_update(compression::decompress(&update)?)
+    }
+
     pub(crate) fn transaction_get_text(&self, name: String) -> Option<Arc<YrsText>> {
         let guard = self.transaction();
         guard.as_ref()
@@ -168,9 +194,267 @@ impl YrsTransaction {
             .unwrap_or_default()
     }
 
+    /// Returns the subdocuments of this document and of its subdocuments, down to `depth`
+    /// levels, parents before their children. A depth of 1 only lists direct subdocuments.
+    pub(crate) fn subdocs_recursive(&self, depth: u32) -> Vec<YrsSubdocNode> {
+        let guard = self.transaction();
+        let tx = guard.as_ref().unwrap();
+
+        let mut nodes = Vec::new();
+        if depth > 0 {
+            let parent_guid = tx.doc().guid().to_string();
+            for subdoc in tx.subdocs() {
+                collect_subdoc_nodes(subdoc, &parent_guid, depth - 1, &mut nodes);
+            }
+        }
+        nodes
+    }
+
+    /// Encodes the state of the document together with the state of every loaded
+    /// subdocument, recursively. The result bundles the document update followed by
+    /// each subdocument's update keyed by its guid, parents before their children.
+    pub(crate) fn transaction_encode_state_with_subdocs(&self) -> Vec<u8> {
+        let guard = self.transaction();
+        let tx = guard.as_ref().unwrap();
+
+        let mut subdocs = Vec::new();
+        for subdoc in tx.subdocs() {
+            collect_subdoc_states(subdoc, &mut subdocs);
+        }
+
+        let mut buf = Vec::new();
+        buf.write_buf(tx.encode_state_as_update_v1(&StateVector::default()));
+        buf.write_var(subdocs.len());
+        for (guid, update) in subdocs {
+            buf.write_string(&guid);
+            buf.write_buf(update);
+        }
+        buf
+    }
+
+    /// Applies a bundle produced by `transaction_encode_state_with_subdocs`. Updates of
+    /// subdocuments that can't be found once their parent has been applied are ignored.
+    pub(crate) fn transaction_apply_state_with_subdocs(&self, state: Vec<u8>) -> Result<(), CodingError> {
+        let mut cursor = Cursor::new(state.as_slice());
+        let root = cursor.read_buf().map_err(|_e| CodingError::DecodingError)?.to_vec();
+        let count: usize = cursor.read_var().map_err(|_e| CodingError::DecodingError)?;
+        // the count comes straight from the bundle, so it must not size any allocation
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            let guid = cursor.read_string().map_err(|_e| CodingError::DecodingError)?.to_string();
+            let update = cursor.read_buf().map_err(|_e| CodingError::DecodingError)?;
+            let update = Update::decode_v1(update).map_err(|_e| CodingError::DecodingError)?;
+            entries.push((guid, update));
+        }
+
+        self.transaction_apply_update(root)?;
+        let mut known = HashMap::new();
+        reachable_subdocs(self.transaction().as_ref().unwrap(), &mut known);
+        for (guid, update) in entries {
+            let Some(doc) = known.get(&guid).cloned() else {
+                continue;
+            };
+            let mut txn = doc.transact_mut();
+            txn.apply_update(update).map_err(|_e| CodingError::DecodingError)?;
+            reachable_subdocs(&txn, &mut known);
+        }
+        Ok(())
+    }
+
     pub(crate) fn free(&self) {
         let _guard = self.0.lock();
         // SAFETY: We hold the lock
         unsafe { *(*self.0.data_ptr()).get() = None };
     }
 }
+
+/// Collects the subdocuments reachable from the root types by guid. Unlike `subdocs`,
+/// this includes subdocuments integrated by a transaction that hasn't committed yet.
+fn reachable_subdocs<T: ReadTxn>(txn: &T, found: &mut HashMap<String, Doc>) {
+    fn visit<T: ReadTxn>(txn: &T, value: Out, found: &mut HashMap<String, Doc>) {
+        match value {
+            Out::YDoc(doc) => {
+                found.insert(doc.guid().to_string(), doc);
+            }
+            Out::YMap(map) => map.iter(txn).for_each(|(_, child)| visit(txn, child, found)),
+            Out::YArray(array) => array.iter(txn).for_each(|child| visit(txn, child, found)),
+            // Root types received in an update have no type until they're first accessed.
+            Out::UndefinedRef(branch) => {
+                visit(txn, Out::YMap(MapRef::from(branch)), found);
+                visit(txn, Out::YArray(ArrayRef::from(branch)), found);
+            }
+            _ => {}
+        }
+    }
+    for (_, value) in txn.root_refs() {
+        visit(txn, value, found);
+    }
+}
+
+/// Appends a subdocument and, while `depth` allows, its own subdocuments.
+fn collect_subdoc_nodes(doc: &Doc, parent_guid: &str, depth: u32, out: &mut Vec<YrsSubdocNode>) {
+    let guid = doc.guid().to_string();
+    out.push(YrsSubdocNode {
+        guid: guid.clone(),
+        parent_guid: parent_guid.to_string(),
+    });
+    if depth == 0 {
+        return;
+    }
+    for subdoc in doc.transact().subdocs() {
+        collect_subdoc_nodes(subdoc, &guid, depth - 1, out);
+    }
+}
+
+/// Appends the state of a loaded subdocument and of its own subdocuments.
+fn collect_subdoc_states(doc: &Doc, out: &mut Vec<(String, Vec<u8>)>) {
+    if !doc.should_load() {
+        return;
+    }
+    let txn = doc.transact();
+    out.push((
+        doc.guid().to_string(),
+        txn.encode_state_as_update_v1(&StateVector::default()),
+    ));
+    for subdoc in txn.subdocs() {
+        collect_subdoc_states(subdoc, out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::CodingError;
+    use crate::subdoc::YrsDocOptions;
+    use crate::YrsDoc;
+
+    /// Returns a document with the given guid, to be inserted as a subdocument.
+    fn reference(guid: String) -> YrsDoc {
+        YrsDoc::new_with_options(YrsDocOptions {
+            auto_load: false,
+            client_id: None,
+            guid: Some(guid),
+            should_load: true,
+            skip_gc: false,
+        })
+    }
+
+    fn state(doc: &YrsDoc) -> Vec<u8> {
+        let txn = doc.transact(None);
+        let state = txn.transaction_encode_state_as_update();
+        txn.free();
+        state
+    }
+
+    #[test]
+    fn state_with_subdocs_round_trips_document_tree() {
+        // yrs refuses to insert a document into a subdocument, so the page is written as a
+        // standalone document first and then synced into the tree.
+        let source = YrsDoc::new();
+        let content = source.get_text("content".to_string());
+        let children = source.get_map("children".to_string());
+        let txn = source.transact(None);
+        content.insert(&txn, 0, "hello".to_string()).unwrap();
+        let child = children.insert_doc(&txn, "child".to_string(), &YrsDoc::new());
+        txn.free();
+
+        let note = child.get_text("note".to_string());
+        let txn = child.transact(None);
+        note.insert(&txn, 0, "deep".to_string()).unwrap();
+        txn.free();
+
+        let doc = YrsDoc::new();
+        let pages = doc.get_map("pages".to_string());
+        let txn = doc.transact(None);
+        let page = pages.insert_doc(&txn, "page".to_string(), &reference(source.guid()));
+        txn.free();
+
+        let children = page.get_map("children".to_string());
+        let txn = page.transact(None);
+        txn.transaction_apply_update(state(&source)).unwrap();
+        let child_ref = children.get_doc(&txn, "child".to_string()).unwrap();
+        child_ref.load(&txn);
+        txn.free();
+        let txn = child_ref.transact(None);
+        txn.transaction_apply_update(state(&child)).unwrap();
+        txn.free();
+
+        let txn = doc.transact(None);
+        let bundle = txn.transaction_encode_state_with_subdocs();
+        txn.free();
+
+        let copy = YrsDoc::new();
+        let copy_pages = copy.get_map("pages".to_string());
+        let txn = copy.transact(None);
+        txn.transaction_apply_state_with_subdocs(bundle).unwrap();
+        let page = copy_pages.get_doc(&txn, "page".to_string()).unwrap();
+        txn.free();
+
+        let content = page.get_text("content".to_string());
+        let children = page.get_map("children".to_string());
+        let txn = page.transact(None);
+        assert_eq!(content.get_string(&txn), "hello");
+        let child = children.get_doc(&txn, "child".to_string()).unwrap();
+        txn.free();
+
+        let note = child.get_text("note".to_string());
+        let txn = child.transact(None);
+        assert_eq!(note.get_string(&txn), "deep");
+    }
+
+    #[test]
+    fn subdocs_recursive_walks_tree_to_depth() {
+        let source = YrsDoc::new();
+        let children = source.get_map("children".to_string());
+        let txn = source.transact(None);
+        children.insert_doc(&txn, "child".to_string(), &reference("child".to_string()));
+        txn.free();
+
+        let doc = YrsDoc::new();
+        let pages = doc.get_map("pages".to_string());
+        let txn = doc.transact(None);
+        pages.insert_doc(&txn, "page".to_string(), &reference("page".to_string()));
+        txn.free();
+
+        let txn = doc.transact(None);
+        let page = pages.get_doc(&txn, "page".to_string()).unwrap();
+        txn.free();
+        let txn = page.transact(None);
+        txn.transaction_apply_update(state(&source)).unwrap();
+        txn.free();
+
+        let pairs = |depth| {
+            let txn = doc.transact(None);
+            let nodes = txn.subdocs_recursive(depth);
+            txn.free();
+            nodes
+                .into_iter()
+                .map(|node| (node.guid, node.parent_guid))
+                .collect::<Vec<_>>()
+        };
+        assert!(pairs(0).is_empty());
+        assert_eq!(pairs(1), vec![("page".to_string(), doc.guid())]);
+        assert_eq!(
+            pairs(2),
+            vec![
+                ("page".to_string(), doc.guid()),
+                ("child".to_string(), "page".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn state_with_subdocs_rejects_malformed_input() {
+        let doc = YrsDoc::new();
+        let txn = doc.transact(None);
+        assert!(matches!(
+            txn.transaction_apply_state_with_subdocs(vec![1, 2, 3]),
+            Err(CodingError::DecodingError)
+        ));
+        // an empty root update followed by a subdocument count of 2^56
+        let huge_count = vec![0, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01];
+        assert!(matches!(
+            txn.transaction_apply_state_with_subdocs(huge_count),
+            Err(CodingError::DecodingError)
+        ));
+    }
+}
@@ -1,28 +1,129 @@
 use std::fmt::Debug;
-use std::sync::{Arc, Mutex, MutexGuard};
-use yrs::undo::EventKind;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::time::Instant;
+use yrs::sync::time::{Clock, SystemClock, Timestamp};
+use yrs::undo::{EventKind, Options, StackItem};
+use yrs::branch::BranchPtr;
+use yrs::{DeleteSet, Doc, Origin};
 use crate::doc::{YrsCollectionPtr, YrsOrigin};
 use crate::subscription::YSubscription;
 
-pub(crate) struct YrsUndoManager(Mutex<yrs::undo::UndoManager<u64>>);
+/// Options used when creating an undo manager.
+pub(crate) struct YrsUndoManagerOptions {
+    /// Period, in milliseconds, within which consecutive changes are merged into one undo step.
+    pub capture_timeout_millis: u64,
+    /// Origins of the transactions to track. When empty, only transactions without an
+    /// origin are tracked.
+    pub tracked_origins: Vec<YrsOrigin>,
+    /// Whether changes are captured at all. Can be changed later on the manager.
+    pub capture_transactions: bool,
+}
+
+impl Default for YrsUndoManagerOptions {
+    fn default() -> Self {
+        YrsUndoManagerOptions {
+            capture_timeout_millis: 500,
+            tracked_origins: Vec::new(),
+            capture_transactions: true,
+        }
+    }
+}
+
+pub(crate) struct YrsUndoManager {
+    manager: Mutex<yrs::undo::UndoManager<ItemMeta>>,
+    clock: Arc<YrsUndoClock>,
+    capturing: Arc<AtomicBool>,
+    _added: yrs::Subscription,
+    _updated: yrs::Subscription,
+}
 
 unsafe impl Send for YrsUndoManager {}
 unsafe impl Sync for YrsUndoManager {}
 
-impl From<yrs::undo::UndoManager<u64>> for YrsUndoManager {
-    fn from(value: yrs::undo::UndoManager<u64>) -> Self {
-        YrsUndoManager(Mutex::new(value))
+impl YrsUndoManager {
+    pub(crate) fn new(
+        doc: &Doc,
+        tracked_refs: Vec<YrsCollectionPtr>,
+        options: YrsUndoManagerOptions,
+    ) -> Self {
+        let clock = Arc::new(YrsUndoClock::new(options.capture_timeout_millis));
+        let capturing = Arc::new(AtomicBool::new(options.capture_transactions));
+        let own_origin = Arc::new(OnceLock::<Origin>::new());
+        let capture_transaction = {
+            let capturing = capturing.clone();
+            let own_origin = own_origin.clone();
+            // Undo and redo are always captured, so that they keep moving items between stacks.
+            Arc::new(move |txn: &yrs::TransactionMut| {
+                capturing.load(Ordering::Relaxed) || txn.origin() == own_origin.get()
+            })
+        };
+        // The clock decides which changes fall into the same capture window, so yrs only
+        // needs to tell apart the windows it hands out.
+        let yrs_options = Options {
+            capture_timeout_millis: 1,
+            tracked_origins: options.tracked_origins.into_iter().map(Origin::from).collect(),
+            capture_transaction: Some(capture_transaction),
+            timestamp: clock.clone(),
+            ..Options::default()
+        };
+        let mut undo_manager: yrs::undo::UndoManager<ItemMeta> =
+            yrs::undo::UndoManager::with_options(doc, yrs_options);
+        let _ = own_origin.set(undo_manager.as_origin());
+        for tracked_ref in tracked_refs {
+            undo_manager.expand_scope(&tracked_ref);
+        }
+        // Registered before any delegate, so that delegates already see what was recorded.
+        let added = undo_manager.observe_item_added(|_, e| {
+            let origin = e.origin().cloned();
+            let changed = e.changed_parent_types().to_vec();
+            let meta = e.meta_mut();
+            meta.origin = origin;
+            meta.record(&changed);
+        });
+        let updated = undo_manager.observe_item_updated(|_, e| {
+            let changed = e.changed_parent_types().to_vec();
+            e.meta_mut().record(&changed);
+        });
+        YrsUndoManager {
+            manager: Mutex::new(undo_manager),
+            clock,
+            capturing,
+            _added: added,
+            _updated: updated,
+        }
     }
 }
 
 impl YrsUndoManager {
 
     #[inline]
-    fn acquire_lock(&self) -> MutexGuard<yrs::undo::UndoManager<u64>> {
+    fn acquire_lock(&self) -> MutexGuard<'_, yrs::undo::UndoManager<ItemMeta>> {
         // unwrap should be safe, as the only occasion to cause error would be a panic
         // while holding a lock and all operations holding a lock here only do so for
         // a time needed to perform a non-panicing operation
-        self.0.lock().unwrap()
+        self.manager.lock().unwrap()
+    }
+
+    /// Returns the period, in milliseconds, within which consecutive changes are merged
+    /// into a single undo step.
+    pub(crate) fn capture_timeout(&self) -> u64 {
+        self.clock.timeout.load(Ordering::Relaxed)
+    }
+
+    /// Changes the period within which consecutive changes are merged into a single undo
+    /// step. A timeout of zero makes every change its own undo step.
+    pub(crate) fn set_capture_timeout(&self, millis: u64) {
+        self.clock.timeout.store(millis, Ordering::Relaxed);
+    }
+
+    pub(crate) fn capture_transactions(&self) -> bool {
+        self.capturing.load(Ordering::Relaxed)
+    }
+
+    /// Pauses or resumes capturing changes. Undo and redo keep working while paused.
+    pub(crate) fn set_capture_transactions(&self, capture: bool) {
+        self.capturing.store(capture, Ordering::Relaxed);
     }
 
     pub(crate) fn add_origin(&self, origin: YrsOrigin) {
@@ -40,14 +141,68 @@ impl YrsUndoManager {
         m.expand_scope(&tracked_ref);
     }
 
-    pub(crate) fn undo(&self) -> Result<bool, YrsUndoError> {
+    pub(crate) fn undo(&self) -> Result<Option<YrsUndoResult>, YrsUndoError> {
         let mut m = self.acquire_lock();
-        Ok(pollster::block_on(m.undo()))
+        let item = m.undo_stack().last().map(YrsUndoStackItem::from);
+        let changed = Self::track_popped(&m);
+        let applied = pollster::block_on(m.undo());
+        Ok(Self::result(applied, item, changed))
     }
 
-    pub(crate) fn redo(&self) -> Result<bool, YrsUndoError> {
+    pub(crate) fn redo(&self) -> Result<Option<YrsUndoResult>, YrsUndoError> {
         let mut m = self.acquire_lock();
-        Ok(pollster::block_on(m.redo()))
+        let item = m.redo_stack().last().map(YrsUndoStackItem::from);
+        let changed = Self::track_popped(&m);
+        let applied = pollster::block_on(m.redo());
+        Ok(Self::result(applied, item, changed))
+    }
+
+    /// Records the collections changed by the next popped stack item, for as long as the
+    /// returned subscription is alive.
+    fn track_popped(
+        m: &yrs::undo::UndoManager<ItemMeta>,
+    ) -> (Arc<Mutex<Vec<YrsCollectionPtr>>>, yrs::Subscription) {
+        let changed = Arc::new(Mutex::new(Vec::new()));
+        let subscription = {
+            let changed = changed.clone();
+            m.observe_item_popped(move |_, e| {
+                *changed.lock().unwrap() = e
+                    .changed_parent_types()
+                    .iter()
+                    .map(|branch| YrsCollectionPtr::from(branch.as_ref()))
+                    .collect();
+            })
+        };
+        (changed, subscription)
+    }
+
+    fn result(
+        applied: bool,
+        item: Option<YrsUndoStackItem>,
+        (changed, subscription): (Arc<Mutex<Vec<YrsCollectionPtr>>>, yrs::Subscription),
+    ) -> Option<YrsUndoResult> {
+        drop(subscription);
+        let item = item.filter(|_| applied)?;
+        let changed = std::mem::take(&mut *changed.lock().unwrap());
+        Some(YrsUndoResult { changed, item })
+    }
+
+    pub(crate) fn undo_stack_len(&self) -> u32 {
+        self.acquire_lock().undo_stack().len() as u32
+    }
+
+    pub(crate) fn redo_stack_len(&self) -> u32 {
+        self.acquire_lock().redo_stack().len() as u32
+    }
+
+    /// Returns summaries of the undo stack items, oldest first.
+    pub(crate) fn undo_stack(&self) -> Vec<YrsUndoStackItem> {
+        self.acquire_lock().undo_stack().iter().map(YrsUndoStackItem::from).collect()
+    }
+
+    /// Returns summaries of the redo stack items, oldest first.
+    pub(crate) fn redo_stack(&self) -> Vec<YrsUndoStackItem> {
+        self.acquire_lock().redo_stack().iter().map(YrsUndoStackItem::from).collect()
     }
 
     pub(crate) fn clear(&self) -> Result<(), YrsUndoError> {
@@ -64,7 +219,8 @@ impl YrsUndoManager {
     pub(crate) fn observe_added(&self, delegate: Box<dyn YrsUndoManagerObservationDelegate>) -> Arc<YSubscription> {
         let m = self.acquire_lock();
         let subscription = m.observe_item_added(move |_, e| {
-            *e.meta_mut() = delegate.call(YrsUndoEvent::new(e), *e.meta_mut());
+            let app = delegate.call(YrsUndoEvent::new(e), e.meta().app);
+            e.meta_mut().app = app;
         });
         Arc::new(YSubscription::new(subscription))
     }
@@ -72,7 +228,8 @@ impl YrsUndoManager {
     pub(crate) fn observe_updated(&self, delegate: Box<dyn YrsUndoManagerObservationDelegate>) -> Arc<YSubscription> {
         let m = self.acquire_lock();
         let subscription = m.observe_item_updated(move |_, e| {
-            *e.meta_mut() = delegate.call(YrsUndoEvent::new(e), *e.meta_mut());
+            let app = delegate.call(YrsUndoEvent::new(e), e.meta().app);
+            e.meta_mut().app = app;
         });
         Arc::new(YSubscription::new(subscription))
     }
@@ -80,14 +237,119 @@ impl YrsUndoManager {
     pub(crate) fn observe_popped(&self, delegate: Box<dyn YrsUndoManagerObservationDelegate>) -> Arc<YSubscription> {
         let m = self.acquire_lock();
         let subscription = m.observe_item_popped(move |_, e| {
-            *e.meta_mut() = delegate.call(YrsUndoEvent::new(e), *e.meta_mut());
+            let app = delegate.call(YrsUndoEvent::new(e), e.meta().app);
+            e.meta_mut().app = app;
         });
         Arc::new(YSubscription::new(subscription))
     }
 }
 
+/// What the manager records about a stack item, next to the metadata of delegates.
+#[derive(Default)]
+struct ItemMeta {
+    /// Metadata attached by observation delegates.
+    app: u64,
+    origin: Option<Origin>,
+    touched: Vec<YrsCollectionPtr>,
+    timestamp_millis: u64,
+}
+
+impl ItemMeta {
+    /// Records a change captured by the item.
+    fn record(&mut self, changed: &[BranchPtr]) {
+        for branch in changed {
+            let ptr = YrsCollectionPtr::from(branch.as_ref());
+            if !self.touched.contains(&ptr) {
+                self.touched.push(ptr);
+            }
+        }
+        self.timestamp_millis = SystemClock.now();
+    }
+}
+
+/// Summary of an undo or redo stack item: how many elements it inserted and deleted,
+/// where the change came from, and the metadata attached to it by observation delegates.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct YrsUndoStackItem {
+    pub insertions: u32,
+    pub deletions: u32,
+    pub meta: u64,
+    /// Origin of the transaction that created the item.
+    pub origin: Option<YrsOrigin>,
+    /// Collections changed by the item.
+    pub touched: Vec<YrsCollectionPtr>,
+    /// When the item was last changed, in milliseconds since the Unix epoch.
+    pub timestamp_millis: u64,
+}
+
+impl From<&StackItem<ItemMeta>> for YrsUndoStackItem {
+    fn from(item: &StackItem<ItemMeta>) -> Self {
+        let meta = item.meta();
+        YrsUndoStackItem {
+            insertions: element_count(item.insertions()),
+            deletions: element_count(item.deletions()),
+            meta: meta.app,
+            origin: meta.origin.clone().map(YrsOrigin::from),
+            touched: meta.touched.clone(),
+            timestamp_millis: meta.timestamp_millis,
+        }
+    }
+}
+
+fn element_count(set: &DeleteSet) -> u32 {
+    set.iter()
+        .flat_map(|(_, range)| range.iter())
+        .map(|range| range.end - range.start)
+        .sum()
+}
+
+/// Describes the effect of an undo or redo operation.
+pub(crate) struct YrsUndoResult {
+    /// Collections changed by the operation.
+    pub changed: Vec<YrsCollectionPtr>,
+    /// The stack item that was applied.
+    pub item: YrsUndoStackItem,
+}
+
+/// A clock handed to yrs in place of wall time. It returns the index of the current
+/// capture window, which only advances once the gap since the previous change reaches
+/// the capture timeout, so the timeout can be changed while the manager is in use.
+struct YrsUndoClock {
+    timeout: AtomicU64,
+    start: Instant,
+    // Time of the previous change and the index of its capture window.
+    window: Mutex<(u64, Timestamp)>,
+}
+
+impl YrsUndoClock {
+    fn new(timeout: u64) -> Self {
+        YrsUndoClock {
+            timeout: AtomicU64::new(timeout),
+            start: Instant::now(),
+            window: Mutex::new((0, 0)),
+        }
+    }
+}
+
+impl Clock for YrsUndoClock {
+    fn now(&self) -> Timestamp {
+        let elapsed = self.start.elapsed().as_millis() as u64;
+        let mut window = self.window.lock().unwrap();
+        let (last_change, index) = *window;
+        // yrs treats a timestamp of zero as "no previous change", so windows start at one.
+        let index = if index == 0 || elapsed - last_change >= self.timeout.load(Ordering::Relaxed) {
+            index + 1
+        } else {
+            index
+        };
+        *window = (elapsed, index);
+        index
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub(crate) enum YrsUndoError {
+    #[allow(dead_code)]
     #[error("Operations failed - there's already an active transaction on a current document")]
     PendingTransaction
 }
@@ -97,15 +359,17 @@ pub(crate) trait YrsUndoManagerObservationDelegate: Send + Sync + Debug {
 }
 
 pub(crate) struct YrsUndoEvent {
-    inner: &'static mut yrs::undo::Event<u64>,
+    inner: &'static mut yrs::undo::Event<ItemMeta>,
 }
 
 unsafe impl Send for YrsUndoEvent {}
 unsafe impl Sync for YrsUndoEvent {}
 
 impl YrsUndoEvent {
-    fn new(inner: &mut yrs::undo::Event<u64>) -> Arc<Self> {
-        let inner = unsafe { std::mem::transmute(inner) };
+    fn new(inner: &mut yrs::undo::Event<ItemMeta>) -> Arc<Self> {
+        let inner = unsafe {
+            std::mem::transmute::<&mut yrs::undo::Event<ItemMeta>, &'static mut yrs::undo::Event<ItemMeta>>(inner)
+        };
         Arc::new(YrsUndoEvent {
             inner
         })
@@ -123,10 +387,383 @@ impl YrsUndoEvent {
     pub(crate) fn has_changed(&self, shared_ref: YrsCollectionPtr) -> bool {
         self.inner.has_changed(&shared_ref)
     }
+    pub(crate) fn changed_parent_types(&self) -> Vec<YrsCollectionPtr> {
+        self.inner
+            .changed_parent_types()
+            .iter()
+            .map(|branch| YrsCollectionPtr::from(branch.as_ref()))
+            .collect()
+    }
 }
 
 #[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub(crate) enum YrsUndoEventKind {
     Undo,
     Redo
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::{YrsUndoEvent, YrsUndoEventKind, YrsUndoManagerObservationDelegate, YrsUndoManagerOptions};
+    use crate::doc::{YrsCollectionPtr, YrsOrigin};
+    use crate::UniffiCustomTypeConverter;
+    use crate::YrsDoc;
+    use std::sync::{Arc, Mutex};
+    use yrs::sync::time::{Clock, SystemClock};
+
+    type StackEvent = (YrsUndoEventKind, Option<Vec<u8>>, Vec<u64>);
+
+    #[derive(Debug, Default)]
+    struct StackEvents(Arc<Mutex<Vec<StackEvent>>>);
+
+    impl YrsUndoManagerObservationDelegate for StackEvents {
+        fn call(&self, e: Arc<YrsUndoEvent>, ptr: u64) -> u64 {
+            let changed = e
+                .changed_parent_types()
+                .into_iter()
+                .map(YrsCollectionPtr::from_custom)
+                .collect();
+            let origin = e.origin().map(YrsOrigin::from_custom);
+            self.0.lock().unwrap().push((e.kind(), origin, changed));
+            ptr
+        }
+    }
+
+    #[test]
+    fn undo_manager_tracks_only_included_origins() {
+        let doc = YrsDoc::new();
+        let text = doc.get_text("text".to_string());
+        let manager = doc.undo_manager(vec![text.raw_ptr()], YrsUndoManagerOptions::default());
+        manager.add_origin(yrs::Origin::from("local").into());
+        manager.add_origin(yrs::Origin::from("other").into());
+        manager.remove_origin(yrs::Origin::from("other").into());
+        manager.set_capture_timeout(0);
+
+        for (origin, chunk) in [("local", "a"), ("remote", "b"), ("other", "c"), ("local", "d")] {
+            let txn = doc.transact(Some(yrs::Origin::from(origin).into()));
+            text.insert(&txn, 0, chunk.to_string()).unwrap();
+            txn.free();
+        }
+
+        assert!(manager.undo().unwrap().is_some());
+        assert!(manager.undo().unwrap().is_some());
+        assert!(manager.undo().unwrap().is_none());
+        let txn = doc.transact(None);
+        assert_eq!(text.get_string(&txn), "cb");
+    }
+
+    #[derive(Debug, Default)]
+    struct Selection {
+        stored: u64,
+        received: Arc<Mutex<Vec<u64>>>,
+    }
+
+    impl YrsUndoManagerObservationDelegate for Selection {
+        fn call(&self, _e: Arc<YrsUndoEvent>, ptr: u64) -> u64 {
+            self.received.lock().unwrap().push(ptr);
+            self.stored
+        }
+    }
+
+    #[test]
+    fn undo_manager_round_trips_item_metadata() {
+        let doc = YrsDoc::new();
+        let text = doc.get_text("text".to_string());
+        let manager = doc.undo_manager(vec![text.raw_ptr()], YrsUndoManagerOptions::default());
+
+        let popped = Arc::new(Mutex::new(Vec::new()));
+        let _added = manager.observe_added(Box::new(Selection {
+            stored: 42,
+            received: Arc::default(),
+        }));
+        let _popped = manager.observe_popped(Box::new(Selection {
+            stored: 0,
+            received: popped.clone(),
+        }));
+
+        let txn = doc.transact(None);
+        text.insert(&txn, 0, "hello".to_string()).unwrap();
+        txn.free();
+        assert_eq!(manager.undo_stack()[0].meta, 42);
+
+        assert!(manager.undo().unwrap().is_some());
+        assert_eq!(*popped.lock().unwrap(), vec![42]);
+    }
+
+    #[test]
+    fn undo_manager_summarizes_stack_items() {
+        let doc = YrsDoc::new();
+        let text = doc.get_text("text".to_string());
+        let manager = doc.undo_manager(vec![text.raw_ptr()], YrsUndoManagerOptions::default());
+        manager.set_capture_timeout(0);
+
+        let txn = doc.transact(None);
+        text.insert(&txn, 0, "hello".to_string()).unwrap();
+        txn.free();
+        let txn = doc.transact(None);
+        text.remove_range(&txn, 0, 2).unwrap();
+        txn.free();
+
+        assert_eq!(manager.undo_stack_len(), 2);
+        assert_eq!(manager.redo_stack_len(), 0);
+        let summaries: Vec<(u32, u32, u64)> = manager
+            .undo_stack()
+            .iter()
+            .map(|item| (item.insertions, item.deletions, item.meta))
+            .collect();
+        assert_eq!(summaries, vec![(5, 0, 0), (0, 2, 0)]);
+
+        assert!(manager.undo().unwrap().is_some());
+        assert_eq!(manager.undo_stack_len(), 1);
+        assert_eq!(manager.redo_stack_len(), 1);
+    }
+
+    #[test]
+    fn undo_manager_records_origin_touched_collections_and_time() {
+        let doc = YrsDoc::new();
+        let title = doc.get_text("title".to_string());
+        let body = doc.get_text("body".to_string());
+        let manager = doc.undo_manager(
+            vec![title.raw_ptr(), body.raw_ptr()],
+            YrsUndoManagerOptions {
+                tracked_origins: vec![yrs::Origin::from("local").into()],
+                ..YrsUndoManagerOptions::default()
+            },
+        );
+        manager.set_capture_timeout(60_000);
+
+        let before = SystemClock.now();
+        let txn = doc.transact(Some(yrs::Origin::from("local").into()));
+        title.insert(&txn, 0, "title".to_string()).unwrap();
+        txn.free();
+        let txn = doc.transact(Some(yrs::Origin::from("local").into()));
+        body.insert(&txn, 0, "body".to_string()).unwrap();
+        title.append(&txn, "!".to_string());
+        txn.free();
+
+        let stack = manager.undo_stack();
+        assert_eq!(stack.len(), 1);
+        let item = &stack[0];
+        assert_eq!(item.origin.clone().map(YrsOrigin::from_custom), Some(b"local".to_vec()));
+        let touched: Vec<u64> = item.touched.iter().copied().map(YrsCollectionPtr::from_custom).collect();
+        assert_eq!(
+            touched,
+            vec![
+                YrsCollectionPtr::from_custom(title.raw_ptr()),
+                YrsCollectionPtr::from_custom(body.raw_ptr()),
+            ]
+        );
+        assert!(item.timestamp_millis >= before);
+        assert!(item.timestamp_millis <= SystemClock.now());
+    }
+
+    #[test]
+    fn undo_manager_clear_resets_both_stacks() {
+        let doc = YrsDoc::new();
+        let text = doc.get_text("text".to_string());
+        let manager = doc.undo_manager(vec![text.raw_ptr()], YrsUndoManagerOptions::default());
+        manager.set_capture_timeout(0);
+
+        for chunk in ["a", "b"] {
+            let txn = doc.transact(None);
+            text.insert(&txn, 0, chunk.to_string()).unwrap();
+            txn.free();
+        }
+        assert!(manager.undo().unwrap().is_some());
+        manager.clear().unwrap();
+
+        assert!(manager.undo().unwrap().is_none());
+        assert!(manager.redo().unwrap().is_none());
+        let txn = doc.transact(None);
+        assert_eq!(text.get_string(&txn), "a");
+    }
+
+    #[test]
+    fn undo_manager_wrap_changes_starts_new_item() {
+        let doc = YrsDoc::new();
+        let text = doc.get_text("text".to_string());
+        let manager = doc.undo_manager(vec![text.raw_ptr()], YrsUndoManagerOptions::default());
+        manager.set_capture_timeout(60_000);
+
+        for chunk in ["a", "b", "c"] {
+            let txn = doc.transact(None);
+            text.insert(&txn, 0, chunk.to_string()).unwrap();
+            txn.free();
+            if chunk == "a" {
+                manager.wrap_changes();
+            }
+        }
+        assert_eq!(manager.undo_stack_len(), 2);
+
+        assert!(manager.undo().unwrap().is_some());
+        let txn = doc.transact(None);
+        assert_eq!(text.get_string(&txn), "a");
+    }
+
+    #[test]
+    fn undo_manager_applies_construction_options() {
+        let doc = YrsDoc::new();
+        let text = doc.get_text("text".to_string());
+        let manager = doc.undo_manager(
+            vec![text.raw_ptr()],
+            YrsUndoManagerOptions {
+                capture_timeout_millis: 0,
+                tracked_origins: vec![yrs::Origin::from("local").into()],
+                capture_transactions: false,
+            },
+        );
+        assert_eq!(manager.capture_timeout(), 0);
+
+        let insert = |chunk: &str| {
+            let txn = doc.transact(Some(yrs::Origin::from("local").into()));
+            text.insert(&txn, 0, chunk.to_string()).unwrap();
+            txn.free();
+        };
+        insert("a");
+        assert_eq!(manager.undo_stack_len(), 0);
+
+        manager.set_capture_transactions(true);
+        insert("b");
+        insert("c");
+        assert_eq!(manager.undo_stack_len(), 2);
+
+        manager.set_capture_transactions(false);
+        assert!(manager.undo().unwrap().is_some());
+        assert!(manager.redo().unwrap().is_some());
+        let txn = doc.transact(None);
+        assert_eq!(text.get_string(&txn), "cba");
+    }
+
+    #[test]
+    fn undo_manager_add_scope_tracks_later_collections() {
+        let doc = YrsDoc::new();
+        let title = doc.get_text("title".to_string());
+        let map = doc.get_map("map".to_string());
+        let manager = doc.undo_manager(vec![title.raw_ptr()], YrsUndoManagerOptions::default());
+
+        let txn = doc.transact(None);
+        let body = map.insert_text(&txn, "body".to_string());
+        txn.free();
+        assert_eq!(manager.undo_stack_len(), 0);
+
+        manager.add_scope(body.raw_ptr());
+        let txn = doc.transact(None);
+        body.insert(&txn, 0, "hello".to_string()).unwrap();
+        txn.free();
+        assert_eq!(manager.undo_stack_len(), 1);
+
+        assert!(manager.undo().unwrap().is_some());
+        let txn = doc.transact(None);
+        assert_eq!(body.get_string(&txn), "");
+    }
+
+    #[test]
+    fn undo_manager_reports_what_undo_changed() {
+        let doc = YrsDoc::new();
+        let title = doc.get_text("title".to_string());
+        let body = doc.get_text("body".to_string());
+        let manager = doc.undo_manager(
+            vec![title.raw_ptr(), body.raw_ptr()],
+            YrsUndoManagerOptions::default(),
+        );
+
+        let txn = doc.transact(None);
+        body.insert(&txn, 0, "hello".to_string()).unwrap();
+        txn.free();
+
+        let body_ptr = YrsCollectionPtr::from_custom(body.raw_ptr());
+        let result = manager.undo().unwrap().unwrap();
+        let changed: Vec<u64> = result.changed.into_iter().map(YrsCollectionPtr::from_custom).collect();
+        assert_eq!(changed, vec![body_ptr]);
+        assert_eq!((result.item.insertions, result.item.deletions, result.item.meta), (5, 0, 0));
+
+        let result = manager.redo().unwrap().unwrap();
+        let changed: Vec<u64> = result.changed.into_iter().map(YrsCollectionPtr::from_custom).collect();
+        assert_eq!(changed, vec![body_ptr]);
+        assert!(manager.redo().unwrap().is_none());
+    }
+
+    #[test]
+    fn local_undo_manager_ignores_remote_updates() {
+        let local = YrsDoc::new();
+        let remote = YrsDoc::new();
+        let text = local.get_text("text".to_string());
+        let remote_text = remote.get_text("text".to_string());
+        let origin: YrsOrigin = yrs::Origin::from("me").into();
+        let manager = local.local_undo_manager(vec![text.raw_ptr()], origin.clone());
+
+        let txn = local.transact(Some(origin));
+        text.insert(&txn, 0, "mine ".to_string()).unwrap();
+        txn.free();
+
+        let txn = remote.transact(None);
+        remote_text.insert(&txn, 0, "theirs".to_string()).unwrap();
+        let update = txn.transaction_encode_update();
+        txn.free();
+        let txn = local.transact(None);
+        txn.transaction_apply_update(update).unwrap();
+        txn.free();
+
+        assert!(manager.undo().unwrap().is_some());
+        assert!(manager.undo().unwrap().is_none());
+        let txn = local.transact(None);
+        assert_eq!(text.get_string(&txn), "theirs");
+    }
+
+    #[test]
+    fn undo_manager_capture_timeout_groups_changes() {
+        let doc = YrsDoc::new();
+        let text = doc.get_text("text".to_string());
+        let manager = doc.undo_manager(vec![text.raw_ptr()], YrsUndoManagerOptions::default());
+        assert_eq!(manager.capture_timeout(), 500);
+
+        manager.set_capture_timeout(60_000);
+        for chunk in ["a", "b"] {
+            let txn = doc.transact(None);
+            text.insert(&txn, 0, chunk.to_string()).unwrap();
+            txn.free();
+        }
+        manager.set_capture_timeout(0);
+        for chunk in ["c", "d"] {
+            let txn = doc.transact(None);
+            text.insert(&txn, 0, chunk.to_string()).unwrap();
+            txn.free();
+        }
+
+        let content = || {
+            let txn = doc.transact(None);
+            text.get_string(&txn)
+        };
+        assert!(manager.undo().unwrap().is_some());
+        assert_eq!(content(), "cba");
+        assert!(manager.undo().unwrap().is_some());
+        assert_eq!(content(), "ba");
+        assert!(manager.undo().unwrap().is_some());
+        assert_eq!(content(), "");
+    }
+
+    #[test]
+    fn undo_manager_reports_added_and_popped_items() {
+        let doc = YrsDoc::new();
+        let text = doc.get_text("text".to_string());
+        let ptr = YrsCollectionPtr::from_custom(text.raw_ptr());
+        let manager = doc.undo_manager(vec![text.raw_ptr()], YrsUndoManagerOptions::default());
+
+        let added = Arc::new(Mutex::new(Vec::new()));
+        let popped = Arc::new(Mutex::new(Vec::new()));
+        let _added = manager.observe_added(Box::new(StackEvents(added.clone())));
+        let _popped = manager.observe_popped(Box::new(StackEvents(popped.clone())));
+
+        let txn = doc.transact(None);
+        text.insert(&txn, 0, "hello".to_string()).unwrap();
+        txn.free();
+        // yrs reports items added by regular (non-undo) edits with the Redo kind.
+        assert_eq!(*added.lock().unwrap(), vec![(YrsUndoEventKind::Redo, None, vec![ptr])]);
+
+        assert!(manager.undo().unwrap().is_some());
+        let popped = popped.lock().unwrap();
+        assert_eq!(popped.len(), 1);
+        assert_eq!(popped[0].0, YrsUndoEventKind::Undo);
+        assert_eq!(popped[0].2, vec![ptr]);
+    }
+}
@@ -0,0 +1,393 @@
+use crate::doc::YrsDoc;
+use crate::transaction::YrsTransaction;
+use parking_lot::Mutex;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::time::Duration;
+use yrs::{
+    Any, Array, ArrayPrelim, ArrayRef, Doc, Map, MapPrelim, MapRef, Observable, Out, ReadTxn, Subscription,
+    Transact, TransactionMut,
+};
+
+/// Name of the shared map Yjs stores permanent user data in by default.
+const DEFAULT_NAME: &str = "users";
+
+/// Origin of the transactions pushing lost mappings again, so that providers and undo
+/// managers can tell them apart from edits.
+pub(crate) const USER_DATA_ORIGIN: &str = "yniffi-permanent-user-data";
+
+/// How long to wait before trying again to push lost mappings while another
+/// transaction is open on the document.
+const RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A client ID along with the durable user it belongs to.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct YrsUserMapping {
+    pub client_id: u64,
+    pub user: String,
+}
+
+/// Maps client IDs to durable user identifiers, following the Yjs "permanent user data"
+/// layout: a root map from user to a map holding the `ids` array of the client IDs the
+/// user edited with. Since the mapping lives in the document itself, it's synced and
+/// persisted along with the content, and every session only has to add its own client.
+///
+/// When a concurrent write replaces the entry of a user, the client IDs mapped through
+/// this helper are pushed again to the entry that won, from a thread of the helper and
+/// in a transaction with the `USER_DATA_ORIGIN` origin. Unlike Yjs, the `ds` arrays are
+/// left empty: delete sets aren't recorded, so removed content can't be attributed.
+pub(crate) struct YrsPermanentUserData {
+    client_id: u64,
+    users: MapRef,
+    /// Mappings set through this helper, kept to be pushed again.
+    mapped: Arc<Mutex<Vec<YrsUserMapping>>>,
+    _subscription: Subscription,
+    /// Wakes the thread pushing lost mappings, which stops once this is dropped.
+    _repusher: Sender<()>,
+}
+
+impl YrsPermanentUserData {
+    /// Must not be called while a transaction on the document is open.
+    pub(crate) fn new(doc: &YrsDoc, name: Option<String>) -> Self {
+        let doc = doc.inner();
+        let name = name.unwrap_or_else(|| DEFAULT_NAME.to_string());
+        let users = doc.get_or_insert_map(name);
+        let mapped: Arc<Mutex<Vec<YrsUserMapping>>> = Arc::default();
+        let lost: Arc<Mutex<Vec<YrsUserMapping>>> = Arc::default();
+        let (repusher, wakeups) = channel();
+
+        let subscription = {
+            let users = users.clone();
+            let mapped = mapped.clone();
+            let lost = lost.clone();
+            let repusher = repusher.clone();
+            users.clone().observe(move |txn, event| {
+                let keys = event.keys(txn);
+                let newly_lost: Vec<YrsUserMapping> = mapped
+                    .lock()
+                    .iter()
+                    .filter(|m| keys.contains_key(m.user.as_str()))
+                    .filter(|m| !client_ids(&users, txn, &m.user).contains(&m.client_id))
+                    .cloned()
+                    .collect();
+                if newly_lost.is_empty() {
+                    return;
+                }
+                // The committing transaction can't be written to anymore, so the mappings
+                // are pushed from a transaction of their own once it's done.
+                lost.lock().extend(newly_lost);
+                let _ = repusher.send(());
+            })
+        };
+
+        {
+            let doc = doc.clone();
+            let users = users.clone();
+            std::thread::Builder::new()
+                .name("yniffi-user-data".to_string())
+                .spawn(move || repush_lost_mappings(doc, users, lost, wakeups))
+                .expect("Failed to spawn permanent user data thread");
+        }
+
+        YrsPermanentUserData {
+            client_id: doc.client_id(),
+            users,
+            mapped,
+            _subscription: subscription,
+            _repusher: repusher,
+        }
+    }
+
+    /// Records that `client_id` belongs to `user`.
+    pub(crate) fn set_user_mapping(&self, transaction: &YrsTransaction, client_id: u64, user: String) {
+        let mut tx = transaction.transaction();
+        let tx = tx.as_mut().unwrap();
+
+        let mapping = YrsUserMapping {
+            client_id,
+            user: user.clone(),
+        };
+        {
+            let mut mapped = self.mapped.lock();
+            if !mapped.contains(&mapping) {
+                mapped.push(mapping);
+            }
+        }
+        push_mapping(&self.users, tx, client_id, user);
+    }
+
+    /// Records that the client ID of this document belongs to `user`.
+    pub(crate) fn set_local_user(&self, transaction: &YrsTransaction, user: String) {
+        self.set_user_mapping(transaction, self.client_id, user)
+    }
+
+    /// Returns the user a client ID belongs to, if it was mapped.
+    pub(crate) fn get_user_by_client_id(&self, transaction: &YrsTransaction, client_id: u64) -> Option<String> {
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+
+        self.users.iter(tx).find_map(|(user, entry)| {
+            let ids = user_ids(&entry, tx)?;
+            client_ids_of(&ids, tx)
+                .contains(&client_id)
+                .then(|| user.to_string())
+        })
+    }
+
+    /// Returns the client IDs a user edited with, in the order they were mapped.
+    pub(crate) fn client_ids(&self, transaction: &YrsTransaction, user: String) -> Vec<u64> {
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+
+        client_ids(&self.users, tx, &user)
+    }
+
+    /// Returns every mapped client ID along with its user, sorted by client ID.
+    pub(crate) fn mappings(&self, transaction: &YrsTransaction) -> Vec<YrsUserMapping> {
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+
+        let mut mappings: Vec<YrsUserMapping> = self
+            .users
+            .iter(tx)
+            .flat_map(|(user, entry)| {
+                let ids = user_ids(&entry, tx).map(|ids| client_ids_of(&ids, tx));
+                ids.unwrap_or_default()
+                    .into_iter()
+                    .map(move |client_id| YrsUserMapping {
+                        client_id,
+                        user: user.to_string(),
+                    })
+            })
+            .collect();
+        mappings.sort_by_key(|m| m.client_id);
+        mappings
+    }
+}
+
+/// Pushes the mappings lost to concurrent writes each time the observer reports some,
+/// until the helper is dropped. Other transactions on the document are never waited
+/// for: while one is open, the push is tried again a bit later.
+fn repush_lost_mappings(doc: Doc, users: MapRef, lost: Arc<Mutex<Vec<YrsUserMapping>>>, wakeups: Receiver<()>) {
+    while wakeups.recv().is_ok() {
+        while !lost.lock().is_empty() {
+            match doc.try_transact_mut_with(USER_DATA_ORIGIN) {
+                Ok(mut tx) => {
+                    let mappings = std::mem::take(&mut *lost.lock());
+                    for m in mappings {
+                        push_mapping(&users, &mut tx, m.client_id, m.user);
+                    }
+                    break;
+                }
+                Err(_) => match wakeups.recv_timeout(RETRY_INTERVAL) {
+                    Err(RecvTimeoutError::Disconnected) => return,
+                    _ => continue,
+                },
+            }
+        }
+    }
+}
+
+/// Adds `client_id` to the entry of `user`, creating the entry if needed.
+fn push_mapping(users: &MapRef, tx: &mut TransactionMut, client_id: u64, user: String) {
+    let entry = match users.get(tx, &user) {
+        Some(Out::YMap(entry)) => entry,
+        _ => users.insert(
+            tx,
+            user,
+            MapPrelim::from([("ids", ArrayPrelim::default()), ("ds", ArrayPrelim::default())]),
+        ),
+    };
+    let ids = match entry.get(tx, "ids") {
+        Some(Out::YArray(ids)) => ids,
+        _ => entry.insert(tx, "ids", ArrayPrelim::default()),
+    };
+    if !client_ids_of(&ids, tx).contains(&client_id) {
+        // Yjs stores client IDs as plain numbers
+        ids.push_back(tx, Any::Number(client_id as f64));
+    }
+}
+
+fn client_ids<T: ReadTxn>(users: &MapRef, txn: &T, user: &str) -> Vec<u64> {
+    users
+        .get(txn, user)
+        .and_then(|entry| user_ids(&entry, txn))
+        .map(|ids| client_ids_of(&ids, txn))
+        .unwrap_or_default()
+}
+
+fn user_ids<T: ReadTxn>(entry: &Out, txn: &T) -> Option<ArrayRef> {
+    match entry {
+        Out::YMap(entry) => match entry.get(txn, "ids") {
+            Some(Out::YArray(ids)) => Some(ids),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn client_ids_of<T: ReadTxn>(ids: &ArrayRef, txn: &T) -> Vec<u64> {
+    ids.iter(txn)
+        .filter_map(|id| match id {
+            Out::Any(Any::Number(id)) => Some(id as u64),
+            Out::Any(Any::BigInt(id)) => Some(id as u64),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{YrsPermanentUserData, YrsUserMapping, USER_DATA_ORIGIN};
+    use crate::delivery::YrsObservationDelivery;
+    use crate::YrsDoc;
+    use std::sync::mpsc::channel;
+    use std::time::{Duration, Instant};
+    use yrs::{Doc, Origin};
+
+    #[test]
+    fn user_mappings_sync_with_the_document() {
+        let alice = YrsDoc::new();
+        let alice_users = YrsPermanentUserData::new(&alice, None);
+        let bob = YrsDoc::new();
+        let bob_users = YrsPermanentUserData::new(&bob, None);
+
+        let txn = alice.transact(None);
+        alice_users.set_local_user(&txn, "alice@example.com".to_string());
+        alice_users.set_local_user(&txn, "alice@example.com".to_string());
+        alice_users.set_user_mapping(&txn, 7, "alice@example.com".to_string());
+        let update = txn.transaction_encode_state_as_update();
+        txn.free();
+
+        let txn = bob.transact(None);
+        txn.transaction_apply_update(update).unwrap();
+        bob_users.set_local_user(&txn, "bob@example.com".to_string());
+
+        assert_eq!(
+            bob_users.get_user_by_client_id(&txn, alice.client_id()).as_deref(),
+            Some("alice@example.com")
+        );
+        assert_eq!(
+            bob_users.client_ids(&txn, "alice@example.com".to_string()),
+            vec![alice.client_id(), 7]
+        );
+        assert_eq!(bob_users.get_user_by_client_id(&txn, 8), None);
+
+        let mut expected = vec![
+            YrsUserMapping {
+                client_id: 7,
+                user: "alice@example.com".to_string(),
+            },
+            YrsUserMapping {
+                client_id: alice.client_id(),
+                user: "alice@example.com".to_string(),
+            },
+            YrsUserMapping {
+                client_id: bob.client_id(),
+                user: "bob@example.com".to_string(),
+            },
+        ];
+        expected.sort_by_key(|m| m.client_id);
+        assert_eq!(bob_users.mappings(&txn), expected);
+    }
+
+    fn sync(from: &YrsDoc, to: &YrsDoc) {
+        let txn = from.transact(None);
+        let update = txn.transaction_encode_state_as_update();
+        txn.free();
+        let txn = to.transact(None);
+        txn.transaction_apply_update(update).unwrap();
+        txn.free();
+    }
+
+    #[test]
+    fn user_mappings_survive_concurrent_entries() {
+        let alice = YrsDoc::new();
+        let alice_users = YrsPermanentUserData::new(&alice, None);
+        let bob = YrsDoc::new();
+        let bob_users = YrsPermanentUserData::new(&bob, None);
+
+        // Both create an entry for the same user, and only one of them survives the sync.
+        let txn = alice.transact(None);
+        alice_users.set_local_user(&txn, "sam@example.com".to_string());
+        txn.free();
+        let txn = bob.transact(None);
+        bob_users.set_local_user(&txn, "sam@example.com".to_string());
+        txn.free();
+
+        let mut expected = vec![alice.client_id(), bob.client_id()];
+        expected.sort();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            sync(&alice, &bob);
+            sync(&bob, &alice);
+            let client_ids = |doc: &YrsDoc, users: &YrsPermanentUserData| {
+                let txn = doc.transact(None);
+                let mut ids = users.client_ids(&txn, "sam@example.com".to_string());
+                ids.sort();
+                ids
+            };
+            if client_ids(&alice, &alice_users) == expected && client_ids(&bob, &bob_users) == expected {
+                break;
+            }
+            assert!(Instant::now() < deadline, "lost mapping was never pushed again");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn lost_mappings_wait_for_open_transactions() {
+        // The entry written by the higher client ID wins, so Alice's mapping is lost.
+        let alice = YrsDoc::from_doc(Doc::with_client_id(1));
+        let alice_users = YrsPermanentUserData::new(&alice, None);
+        let bob = YrsDoc::from_doc(Doc::with_client_id(2));
+        let bob_users = YrsPermanentUserData::new(&bob, None);
+
+        let txn = alice.transact(None);
+        alice_users.set_local_user(&txn, "sam@example.com".to_string());
+        txn.free();
+        let txn = bob.transact(None);
+        bob_users.set_local_user(&txn, "sam@example.com".to_string());
+        let update = txn.transaction_encode_state_as_update();
+        txn.free();
+
+        let (origins, pushes) = channel();
+        let _subscription = alice
+            .inner()
+            .observe_update_v1(move |txn, _| {
+                let _ = origins.send(txn.origin().cloned());
+            })
+            .unwrap();
+
+        // The transaction held below keeps the lost mapping from being pushed until it's
+        // freed.
+        let txn = alice.transact(None);
+        txn.transaction_apply_update(update).unwrap();
+        txn.free();
+        let held = alice.transact(None);
+
+        // Deferred observers keep being delivered in the meantime.
+        let (sender, receiver) = channel();
+        YrsObservationDelivery::Deferred.deliver(move || sender.send(()).unwrap());
+        assert!(receiver.recv_timeout(Duration::from_secs(5)).is_ok());
+        held.free();
+
+        let mut ids = vec![alice.client_id(), bob.client_id()];
+        ids.sort();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let txn = alice.transact(None);
+            let mut client_ids = alice_users.client_ids(&txn, "sam@example.com".to_string());
+            txn.free();
+            client_ids.sort();
+            if client_ids == ids {
+                break;
+            }
+            assert!(Instant::now() < deadline, "lost mapping was never pushed again");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+
+        let origins: Vec<_> = pushes.try_iter().collect();
+        assert_eq!(origins.last(), Some(&Some(Origin::from(USER_DATA_ORIGIN))));
+    }
+}
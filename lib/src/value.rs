@@ -0,0 +1,113 @@
+use crate::error::CodingError;
+use std::collections::HashMap;
+use std::sync::Arc;
+use yrs::{Any, Out};
+
+/// Kind of a shared type referenced from within a collection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum YrsSharedKind {
+    Text,
+    Array,
+    Map,
+    XmlElement,
+    XmlFragment,
+    XmlText,
+    Doc,
+    WeakLink,
+    Undefined,
+}
+
+/// A typed representation of a value stored in a document. Shared types are reported
+/// by kind only; use the typed getters of their parent collection to obtain a handle.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum YrsValue {
+    Null,
+    Undefined,
+    Bool { value: bool },
+    Number { value: f64 },
+    BigInt { value: i64 },
+    String { value: String },
+    Buffer { value: Vec<u8> },
+    Array { values: Vec<YrsValue> },
+    Map { entries: HashMap<String, YrsValue> },
+    SharedRef { kind: YrsSharedKind },
+}
+
+impl From<Any> for YrsValue {
+    fn from(value: Any) -> Self {
+        YrsValue::from(&value)
+    }
+}
+
+impl From<&Any> for YrsValue {
+    fn from(value: &Any) -> Self {
+        match value {
+            Any::Null => YrsValue::Null,
+            Any::Undefined => YrsValue::Undefined,
+            Any::Bool(value) => YrsValue::Bool { value: *value },
+            Any::Number(value) => YrsValue::Number { value: *value },
+            Any::BigInt(value) => YrsValue::BigInt { value: *value },
+            Any::String(value) => YrsValue::String { value: value.to_string() },
+            Any::Buffer(value) => YrsValue::Buffer { value: value.to_vec() },
+            Any::Array(values) => YrsValue::Array {
+                values: values.iter().map(YrsValue::from).collect(),
+            },
+            Any::Map(entries) => YrsValue::Map {
+                entries: entries
+                    .iter()
+                    .map(|(k, v)| (k.clone(), YrsValue::from(v)))
+                    .collect(),
+            },
+        }
+    }
+}
+
+impl From<Out> for YrsValue {
+    fn from(value: Out) -> Self {
+        let kind = match value {
+            Out::Any(any) => return YrsValue::from(any),
+            Out::YText(_) => YrsSharedKind::Text,
+            Out::YArray(_) => YrsSharedKind::Array,
+            Out::YMap(_) => YrsSharedKind::Map,
+            Out::YXmlElement(_) => YrsSharedKind::XmlElement,
+            Out::YXmlFragment(_) => YrsSharedKind::XmlFragment,
+            Out::YXmlText(_) => YrsSharedKind::XmlText,
+            Out::YDoc(_) => YrsSharedKind::Doc,
+            Out::YWeakLink(_) => YrsSharedKind::WeakLink,
+            Out::UndefinedRef(_) => YrsSharedKind::Undefined,
+        };
+        YrsValue::SharedRef { kind }
+    }
+}
+
+/// Shared references can't be written as plain values, so converting one (at any depth)
+/// fails with `CodingError::EncodingError`.
+impl TryFrom<YrsValue> for Any {
+    type Error = CodingError;
+
+    fn try_from(value: YrsValue) -> Result<Self, Self::Error> {
+        Ok(match value {
+            YrsValue::Null => Any::Null,
+            YrsValue::Undefined => Any::Undefined,
+            YrsValue::Bool { value } => Any::Bool(value),
+            YrsValue::Number { value } => Any::Number(value),
+            YrsValue::BigInt { value } => Any::BigInt(value),
+            YrsValue::String { value } => Any::String(Arc::from(value)),
+            YrsValue::Buffer { value } => Any::Buffer(Arc::from(value)),
+            YrsValue::Array { values } => Any::Array(
+                values
+                    .into_iter()
+                    .map(Any::try_from)
+                    .collect::<Result<Vec<_>, _>>()?
+                    .into(),
+            ),
+            YrsValue::Map { entries } => Any::Map(Arc::new(
+                entries
+                    .into_iter()
+                    .map(|(k, v)| Ok((k, Any::try_from(v)?)))
+                    .collect::<Result<HashMap<_, _>, CodingError>>()?,
+            )),
+            YrsValue::SharedRef { .. } => return Err(CodingError::EncodingError),
+        })
+    }
+}
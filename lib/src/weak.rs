@@ -0,0 +1,242 @@
+use crate::change::YrsSharedHandle;
+use crate::doc::YrsCollectionPtr;
+use crate::transaction::YrsTransaction;
+use crate::value::YrsValue;
+use parking_lot::ReentrantMutex;
+use std::cell::UnsafeCell;
+use yrs::branch::{Branch, BranchPtr};
+use yrs::types::weak::QuoteError;
+use yrs::{ArrayRef, MapRef, Out, WeakPrelim, WeakRef};
+
+/// Error that can occur when quoting a range of a shared collection.
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum YrsQuoteError {
+    #[error("Quoted range spans beyond the bounds of current collection")]
+    OutOfBounds,
+}
+
+impl From<QuoteError> for YrsQuoteError {
+    fn from(value: QuoteError) -> Self {
+        match value {
+            QuoteError::OutOfBounds => YrsQuoteError::OutOfBounds,
+        }
+    }
+}
+
+/// A quotation of a range of elements which has not yet been integrated into a document.
+/// Insert it into a map or an array to obtain a live `YrsWeakLink`.
+pub(crate) struct YrsWeakPrelim(WeakPrelim<BranchPtr>);
+
+impl<P: AsRef<Branch>> From<WeakPrelim<P>> for YrsWeakPrelim {
+    fn from(value: WeakPrelim<P>) -> Self {
+        YrsWeakPrelim(value.upcast())
+    }
+}
+
+impl YrsWeakPrelim {
+    pub(crate) fn prelim(&self) -> WeakPrelim<BranchPtr> {
+        self.0.clone()
+    }
+}
+
+pub(crate) struct YrsWeakLink(ReentrantMutex<UnsafeCell<WeakRef<BranchPtr>>>);
+
+// Safe because ReentrantMutex provides proper thread synchronization.
+unsafe impl Send for YrsWeakLink {}
+unsafe impl Sync for YrsWeakLink {}
+
+/// A guard that holds the lock and provides access to the inner WeakRef.
+pub(crate) struct WeakRefGuard<'a> {
+    _guard: parking_lot::ReentrantMutexGuard<'a, UnsafeCell<WeakRef<BranchPtr>>>,
+    ptr: *mut WeakRef<BranchPtr>,
+}
+
+impl WeakRefGuard<'_> {
+    pub(crate) fn as_ref(&self) -> &WeakRef<BranchPtr> {
+        unsafe { &*self.ptr }
+    }
+
+    #[allow(dead_code)]
+    pub(crate) fn as_mut(&mut self) -> &mut WeakRef<BranchPtr> {
+        unsafe { &mut *self.ptr }
+    }
+}
+
+impl YrsWeakLink {
+    fn inner(&self) -> WeakRefGuard<'_> {
+        let guard = self.0.lock();
+        let ptr = unsafe { (*self.0.data_ptr()).get() };
+        WeakRefGuard { _guard: guard, ptr }
+    }
+}
+
+impl<P: AsRef<Branch>> From<WeakRef<P>> for YrsWeakLink {
+    fn from(value: WeakRef<P>) -> Self {
+        YrsWeakLink(ReentrantMutex::new(UnsafeCell::new(value.into_inner())))
+    }
+}
+
+impl YrsWeakLink {
+    pub(crate) fn raw_ptr(&self) -> YrsCollectionPtr {
+        let guard = self.inner();
+        YrsCollectionPtr::from(guard.as_ref().as_ref())
+    }
+
+    /// Returns the current contents of a quoted text range as a plain string.
+    /// Returns an empty string if the quoted range no longer exists.
+    pub(crate) fn get_string(&self, transaction: &YrsTransaction) -> String {
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+
+        let link = self.inner();
+        link.as_ref()
+            .try_source()
+            .map(|source| source.to_string(tx))
+            .unwrap_or_default()
+    }
+
+    /// Returns the current value of a linked map entry or of a single quoted array element.
+    /// Returns None if the linked value no longer exists.
+    pub(crate) fn deref(&self, transaction: &YrsTransaction) -> Option<YrsValue> {
+        self.deref_out(transaction).map(YrsValue::from)
+    }
+
+    /// Returns a handle to the shared type a map entry or single array element link
+    /// points to. Returns None if the linked value no longer exists or isn't a shared type.
+    pub(crate) fn deref_shared(&self, transaction: &YrsTransaction) -> Option<YrsSharedHandle> {
+        YrsSharedHandle::new(&self.deref_out(transaction)?)
+    }
+
+    fn deref_out(&self, transaction: &YrsTransaction) -> Option<Out> {
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+
+        let link: WeakRef<MapRef> = WeakRef::from(self.inner().as_ref().clone());
+        if !link.try_source()?.is_single() {
+            return None;
+        }
+        link.try_deref_value(tx)
+    }
+
+    /// Returns the current elements of a quoted array range.
+    /// Returns an empty list if the quoted range no longer exists.
+    pub(crate) fn unquote(&self, transaction: &YrsTransaction) -> Vec<YrsValue> {
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+
+        let link: WeakRef<ArrayRef> = WeakRef::from(self.inner().as_ref().clone());
+        link.unquote(tx).map(YrsValue::from).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::YrsQuoteError;
+    use crate::value::{YrsSharedKind, YrsValue};
+    use crate::YrsDoc;
+
+    #[test]
+    fn text_quote_follows_source_changes() {
+        let doc = YrsDoc::new();
+        let text = doc.get_text("text".to_string());
+        let map = doc.get_map("map".to_string());
+
+        let txn = doc.transact(None);
+        text.insert(&txn, 0, "hello world".to_string()).unwrap();
+
+        let prelim = text.quote(&txn, 0, 5).unwrap();
+        let link = map.insert_weak_link(&txn, "quote".to_string(), &prelim);
+        assert_eq!(link.get_string(&txn), "hello");
+
+        text.insert(&txn, 1, "EE".to_string()).unwrap();
+        assert_eq!(link.get_string(&txn), "hEEello");
+
+        let link = map.get_weak_link(&txn, "quote".to_string()).unwrap();
+        assert_eq!(link.get_string(&txn), "hEEello");
+    }
+
+    #[test]
+    fn text_quote_out_of_bounds() {
+        let doc = YrsDoc::new();
+        let text = doc.get_text("text".to_string());
+
+        let txn = doc.transact(None);
+        text.insert(&txn, 0, "hello".to_string()).unwrap();
+
+        assert!(text.quote(&txn, 3, 10).is_err());
+        assert!(text.quote(&txn, 0, 0).is_err());
+        assert!(matches!(text.quote(&txn, u32::MAX, 2), Err(YrsQuoteError::OutOfBounds)));
+    }
+
+    #[test]
+    fn map_entry_link_follows_overwrites() {
+        let doc = YrsDoc::new();
+        let users = doc.get_map("users".to_string());
+        let mentions = doc.get_array("mentions".to_string());
+
+        let txn = doc.transact(None);
+        users.insert(&txn, "alice".to_string(), "\"Alice\"".to_string());
+        assert!(users.link(&txn, "bob".to_string()).is_none());
+
+        let prelim = users.link(&txn, "alice".to_string()).unwrap();
+        let link = mentions.insert_weak_link(&txn, 0, &prelim);
+        assert_eq!(link.deref(&txn), Some(YrsValue::String { value: "Alice".to_string() }));
+
+        users.insert(&txn, "alice".to_string(), "\"Alice Liddell\"".to_string());
+        let link = mentions.get_weak_link(&txn, 0).unwrap();
+        assert_eq!(
+            link.deref(&txn),
+            Some(YrsValue::String { value: "Alice Liddell".to_string() })
+        );
+        assert!(link.deref_shared(&txn).is_none());
+
+        users.remove(&txn, "alice".to_string()).unwrap();
+        assert_eq!(link.deref(&txn), None);
+    }
+
+    #[test]
+    fn map_entry_link_resolves_shared_types() {
+        let doc = YrsDoc::new();
+        let pages = doc.get_map("pages".to_string());
+        let links = doc.get_map("links".to_string());
+
+        let txn = doc.transact(None);
+        let page = pages.insert_map(&txn, "home".to_string());
+        page.insert(&txn, "title".to_string(), "\"Home\"".to_string());
+        let prelim = pages.link(&txn, "home".to_string()).unwrap();
+        let link = links.insert_weak_link(&txn, "nav".to_string(), &prelim);
+
+        let handle = link.deref_shared(&txn).unwrap();
+        assert_eq!(handle.kind, YrsSharedKind::Map);
+        assert_eq!(handle.map.unwrap().get(&txn, "title".to_string()).unwrap(), "\"Home\"");
+
+        let handle = links.get_value(&txn, "nav".to_string()).unwrap();
+        assert_eq!(handle, YrsValue::SharedRef { kind: YrsSharedKind::WeakLink });
+    }
+
+    #[test]
+    fn array_quote_follows_source_changes() {
+        let doc = YrsDoc::new();
+        let array = doc.get_array("array".to_string());
+        let map = doc.get_map("map".to_string());
+
+        let txn = doc.transact(None);
+        array.insert_range(&txn, 0, vec!["1".into(), "2".into(), "3".into(), "4".into()]);
+
+        let prelim = array.quote(&txn, 1, 2).unwrap();
+        let link = map.insert_weak_link(&txn, "quote".to_string(), &prelim);
+        assert_eq!(
+            link.unquote(&txn),
+            vec![YrsValue::Number { value: 2.0 }, YrsValue::Number { value: 3.0 }]
+        );
+
+        array.insert(&txn, 2, "\"x\"".to_string());
+        array.remove(&txn, 1);
+        assert_eq!(
+            link.unquote(&txn),
+            vec![YrsValue::String { value: "x".to_string() }, YrsValue::Number { value: 3.0 }]
+        );
+        assert!(array.quote(&txn, 2, 5).is_err());
+        assert!(matches!(array.quote(&txn, 1, u32::MAX), Err(YrsQuoteError::OutOfBounds)));
+    }
+}
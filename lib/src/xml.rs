@@ -0,0 +1,1015 @@
+use crate::change::{YrsChange, YrsSharedHandle};
+use crate::delta::YrsDelta;
+use crate::doc::{YrsCollectionPtr, YrsOrigin};
+use crate::event::{attribute_changes, YrsDeepObservationDelegate, YrsEvent};
+use crate::mapchange::YrsMapChange;
+use crate::path::{path_segments, root_path, YrsPathSegment};
+use crate::subscription::YSubscription;
+use crate::transaction::YrsTransaction;
+use crate::value::YrsSharedKind;
+use parking_lot::{Mutex, ReentrantMutex};
+use std::cell::UnsafeCell;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use yrs::branch::{Branch, BranchPtr};
+use yrs::types::xml::XmlEvent;
+use yrs::{
+    DeepObservable, GetString, Observable, Out, ReadTxn, Text, Xml, XmlElementPrelim,
+    XmlElementRef, XmlFragment, XmlFragmentRef, XmlOut, XmlTextPrelim, XmlTextRef,
+};
+
+pub(crate) struct YrsXmlFragment(ReentrantMutex<UnsafeCell<XmlFragmentRef>>);
+
+// Safe because ReentrantMutex provides proper thread synchronization.
+unsafe impl Send for YrsXmlFragment {}
+unsafe impl Sync for YrsXmlFragment {}
+
+/// A guard that holds the lock and provides access to the inner XmlFragmentRef.
+pub(crate) struct XmlFragmentRefGuard<'a> {
+    _guard: parking_lot::ReentrantMutexGuard<'a, UnsafeCell<XmlFragmentRef>>,
+    ptr: *mut XmlFragmentRef,
+}
+
+impl XmlFragmentRefGuard<'_> {
+    pub(crate) fn as_ref(&self) -> &XmlFragmentRef {
+        unsafe { &*self.ptr }
+    }
+
+    #[allow(dead_code)]
+    pub(crate) fn as_mut(&mut self) -> &mut XmlFragmentRef {
+        unsafe { &mut *self.ptr }
+    }
+}
+
+impl YrsXmlFragment {
+    pub(crate) fn inner(&self) -> XmlFragmentRefGuard<'_> {
+        let guard = self.0.lock();
+        let ptr = unsafe { (*self.0.data_ptr()).get() };
+        XmlFragmentRefGuard { _guard: guard, ptr }
+    }
+}
+
+impl AsRef<Branch> for YrsXmlFragment {
+    fn as_ref(&self) -> &Branch {
+        //FIXME: after yrs v0.18 use logical references
+        let guard = self.inner();
+        let branch: &Branch = guard.as_ref().as_ref();
+        unsafe { std::mem::transmute::<&Branch, &Branch>(branch) }
+    }
+}
+
+impl From<XmlFragmentRef> for YrsXmlFragment {
+    fn from(value: XmlFragmentRef) -> Self {
+        YrsXmlFragment(ReentrantMutex::new(UnsafeCell::new(value)))
+    }
+}
+
+pub(crate) struct YrsXmlElement(ReentrantMutex<UnsafeCell<XmlElementRef>>);
+
+// Safe because ReentrantMutex provides proper thread synchronization.
+unsafe impl Send for YrsXmlElement {}
+unsafe impl Sync for YrsXmlElement {}
+
+/// A guard that holds the lock and provides access to the inner XmlElementRef.
+pub(crate) struct XmlElementRefGuard<'a> {
+    _guard: parking_lot::ReentrantMutexGuard<'a, UnsafeCell<XmlElementRef>>,
+    ptr: *mut XmlElementRef,
+}
+
+impl XmlElementRefGuard<'_> {
+    pub(crate) fn as_ref(&self) -> &XmlElementRef {
+        unsafe { &*self.ptr }
+    }
+
+    #[allow(dead_code)]
+    pub(crate) fn as_mut(&mut self) -> &mut XmlElementRef {
+        unsafe { &mut *self.ptr }
+    }
+}
+
+impl YrsXmlElement {
+    fn inner(&self) -> XmlElementRefGuard<'_> {
+        let guard = self.0.lock();
+        let ptr = unsafe { (*self.0.data_ptr()).get() };
+        XmlElementRefGuard { _guard: guard, ptr }
+    }
+}
+
+impl AsRef<Branch> for YrsXmlElement {
+    fn as_ref(&self) -> &Branch {
+        //FIXME: after yrs v0.18 use logical references
+        let guard = self.inner();
+        let branch: &Branch = guard.as_ref().as_ref();
+        unsafe { std::mem::transmute::<&Branch, &Branch>(branch) }
+    }
+}
+
+impl From<XmlElementRef> for YrsXmlElement {
+    fn from(value: XmlElementRef) -> Self {
+        YrsXmlElement(ReentrantMutex::new(UnsafeCell::new(value)))
+    }
+}
+
+pub(crate) struct YrsXmlText(ReentrantMutex<UnsafeCell<XmlTextRef>>);
+
+// Safe because ReentrantMutex provides proper thread synchronization.
+unsafe impl Send for YrsXmlText {}
+unsafe impl Sync for YrsXmlText {}
+
+/// A guard that holds the lock and provides access to the inner XmlTextRef.
+pub(crate) struct XmlTextRefGuard<'a> {
+    _guard: parking_lot::ReentrantMutexGuard<'a, UnsafeCell<XmlTextRef>>,
+    ptr: *mut XmlTextRef,
+}
+
+impl XmlTextRefGuard<'_> {
+    pub(crate) fn as_ref(&self) -> &XmlTextRef {
+        unsafe { &*self.ptr }
+    }
+
+    #[allow(dead_code)]
+    pub(crate) fn as_mut(&mut self) -> &mut XmlTextRef {
+        unsafe { &mut *self.ptr }
+    }
+}
+
+impl YrsXmlText {
+    fn inner(&self) -> XmlTextRefGuard<'_> {
+        let guard = self.0.lock();
+        let ptr = unsafe { (*self.0.data_ptr()).get() };
+        XmlTextRefGuard { _guard: guard, ptr }
+    }
+}
+
+impl AsRef<Branch> for YrsXmlText {
+    fn as_ref(&self) -> &Branch {
+        //FIXME: after yrs v0.18 use logical references
+        let guard = self.inner();
+        let branch: &Branch = guard.as_ref().as_ref();
+        unsafe { std::mem::transmute::<&Branch, &Branch>(branch) }
+    }
+}
+
+impl From<XmlTextRef> for YrsXmlText {
+    fn from(value: XmlTextRef) -> Self {
+        YrsXmlText(ReentrantMutex::new(UnsafeCell::new(value)))
+    }
+}
+
+pub(crate) trait YrsXmlObservationDelegate: Send + Sync + Debug {
+    fn call(
+        &self,
+        changes: Vec<YrsChange>,
+        attributes: Vec<YrsMapChange>,
+        origin: Option<YrsOrigin>,
+        path: Vec<YrsPathSegment>,
+    );
+}
+
+pub(crate) trait YrsXmlTextObservationDelegate: Send + Sync + Debug {
+    fn call(
+        &self,
+        delta: Vec<YrsDelta>,
+        attributes: Vec<YrsMapChange>,
+        origin: Option<YrsOrigin>,
+        path: Vec<YrsPathSegment>,
+    );
+}
+
+/// Returns the kind of an XML node.
+fn xml_kind(node: &XmlOut) -> YrsSharedKind {
+    match node {
+        XmlOut::Element(_) => YrsSharedKind::XmlElement,
+        XmlOut::Fragment(_) => YrsSharedKind::XmlFragment,
+        XmlOut::Text(_) => YrsSharedKind::XmlText,
+    }
+}
+
+/// Returns a typed handle to an XML node.
+pub(crate) fn xml_handle(node: XmlOut) -> YrsSharedHandle {
+    YrsSharedHandle::new(&Out::from(node)).expect("XML nodes are shared types")
+}
+
+// Child management shared by fragments and elements, which both hold a sequence
+// of XML nodes.
+
+fn length_of<F: XmlFragment>(parent: &F, transaction: &YrsTransaction) -> u32 {
+    let tx = transaction.transaction();
+    let tx = tx.as_ref().unwrap();
+    parent.len(tx)
+}
+
+fn insert_element_into<F: XmlFragment>(
+    parent: &F,
+    transaction: &YrsTransaction,
+    index: u32,
+    tag: String,
+) -> Arc<YrsXmlElement> {
+    let mut tx = transaction.transaction();
+    let tx = tx.as_mut().unwrap();
+    let element = parent.insert(tx, index, XmlElementPrelim::empty(tag));
+    Arc::new(YrsXmlElement::from(element))
+}
+
+fn insert_text_into<F: XmlFragment>(
+    parent: &F,
+    transaction: &YrsTransaction,
+    index: u32,
+    content: String,
+) -> Arc<YrsXmlText> {
+    let mut tx = transaction.transaction();
+    let tx = tx.as_mut().unwrap();
+    let text = parent.insert(tx, index, XmlTextPrelim::new(content));
+    Arc::new(YrsXmlText::from(text))
+}
+
+fn remove_range_from<F: XmlFragment>(
+    parent: &F,
+    transaction: &YrsTransaction,
+    index: u32,
+    length: u32,
+) {
+    let mut tx = transaction.transaction();
+    let tx = tx.as_mut().unwrap();
+    parent.remove_range(tx, index, length);
+}
+
+fn child_at<F: XmlFragment>(
+    parent: &F,
+    transaction: &YrsTransaction,
+    index: u32,
+) -> Option<XmlOut> {
+    let tx = transaction.transaction();
+    let tx = tx.as_ref().unwrap();
+    parent.get(tx, index)
+}
+
+fn observe_children<F>(
+    parent: &F,
+    delegate: Box<dyn YrsXmlObservationDelegate>,
+) -> Arc<YSubscription>
+where
+    F: Observable<Event = XmlEvent>,
+{
+    let subscription = parent.observe(move |transaction, event| {
+        let changes = event
+            .delta(transaction)
+            .iter()
+            .map(YrsChange::from)
+            .collect();
+        let attributes = attribute_changes(event.keys(transaction));
+        let origin = transaction.origin().cloned().map(YrsOrigin::from);
+        let path = path_segments(root_path(transaction, event.target().as_ref()));
+        delegate.call(changes, attributes, origin, path)
+    });
+    Arc::new(YSubscription::new(subscription))
+}
+
+fn observe_deep_events<F: DeepObservable>(
+    parent: &F,
+    delegate: Box<dyn YrsDeepObservationDelegate>,
+) -> Arc<YSubscription> {
+    let subscription = parent.observe_deep(move |transaction, events| {
+        let result: Vec<YrsEvent> = events
+            .iter()
+            .map(|event| YrsEvent::new(transaction, event))
+            .collect();
+        let origin = transaction.origin().cloned().map(YrsOrigin::from);
+        delegate.call(result, origin)
+    });
+    Arc::new(YSubscription::new(subscription))
+}
+
+fn parent_of(node: &XmlOut) -> Option<XmlOut> {
+    match node {
+        XmlOut::Element(element) => element.parent(),
+        XmlOut::Fragment(fragment) => fragment.parent(),
+        XmlOut::Text(text) => text.parent(),
+    }
+}
+
+fn next_sibling_of<T: ReadTxn>(node: &XmlOut, txn: &T) -> Option<XmlOut> {
+    match node {
+        XmlOut::Element(element) => element.siblings(txn).next(),
+        XmlOut::Text(text) => text.siblings(txn).next(),
+        XmlOut::Fragment(_) => None,
+    }
+}
+
+/// Walks the descendants of an XML fragment or element depth-first, parents before
+/// their children, within a transaction held for its lifetime. The next node is found
+/// from the last one returned, so nodes inserted ahead of the walker are visited too.
+pub(crate) struct YrsXmlTreeWalker {
+    transaction: Arc<YrsTransaction>,
+    root: BranchPtr,
+    current: Mutex<Option<XmlOut>>,
+    started: AtomicBool,
+}
+
+// Safe because Mutex provides proper thread synchronization.
+unsafe impl Send for YrsXmlTreeWalker {}
+unsafe impl Sync for YrsXmlTreeWalker {}
+
+impl YrsXmlTreeWalker {
+    fn new<F: XmlFragment>(root: &F, transaction: Arc<YrsTransaction>) -> Arc<Self> {
+        Arc::new(YrsXmlTreeWalker {
+            transaction,
+            root: BranchPtr::from(root.as_ref()),
+            current: Mutex::new(None),
+            started: AtomicBool::new(false),
+        })
+    }
+
+    /// Returns the next node, or `None` once the tree is exhausted or the transaction
+    /// has been freed.
+    pub(crate) fn next(&self) -> Option<YrsSharedHandle> {
+        let tx = self.transaction.transaction();
+        let tx = tx.as_ref()?;
+        let mut current = self.current.lock();
+
+        let next = if !self.started.swap(true, Ordering::SeqCst) {
+            XmlFragmentRef::from(self.root).first_child()
+        } else {
+            let node = current.take()?;
+            let first_child = match &node {
+                XmlOut::Element(element) => element.first_child(),
+                _ => None,
+            };
+            first_child.or_else(|| {
+                let mut node = node;
+                loop {
+                    if let Some(sibling) = next_sibling_of(&node, tx) {
+                        return Some(sibling);
+                    }
+                    node = parent_of(&node).filter(|parent| parent.as_ptr() != self.root)?;
+                }
+            })
+        };
+        *current = next.clone();
+        next.map(xml_handle)
+    }
+}
+
+impl YrsXmlFragment {
+    pub(crate) fn raw_ptr(&self) -> YrsCollectionPtr {
+        let guard = self.inner();
+        YrsCollectionPtr::from(guard.as_ref().as_ref())
+    }
+
+    /// Returns the number of direct children of the fragment.
+    pub(crate) fn length(&self, transaction: &YrsTransaction) -> u32 {
+        length_of(self.inner().as_ref(), transaction)
+    }
+
+    /// Inserts an empty element with the given tag at the given index.
+    pub(crate) fn insert_element(
+        &self,
+        transaction: &YrsTransaction,
+        index: u32,
+        tag: String,
+    ) -> Arc<YrsXmlElement> {
+        insert_element_into(self.inner().as_ref(), transaction, index, tag)
+    }
+
+    /// Inserts a text node with the given content at the given index.
+    pub(crate) fn insert_text(
+        &self,
+        transaction: &YrsTransaction,
+        index: u32,
+        content: String,
+    ) -> Arc<YrsXmlText> {
+        insert_text_into(self.inner().as_ref(), transaction, index, content)
+    }
+
+    /// Appends an empty element with the given tag after the last child.
+    pub(crate) fn push_element(
+        &self,
+        transaction: &YrsTransaction,
+        tag: String,
+    ) -> Arc<YrsXmlElement> {
+        let index = self.length(transaction);
+        self.insert_element(transaction, index, tag)
+    }
+
+    /// Appends a text node with the given content after the last child.
+    pub(crate) fn push_text(
+        &self,
+        transaction: &YrsTransaction,
+        content: String,
+    ) -> Arc<YrsXmlText> {
+        let index = self.length(transaction);
+        self.insert_text(transaction, index, content)
+    }
+
+    /// Removes `length` children starting at the given index.
+    pub(crate) fn remove_range(&self, transaction: &YrsTransaction, index: u32, length: u32) {
+        remove_range_from(self.inner().as_ref(), transaction, index, length)
+    }
+
+    /// Returns the kind of the child at the given index, or `None` past the last child.
+    pub(crate) fn get_kind(
+        &self,
+        transaction: &YrsTransaction,
+        index: u32,
+    ) -> Option<YrsSharedKind> {
+        child_at(self.inner().as_ref(), transaction, index)
+            .as_ref()
+            .map(xml_kind)
+    }
+
+    /// Returns the child at the given index if it is an element.
+    pub(crate) fn get_element(
+        &self,
+        transaction: &YrsTransaction,
+        index: u32,
+    ) -> Option<Arc<YrsXmlElement>> {
+        match child_at(self.inner().as_ref(), transaction, index) {
+            Some(XmlOut::Element(element)) => Some(Arc::new(YrsXmlElement::from(element))),
+            _ => None,
+        }
+    }
+
+    /// Returns the child at the given index if it is a text node.
+    pub(crate) fn get_text(
+        &self,
+        transaction: &YrsTransaction,
+        index: u32,
+    ) -> Option<Arc<YrsXmlText>> {
+        match child_at(self.inner().as_ref(), transaction, index) {
+            Some(XmlOut::Text(text)) => Some(Arc::new(YrsXmlText::from(text))),
+            _ => None,
+        }
+    }
+
+    /// Returns the first child of the fragment.
+    pub(crate) fn first_child(&self, transaction: &YrsTransaction) -> Option<YrsSharedHandle> {
+        child_at(self.inner().as_ref(), transaction, 0).map(xml_handle)
+    }
+
+    /// Returns a walker over the descendants of the fragment, depth-first. The walker
+    /// holds on to the transaction, and stops yielding nodes once it is freed.
+    pub(crate) fn successors(&self, transaction: Arc<YrsTransaction>) -> Arc<YrsXmlTreeWalker> {
+        YrsXmlTreeWalker::new(self.inner().as_ref(), transaction)
+    }
+
+    /// Observes changes to the children of the fragment.
+    pub(crate) fn observe(
+        &self,
+        delegate: Box<dyn YrsXmlObservationDelegate>,
+    ) -> Arc<YSubscription> {
+        observe_children(self.inner().as_ref(), delegate)
+    }
+
+    /// Observes changes made to the fragment and to any node nested inside it.
+    pub(crate) fn observe_deep(
+        &self,
+        delegate: Box<dyn YrsDeepObservationDelegate>,
+    ) -> Arc<YSubscription> {
+        observe_deep_events(self.inner().as_ref(), delegate)
+    }
+
+    /// Serializes the children of the fragment to an XML string.
+    pub(crate) fn get_string(&self, transaction: &YrsTransaction) -> String {
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+        self.inner().as_ref().get_string(tx)
+    }
+}
+
+impl YrsXmlElement {
+    pub(crate) fn raw_ptr(&self) -> YrsCollectionPtr {
+        let guard = self.inner();
+        YrsCollectionPtr::from(guard.as_ref().as_ref())
+    }
+
+    /// Returns the tag name of the element.
+    pub(crate) fn tag(&self) -> String {
+        self.inner().as_ref().tag().to_string()
+    }
+
+    /// Sets an attribute, replacing any previous value.
+    pub(crate) fn insert_attribute(
+        &self,
+        transaction: &YrsTransaction,
+        name: String,
+        value: String,
+    ) {
+        let mut tx = transaction.transaction();
+        let tx = tx.as_mut().unwrap();
+        self.inner().as_ref().insert_attribute(tx, name, value);
+    }
+
+    /// Returns the value of an attribute, or `None` if it isn't set.
+    pub(crate) fn get_attribute(
+        &self,
+        transaction: &YrsTransaction,
+        name: String,
+    ) -> Option<String> {
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+        self.inner()
+            .as_ref()
+            .get_attribute(tx, name.as_str())
+            .map(|value| value.to_string(tx))
+    }
+
+    pub(crate) fn remove_attribute(&self, transaction: &YrsTransaction, name: String) {
+        let mut tx = transaction.transaction();
+        let tx = tx.as_mut().unwrap();
+        self.inner().as_ref().remove_attribute(tx, &name);
+    }
+
+    /// Returns every attribute of the element by name.
+    pub(crate) fn attributes(&self, transaction: &YrsTransaction) -> HashMap<String, String> {
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+        let element = self.inner();
+        element
+            .as_ref()
+            .attributes(tx)
+            .map(|(name, value)| (name.to_string(), value.to_string(tx)))
+            .collect()
+    }
+
+    /// Returns the number of direct children of the element.
+    pub(crate) fn length(&self, transaction: &YrsTransaction) -> u32 {
+        length_of(self.inner().as_ref(), transaction)
+    }
+
+    /// Inserts an empty child element with the given tag at the given index.
+    pub(crate) fn insert_element(
+        &self,
+        transaction: &YrsTransaction,
+        index: u32,
+        tag: String,
+    ) -> Arc<YrsXmlElement> {
+        insert_element_into(self.inner().as_ref(), transaction, index, tag)
+    }
+
+    /// Inserts a child text node with the given content at the given index.
+    pub(crate) fn insert_text(
+        &self,
+        transaction: &YrsTransaction,
+        index: u32,
+        content: String,
+    ) -> Arc<YrsXmlText> {
+        insert_text_into(self.inner().as_ref(), transaction, index, content)
+    }
+
+    /// Appends an empty child element with the given tag after the last child.
+    pub(crate) fn push_element(
+        &self,
+        transaction: &YrsTransaction,
+        tag: String,
+    ) -> Arc<YrsXmlElement> {
+        let index = self.length(transaction);
+        self.insert_element(transaction, index, tag)
+    }
+
+    /// Appends a child text node with the given content after the last child.
+    pub(crate) fn push_text(
+        &self,
+        transaction: &YrsTransaction,
+        content: String,
+    ) -> Arc<YrsXmlText> {
+        let index = self.length(transaction);
+        self.insert_text(transaction, index, content)
+    }
+
+    /// Removes `length` children starting at the given index.
+    pub(crate) fn remove_range(&self, transaction: &YrsTransaction, index: u32, length: u32) {
+        remove_range_from(self.inner().as_ref(), transaction, index, length)
+    }
+
+    /// Returns the child at the given index, or `None` past the last child.
+    pub(crate) fn get(&self, transaction: &YrsTransaction, index: u32) -> Option<YrsSharedHandle> {
+        child_at(self.inner().as_ref(), transaction, index).map(xml_handle)
+    }
+
+    /// Returns the first child of the element.
+    pub(crate) fn first_child(&self, transaction: &YrsTransaction) -> Option<YrsSharedHandle> {
+        child_at(self.inner().as_ref(), transaction, 0).map(xml_handle)
+    }
+
+    /// Returns a walker over the descendants of the element, depth-first. The walker
+    /// holds on to the transaction, and stops yielding nodes once it is freed.
+    pub(crate) fn successors(&self, transaction: Arc<YrsTransaction>) -> Arc<YrsXmlTreeWalker> {
+        YrsXmlTreeWalker::new(self.inner().as_ref(), transaction)
+    }
+
+    /// Returns the element or fragment holding this element. Elements stored outside
+    /// of an XML tree, such as in a map, have no parent.
+    pub(crate) fn parent(&self) -> Option<YrsSharedHandle> {
+        self.inner().as_ref().parent().map(xml_handle)
+    }
+
+    /// Returns the node following this element within its parent.
+    pub(crate) fn next_sibling(&self, transaction: &YrsTransaction) -> Option<YrsSharedHandle> {
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+        self.inner().as_ref().siblings(tx).next().map(xml_handle)
+    }
+
+    /// Returns the node preceding this element within its parent.
+    pub(crate) fn prev_sibling(&self, transaction: &YrsTransaction) -> Option<YrsSharedHandle> {
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+        self.inner()
+            .as_ref()
+            .siblings(tx)
+            .next_back()
+            .map(xml_handle)
+    }
+
+    /// Observes changes to the attributes and children of the element.
+    pub(crate) fn observe(
+        &self,
+        delegate: Box<dyn YrsXmlObservationDelegate>,
+    ) -> Arc<YSubscription> {
+        observe_children(self.inner().as_ref(), delegate)
+    }
+
+    /// Observes changes made to the element and to any node nested inside it.
+    pub(crate) fn observe_deep(
+        &self,
+        delegate: Box<dyn YrsDeepObservationDelegate>,
+    ) -> Arc<YSubscription> {
+        observe_deep_events(self.inner().as_ref(), delegate)
+    }
+
+    /// Serializes the element, its attributes and its children to an XML string.
+    pub(crate) fn get_string(&self, transaction: &YrsTransaction) -> String {
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+        self.inner().as_ref().get_string(tx)
+    }
+}
+
+impl YrsXmlText {
+    pub(crate) fn raw_ptr(&self) -> YrsCollectionPtr {
+        let guard = self.inner();
+        YrsCollectionPtr::from(guard.as_ref().as_ref())
+    }
+
+    /// Returns the length of the text.
+    pub(crate) fn length(&self, transaction: &YrsTransaction) -> u32 {
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+        self.inner().as_ref().len(tx)
+    }
+
+    pub(crate) fn insert(&self, transaction: &YrsTransaction, index: u32, chunk: String) {
+        let mut tx = transaction.transaction();
+        let tx = tx.as_mut().unwrap();
+        self.inner().as_ref().insert(tx, index, chunk.as_str());
+    }
+
+    pub(crate) fn push(&self, transaction: &YrsTransaction, chunk: String) {
+        let mut tx = transaction.transaction();
+        let tx = tx.as_mut().unwrap();
+        self.inner().as_ref().push(tx, chunk.as_str());
+    }
+
+    pub(crate) fn remove_range(&self, transaction: &YrsTransaction, index: u32, length: u32) {
+        let mut tx = transaction.transaction();
+        let tx = tx.as_mut().unwrap();
+        self.inner().as_ref().remove_range(tx, index, length);
+    }
+
+    /// Returns the element or fragment holding this text node.
+    pub(crate) fn parent(&self) -> Option<YrsSharedHandle> {
+        self.inner().as_ref().parent().map(xml_handle)
+    }
+
+    /// Returns the node following this text node within its parent.
+    pub(crate) fn next_sibling(&self, transaction: &YrsTransaction) -> Option<YrsSharedHandle> {
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+        self.inner().as_ref().siblings(tx).next().map(xml_handle)
+    }
+
+    /// Returns the node preceding this text node within its parent.
+    pub(crate) fn prev_sibling(&self, transaction: &YrsTransaction) -> Option<YrsSharedHandle> {
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+        self.inner()
+            .as_ref()
+            .siblings(tx)
+            .next_back()
+            .map(xml_handle)
+    }
+
+    /// Observes changes to the content and attributes of the text node.
+    pub(crate) fn observe(
+        &self,
+        delegate: Box<dyn YrsXmlTextObservationDelegate>,
+    ) -> Arc<YSubscription> {
+        let text = self.inner();
+        let subscription = text.as_ref().observe(move |transaction, event| {
+            let delta = event
+                .delta(transaction)
+                .iter()
+                .map(YrsDelta::from)
+                .collect();
+            let attributes = attribute_changes(event.keys(transaction));
+            let origin = transaction.origin().cloned().map(YrsOrigin::from);
+            let path = path_segments(root_path(transaction, event.target().as_ref()));
+            delegate.call(delta, attributes, origin, path)
+        });
+        Arc::new(YSubscription::new(subscription))
+    }
+
+    /// Observes changes made to the text node.
+    pub(crate) fn observe_deep(
+        &self,
+        delegate: Box<dyn YrsDeepObservationDelegate>,
+    ) -> Arc<YSubscription> {
+        observe_deep_events(self.inner().as_ref(), delegate)
+    }
+
+    /// Returns the content of the text node, with formatting serialized as XML tags.
+    pub(crate) fn get_string(&self, transaction: &YrsTransaction) -> String {
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+        self.inner().as_ref().get_string(tx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{YrsXmlObservationDelegate, YrsXmlTreeWalker};
+    use crate::change::{YrsChange, YrsSharedHandle};
+    use crate::doc::YrsOrigin;
+    use crate::event::{YrsDeepObservationDelegate, YrsEvent};
+    use crate::mapchange::YrsMapChange;
+    use crate::path::YrsPathSegment;
+    use crate::value::YrsSharedKind;
+    use crate::YrsDoc;
+    use parking_lot::Mutex;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    #[test]
+    fn fragment_inserts_indexes_and_serializes_children() {
+        let doc = YrsDoc::new();
+        let fragment = doc.get_xml_fragment("xml".to_string());
+        let txn = doc.transact(None);
+        let p = fragment.push_element(&txn, "p".to_string());
+        fragment.insert_text(&txn, 0, "intro".to_string());
+        fragment.push_text(&txn, "outro".to_string());
+
+        assert_eq!(fragment.length(&txn), 3);
+        assert_eq!(p.tag(), "p");
+        assert_eq!(fragment.get_kind(&txn, 0), Some(YrsSharedKind::XmlText));
+        assert_eq!(fragment.get_kind(&txn, 1), Some(YrsSharedKind::XmlElement));
+        assert_eq!(fragment.get_kind(&txn, 3), None);
+        assert_eq!(fragment.get_element(&txn, 1).unwrap().tag(), "p");
+        assert!(fragment.get_element(&txn, 0).is_none());
+        assert_eq!(
+            fragment.get_text(&txn, 2).unwrap().get_string(&txn),
+            "outro"
+        );
+        assert_eq!(fragment.get_string(&txn), "intro<p></p>outro");
+
+        fragment.remove_range(&txn, 0, 2);
+        assert_eq!(fragment.get_string(&txn), "outro");
+    }
+
+    #[test]
+    fn element_manages_attributes_and_children() {
+        let doc = YrsDoc::new();
+        let fragment = doc.get_xml_fragment("xml".to_string());
+        let txn = doc.transact(None);
+        let link = fragment.push_element(&txn, "a".to_string());
+        link.insert_attribute(&txn, "href".to_string(), "/home".to_string());
+        link.insert_attribute(&txn, "title".to_string(), "Home".to_string());
+        link.push_text(&txn, "home".to_string());
+        link.insert_element(&txn, 0, "img".to_string());
+
+        assert_eq!(
+            link.get_attribute(&txn, "href".to_string()).as_deref(),
+            Some("/home")
+        );
+        link.remove_attribute(&txn, "title".to_string());
+        assert_eq!(link.get_attribute(&txn, "title".to_string()), None);
+        assert_eq!(
+            link.attributes(&txn),
+            HashMap::from([("href".to_string(), "/home".to_string())])
+        );
+        assert_eq!(link.length(&txn), 2);
+        assert_eq!(link.get(&txn, 0).unwrap().xml_element.unwrap().tag(), "img");
+        assert_eq!(link.get(&txn, 1).unwrap().kind, YrsSharedKind::XmlText);
+        assert_eq!(
+            fragment.get_string(&txn),
+            "<a href=\"/home\"><img></img>home</a>"
+        );
+
+        link.remove_range(&txn, 0, 1);
+        assert_eq!(link.get_string(&txn), "<a href=\"/home\">home</a>");
+    }
+
+    #[test]
+    fn element_navigates_to_parent_and_siblings() {
+        let doc = YrsDoc::new();
+        let fragment = doc.get_xml_fragment("xml".to_string());
+        let txn = doc.transact(None);
+        let list = fragment.push_element(&txn, "ul".to_string());
+        let first = list.push_element(&txn, "li".to_string());
+        list.push_text(&txn, "between".to_string());
+        let last = list.push_element(&txn, "li".to_string());
+
+        let parent = first.parent().unwrap();
+        assert_eq!(parent.kind, YrsSharedKind::XmlElement);
+        assert_eq!(parent.xml_element.unwrap().tag(), "ul");
+        assert_eq!(list.parent().unwrap().kind, YrsSharedKind::XmlFragment);
+
+        let next = first.next_sibling(&txn).unwrap();
+        assert_eq!(next.xml_text.unwrap().get_string(&txn), "between");
+        assert!(first.prev_sibling(&txn).is_none());
+        assert!(last.next_sibling(&txn).is_none());
+        assert_eq!(
+            last.prev_sibling(&txn).unwrap().kind,
+            YrsSharedKind::XmlText
+        );
+    }
+
+    #[test]
+    fn tree_walker_visits_descendants_depth_first() {
+        let doc = YrsDoc::new();
+        let fragment = doc.get_xml_fragment("xml".to_string());
+        let txn = doc.transact(None);
+        let div = fragment.push_element(&txn, "div".to_string());
+        let p = div.push_element(&txn, "p".to_string());
+        p.push_text(&txn, "Hello ".to_string());
+        p.push_element(&txn, "b".to_string())
+            .push_text(&txn, "world".to_string());
+        div.push_text(&txn, "again".to_string());
+        fragment.push_element(&txn, "footer".to_string());
+
+        let label = |handle: YrsSharedHandle| match handle.kind {
+            YrsSharedKind::XmlElement => handle.xml_element.unwrap().tag(),
+            _ => handle.xml_text.unwrap().get_string(&txn),
+        };
+        let walk = |walker: Arc<YrsXmlTreeWalker>| {
+            std::iter::from_fn(|| walker.next())
+                .map(label)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            walk(fragment.successors(txn.clone())),
+            vec!["div", "p", "Hello ", "b", "world", "again", "footer"]
+        );
+        assert_eq!(
+            walk(div.successors(txn.clone())),
+            vec!["p", "Hello ", "b", "world", "again"]
+        );
+        assert_eq!(label(fragment.first_child(&txn).unwrap()), "div");
+        assert_eq!(label(p.first_child(&txn).unwrap()), "Hello ");
+
+        let walker = fragment.successors(txn.clone());
+        txn.free();
+        assert!(walker.next().is_none());
+    }
+
+    #[test]
+    fn text_node_navigates_to_parent_and_siblings() {
+        let doc = YrsDoc::new();
+        let fragment = doc.get_xml_fragment("xml".to_string());
+        let txn = doc.transact(None);
+        let text = fragment.push_text(&txn, "lead".to_string());
+        fragment.push_element(&txn, "hr".to_string());
+
+        assert_eq!(text.parent().unwrap().kind, YrsSharedKind::XmlFragment);
+        assert_eq!(
+            text.next_sibling(&txn).unwrap().xml_element.unwrap().tag(),
+            "hr"
+        );
+        assert!(text.prev_sibling(&txn).is_none());
+    }
+
+    type ObservedChange = (Vec<String>, Vec<String>, Vec<String>);
+
+    fn labels(path: Vec<YrsPathSegment>) -> Vec<String> {
+        path.into_iter()
+            .map(|segment| match segment {
+                YrsPathSegment::Key { key } => key,
+                YrsPathSegment::Index { index } => index.to_string(),
+            })
+            .collect()
+    }
+
+    #[derive(Debug, Default)]
+    struct ChildrenDelegate(Arc<Mutex<Vec<ObservedChange>>>);
+
+    impl YrsXmlObservationDelegate for ChildrenDelegate {
+        fn call(
+            &self,
+            changes: Vec<YrsChange>,
+            attributes: Vec<YrsMapChange>,
+            _origin: Option<YrsOrigin>,
+            path: Vec<YrsPathSegment>,
+        ) {
+            let changes = changes
+                .into_iter()
+                .map(|change| match change {
+                    YrsChange::Added { shared, .. } => format!(
+                        "added {}",
+                        shared
+                            .into_iter()
+                            .map(|item| item.handle.xml_element.unwrap().tag())
+                            .collect::<Vec<_>>()
+                            .join(",")
+                    ),
+                    YrsChange::Removed { range } => format!("removed {range}"),
+                    YrsChange::Retained { range } => format!("retained {range}"),
+                })
+                .collect();
+            let attributes = attributes.into_iter().map(|change| change.key).collect();
+            self.0.lock().push((changes, attributes, labels(path)));
+        }
+    }
+
+    #[test]
+    fn element_observer_reports_children_attributes_and_path() {
+        let doc = YrsDoc::new();
+        let fragment = doc.get_xml_fragment("xml".to_string());
+        let txn = doc.transact(None);
+        fragment.push_element(&txn, "header".to_string());
+        let list = fragment.push_element(&txn, "ul".to_string());
+        txn.free();
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let _subscription = list.observe(Box::new(ChildrenDelegate(events.clone())));
+        let txn = doc.transact(None);
+        list.push_element(&txn, "li".to_string());
+        list.insert_attribute(&txn, "class".to_string(), "todo".to_string());
+        txn.free();
+
+        assert_eq!(
+            *events.lock(),
+            vec![(
+                vec!["added li".to_string()],
+                vec!["class".to_string()],
+                vec!["xml".to_string(), "1".to_string()],
+            )]
+        );
+    }
+
+    #[derive(Debug, Default)]
+    struct DeepKinds(Arc<Mutex<Vec<String>>>);
+
+    impl YrsDeepObservationDelegate for DeepKinds {
+        fn call(&self, events: Vec<YrsEvent>, _origin: Option<YrsOrigin>) {
+            for event in events {
+                let (kind, path) = match event {
+                    YrsEvent::Xml { path, .. } => ("xml", path),
+                    YrsEvent::XmlText { path, delta, .. } => {
+                        assert!(!delta.is_empty());
+                        ("xml-text", path)
+                    }
+                    _ => ("other", Vec::new()),
+                };
+                self.0
+                    .lock()
+                    .push(format!("{kind}:{}", labels(path).join("/")));
+            }
+        }
+    }
+
+    #[test]
+    fn deep_observer_reports_nested_xml_events() {
+        let doc = YrsDoc::new();
+        let fragment = doc.get_xml_fragment("xml".to_string());
+        let txn = doc.transact(None);
+        let p = fragment.push_element(&txn, "p".to_string());
+        let text = p.push_text(&txn, "hi".to_string());
+        txn.free();
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let _subscription = fragment.observe_deep(Box::new(DeepKinds(events.clone())));
+        let txn = doc.transact(None);
+        text.push(&txn, "!".to_string());
+        p.insert_attribute(&txn, "id".to_string(), "intro".to_string());
+        txn.free();
+
+        let mut events = events.lock().clone();
+        events.sort();
+        assert_eq!(events, vec!["xml-text:0/0", "xml:0"]);
+    }
+
+    #[test]
+    fn text_node_edits_content() {
+        let doc = YrsDoc::new();
+        let fragment = doc.get_xml_fragment("xml".to_string());
+        let txn = doc.transact(None);
+        let text = fragment.push_text(&txn, "hello".to_string());
+        text.push(&txn, " world".to_string());
+        text.insert(&txn, 0, "oh,".to_string());
+        text.remove_range(&txn, 3, 5);
+
+        assert_eq!(text.length(&txn), 9);
+        assert_eq!(fragment.get_string(&txn), "oh, world");
+    }
+}
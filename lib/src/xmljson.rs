@@ -0,0 +1,237 @@
+use crate::error::CodingError;
+use crate::transaction::YrsTransaction;
+use crate::xml::YrsXmlFragment;
+use std::collections::HashMap;
+use yrs::{
+    Any, GetString, Out, ReadTxn, TextRef, TransactionMut, Xml, XmlElementPrelim, XmlFragment,
+    XmlOut, XmlTextPrelim,
+};
+
+/// The `type` of text nodes. Tag names can't start with `#`, so it never clashes with
+/// the type of an element.
+const TEXT_TYPE: &str = "#text";
+
+/// A node parsed from its JSON representation, checked before anything is written.
+enum Node {
+    Element {
+        tag: String,
+        attrs: Vec<(String, Any)>,
+        children: Vec<Node>,
+    },
+    Text(String),
+}
+
+fn node_to_any<T: ReadTxn>(node: XmlOut, txn: &T) -> Any {
+    match node {
+        XmlOut::Element(element) => {
+            let attrs: HashMap<String, Any> = element
+                .attributes(txn)
+                .map(|(name, value)| {
+                    let value = match value {
+                        Out::Any(any) => any,
+                        other => Any::String(other.to_string(txn).into()),
+                    };
+                    (name.to_string(), value)
+                })
+                .collect();
+            Any::from(HashMap::from([
+                ("type".to_string(), Any::from(element.tag().to_string())),
+                ("attrs".to_string(), Any::from(attrs)),
+                ("children".to_string(), children_to_any(&element, txn)),
+            ]))
+        }
+        XmlOut::Text(text) => {
+            let text: &TextRef = text.as_ref();
+            Any::from(HashMap::from([
+                ("type".to_string(), Any::from(TEXT_TYPE)),
+                ("text".to_string(), Any::from(text.get_string(txn))),
+            ]))
+        }
+        XmlOut::Fragment(fragment) => children_to_any(&fragment, txn),
+    }
+}
+
+fn children_to_any<F: XmlFragment, T: ReadTxn>(parent: &F, txn: &T) -> Any {
+    let children: Vec<Any> = parent
+        .children(txn)
+        .map(|child| node_to_any(child, txn))
+        .collect();
+    Any::from(children)
+}
+
+fn parse_nodes(value: &Any) -> Result<Vec<Node>, CodingError> {
+    let Any::Array(nodes) = value else {
+        return Err(CodingError::UnexpectedType);
+    };
+    nodes.iter().map(parse_node).collect()
+}
+
+fn parse_node(value: &Any) -> Result<Node, CodingError> {
+    let Any::Map(fields) = value else {
+        return Err(CodingError::UnexpectedType);
+    };
+    let Some(Any::String(kind)) = fields.get("type") else {
+        return Err(CodingError::UnexpectedType);
+    };
+    if kind.as_ref() == TEXT_TYPE {
+        return match fields.get("text") {
+            Some(Any::String(text)) => Ok(Node::Text(text.to_string())),
+            _ => Err(CodingError::UnexpectedType),
+        };
+    }
+
+    let attrs = match fields.get("attrs") {
+        None | Some(Any::Null) => Vec::new(),
+        Some(Any::Map(attrs)) => {
+            let mut attrs: Vec<(String, Any)> = attrs
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect();
+            attrs.sort_by(|a, b| a.0.cmp(&b.0));
+            attrs
+        }
+        Some(_) => return Err(CodingError::UnexpectedType),
+    };
+    let children = match fields.get("children") {
+        None | Some(Any::Null) => Vec::new(),
+        Some(children) => parse_nodes(children)?,
+    };
+    Ok(Node::Element {
+        tag: kind.to_string(),
+        attrs,
+        children,
+    })
+}
+
+fn push_node<F: XmlFragment>(parent: &F, txn: &mut TransactionMut, node: Node) {
+    match node {
+        Node::Element {
+            tag,
+            attrs,
+            children,
+        } => {
+            let element = parent.push_back(txn, XmlElementPrelim::empty(tag));
+            for (name, value) in attrs {
+                element.insert_attribute(txn, name, value);
+            }
+            for child in children {
+                push_node(&element, txn, child);
+            }
+        }
+        Node::Text(text) => {
+            parent.push_back(txn, XmlTextPrelim::new(text));
+        }
+    }
+}
+
+impl YrsXmlFragment {
+    /// Converts the children of the fragment into a JSON array of nodes. Elements become
+    /// `{"type": tag, "attrs": {...}, "children": [...]}` objects and text nodes become
+    /// `{"type": "#text", "text": "..."}` objects, leaving out text formatting.
+    pub(crate) fn to_json_tree(&self, transaction: &YrsTransaction) -> String {
+        let tx = transaction.transaction();
+        let tx = tx.as_ref().unwrap();
+
+        let mut buf = String::new();
+        children_to_any(self.inner().as_ref(), tx).to_json(&mut buf);
+        buf
+    }
+
+    /// Replaces the children of the fragment with the nodes of a JSON array shaped like
+    /// the output of `to_json_tree`. The whole tree is checked first, so nothing changes
+    /// when it is malformed.
+    pub(crate) fn apply_json_tree(
+        &self,
+        transaction: &YrsTransaction,
+        json: String,
+    ) -> Result<(), CodingError> {
+        let value = Any::from_json(json.as_str()).map_err(|_| CodingError::DecodingError)?;
+        let nodes = parse_nodes(&value)?;
+
+        let mut tx = transaction.transaction();
+        let tx = tx.as_mut().unwrap();
+        let fragment = self.inner();
+        let len = fragment.as_ref().len(tx);
+        if len > 0 {
+            fragment.as_ref().remove_range(tx, 0, len);
+        }
+        for node in nodes {
+            push_node(fragment.as_ref(), tx, node);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::CodingError;
+    use crate::YrsDoc;
+    use yrs::Any;
+
+    #[test]
+    fn json_tree_round_trips_structure() {
+        let doc = YrsDoc::new();
+        let fragment = doc.get_xml_fragment("xml".to_string());
+        let txn = doc.transact(None);
+        let p = fragment.push_element(&txn, "p".to_string());
+        p.insert_attribute(&txn, "class".to_string(), "lead".to_string());
+        p.push_text(&txn, "Hello ".to_string());
+        p.push_element(&txn, "b".to_string())
+            .push_text(&txn, "world".to_string());
+        fragment.push_element(&txn, "hr".to_string());
+
+        let json = fragment.to_json_tree(&txn);
+        assert_eq!(
+            Any::from_json(&json).unwrap(),
+            Any::from_json(
+                r##"[
+                    {"type": "p", "attrs": {"class": "lead"}, "children": [
+                        {"type": "#text", "text": "Hello "},
+                        {"type": "b", "attrs": {}, "children": [{"type": "#text", "text": "world"}]}
+                    ]},
+                    {"type": "hr", "attrs": {}, "children": []}
+                ]"##
+            )
+            .unwrap()
+        );
+
+        let copy = YrsDoc::new();
+        let copy_fragment = copy.get_xml_fragment("xml".to_string());
+        let copy_txn = copy.transact(None);
+        copy_fragment.push_text(&copy_txn, "replaced".to_string());
+        copy_fragment.apply_json_tree(&copy_txn, json).unwrap();
+        assert_eq!(
+            copy_fragment.get_string(&copy_txn),
+            fragment.get_string(&txn)
+        );
+        assert_eq!(
+            copy_fragment.get_string(&copy_txn),
+            "<p class=\"lead\">Hello <b>world</b></p><hr></hr>"
+        );
+    }
+
+    #[test]
+    fn apply_json_tree_rejects_malformed_trees() {
+        let doc = YrsDoc::new();
+        let fragment = doc.get_xml_fragment("xml".to_string());
+        let txn = doc.transact(None);
+        fragment.push_text(&txn, "kept".to_string());
+
+        assert!(matches!(
+            fragment.apply_json_tree(&txn, "[".to_string()),
+            Err(CodingError::DecodingError)
+        ));
+        assert!(matches!(
+            fragment.apply_json_tree(
+                &txn,
+                r#"[{"type": "p", "children": [{"text": "x"}]}]"#.to_string()
+            ),
+            Err(CodingError::UnexpectedType)
+        ));
+        assert!(matches!(
+            fragment.apply_json_tree(&txn, r#"{"type": "p"}"#.to_string()),
+            Err(CodingError::UnexpectedType)
+        ));
+        assert_eq!(fragment.get_string(&txn), "kept");
+    }
+}